@@ -0,0 +1,673 @@
+// Device Color Calibration
+//
+// Per-device and per-model profiles (gamma, RGB gain matrix, white point,
+// minimum brightness floor) applied to outgoing colors ahead of the
+// photosensitivity limiter, so the same RGBColor looks alike across models.
+// Color-temperature-only devices get the nearest Kelvin value instead of
+// RGB. A guided flow shows test colors on a light while the user tunes a
+// draft profile.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+use crate::govee::RGBColor;
+use crate::lighting::{self, LightBackend, LightTarget, TargetFrame};
+use crate::storage;
+
+const CALIBRATION_FILE: &str = "calibration.json";
+const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+fn default_gamma() -> f32 {
+    1.0
+}
+
+fn default_gain_matrix() -> [[f32; 3]; 3] {
+    IDENTITY
+}
+
+fn default_white_point() -> RGBColor {
+    RGBColor { r: 255, g: 255, b: 255 }
+}
+
+fn default_kelvin_range() -> [u16; 2] {
+    [2000, 9000]
+}
+
+/// Calibration applied to a device's output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationProfile {
+    /// Output exponent per channel (1.0 = unchanged)
+    #[serde(default = "default_gamma")]
+    pub gamma: f32,
+    /// Row-major matrix mixing input RGB into output RGB
+    #[serde(rename = "gainMatrix", default = "default_gain_matrix")]
+    pub gain_matrix: [[f32; 3]; 3],
+    /// Device RGB that renders as neutral white
+    #[serde(rename = "whitePoint", default = "default_white_point")]
+    pub white_point: RGBColor,
+    /// Lowest usable brightness (0.0 - 1.0); dim colors are lifted above it
+    #[serde(rename = "minBrightness", default)]
+    pub min_brightness: f32,
+    /// Device only supports white color temperature
+    #[serde(rename = "colorTemperatureOnly", default)]
+    pub color_temperature_only: bool,
+    /// Supported color temperature range in Kelvin
+    #[serde(rename = "kelvinRange", default = "default_kelvin_range")]
+    pub kelvin_range: [u16; 2],
+}
+
+impl Default for CalibrationProfile {
+    fn default() -> Self {
+        Self {
+            gamma: default_gamma(),
+            gain_matrix: IDENTITY,
+            white_point: default_white_point(),
+            min_brightness: 0.0,
+            color_temperature_only: false,
+            kelvin_range: default_kelvin_range(),
+        }
+    }
+}
+
+impl CalibrationProfile {
+    /// Map a requested color to the device color
+    pub fn apply(&self, color: &RGBColor) -> RGBColor {
+        let input = [color.r as f32 / 255.0, color.g as f32 / 255.0, color.b as f32 / 255.0];
+        let white = [
+            self.white_point.r as f32 / 255.0,
+            self.white_point.g as f32 / 255.0,
+            self.white_point.b as f32 / 255.0,
+        ];
+
+        let mut output = [0.0f32; 3];
+        for (channel, row) in self.gain_matrix.iter().enumerate() {
+            let mixed: f32 = row.iter().zip(input).map(|(gain, value)| gain * value).sum();
+            let gamma = if self.gamma > 0.0 { self.gamma } else { 1.0 };
+            output[channel] = (mixed * white[channel]).clamp(0.0, 1.0).powf(gamma);
+        }
+
+        // Lift dim colors into the usable range, keeping the hue
+        let peak = output.iter().cloned().fold(0.0f32, f32::max);
+        let floor = self.min_brightness.clamp(0.0, 1.0);
+        if peak > 0.0 && floor > 0.0 {
+            let lifted = floor + peak * (1.0 - floor);
+            for value in output.iter_mut() {
+                *value *= lifted / peak;
+            }
+        }
+
+        let channel = |value: f32| (value * 255.0).round().clamp(0.0, 255.0) as u8;
+        RGBColor {
+            r: channel(output[0]),
+            g: channel(output[1]),
+            b: channel(output[2]),
+        }
+    }
+
+    /// Nearest color temperature and brightness (0-100) for a requested color
+    pub fn to_temperature(&self, color: &RGBColor) -> (u16, u8) {
+        let peak = color.r.max(color.g).max(color.b);
+        let brightness = (peak as f32 / 255.0).powf(self.gamma.max(0.01));
+        let floor = self.min_brightness.clamp(0.0, 1.0);
+        let brightness = if peak > 0 { floor + brightness * (1.0 - floor) } else { 0.0 };
+        (nearest_kelvin(color, self.kelvin_range), (brightness * 100.0).round() as u8)
+    }
+}
+
+/// Which devices a profile applies to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "scope", rename_all = "lowercase")]
+pub enum ProfileScope {
+    Device {
+        backend: LightBackend,
+        #[serde(rename = "deviceId")]
+        device_id: String,
+    },
+    Model { model: String },
+}
+
+fn device_key(backend: LightBackend, device_id: &str) -> String {
+    format!("{}:{}", backend.as_str(), device_id)
+}
+
+/// Stored profiles (device profiles take precedence over model profiles)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CalibrationSettings {
+    #[serde(default)]
+    pub devices: HashMap<String, CalibrationProfile>,
+    #[serde(default)]
+    pub models: HashMap<String, CalibrationProfile>,
+}
+
+/// White color temperature command for a CT-only light
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemperatureCommand {
+    pub target: LightTarget,
+    pub kelvin: u16,
+    pub brightness: u8,
+}
+
+/// Calibrated output ready for the backends
+#[derive(Debug, Clone, Default)]
+pub struct CalibratedOutput {
+    pub frames: Vec<TargetFrame>,
+    pub temperatures: Vec<TemperatureCommand>,
+}
+
+/// Calibrate frames with a profile lookup
+fn calibrate(frames: Vec<TargetFrame>, profile_for: impl Fn(&LightTarget) -> Option<CalibrationProfile>) -> CalibratedOutput {
+    let mut output = CalibratedOutput::default();
+
+    for frame in frames {
+        let Some(profile) = profile_for(&frame.target) else {
+            output.frames.push(frame);
+            continue;
+        };
+
+        if profile.color_temperature_only {
+            let (kelvin, brightness) = profile.to_temperature(&lighting::average_color(&frame.colors));
+            if lighting::supports_color_temperature(frame.target.backend) {
+                output.temperatures.push(TemperatureCommand {
+                    target: frame.target,
+                    kelvin,
+                    brightness,
+                });
+            } else {
+                let white = kelvin_to_rgb(kelvin);
+                let scale = |v: u8| (v as f32 * brightness as f32 / 100.0).round() as u8;
+                output.frames.push(TargetFrame {
+                    target: frame.target,
+                    colors: vec![RGBColor { r: scale(white.r), g: scale(white.g), b: scale(white.b) }],
+                });
+            }
+            continue;
+        }
+
+        output.frames.push(TargetFrame {
+            colors: frame.colors.iter().map(|color| profile.apply(color)).collect(),
+            target: frame.target,
+        });
+    }
+
+    output
+}
+
+/// Approximate RGB of a black-body white at `kelvin` (Tanner Helland's fit)
+pub fn kelvin_to_rgb(kelvin: u16) -> RGBColor {
+    let t = kelvin.clamp(1000, 40000) as f32 / 100.0;
+
+    let r = if t <= 66.0 { 255.0 } else { 329.698_73 * (t - 60.0).powf(-0.133_204_76) };
+    let g = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_17 * (t - 60.0).powf(-0.075_514_85)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+
+    let channel = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+    RGBColor {
+        r: channel(r),
+        g: channel(g),
+        b: channel(b),
+    }
+}
+
+/// Color temperature whose white is closest in chromaticity to `color`
+fn nearest_kelvin(color: &RGBColor, range: [u16; 2]) -> u16 {
+    let normalize = |c: &RGBColor| {
+        let peak = c.r.max(c.g).max(c.b).max(1) as f32;
+        [c.r as f32 / peak, c.g as f32 / peak, c.b as f32 / peak]
+    };
+    let target = normalize(color);
+    let (low, high) = (range[0].min(range[1]), range[0].max(range[1]));
+
+    (low..=high)
+        .step_by(50)
+        .min_by(|a, b| {
+            let distance = |kelvin: u16| {
+                let white = normalize(&kelvin_to_rgb(kelvin));
+                white.iter().zip(target).map(|(w, t)| (w - t) * (w - t)).sum::<f32>()
+            };
+            distance(*a).total_cmp(&distance(*b))
+        })
+        .unwrap_or(low)
+}
+
+/// Guided calibration step
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CalibrationStepKind {
+    WhiteBalance,
+    Gamma,
+    MinBrightness,
+}
+
+const STEPS: [CalibrationStepKind; 3] = [
+    CalibrationStepKind::WhiteBalance,
+    CalibrationStepKind::Gamma,
+    CalibrationStepKind::MinBrightness,
+];
+
+/// Step shown to the user with the color currently on the light
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationStep {
+    pub index: usize,
+    pub total: usize,
+    pub kind: CalibrationStepKind,
+    pub instruction: String,
+    #[serde(rename = "testColor")]
+    pub test_color: RGBColor,
+    pub draft: CalibrationProfile,
+}
+
+fn step_info(kind: CalibrationStepKind) -> (&'static str, RGBColor) {
+    match kind {
+        CalibrationStepKind::WhiteBalance => (
+            "Adjust the white point until the light looks neutral white, without a green or pink cast",
+            RGBColor { r: 255, g: 255, b: 255 },
+        ),
+        CalibrationStepKind::Gamma => (
+            "Adjust gamma until the light looks about half as bright as full white",
+            RGBColor { r: 128, g: 128, b: 128 },
+        ),
+        CalibrationStepKind::MinBrightness => (
+            "Raise the minimum brightness until the light is just visibly on",
+            RGBColor { r: 5, g: 5, b: 5 },
+        ),
+    }
+}
+
+struct CalibrationSession {
+    target: LightTarget,
+    draft: CalibrationProfile,
+    step: usize,
+}
+
+impl CalibrationSession {
+    fn current_step(&self) -> CalibrationStep {
+        let kind = STEPS[self.step];
+        let (instruction, test_color) = step_info(kind);
+        CalibrationStep {
+            index: self.step,
+            total: STEPS.len(),
+            kind,
+            instruction: instruction.to_string(),
+            test_color,
+            draft: self.draft.clone(),
+        }
+    }
+}
+
+/// Calibration state for Tauri
+pub struct CalibrationState {
+    settings: Mutex<CalibrationSettings>,
+    /// Device model per (backend, device id), resolved on first use
+    models: Mutex<HashMap<String, String>>,
+    session: Mutex<Option<CalibrationSession>>,
+}
+
+impl CalibrationState {
+    pub fn new() -> Self {
+        let settings = match storage::load_json::<CalibrationSettings>(CALIBRATION_FILE) {
+            Ok(settings) => settings.unwrap_or_default(),
+            Err(e) => {
+                println!("Failed to load calibration profiles: {}", e);
+                CalibrationSettings::default()
+            }
+        };
+
+        Self {
+            settings: Mutex::new(settings),
+            models: Mutex::new(HashMap::new()),
+            session: Mutex::new(None),
+        }
+    }
+
+    /// Profile for a target: device profile first, then its model's profile
+    fn profile_for(&self, app: &AppHandle, target: &LightTarget) -> Option<CalibrationProfile> {
+        let settings = self.settings.lock().unwrap();
+        let key = device_key(target.backend, &target.device_id);
+        if let Some(profile) = settings.devices.get(&key) {
+            return Some(profile.clone());
+        }
+        if settings.models.is_empty() {
+            return None;
+        }
+
+        let cached = self.models.lock().unwrap().get(&key).cloned();
+        let model = match cached {
+            Some(model) => model,
+            None => {
+                // Only cache devices that report a model; undiscovered
+                // devices are looked up again next time
+                let model = lighting::device_model(app, target)?;
+                self.models.lock().unwrap().insert(key, model.clone());
+                model
+            }
+        };
+        settings.models.get(&model).cloned()
+    }
+
+    /// Apply stored profiles to a batch of frames
+    pub fn apply(&self, app: &AppHandle, frames: Vec<TargetFrame>) -> CalibratedOutput {
+        calibrate(frames, |target| self.profile_for(app, target))
+    }
+}
+
+/// Show the current step's test color through the draft profile
+fn show_step(app: &AppHandle, session: &CalibrationSession) -> Result<(), String> {
+    let frame = TargetFrame {
+        target: session.target.clone(),
+        colors: vec![step_info(STEPS[session.step]).1],
+    };
+    let draft = session.draft.clone();
+    let report = lighting::send_frames_with(app, &[frame], |frames| calibrate(frames, |_| Some(draft.clone())));
+    match report.errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+fn save_settings(settings: &CalibrationSettings) -> Result<(), String> {
+    storage::save_json(CALIBRATION_FILE, settings)
+}
+
+/// Get all stored profiles
+#[tauri::command]
+pub fn calibration_get_profiles(state: State<CalibrationState>) -> CalibrationSettings {
+    state.settings.lock().unwrap().clone()
+}
+
+/// Store a profile for a device or model
+#[tauri::command]
+pub fn calibration_set_profile(
+    scope: ProfileScope,
+    profile: CalibrationProfile,
+    state: State<CalibrationState>,
+) -> Result<(), String> {
+    let mut settings = state.settings.lock().unwrap();
+    match scope {
+        ProfileScope::Device { backend, device_id } => {
+            settings.devices.insert(device_key(backend, &device_id), profile);
+        }
+        ProfileScope::Model { model } => {
+            settings.models.insert(model, profile);
+        }
+    }
+    save_settings(&settings)
+}
+
+/// Remove a device or model profile
+#[tauri::command]
+pub fn calibration_remove_profile(scope: ProfileScope, state: State<CalibrationState>) -> Result<(), String> {
+    let mut settings = state.settings.lock().unwrap();
+    match scope {
+        ProfileScope::Device { backend, device_id } => {
+            settings.devices.remove(&device_key(backend, &device_id));
+        }
+        ProfileScope::Model { model } => {
+            settings.models.remove(&model);
+        }
+    }
+    save_settings(&settings)
+}
+
+/// Convert a color temperature to RGB
+#[tauri::command]
+pub fn calibration_kelvin_to_rgb(kelvin: u16) -> RGBColor {
+    kelvin_to_rgb(kelvin)
+}
+
+/// Start guided calibration of a light, showing the first test color
+#[tauri::command]
+pub fn calibration_begin(
+    target: LightTarget,
+    app: AppHandle,
+    state: State<CalibrationState>,
+) -> Result<CalibrationStep, String> {
+    let draft = state.profile_for(&app, &target).unwrap_or_default();
+    let session = CalibrationSession { target, draft, step: 0 };
+
+    show_step(&app, &session)?;
+
+    let step = session.current_step();
+    *state.session.lock().unwrap() = Some(session);
+    Ok(step)
+}
+
+/// Replace the draft profile and re-show the test color with it
+#[tauri::command]
+pub fn calibration_update_draft(
+    draft: CalibrationProfile,
+    app: AppHandle,
+    state: State<CalibrationState>,
+) -> Result<CalibrationStep, String> {
+    let mut session = state.session.lock().unwrap();
+    let session = session.as_mut().ok_or_else(|| "No calibration in progress".to_string())?;
+
+    session.draft = draft;
+    show_step(&app, session)?;
+    Ok(session.current_step())
+}
+
+/// Advance to the next step; returns None after the last step
+#[tauri::command]
+pub fn calibration_next(app: AppHandle, state: State<CalibrationState>) -> Result<Option<CalibrationStep>, String> {
+    let mut session = state.session.lock().unwrap();
+    let session = session.as_mut().ok_or_else(|| "No calibration in progress".to_string())?;
+
+    if session.step + 1 >= STEPS.len() {
+        return Ok(None);
+    }
+
+    session.step += 1;
+    show_step(&app, session)?;
+    Ok(Some(session.current_step()))
+}
+
+/// Save the draft for the calibrated device, or for every device of its model
+#[tauri::command]
+pub fn calibration_finish(
+    apply_to_model: bool,
+    app: AppHandle,
+    state: State<CalibrationState>,
+) -> Result<CalibrationProfile, String> {
+    // Resolve the scope before taking the session so a failure keeps the draft
+    let (scope, draft) = {
+        let session = state.session.lock().unwrap();
+        let session = session.as_ref().ok_or_else(|| "No calibration in progress".to_string())?;
+
+        let scope = if apply_to_model {
+            let model = lighting::device_model(&app, &session.target)
+                .ok_or_else(|| "Device model is unknown".to_string())?;
+            ProfileScope::Model { model }
+        } else {
+            ProfileScope::Device {
+                backend: session.target.backend,
+                device_id: session.target.device_id.clone(),
+            }
+        };
+        (scope, session.draft.clone())
+    };
+
+    calibration_set_profile(scope, draft.clone(), state.clone())?;
+    state.session.lock().unwrap().take();
+    Ok(draft)
+}
+
+/// Abandon guided calibration without saving
+#[tauri::command]
+pub fn calibration_cancel(state: State<CalibrationState>) {
+    state.session.lock().unwrap().take();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(r: u8, g: u8, b: u8) -> RGBColor {
+        RGBColor { r, g, b }
+    }
+
+    fn frame(backend: LightBackend, id: &str, colors: Vec<RGBColor>) -> TargetFrame {
+        TargetFrame {
+            target: LightTarget::device(backend, id),
+            colors,
+        }
+    }
+
+    #[test]
+    fn default_profile_leaves_colors_unchanged() {
+        let profile = CalibrationProfile::default();
+
+        for color in [rgb(0, 0, 0), rgb(1, 128, 254), rgb(255, 255, 255)] {
+            assert_eq!(profile.apply(&color), color);
+        }
+    }
+
+    #[test]
+    fn gamma_curves_each_channel() {
+        let profile = CalibrationProfile {
+            gamma: 2.0,
+            ..Default::default()
+        };
+
+        assert_eq!(profile.apply(&rgb(128, 255, 0)), rgb(64, 255, 0));
+
+        // A non-positive gamma is ignored instead of inverting the output
+        let broken = CalibrationProfile {
+            gamma: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(broken.apply(&rgb(128, 64, 0)), rgb(128, 64, 0));
+    }
+
+    #[test]
+    fn gain_matrix_mixes_and_clamps_channels() {
+        let swapped = CalibrationProfile {
+            gain_matrix: [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+            ..Default::default()
+        };
+        assert_eq!(swapped.apply(&rgb(10, 20, 30)), rgb(20, 10, 30));
+
+        let mixed = CalibrationProfile {
+            gain_matrix: [[0.5, 0.5, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, -1.0]],
+            ..Default::default()
+        };
+        assert_eq!(mixed.apply(&rgb(200, 200, 100)), rgb(200, 255, 0));
+    }
+
+    #[test]
+    fn white_point_scales_channels() {
+        let profile = CalibrationProfile {
+            white_point: rgb(255, 128, 204),
+            ..Default::default()
+        };
+
+        assert_eq!(profile.apply(&rgb(255, 255, 255)), rgb(255, 128, 204));
+        assert_eq!(profile.apply(&rgb(200, 200, 0)), rgb(200, 100, 0));
+    }
+
+    #[test]
+    fn min_brightness_lifts_dim_colors_keeping_their_hue() {
+        let profile = CalibrationProfile {
+            min_brightness: 0.2,
+            ..Default::default()
+        };
+
+        assert_eq!(profile.apply(&rgb(0, 0, 0)), rgb(0, 0, 0));
+        assert_eq!(profile.apply(&rgb(255, 0, 0)), rgb(255, 0, 0));
+        assert_eq!(profile.apply(&rgb(0, 0, 10)), rgb(0, 0, 59));
+
+        let lifted = profile.apply(&rgb(10, 5, 0));
+        assert_eq!(lifted.r, 59);
+        assert!((29..=30).contains(&lifted.g), "{:?}", lifted);
+
+        let (_, brightness) = profile.to_temperature(&rgb(1, 1, 1));
+        assert_eq!(brightness, 20);
+        assert_eq!(profile.to_temperature(&rgb(0, 0, 0)).1, 0);
+    }
+
+    #[test]
+    fn kelvin_to_rgb_follows_the_black_body_curve() {
+        assert_eq!(kelvin_to_rgb(6600), rgb(255, 255, 255));
+        assert_eq!(kelvin_to_rgb(1000), rgb(255, 68, 0));
+        assert_eq!(kelvin_to_rgb(0), kelvin_to_rgb(1000));
+        assert_eq!(kelvin_to_rgb(u16::MAX), kelvin_to_rgb(40000));
+
+        let warm = kelvin_to_rgb(2700);
+        let cool = kelvin_to_rgb(9000);
+        assert!(warm.r > warm.g && warm.g > warm.b);
+        assert!(cool.b > cool.r);
+    }
+
+    #[test]
+    fn colors_map_to_the_nearest_temperature_in_range() {
+        let profile = CalibrationProfile {
+            color_temperature_only: true,
+            ..Default::default()
+        };
+
+        assert_eq!(profile.to_temperature(&rgb(255, 255, 255)), (6600, 100));
+        let (kelvin, brightness) = profile.to_temperature(&kelvin_to_rgb(2700));
+        assert!(kelvin.abs_diff(2700) <= 50, "{}", kelvin);
+        assert_eq!(brightness, 100);
+
+        let narrow = CalibrationProfile {
+            kelvin_range: [6500, 2700],
+            ..profile
+        };
+        assert_eq!(narrow.to_temperature(&rgb(255, 255, 255)).0, 6500);
+        assert_eq!(narrow.to_temperature(&rgb(255, 0, 0)).0, 2700);
+    }
+
+    #[test]
+    fn temperature_only_lights_get_temperature_commands() {
+        let profile = CalibrationProfile {
+            color_temperature_only: true,
+            ..Default::default()
+        };
+        let frames = vec![
+            frame(LightBackend::Yeelight, "bulb", vec![rgb(255, 255, 255), rgb(255, 255, 255)]),
+            frame(LightBackend::Wled, "strip", vec![rgb(128, 128, 128)]),
+        ];
+
+        let output = calibrate(frames, |_| Some(profile.clone()));
+
+        assert_eq!(output.temperatures.len(), 1);
+        let command = &output.temperatures[0];
+        assert_eq!(command.target.device_id, "bulb");
+        assert_eq!((command.kelvin, command.brightness), (6600, 100));
+
+        // Backends without a white channel get the temperature's RGB instead
+        assert_eq!(output.frames.len(), 1);
+        assert_eq!(output.frames[0].target.device_id, "strip");
+        assert_eq!(output.frames[0].colors, vec![rgb(128, 128, 128)]);
+    }
+
+    #[test]
+    fn only_profiled_targets_are_calibrated() {
+        let profile = CalibrationProfile {
+            gamma: 2.0,
+            ..Default::default()
+        };
+        let frames = vec![
+            frame(LightBackend::Wled, "calibrated", vec![rgb(128, 128, 128)]),
+            frame(LightBackend::Wled, "plain", vec![rgb(128, 128, 128)]),
+        ];
+
+        let output = calibrate(frames, |target| (target.device_id == "calibrated").then(|| profile.clone()));
+
+        assert_eq!(output.frames[0].colors, vec![rgb(64, 64, 64)]);
+        assert_eq!(output.frames[1].colors, vec![rgb(128, 128, 128)]);
+        assert!(output.temperatures.is_empty());
+    }
+}
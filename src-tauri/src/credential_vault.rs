@@ -0,0 +1,619 @@
+// Credential Vault
+// One place for service secrets (Spotify tokens, Govee API key, Hue and
+// Nanoleaf pairing credentials, MQTT password, ...). Entries are namespaced
+// per service and stored through pluggable backends: the OS keyring first,
+// an encrypted file when the keyring cannot hold them, or memory in tests.
+// The webview can set and delete secrets and see which exist, but never
+// reads them back; services that need a secret call out from Rust.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use keyring::Entry;
+
+use crate::secure_file;
+use crate::storage;
+
+// Keychain service shared by all entries; accounts are "<namespace>_<name>"
+// (the naming Hue, Nanoleaf and Spotify used before the vault)
+const KEYRING_SERVICE: &str = "musicViz";
+
+// Encrypted file holding entries the keyring could not store
+const VAULT_FILE: &str = "credentials.vault";
+
+// Names and locations of stored secrets (never the secrets themselves)
+const INDEX_FILE: &str = "credentials_index.json";
+
+const MAX_NAME_LEN: usize = 128;
+const MAX_SECRET_LEN: usize = 16 * 1024;
+
+static VAULT: OnceLock<CredentialVault> = OnceLock::new();
+
+/// Service a secret belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CredentialNamespace {
+    Spotify,
+    Govee,
+    Hue,
+    Nanoleaf,
+    Mqtt,
+    Other,
+}
+
+impl CredentialNamespace {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CredentialNamespace::Spotify => "spotify",
+            CredentialNamespace::Govee => "govee",
+            CredentialNamespace::Hue => "hue",
+            CredentialNamespace::Nanoleaf => "nanoleaf",
+            CredentialNamespace::Mqtt => "mqtt",
+            CredentialNamespace::Other => "other",
+        }
+    }
+}
+
+/// Storage for secrets addressed by account ("<namespace>_<name>")
+pub trait VaultBackend: Send + Sync {
+    /// Short name reported to the webview ("keyring", "file", "memory")
+    fn name(&self) -> &'static str;
+    fn get(&self, account: &str) -> Result<Option<String>, String>;
+    fn set(&self, account: &str, secret: &str) -> Result<(), String>;
+    fn delete(&self, account: &str) -> Result<(), String>;
+}
+
+/// OS keychain / credential manager
+pub struct KeyringBackend;
+
+impl VaultBackend for KeyringBackend {
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    fn get(&self, account: &str) -> Result<Option<String>, String> {
+        let entry = Entry::new(KEYRING_SERVICE, account)
+            .map_err(|e| format!("Failed to create keyring entry: {:?}", e))?;
+
+        match entry.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(format!("Failed to load {} from keyring: {:?}", account, e)),
+        }
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+        Entry::new(KEYRING_SERVICE, account)
+            .and_then(|entry| entry.set_password(secret))
+            .map_err(|e| format!("Failed to save {} to keyring: {:?}", account, e))?;
+
+        // Read back through a new entry: a keyring that does not persist
+        // across entries would lose the secret with the next launch
+        match self.get(account)? {
+            Some(stored) if stored == secret => Ok(()),
+            _ => Err(format!("Keyring did not keep {}", account)),
+        }
+    }
+
+    fn delete(&self, account: &str) -> Result<(), String> {
+        let entry = Entry::new(KEYRING_SERVICE, account)
+            .map_err(|e| format!("Failed to create keyring entry: {:?}", e))?;
+
+        match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("Failed to delete {} from keyring: {:?}", account, e)),
+        }
+    }
+}
+
+/// All entries in one encrypted file (see secure_file)
+#[derive(Default)]
+pub struct EncryptedFileBackend {
+    lock: Mutex<()>,
+}
+
+impl EncryptedFileBackend {
+    fn load(&self) -> Result<BTreeMap<String, String>, String> {
+        match secure_file::read(VAULT_FILE)? {
+            Some(secure_file::SecretFile::Encrypted(json)) => serde_json::from_slice(&json)
+                .map_err(|e| format!("Failed to deserialize {}: {}", VAULT_FILE, e)),
+            Some(secure_file::SecretFile::Plaintext(_)) => {
+                Err(format!("{} is not encrypted; refusing to use it", VAULT_FILE))
+            }
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    fn save(&self, entries: &BTreeMap<String, String>) -> Result<(), String> {
+        if entries.is_empty() {
+            return secure_file::delete(VAULT_FILE);
+        }
+        let json = serde_json::to_vec(entries)
+            .map_err(|e| format!("Failed to serialize {}: {}", VAULT_FILE, e))?;
+        secure_file::write_encrypted(VAULT_FILE, &json)
+    }
+}
+
+impl VaultBackend for EncryptedFileBackend {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn get(&self, account: &str) -> Result<Option<String>, String> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self.load()?.remove(account))
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+        let _guard = self.lock.lock().unwrap();
+        let mut entries = self.load()?;
+        entries.insert(account.to_string(), secret.to_string());
+        self.save(&entries)
+    }
+
+    fn delete(&self, account: &str) -> Result<(), String> {
+        let _guard = self.lock.lock().unwrap();
+        let mut entries = self.load()?;
+        if entries.remove(account).is_some() {
+            self.save(&entries)?;
+        }
+        Ok(())
+    }
+}
+
+/// Process memory only (tests, or devices without persistent storage)
+#[derive(Default)]
+pub struct MemoryBackend {
+    entries: Mutex<HashMap<String, String>>,
+}
+
+impl VaultBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn get(&self, account: &str) -> Result<Option<String>, String> {
+        Ok(self.entries.lock().unwrap().get(account).cloned())
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(account.to_string(), secret.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, account: &str) -> Result<(), String> {
+        self.entries.lock().unwrap().remove(account);
+        Ok(())
+    }
+}
+
+/// A stored secret as reported to the webview
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretInfo {
+    pub namespace: CredentialNamespace,
+    pub name: String,
+    /// Backend holding the secret
+    pub backend: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: u64,
+}
+
+/// Secrets in backends tried in order
+pub struct CredentialVault {
+    backends: Vec<Box<dyn VaultBackend>>,
+    index: Mutex<BTreeMap<String, SecretInfo>>,
+    /// Whether the index is saved to the storage directory
+    persist_index: bool,
+}
+
+impl CredentialVault {
+    /// Keyring first, encrypted file fallback (memory when there is no
+    /// storage directory)
+    pub fn new() -> Self {
+        let fallback: Box<dyn VaultBackend> = match storage::storage_dir(true) {
+            Ok(_) => Box::new(EncryptedFileBackend::default()),
+            Err(e) => {
+                println!("No credential storage ({}), keeping secrets in memory", e);
+                Box::new(MemoryBackend::default())
+            }
+        };
+
+        let index = match storage::load_json::<Vec<SecretInfo>>(INDEX_FILE) {
+            Ok(entries) => entries
+                .unwrap_or_default()
+                .into_iter()
+                .map(|info| (account(info.namespace, &info.name), info))
+                .collect(),
+            Err(e) => {
+                println!("Failed to load credential index: {}", e);
+                BTreeMap::new()
+            }
+        };
+
+        let mut vault = Self::with_backends(vec![Box::new(KeyringBackend), fallback]);
+        vault.index = Mutex::new(index);
+        vault.persist_index = true;
+        vault
+    }
+
+    /// Vault over the given backends that keeps its index in memory
+    pub fn with_backends(backends: Vec<Box<dyn VaultBackend>>) -> Self {
+        CredentialVault {
+            backends,
+            index: Mutex::new(BTreeMap::new()),
+            persist_index: false,
+        }
+    }
+
+    /// Vault that forgets everything when the process exits
+    pub fn in_memory() -> Self {
+        Self::with_backends(vec![Box::new(MemoryBackend::default())])
+    }
+
+    /// Load a secret from the first backend holding it
+    pub fn get(&self, namespace: CredentialNamespace, name: &str) -> Result<Option<String>, String> {
+        let account = checked_account(namespace, name)?;
+
+        let mut errors = Vec::new();
+        for backend in &self.backends {
+            match backend.get(&account) {
+                Ok(Some(secret)) => return Ok(Some(secret)),
+                Ok(None) => {}
+                Err(e) => {
+                    println!("Credential backend {} unavailable: {}", backend.name(), e);
+                    errors.push(e);
+                }
+            }
+        }
+
+        // Missing unless no backend could be asked
+        if errors.len() == self.backends.len() {
+            return Err(format!("Failed to load {}: {}", account, errors.join("; ")));
+        }
+        Ok(None)
+    }
+
+    /// Store a secret in the first backend that accepts it and remove
+    /// stale copies from the others
+    pub fn set(&self, namespace: CredentialNamespace, name: &str, secret: &str) -> Result<(), String> {
+        let account = checked_account(namespace, name)?;
+        if secret.len() > MAX_SECRET_LEN {
+            return Err(format!("Secret {} is too large", account));
+        }
+
+        let mut errors = Vec::new();
+        let mut stored_in = None;
+        for (i, backend) in self.backends.iter().enumerate() {
+            match backend.set(&account, secret) {
+                Ok(()) => {
+                    stored_in = Some(i);
+                    break;
+                }
+                Err(e) => {
+                    println!("Credential backend {} rejected {}: {}", backend.name(), account, e);
+                    errors.push(e);
+                }
+            }
+        }
+        let stored_in = stored_in.ok_or_else(|| format!("Failed to store {}: {}", account, errors.join("; ")))?;
+
+        for (i, backend) in self.backends.iter().enumerate() {
+            if i != stored_in {
+                if let Err(e) = backend.delete(&account) {
+                    println!("Warning: Failed to remove stale {} from {}: {}", account, backend.name(), e);
+                }
+            }
+        }
+
+        self.update_index(|index| {
+            index.insert(
+                account.clone(),
+                SecretInfo {
+                    namespace,
+                    name: name.to_string(),
+                    backend: self.backends[stored_in].name().to_string(),
+                    updated_at: now_secs(),
+                },
+            );
+        });
+        Ok(())
+    }
+
+    /// Delete a secret from every backend
+    pub fn delete(&self, namespace: CredentialNamespace, name: &str) -> Result<(), String> {
+        let account = checked_account(namespace, name)?;
+
+        let errors: Vec<String> = self
+            .backends
+            .iter()
+            .filter_map(|backend| backend.delete(&account).err())
+            .collect();
+
+        self.update_index(|index| {
+            index.remove(&account);
+        });
+
+        if errors.len() == self.backends.len() {
+            return Err(format!("Failed to delete {}: {}", account, errors.join("; ")));
+        }
+        for e in errors {
+            println!("Warning: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Whether a secret exists
+    pub fn contains(&self, namespace: CredentialNamespace, name: &str) -> bool {
+        matches!(self.get(namespace, name), Ok(Some(_)))
+    }
+
+    /// Secrets stored through the vault (names and backends only)
+    pub fn list(&self) -> Vec<SecretInfo> {
+        self.index.lock().unwrap().values().cloned().collect()
+    }
+
+    fn update_index(&self, update: impl FnOnce(&mut BTreeMap<String, SecretInfo>)) {
+        let mut index = self.index.lock().unwrap();
+        update(&mut index);
+
+        if self.persist_index {
+            let entries: Vec<&SecretInfo> = index.values().collect();
+            if let Err(e) = storage::save_json(INDEX_FILE, &entries) {
+                println!("Warning: Failed to save credential index: {}", e);
+            }
+        }
+    }
+}
+
+impl Default for CredentialVault {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The process-wide vault (keyring with encrypted file fallback unless
+/// another vault was installed first)
+pub fn vault() -> &'static CredentialVault {
+    VAULT.get_or_init(CredentialVault::new)
+}
+
+/// Use `vault` as the process-wide vault; fails once the vault is in use
+pub fn install(vault: CredentialVault) -> Result<(), String> {
+    VAULT
+        .set(vault)
+        .map_err(|_| "Credential vault is already initialized".to_string())
+}
+
+fn account(namespace: CredentialNamespace, name: &str) -> String {
+    format!("{}_{}", namespace.as_str(), name)
+}
+
+fn checked_account(namespace: CredentialNamespace, name: &str) -> Result<String, String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '@'));
+    if !valid {
+        return Err(format!("Invalid credential name: {:?}", name));
+    }
+    Ok(account(namespace, name))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Store a secret
+#[tauri::command]
+pub fn vault_set_secret(namespace: CredentialNamespace, name: String, secret: String) -> Result<(), String> {
+    vault().set(namespace, &name, &secret)
+}
+
+/// Delete a secret
+#[tauri::command]
+pub fn vault_delete_secret(namespace: CredentialNamespace, name: String) -> Result<(), String> {
+    vault().delete(namespace, &name)
+}
+
+/// Whether a secret exists
+#[tauri::command]
+pub fn vault_has_secret(namespace: CredentialNamespace, name: String) -> bool {
+    vault().contains(namespace, &name)
+}
+
+/// List stored secrets without their values
+#[tauri::command]
+pub fn vault_list_secrets() -> Vec<SecretInfo> {
+    vault().list()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Backend whose entries and availability the test can see and change
+    #[derive(Clone)]
+    struct SharedBackend {
+        name: &'static str,
+        entries: Arc<Mutex<HashMap<String, String>>>,
+        available: Arc<AtomicBool>,
+    }
+
+    impl SharedBackend {
+        fn new(name: &'static str) -> Self {
+            Self {
+                name,
+                entries: Arc::default(),
+                available: Arc::new(AtomicBool::new(true)),
+            }
+        }
+
+        fn check(&self) -> Result<(), String> {
+            match self.available.load(Ordering::SeqCst) {
+                true => Ok(()),
+                false => Err(format!("{} unavailable", self.name)),
+            }
+        }
+
+        fn holds(&self, account: &str) -> bool {
+            self.entries.lock().unwrap().contains_key(account)
+        }
+    }
+
+    impl VaultBackend for SharedBackend {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn get(&self, account: &str) -> Result<Option<String>, String> {
+            self.check()?;
+            Ok(self.entries.lock().unwrap().get(account).cloned())
+        }
+
+        fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+            self.check()?;
+            self.entries.lock().unwrap().insert(account.to_string(), secret.to_string());
+            Ok(())
+        }
+
+        fn delete(&self, account: &str) -> Result<(), String> {
+            self.check()?;
+            self.entries.lock().unwrap().remove(account);
+            Ok(())
+        }
+    }
+
+    fn names(vault: &CredentialVault) -> Vec<(CredentialNamespace, String, String)> {
+        vault
+            .list()
+            .into_iter()
+            .map(|info| (info.namespace, info.name, info.backend))
+            .collect()
+    }
+
+    #[test]
+    fn secrets_are_set_checked_and_deleted() {
+        let vault = CredentialVault::in_memory();
+
+        assert_eq!(vault.get(CredentialNamespace::Govee, "api_key").unwrap(), None);
+        assert!(!vault.contains(CredentialNamespace::Govee, "api_key"));
+
+        vault.set(CredentialNamespace::Govee, "api_key", "secret-1").unwrap();
+        vault.set(CredentialNamespace::Govee, "api_key", "secret-2").unwrap();
+        assert!(vault.contains(CredentialNamespace::Govee, "api_key"));
+        assert_eq!(vault.get(CredentialNamespace::Govee, "api_key").unwrap().as_deref(), Some("secret-2"));
+
+        // Namespaces keep same-named secrets apart
+        assert!(!vault.contains(CredentialNamespace::Mqtt, "api_key"));
+
+        vault.delete(CredentialNamespace::Govee, "api_key").unwrap();
+        assert!(!vault.contains(CredentialNamespace::Govee, "api_key"));
+        vault.delete(CredentialNamespace::Govee, "api_key").unwrap();
+    }
+
+    #[test]
+    fn index_follows_sets_and_deletes() {
+        let vault = CredentialVault::in_memory();
+
+        vault.set(CredentialNamespace::Mqtt, "password", "hunter2").unwrap();
+        vault.set(CredentialNamespace::Hue, "001788fffe0000a1", "{}").unwrap();
+        vault.set(CredentialNamespace::Mqtt, "password", "hunter3").unwrap();
+
+        assert_eq!(
+            names(&vault),
+            vec![
+                (CredentialNamespace::Hue, "001788fffe0000a1".to_string(), "memory".to_string()),
+                (CredentialNamespace::Mqtt, "password".to_string(), "memory".to_string()),
+            ]
+        );
+
+        vault.delete(CredentialNamespace::Mqtt, "password").unwrap();
+        assert_eq!(names(&vault).len(), 1);
+        assert_eq!(names(&vault)[0].0, CredentialNamespace::Hue);
+    }
+
+    #[test]
+    fn invalid_names_and_oversized_secrets_are_rejected() {
+        let vault = CredentialVault::in_memory();
+
+        for name in ["", "a/b", "../x", "white space", &"n".repeat(MAX_NAME_LEN + 1)] {
+            assert!(vault.set(CredentialNamespace::Other, name, "secret").is_err(), "{:?}", name);
+            assert!(vault.get(CredentialNamespace::Other, name).is_err());
+        }
+        assert!(vault
+            .set(CredentialNamespace::Other, "big", &"x".repeat(MAX_SECRET_LEN + 1))
+            .is_err());
+        vault.set(CredentialNamespace::Other, "user@host:1.2_3-4", "secret").unwrap();
+
+        assert_eq!(names(&vault).len(), 1);
+    }
+
+    #[test]
+    fn secrets_fall_back_and_move_back_when_the_first_backend_returns() {
+        let keyring = SharedBackend::new("keyring");
+        let file = SharedBackend::new("file");
+        let vault = CredentialVault::with_backends(vec![Box::new(keyring.clone()), Box::new(file.clone())]);
+
+        keyring.available.store(false, Ordering::SeqCst);
+        vault.set(CredentialNamespace::Spotify, "tokens:user", "token-1").unwrap();
+        assert!(file.holds("spotify_tokens:user"));
+        assert_eq!(names(&vault)[0].2, "file");
+        assert_eq!(vault.get(CredentialNamespace::Spotify, "tokens:user").unwrap().as_deref(), Some("token-1"));
+
+        // The newer copy lands in the keyring and the stale one is removed
+        keyring.available.store(true, Ordering::SeqCst);
+        vault.set(CredentialNamespace::Spotify, "tokens:user", "token-2").unwrap();
+        assert!(keyring.holds("spotify_tokens:user"));
+        assert!(!file.holds("spotify_tokens:user"));
+        assert_eq!(names(&vault)[0].2, "keyring");
+    }
+
+    #[test]
+    fn unavailable_backends_are_reported() {
+        let keyring = SharedBackend::new("keyring");
+        let file = SharedBackend::new("file");
+        let vault = CredentialVault::with_backends(vec![Box::new(keyring.clone()), Box::new(file.clone())]);
+        vault.set(CredentialNamespace::Nanoleaf, "panel", "token").unwrap();
+
+        // Missing only counts as missing if some backend could answer
+        keyring.available.store(false, Ordering::SeqCst);
+        assert_eq!(vault.get(CredentialNamespace::Nanoleaf, "other").unwrap(), None);
+
+        file.available.store(false, Ordering::SeqCst);
+        assert!(vault.get(CredentialNamespace::Nanoleaf, "panel").is_err());
+        assert!(!vault.contains(CredentialNamespace::Nanoleaf, "panel"));
+        assert!(vault.set(CredentialNamespace::Nanoleaf, "new", "token").is_err());
+        assert!(vault.delete(CredentialNamespace::Nanoleaf, "panel").is_err());
+
+        // A failed store leaves no index entry behind
+        assert!(!names(&vault).iter().any(|(_, name, _)| name == "new"));
+    }
+
+    #[test]
+    fn commands_never_return_secrets() {
+        let _ = install(CredentialVault::in_memory());
+        let secret = "vault-command-secret-value";
+
+        vault_set_secret(CredentialNamespace::Other, "vault-command-test".to_string(), secret.to_string()).unwrap();
+
+        assert!(vault_has_secret(CredentialNamespace::Other, "vault-command-test".to_string()));
+        let listed = vault_list_secrets();
+        assert!(listed.iter().any(|info| info.name == "vault-command-test"));
+        assert!(!serde_json::to_string(&listed).unwrap().contains(secret));
+
+        vault_delete_secret(CredentialNamespace::Other, "vault-command-test".to_string()).unwrap();
+        assert!(!vault_has_secret(CredentialNamespace::Other, "vault-command-test".to_string()));
+        assert!(!vault_list_secrets().iter().any(|info| info.name == "vault-command-test"));
+    }
+}
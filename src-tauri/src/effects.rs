@@ -0,0 +1,756 @@
+// Audio-Reactive Effect Library
+//
+// Native lighting effects driven by audio features pushed from the frontend
+// analyzer. Each effect has typed parameters and renders one color per
+// target of a device group; groups are persisted and rendered together on
+// every tick.
+
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+use crate::govee::{self, GoveeState, RGBColor};
+use crate::layers::{self, LayersState};
+use crate::lighting::{FlushReport, LightTarget, TargetFrame};
+use crate::recorder::RecorderState;
+use crate::room_layout::{FieldInputs, RoomLayoutState};
+use crate::schedule::ScheduleState;
+use crate::storage;
+
+const EFFECTS_FILE: &str = "effect_groups.json";
+/// Tempo assumed when the analyzer has not locked onto one yet
+const DEFAULT_TEMPO_BPM: f32 = 120.0;
+/// Effect groups are rendered at about 30 frames per second
+const TICK_INTERVAL: Duration = Duration::from_millis(33);
+/// Most colors a preview renders (a full segmented strip)
+const MAX_PREVIEW_COUNT: u32 = 1024;
+
+/// Audio analysis snapshot for one frame (levels are 0.0 - 1.0)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AudioFeatures {
+    #[serde(rename = "timeMs", default)]
+    pub time_ms: f64,
+    #[serde(default)]
+    pub energy: f32,
+    #[serde(default)]
+    pub bass: f32,
+    #[serde(default)]
+    pub mid: f32,
+    #[serde(default)]
+    pub treble: f32,
+    /// Magnitudes per frequency bin, low to high
+    #[serde(default)]
+    pub spectrum: Vec<f32>,
+    /// True on frames where a beat was detected
+    #[serde(default)]
+    pub beat: bool,
+    /// Beats per minute (0 when unknown)
+    #[serde(default)]
+    pub tempo: f32,
+    /// Spectral centroid in Hz
+    #[serde(rename = "spectralCentroid", default)]
+    pub spectral_centroid: f32,
+}
+
+impl From<&AudioFeatures> for FieldInputs {
+    fn from(features: &AudioFeatures) -> Self {
+        FieldInputs {
+            time_ms: features.time_ms,
+            bass: features.bass,
+            mid: features.mid,
+            treble: features.treble,
+            energy: features.energy,
+        }
+    }
+}
+
+/// Effect with its parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Effect {
+    /// Spectrum bands spread across the targets, low to high
+    Spectrum {
+        #[serde(rename = "lowColor")]
+        low_color: RGBColor,
+        #[serde(rename = "highColor")]
+        high_color: RGBColor,
+        gain: f32,
+    },
+    /// Level meter filling the targets in order, with a falling peak marker
+    VuMeter {
+        color: RGBColor,
+        #[serde(rename = "peakColor")]
+        peak_color: RGBColor,
+        gain: f32,
+        /// Level drop per second
+        decay: f32,
+    },
+    /// Full flash on every beat
+    BeatStrobe {
+        color: RGBColor,
+        #[serde(rename = "flashMs")]
+        flash_ms: u32,
+    },
+    /// Brightness follows the bass above a threshold, then decays
+    BassPulse {
+        color: RGBColor,
+        threshold: f32,
+        decay: f32,
+    },
+    /// Head advances one target per beat, leaving a fading tail
+    BeatChase {
+        color: RGBColor,
+        background: RGBColor,
+        /// Tail length in targets
+        tail: f32,
+    },
+    /// Slow brightness breathing locked to the tempo
+    Breathing {
+        color: RGBColor,
+        #[serde(rename = "beatsPerBreath")]
+        beats_per_breath: f32,
+        #[serde(rename = "minBrightness")]
+        min_brightness: f32,
+    },
+    /// Hue follows the spectral centroid (dark/bassy = red, bright = violet)
+    CentroidCycle {
+        #[serde(rename = "minHz")]
+        min_hz: f32,
+        #[serde(rename = "maxHz")]
+        max_hz: f32,
+        saturation: f32,
+        /// Hue smoothing per second (higher reacts faster)
+        smoothing: f32,
+    },
+}
+
+/// Catalog entry describing an effect and its default parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EffectInfo {
+    pub name: String,
+    pub description: String,
+    pub defaults: Effect,
+}
+
+/// Running state carried between frames of an effect
+#[derive(Debug, Clone, Default)]
+struct EffectRuntime {
+    last_time_ms: Option<f64>,
+    level: f32,
+    peak: f32,
+    flash_until_ms: f64,
+    was_beat: bool,
+    position: usize,
+    hue: f32,
+}
+
+impl Effect {
+    /// Strobe-type effects are disabled during quiet hours
+    fn is_strobe(&self) -> bool {
+        matches!(self, Effect::BeatStrobe { .. })
+    }
+
+    /// Gentle stand-in used when strobing is not allowed
+    fn without_strobe(&self) -> Effect {
+        match self {
+            Effect::BeatStrobe { color, .. } => Effect::BassPulse {
+                color: color.clone(),
+                threshold: 0.3,
+                decay: 1.0,
+            },
+            other => other.clone(),
+        }
+    }
+
+    /// Render one color per target and advance the runtime state
+    fn render(&self, count: usize, features: &AudioFeatures, runtime: &mut EffectRuntime) -> Vec<RGBColor> {
+        let now = features.time_ms;
+        let first_frame = runtime.last_time_ms.is_none();
+        let dt = runtime
+            .last_time_ms
+            .map(|last| ((now - last) / 1000.0).clamp(0.0, 1.0) as f32)
+            .unwrap_or(0.0);
+        runtime.last_time_ms = Some(now);
+
+        let beat_started = features.beat && !runtime.was_beat;
+        runtime.was_beat = features.beat;
+
+        match self {
+            Effect::Spectrum { low_color, high_color, gain } => {
+                let bands = bands(&features.spectrum, count);
+                (0..count)
+                    .map(|i| {
+                        let position = if count > 1 { i as f32 / (count - 1) as f32 } else { 0.0 };
+                        scale(&mix(low_color, high_color, position), bands[i] * gain)
+                    })
+                    .collect()
+            }
+            Effect::VuMeter { color, peak_color, gain, decay } => {
+                let level = (features.energy * gain).clamp(0.0, 1.0);
+                runtime.level = level.max(runtime.level - decay * dt);
+                runtime.peak = runtime.level.max(runtime.peak - decay * 0.5 * dt);
+
+                let lit = runtime.level * count as f32;
+                let peak_index = ((runtime.peak * count as f32).ceil() as usize).saturating_sub(1);
+                (0..count)
+                    .map(|i| {
+                        if runtime.peak > 0.0 && i == peak_index {
+                            peak_color.clone()
+                        } else {
+                            scale(color, lit - i as f32)
+                        }
+                    })
+                    .collect()
+            }
+            Effect::BeatStrobe { color, flash_ms } => {
+                if beat_started {
+                    runtime.flash_until_ms = now + *flash_ms as f64;
+                }
+                let on = now < runtime.flash_until_ms;
+                vec![if on { color.clone() } else { black() }; count]
+            }
+            Effect::BassPulse { color, threshold, decay } => {
+                let range = (1.0 - threshold).max(f32::EPSILON);
+                let drive = ((features.bass - threshold) / range).clamp(0.0, 1.0);
+                runtime.level = drive.max(runtime.level - decay * dt);
+                vec![scale(color, runtime.level); count]
+            }
+            Effect::BeatChase { color, background, tail } => {
+                if beat_started && count > 0 {
+                    runtime.position = (runtime.position + 1) % count;
+                }
+                (0..count)
+                    .map(|i| {
+                        let behind = (runtime.position + count - i) % count.max(1);
+                        let amount = if *tail > 0.0 { 1.0 - behind as f32 / tail } else if behind == 0 { 1.0 } else { 0.0 };
+                        mix(background, color, amount)
+                    })
+                    .collect()
+            }
+            Effect::Breathing { color, beats_per_breath, min_brightness } => {
+                let tempo = if features.tempo > 0.0 { features.tempo } else { DEFAULT_TEMPO_BPM };
+                let period_ms = (beats_per_breath.max(0.25) * 60_000.0 / tempo) as f64;
+                let phase = (now / period_ms).fract() as f32;
+                let wave = 0.5 - 0.5 * (2.0 * PI * phase).cos();
+                let min = min_brightness.clamp(0.0, 1.0);
+                vec![scale(color, min + (1.0 - min) * wave); count]
+            }
+            Effect::CentroidCycle { min_hz, max_hz, saturation, smoothing } => {
+                let span = (max_hz - min_hz).max(1.0);
+                let target = ((features.spectral_centroid - min_hz) / span).clamp(0.0, 1.0) * 300.0;
+                let alpha = if first_frame { 1.0 } else { (smoothing * dt).clamp(0.0, 1.0) };
+                runtime.hue += (target - runtime.hue) * alpha;
+                let value = features.energy.clamp(0.0, 1.0).max(0.2);
+                vec![hsv_to_rgb(runtime.hue, saturation.clamp(0.0, 1.0), value); count]
+            }
+        }
+    }
+}
+
+/// Built-in effects with their default parameters
+fn catalog() -> Vec<EffectInfo> {
+    let white = RGBColor { r: 255, g: 255, b: 255 };
+    let entry = |name: &str, description: &str, defaults: Effect| EffectInfo {
+        name: name.to_string(),
+        description: description.to_string(),
+        defaults,
+    };
+
+    vec![
+        entry("Spectrum", "Frequency bands spread across the group", Effect::Spectrum {
+            low_color: RGBColor { r: 255, g: 0, b: 0 },
+            high_color: RGBColor { r: 0, g: 0, b: 255 },
+            gain: 1.0,
+        }),
+        entry("VU Meter", "Level meter with peak hold", Effect::VuMeter {
+            color: RGBColor { r: 0, g: 255, b: 0 },
+            peak_color: RGBColor { r: 255, g: 0, b: 0 },
+            gain: 1.0,
+            decay: 1.5,
+        }),
+        entry("Beat Strobe", "Flash on every beat", Effect::BeatStrobe { color: white.clone(), flash_ms: 60 }),
+        entry("Bass Pulse", "Brightness follows the bass", Effect::BassPulse {
+            color: RGBColor { r: 255, g: 0, b: 80 },
+            threshold: 0.3,
+            decay: 2.0,
+        }),
+        entry("Beat Chase", "Light chases along the group on each beat", Effect::BeatChase {
+            color: RGBColor { r: 0, g: 200, b: 255 },
+            background: black(),
+            tail: 2.0,
+        }),
+        entry("Breathing", "Slow breathing locked to the tempo", Effect::Breathing {
+            color: RGBColor { r: 255, g: 120, b: 40 },
+            beats_per_breath: 4.0,
+            min_brightness: 0.15,
+        }),
+        entry("Centroid Cycle", "Hue follows the brightness of the sound", Effect::CentroidCycle {
+            min_hz: 200.0,
+            max_hz: 5000.0,
+            saturation: 1.0,
+            smoothing: 4.0,
+        }),
+    ]
+}
+
+/// Device group rendered with one effect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EffectGroup {
+    pub id: String,
+    pub name: String,
+    /// Targets in render order (pixel i goes to target i)
+    pub targets: Vec<LightTarget>,
+    pub effect: Effect,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+struct GroupRuntime {
+    group: EffectGroup,
+    runtime: EffectRuntime,
+}
+
+impl GroupRuntime {
+    fn render(&mut self, features: &AudioFeatures, strobe_allowed: bool) -> Vec<TargetFrame> {
+        let count = self.group.targets.len();
+        let colors = if strobe_allowed || !self.group.effect.is_strobe() {
+            self.group.effect.render(count, features, &mut self.runtime)
+        } else {
+            self.group.effect.without_strobe().render(count, features, &mut self.runtime)
+        };
+        self.group
+            .targets
+            .iter()
+            .zip(colors)
+            .map(|(target, color)| TargetFrame {
+                target: target.clone(),
+                colors: vec![color],
+            })
+            .collect()
+    }
+}
+
+/// Effect engine state for Tauri
+pub struct EffectsState {
+    features: Mutex<AudioFeatures>,
+    groups: Mutex<Vec<GroupRuntime>>,
+}
+
+impl EffectsState {
+    pub fn new() -> Self {
+        let groups = match storage::load_json::<Vec<EffectGroup>>(EFFECTS_FILE) {
+            Ok(groups) => groups.unwrap_or_default(),
+            Err(e) => {
+                println!("Failed to load effect groups: {}", e);
+                Vec::new()
+            }
+        };
+
+        Self {
+            features: Mutex::new(AudioFeatures::default()),
+            groups: Mutex::new(
+                groups
+                    .into_iter()
+                    .map(|group| GroupRuntime {
+                        group,
+                        runtime: EffectRuntime::default(),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Latest audio features pushed by the analyzer
+    pub fn features(&self) -> AudioFeatures {
+        self.features.lock().unwrap().clone()
+    }
+
+    /// Render every enabled group against the latest features
+    pub fn render(&self, strobe_allowed: impl Fn(&[LightTarget]) -> bool) -> Vec<TargetFrame> {
+        let features = self.features();
+        self.groups
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter(|group| group.group.enabled)
+            .flat_map(|group| {
+                let allowed = strobe_allowed(&group.group.targets);
+                group.render(&features, allowed)
+            })
+            .collect()
+    }
+
+    fn save(groups: &[GroupRuntime]) -> Result<(), String> {
+        let groups: Vec<&EffectGroup> = groups.iter().map(|g| &g.group).collect();
+        storage::save_json(EFFECTS_FILE, &groups)
+    }
+}
+
+fn black() -> RGBColor {
+    RGBColor { r: 0, g: 0, b: 0 }
+}
+
+fn mix(a: &RGBColor, b: &RGBColor, amount: f32) -> RGBColor {
+    let t = amount.clamp(0.0, 1.0);
+    let lerp = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * t).round() as u8;
+    RGBColor {
+        r: lerp(a.r, b.r),
+        g: lerp(a.g, b.g),
+        b: lerp(a.b, b.b),
+    }
+}
+
+fn scale(color: &RGBColor, amount: f32) -> RGBColor {
+    mix(&black(), color, amount)
+}
+
+/// Average spectrum bins into `count` bands
+fn bands(spectrum: &[f32], count: usize) -> Vec<f32> {
+    if spectrum.is_empty() || count == 0 {
+        return vec![0.0; count];
+    }
+
+    (0..count)
+        .map(|i| {
+            let start = i * spectrum.len() / count;
+            let end = ((i + 1) * spectrum.len() / count).max(start + 1).min(spectrum.len());
+            let slice = &spectrum[start.min(spectrum.len() - 1)..end];
+            slice.iter().sum::<f32>() / slice.len() as f32
+        })
+        .collect()
+}
+
+/// Convert hue (degrees), saturation and value (0.0 - 1.0) to RGB
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> RGBColor {
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let c = value * saturation;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let m = value - c;
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let channel = |v: f32| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    RGBColor {
+        r: channel(r),
+        g: channel(g),
+        b: channel(b),
+    }
+}
+
+/// List the built-in effects with their default parameters
+#[tauri::command]
+pub fn effects_list() -> Vec<EffectInfo> {
+    catalog()
+}
+
+/// Store the latest audio features from the analyzer
+#[tauri::command]
+pub fn effects_push_audio_features(
+    features: AudioFeatures,
+    state: State<EffectsState>,
+    schedule: State<ScheduleState>,
+    govee_state: State<GoveeState>,
+    recorder: State<RecorderState>,
+) {
+    recorder.record_input(&features);
+    schedule.note_audio_energy(features.energy);
+    govee::govee_report_audio_level(features.energy, govee_state);
+    *state.features.lock().unwrap() = features;
+}
+
+/// Get all effect groups
+#[tauri::command]
+pub fn effects_get_groups(state: State<EffectsState>) -> Vec<EffectGroup> {
+    state.groups.lock().unwrap().iter().map(|g| g.group.clone()).collect()
+}
+
+/// Create or update a group (its effect state restarts)
+#[tauri::command]
+pub fn effects_set_group(group: EffectGroup, state: State<EffectsState>) -> Result<(), String> {
+    if group.id.is_empty() {
+        return Err("Group id must not be empty".to_string());
+    }
+
+    let mut groups = state.groups.lock().unwrap();
+    let entry = GroupRuntime {
+        group,
+        runtime: EffectRuntime::default(),
+    };
+    match groups.iter_mut().find(|g| g.group.id == entry.group.id) {
+        Some(existing) => *existing = entry,
+        None => groups.push(entry),
+    }
+
+    EffectsState::save(&groups)
+}
+
+/// Remove a group
+#[tauri::command]
+pub fn effects_remove_group(group_id: String, state: State<EffectsState>) -> Result<(), String> {
+    let mut groups = state.groups.lock().unwrap();
+    groups.retain(|g| g.group.id != group_id);
+    EffectsState::save(&groups)
+}
+
+/// Render an effect without sending it (stateless, for UI previews; at
+/// most 1024 colors)
+#[tauri::command]
+pub fn effects_preview(effect: Effect, count: u32, features: Option<AudioFeatures>, state: State<EffectsState>) -> Vec<RGBColor> {
+    let features = features.unwrap_or_else(|| state.features());
+    let count = count.min(MAX_PREVIEW_COUNT) as usize;
+    effect.render(count, &features, &mut EffectRuntime::default())
+}
+
+/// Render every enabled group into the effects layer and send the result
+fn tick(app: &AppHandle, state: &EffectsState) -> Result<FlushReport, String> {
+    let layout = app.state::<RoomLayoutState>().snapshot();
+    let schedule = app.state::<ScheduleState>();
+    let frames = state.render(|targets| schedule.strobe_allowed(targets, &layout));
+    if frames.is_empty() {
+        return Ok(FlushReport::default());
+    }
+    layers::submit(app, &app.state::<LayersState>(), "effects", frames)
+}
+
+/// Render effect groups for the lifetime of the app
+pub fn start_timer(app: &AppHandle) {
+    let app = app.clone();
+    if let Err(e) = std::thread::Builder::new()
+        .name("effects".to_string())
+        .spawn(move || {
+            // Report a failure once, not on every frame
+            let mut last_error = None;
+            loop {
+                match tick(&app, &app.state::<EffectsState>()) {
+                    Ok(_) => last_error = None,
+                    Err(e) if last_error.as_ref() != Some(&e) => {
+                        println!("Effects tick failed: {}", e);
+                        last_error = Some(e);
+                    }
+                    Err(_) => {}
+                }
+                std::thread::sleep(TICK_INTERVAL);
+            }
+        })
+    {
+        println!("Failed to start effects thread: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lighting::LightBackend;
+    use crate::room_layout::RoomLayout;
+    use crate::schedule::{ScheduleRule, ScheduleSettings};
+
+    const WHITE: RGBColor = RGBColor { r: 255, g: 255, b: 255 };
+    const RED: RGBColor = RGBColor { r: 255, g: 0, b: 0 };
+    const BLUE: RGBColor = RGBColor { r: 0, g: 0, b: 255 };
+    const GREY: RGBColor = RGBColor { r: 128, g: 128, b: 128 };
+
+    fn at(time_ms: f64) -> AudioFeatures {
+        AudioFeatures {
+            time_ms,
+            ..Default::default()
+        }
+    }
+
+    fn targets(count: usize) -> Vec<LightTarget> {
+        (0..count)
+            .map(|i| LightTarget::device(LightBackend::Wled, &format!("10.0.0.{}", i + 1)))
+            .collect()
+    }
+
+    fn strobe() -> Effect {
+        Effect::BeatStrobe { color: WHITE, flash_ms: 60 }
+    }
+
+    #[test]
+    fn spectrum_spreads_bands_from_low_to_high() {
+        let effect = Effect::Spectrum { low_color: RED, high_color: BLUE, gain: 1.0 };
+        let features = AudioFeatures {
+            spectrum: vec![1.0, 1.0, 0.5, 0.5, 0.0, 0.0],
+            ..at(0.0)
+        };
+
+        let colors = effect.render(3, &features, &mut EffectRuntime::default());
+
+        assert_eq!(colors[0], RED);
+        assert_eq!(colors[1], RGBColor { r: 64, g: 0, b: 64 });
+        assert_eq!(colors[2], RGBColor { r: 0, g: 0, b: 0 });
+        assert!(effect.render(0, &features, &mut EffectRuntime::default()).is_empty());
+    }
+
+    #[test]
+    fn vu_meter_fills_and_decays_with_a_peak_marker() {
+        let effect = Effect::VuMeter { color: WHITE, peak_color: RED, gain: 1.0, decay: 1.0 };
+        let mut runtime = EffectRuntime::default();
+
+        let colors = effect.render(4, &AudioFeatures { energy: 0.5, ..at(0.0) }, &mut runtime);
+        assert_eq!(colors, vec![WHITE, RED, black(), black()]);
+
+        // Silence half a second later: the level falls, the peak lags behind
+        let colors = effect.render(4, &at(500.0), &mut runtime);
+        assert!((runtime.level - 0.0).abs() < 1e-6);
+        assert!((runtime.peak - 0.25).abs() < 1e-6);
+        assert_eq!(colors, vec![RED, black(), black(), black()]);
+    }
+
+    #[test]
+    fn beat_strobe_flashes_once_per_beat() {
+        let effect = strobe();
+        let mut runtime = EffectRuntime::default();
+        let beat = |time_ms| AudioFeatures { beat: true, ..at(time_ms) };
+
+        assert_eq!(effect.render(2, &beat(0.0), &mut runtime), vec![WHITE; 2]);
+        // A beat held over several frames does not extend the flash
+        assert_eq!(effect.render(2, &beat(30.0), &mut runtime), vec![WHITE; 2]);
+        assert_eq!(effect.render(2, &beat(70.0), &mut runtime), vec![black(); 2]);
+        assert_eq!(effect.render(2, &at(100.0), &mut runtime), vec![black(); 2]);
+        assert_eq!(effect.render(2, &beat(130.0), &mut runtime), vec![WHITE; 2]);
+    }
+
+    #[test]
+    fn bass_pulse_follows_the_bass_above_the_threshold() {
+        let effect = Effect::BassPulse { color: WHITE, threshold: 0.5, decay: 2.0 };
+        let mut runtime = EffectRuntime::default();
+
+        assert_eq!(effect.render(1, &AudioFeatures { bass: 0.4, ..at(0.0) }, &mut runtime), vec![black()]);
+        assert_eq!(effect.render(1, &AudioFeatures { bass: 1.0, ..at(10.0) }, &mut runtime), vec![WHITE]);
+        // Decays at 2.0 per second once the bass drops
+        assert_eq!(effect.render(1, &at(260.0), &mut runtime), vec![GREY]);
+    }
+
+    #[test]
+    fn beat_chase_advances_one_target_per_beat() {
+        let effect = Effect::BeatChase { color: WHITE, background: black(), tail: 2.0 };
+        let mut runtime = EffectRuntime::default();
+        let beat = |time_ms| AudioFeatures { beat: true, ..at(time_ms) };
+
+        assert_eq!(effect.render(4, &at(0.0), &mut runtime), vec![WHITE, black(), black(), GREY]);
+        assert_eq!(effect.render(4, &beat(10.0), &mut runtime), vec![GREY, WHITE, black(), black()]);
+        assert_eq!(effect.render(4, &beat(20.0), &mut runtime), vec![GREY, WHITE, black(), black()]);
+        effect.render(4, &at(30.0), &mut runtime);
+        effect.render(4, &beat(40.0), &mut runtime);
+        effect.render(4, &at(50.0), &mut runtime);
+        effect.render(4, &beat(60.0), &mut runtime);
+        // Wraps around after the last target
+        assert_eq!(runtime.position, 3);
+        effect.render(4, &at(70.0), &mut runtime);
+        assert_eq!(effect.render(4, &beat(80.0), &mut runtime), vec![WHITE, black(), black(), GREY]);
+    }
+
+    #[test]
+    fn breathing_follows_the_tempo() {
+        let effect = Effect::Breathing { color: WHITE, beats_per_breath: 4.0, min_brightness: 0.2 };
+        let tempo = |time_ms| AudioFeatures { tempo: 60.0, ..at(time_ms) };
+        let mut runtime = EffectRuntime::default();
+
+        // Four beats at 60 BPM: dimmest at 0s, brightest at 2s
+        let dim = RGBColor { r: 51, g: 51, b: 51 };
+        assert_eq!(effect.render(1, &tempo(0.0), &mut runtime), vec![dim.clone()]);
+        assert_eq!(effect.render(1, &tempo(2000.0), &mut runtime), vec![WHITE]);
+        assert_eq!(effect.render(1, &tempo(4000.0), &mut runtime), vec![dim]);
+
+        // Unknown tempo falls back to 120 BPM (a two second breath)
+        assert_eq!(effect.render(1, &at(1000.0), &mut runtime), vec![WHITE]);
+    }
+
+    #[test]
+    fn centroid_cycle_maps_brightness_of_the_sound_to_hue() {
+        let effect = Effect::CentroidCycle { min_hz: 200.0, max_hz: 5000.0, saturation: 1.0, smoothing: 4.0 };
+        let mut runtime = EffectRuntime::default();
+
+        let bassy = AudioFeatures { spectral_centroid: 100.0, energy: 1.0, ..at(0.0) };
+        assert_eq!(effect.render(1, &bassy, &mut runtime), vec![RED]);
+
+        // Smoothing: a quarter second at 4.0 per second reaches the target
+        let bright = AudioFeatures { spectral_centroid: 5000.0, energy: 1.0, ..at(250.0) };
+        effect.render(1, &bright, &mut runtime);
+        assert!((runtime.hue - 300.0).abs() < 1e-3);
+
+        // Quiet sound still shows the hue at the minimum value
+        let quiet = effect.render(1, &AudioFeatures { spectral_centroid: 5000.0, ..at(300.0) }, &mut runtime);
+        assert_eq!(quiet, vec![RGBColor { r: 51, g: 0, b: 51 }]);
+    }
+
+    #[test]
+    fn disallowed_strobe_falls_back_to_a_bass_pulse() {
+        let mut group = GroupRuntime {
+            group: EffectGroup {
+                id: "stage".to_string(),
+                name: "Stage".to_string(),
+                targets: targets(2),
+                effect: strobe(),
+                enabled: true,
+            },
+            runtime: EffectRuntime::default(),
+        };
+        let beat = AudioFeatures { beat: true, bass: 0.2, ..at(0.0) };
+
+        let frames = group.render(&beat, false);
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|frame| frame.colors == vec![black()]));
+
+        // Once allowed again the next beat flashes
+        group.render(&at(50.0), true);
+        let frames = group.render(&AudioFeatures { beat: true, ..at(100.0) }, true);
+        assert!(frames.iter().all(|frame| frame.colors == vec![WHITE]));
+    }
+
+    #[test]
+    fn quiet_hours_gate_strobe_per_group() {
+        // Two rules covering the whole day, one for each half
+        let rule = |id: &str, start: &str, end: &str| ScheduleRule {
+            id: id.to_string(),
+            name: id.to_string(),
+            enabled: true,
+            days: Vec::new(),
+            start: start.to_string(),
+            end: end.to_string(),
+            groups: Vec::new(),
+            max_brightness: None,
+            disable_strobe: true,
+        };
+        let quiet = ScheduleState::with_settings(ScheduleSettings {
+            rules: vec![rule("morning", "00:00", "12:00"), rule("evening", "12:00", "00:00")],
+            ..Default::default()
+        });
+        let open = ScheduleState::with_settings(ScheduleSettings::default());
+        let layout = RoomLayout::default();
+
+        let state = EffectsState {
+            features: Mutex::new(AudioFeatures { beat: true, ..at(0.0) }),
+            groups: Mutex::new(vec![GroupRuntime {
+                group: EffectGroup {
+                    id: "stage".to_string(),
+                    name: "Stage".to_string(),
+                    targets: targets(1),
+                    effect: strobe(),
+                    enabled: true,
+                },
+                runtime: EffectRuntime::default(),
+            }]),
+        };
+
+        let frames = state.render(|targets| quiet.strobe_allowed(targets, &layout));
+        assert_eq!(frames[0].colors, vec![black()]);
+
+        *state.features.lock().unwrap() = AudioFeatures { beat: true, ..at(100.0) };
+        state.groups.lock().unwrap()[0].runtime.was_beat = false;
+        let frames = state.render(|targets| open.strobe_allowed(targets, &layout));
+        assert_eq!(frames[0].colors, vec![WHITE]);
+    }
+}
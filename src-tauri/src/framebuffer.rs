@@ -0,0 +1,393 @@
+// Virtual LED Framebuffer
+//
+// A logical pixel canvas (a strip when height is 1) that effects draw into
+// once. A persisted mapping table assigns rectangular regions of the canvas
+// to light targets; each flush resamples every region to the target's
+// physical pixel count and routes the whole frame to the backends in one
+// batch.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+use crate::govee::RGBColor;
+use crate::layers::{self, LayersState};
+use crate::lighting::{self, FlushReport, LightTarget, TargetFrame};
+use crate::storage;
+
+const FRAMEBUFFER_FILE: &str = "framebuffer.json";
+
+// Upper bound on logical and physical pixel counts
+const MAX_PIXELS: u32 = 65_536;
+
+fn default_one() -> u32 {
+    1
+}
+
+/// Maps a region of logical pixels onto one light target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PixelMapping {
+    pub target: LightTarget,
+    pub x: u32,
+    #[serde(default)]
+    pub y: u32,
+    #[serde(default = "default_one")]
+    pub width: u32,
+    #[serde(default = "default_one")]
+    pub height: u32,
+    /// Physical pixels on the target (1 for single bulbs and segments)
+    #[serde(default = "default_one")]
+    pub pixels: u32,
+    /// Reverse pixel order (strips mounted right-to-left)
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+/// Framebuffer dimensions and mapping table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FramebufferConfig {
+    pub width: u32,
+    pub height: u32,
+    pub mappings: Vec<PixelMapping>,
+}
+
+impl Default for FramebufferConfig {
+    fn default() -> Self {
+        Self {
+            width: 60,
+            height: 1,
+            mappings: Vec::new(),
+        }
+    }
+}
+
+impl FramebufferConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err("Framebuffer dimensions must be non-zero".to_string());
+        }
+        if self.width.checked_mul(self.height).is_none_or(|count| count > MAX_PIXELS) {
+            return Err(format!("Framebuffer is limited to {} pixels", MAX_PIXELS));
+        }
+
+        for mapping in &self.mappings {
+            if mapping.width == 0 || mapping.height == 0 || mapping.pixels == 0 {
+                return Err(format!("Empty mapping for {}", mapping.target.device_id));
+            }
+            if mapping.pixels > MAX_PIXELS {
+                return Err(format!(
+                    "Mapping for {} is limited to {} pixels",
+                    mapping.target.device_id, MAX_PIXELS
+                ));
+            }
+
+            let fits = |start: u32, size: u32, limit: u32| start.checked_add(size).is_some_and(|end| end <= limit);
+            if !fits(mapping.x, mapping.width, self.width) || !fits(mapping.y, mapping.height, self.height) {
+                return Err(format!(
+                    "Mapping for {} exceeds the {}x{} framebuffer",
+                    mapping.target.device_id, self.width, self.height
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pixel_count(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+}
+
+/// Mapping table and the pixels it was sized for, swapped together
+struct Framebuffer {
+    config: FramebufferConfig,
+    pixels: Vec<RGBColor>,
+}
+
+/// Framebuffer state for Tauri
+pub struct FramebufferState {
+    buffer: Mutex<Framebuffer>,
+}
+
+impl FramebufferState {
+    pub fn new() -> Self {
+        let config = match storage::load_json::<FramebufferConfig>(FRAMEBUFFER_FILE) {
+            Ok(Some(config)) if config.validate().is_ok() => config,
+            Ok(_) => FramebufferConfig::default(),
+            Err(e) => {
+                println!("Failed to load framebuffer config: {}", e);
+                FramebufferConfig::default()
+            }
+        };
+
+        Self::with_config(config)
+    }
+
+    fn with_config(config: FramebufferConfig) -> Self {
+        let pixels = black(config.pixel_count());
+
+        Self {
+            buffer: Mutex::new(Framebuffer { config, pixels }),
+        }
+    }
+
+    /// Copy logical pixels (row-major) into the buffer starting at `offset`
+    pub fn write(&self, offset: usize, colors: Vec<RGBColor>) -> Result<(), String> {
+        let pixels = &mut self.buffer.lock().unwrap().pixels;
+        if offset.checked_add(colors.len()).is_none_or(|end| end > pixels.len()) {
+            return Err(format!(
+                "Write of {} pixels at {} exceeds framebuffer size {}",
+                colors.len(),
+                offset,
+                pixels.len()
+            ));
+        }
+
+        for (slot, color) in pixels[offset..].iter_mut().zip(colors) {
+            *slot = color;
+        }
+        Ok(())
+    }
+
+    /// Resample the buffer into one frame per mapped target
+    pub fn frames(&self) -> Vec<TargetFrame> {
+        let buffer = self.buffer.lock().unwrap();
+        let Framebuffer { config, pixels } = &*buffer;
+
+        config
+            .mappings
+            .iter()
+            .map(|mapping| {
+                let mut region = Vec::with_capacity((mapping.width * mapping.height) as usize);
+                for y in mapping.y..mapping.y + mapping.height {
+                    let row = (y * config.width) as usize;
+                    let start = row + mapping.x as usize;
+                    region.extend_from_slice(&pixels[start..start + mapping.width as usize]);
+                }
+
+                let mut colors = resample(&region, mapping.pixels as usize);
+                if mapping.reverse {
+                    colors.reverse();
+                }
+
+                TargetFrame {
+                    target: mapping.target.clone(),
+                    colors,
+                }
+            })
+            .collect()
+    }
+}
+
+fn black(count: usize) -> Vec<RGBColor> {
+    vec![RGBColor { r: 0, g: 0, b: 0 }; count]
+}
+
+/// Resample a run of pixels to `count` pixels: box-average when shrinking,
+/// linear interpolation when stretching
+fn resample(source: &[RGBColor], count: usize) -> Vec<RGBColor> {
+    if source.is_empty() || count == 0 {
+        return black(count);
+    }
+    if source.len() == count {
+        return source.to_vec();
+    }
+
+    let ratio = source.len() as f32 / count as f32;
+
+    if source.len() > count {
+        return (0..count)
+            .map(|i| {
+                let start = (i as f32 * ratio) as usize;
+                let end = (((i + 1) as f32 * ratio) as usize).clamp(start + 1, source.len());
+                lighting::average_color(&source[start..end])
+            })
+            .collect();
+    }
+
+    (0..count)
+        .map(|i| {
+            let position = ((i as f32 + 0.5) * ratio - 0.5).clamp(0.0, (source.len() - 1) as f32);
+            let index = position.floor() as usize;
+            let next = (index + 1).min(source.len() - 1);
+            let t = position - index as f32;
+            let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+            RGBColor {
+                r: lerp(source[index].r, source[next].r),
+                g: lerp(source[index].g, source[next].g),
+                b: lerp(source[index].b, source[next].b),
+            }
+        })
+        .collect()
+}
+
+/// Get framebuffer dimensions and mapping table
+#[tauri::command]
+pub fn framebuffer_get_config(state: State<FramebufferState>) -> FramebufferConfig {
+    state.buffer.lock().unwrap().config.clone()
+}
+
+/// Replace framebuffer dimensions and mapping table (clears the buffer)
+#[tauri::command]
+pub fn framebuffer_set_config(config: FramebufferConfig, state: State<FramebufferState>) -> Result<(), String> {
+    config.validate()?;
+    storage::save_json(FRAMEBUFFER_FILE, &config)?;
+
+    let pixels = black(config.pixel_count());
+    *state.buffer.lock().unwrap() = Framebuffer { config, pixels };
+    Ok(())
+}
+
+/// Write logical pixels (row-major) starting at `offset`
+#[tauri::command]
+pub fn framebuffer_write(offset: Option<u32>, colors: Vec<RGBColor>, state: State<FramebufferState>) -> Result<(), String> {
+    state.write(offset.unwrap_or(0) as usize, colors)
+}
+
+/// Get the per-target frames the next flush would send
+#[tauri::command]
+pub fn framebuffer_preview(state: State<FramebufferState>) -> Vec<TargetFrame> {
+    state.frames()
+}
+
+/// Send the current buffer to every mapped light in one batch (through the
+/// framebuffer layer)
+#[tauri::command]
+pub fn framebuffer_flush(
+    app: AppHandle,
+    state: State<FramebufferState>,
+    layers_state: State<LayersState>,
+) -> Result<FlushReport, String> {
+    layers::submit(&app, &layers_state, "framebuffer", state.frames())
+}
+
+/// Write a full frame and flush it (one call per tick)
+#[tauri::command]
+pub fn framebuffer_present(
+    colors: Vec<RGBColor>,
+    app: AppHandle,
+    state: State<FramebufferState>,
+    layers_state: State<LayersState>,
+) -> Result<FlushReport, String> {
+    state.write(0, colors)?;
+    layers::submit(&app, &layers_state, "framebuffer", state.frames())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lighting::LightBackend;
+
+    fn grey(v: u8) -> RGBColor {
+        RGBColor { r: v, g: v, b: v }
+    }
+
+    fn levels(colors: &[RGBColor]) -> Vec<u8> {
+        colors.iter().map(|c| c.r).collect()
+    }
+
+    fn mapping(id: &str, x: u32, y: u32, width: u32, height: u32, pixels: u32) -> PixelMapping {
+        PixelMapping {
+            target: LightTarget::device(LightBackend::Wled, id),
+            x,
+            y,
+            width,
+            height,
+            pixels,
+            reverse: false,
+        }
+    }
+
+    #[test]
+    fn resample_keeps_equal_lengths_and_handles_empty_input() {
+        let source = vec![grey(10), grey(20), grey(30)];
+
+        assert_eq!(resample(&source, 3), source);
+        assert_eq!(resample(&[], 2), black(2));
+        assert!(resample(&source, 0).is_empty());
+    }
+
+    #[test]
+    fn resample_interpolates_when_stretching() {
+        assert_eq!(levels(&resample(&[grey(0), grey(255)], 4)), vec![0, 64, 191, 255]);
+        assert_eq!(levels(&resample(&[grey(80)], 3)), vec![80, 80, 80]);
+
+        // The ends hold the source's edge pixels rather than reading past them
+        let stretched = resample(&[grey(0), grey(100), grey(200)], 7);
+        assert_eq!(stretched.len(), 7);
+        assert_eq!((stretched[0].r, stretched[6].r), (0, 200));
+    }
+
+    #[test]
+    fn resample_averages_when_shrinking() {
+        assert_eq!(levels(&resample(&[grey(0), grey(100), grey(200), grey(50)], 2)), vec![50, 125]);
+        assert_eq!(levels(&resample(&[grey(0), grey(100), grey(200)], 2)), vec![0, 150]);
+        assert_eq!(levels(&resample(&(0..10).map(|i| grey(i * 10)).collect::<Vec<_>>(), 1)), vec![45]);
+
+        // Uneven buckets still cover every source pixel, including the last
+        let shrunk = resample(&[grey(0), grey(0), grey(0), grey(0), grey(250)], 3);
+        assert_eq!(levels(&shrunk), vec![0, 0, 125]);
+    }
+
+    #[test]
+    fn regions_are_mapped_onto_their_targets() {
+        let state = FramebufferState::with_config(FramebufferConfig {
+            width: 4,
+            height: 2,
+            mappings: vec![
+                mapping("strip", 0, 0, 4, 1, 4),
+                PixelMapping {
+                    reverse: true,
+                    ..mapping("reversed", 0, 0, 4, 1, 4)
+                },
+                mapping("block", 1, 0, 2, 2, 4),
+                mapping("bulb", 0, 1, 4, 1, 1),
+                mapping("long", 0, 0, 4, 1, 8),
+            ],
+        });
+        state.write(0, (0..8).map(|i| grey(i * 10)).collect()).unwrap();
+
+        let frames = state.frames();
+
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames[0].target.device_id, "strip");
+        assert_eq!(levels(&frames[0].colors), vec![0, 10, 20, 30]);
+        assert_eq!(levels(&frames[1].colors), vec![30, 20, 10, 0]);
+        // Regions are read row by row
+        assert_eq!(levels(&frames[2].colors), vec![10, 20, 50, 60]);
+        assert_eq!(levels(&frames[3].colors), vec![55]);
+        assert_eq!(frames[4].colors.len(), 8);
+    }
+
+    #[test]
+    fn writes_outside_the_buffer_are_rejected() {
+        let state = FramebufferState::with_config(FramebufferConfig {
+            width: 4,
+            height: 1,
+            mappings: vec![mapping("strip", 0, 0, 4, 1, 4)],
+        });
+
+        assert!(state.write(2, vec![grey(1); 3]).is_err());
+        assert!(state.write(usize::MAX, vec![grey(1)]).is_err());
+        state.write(2, vec![grey(1); 2]).unwrap();
+        assert_eq!(levels(&state.frames()[0].colors), vec![0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn mappings_must_fit_the_framebuffer() {
+        let config = |mappings| FramebufferConfig {
+            width: 4,
+            height: 2,
+            mappings,
+        };
+
+        assert!(config(vec![mapping("a", 2, 1, 2, 1, 10)]).validate().is_ok());
+        assert!(config(vec![mapping("a", 3, 0, 2, 1, 1)]).validate().is_err());
+        assert!(config(vec![mapping("a", 0, 2, 1, 1, 1)]).validate().is_err());
+        assert!(config(vec![mapping("a", u32::MAX, 0, 2, 1, 1)]).validate().is_err());
+        assert!(config(vec![mapping("a", 0, 0, 1, 1, 0)]).validate().is_err());
+        assert!(config(vec![mapping("a", 0, 0, 1, 1, MAX_PIXELS + 1)]).validate().is_err());
+        assert!(FramebufferConfig { width: 0, ..config(Vec::new()) }.validate().is_err());
+        assert!(FramebufferConfig { width: MAX_PIXELS, ..config(Vec::new()) }.validate().is_err());
+    }
+}
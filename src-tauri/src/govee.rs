@@ -4,11 +4,16 @@
 /// with Govee smart lighting devices.
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::{SocketAddr, UdpSocket};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tauri::State;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use tauri::{AppHandle, Manager, State};
+
+use crate::credential_vault::{self, CredentialNamespace};
+use crate::lighting::{self, LightBackend};
+use crate::storage;
 
 /// Govee device information
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,7 +39,7 @@ pub struct DeviceState {
     pub mode: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RGBColor {
     pub r: u8,
     pub g: u8,
@@ -71,8 +76,31 @@ struct MessageContent {
 #[derive(Default)]
 pub struct GoveeState {
     devices: Arc<Mutex<HashMap<String, GoveeDevice>>>,
+    /// Devices already switched into per-segment (razer) mode
+    segment_mode: Arc<Mutex<HashSet<String>>>,
+    /// Pause/silence tracking and idle behavior
+    idle: Arc<Mutex<IdleTracker>>,
 }
 
+impl GoveeState {
+    pub fn new() -> Self {
+        let policy = match storage::load_json::<GoveeIdlePolicy>(IDLE_POLICY_FILE) {
+            Ok(policy) => policy.unwrap_or_default(),
+            Err(e) => {
+                println!("Failed to load Govee idle policy: {}", e);
+                GoveeIdlePolicy::default()
+            }
+        };
+
+        let state = Self::default();
+        state.idle.lock().unwrap().policy = policy;
+        state
+    }
+}
+
+/// LAN API control port
+const CONTROL_PORT: u16 = 4003;
+
 /// Discover Govee devices on the local network
 #[tauri::command]
 pub fn govee_discover_devices(
@@ -318,13 +346,111 @@ fn parse_color(color_value: Option<&serde_json::Value>) -> RGBColor {
     }
 }
 
-/// Send LAN API command to a device
+/// Send LAN API command to a device (light output goes through schedule
+/// caps and the safety limiter first)
 #[tauri::command]
 pub fn govee_send_lan_command(
     device_ip: String,
     message: String,
     expect_response: bool,
     port: u16,
+    app: AppHandle,
+    state: State<GoveeState>,
+) -> Result<serde_json::Value, String> {
+    let message = guard_lan_message(&app, &state, &device_ip, message)?;
+    let response = send_lan_message(&device_ip, &message, expect_response, port)?;
+    note_sent_command(&state, &device_ip, &message);
+    Ok(response)
+}
+
+/// Rewrite the color or brightness of a raw command to what the limiter lets
+/// through. Per-segment packets are only accepted from the lighting pipeline.
+fn guard_lan_message(app: &AppHandle, state: &GoveeState, device_ip: &str, message: String) -> Result<String, String> {
+    let Ok(mut parsed) = serde_json::from_str::<LanMessage>(&message) else {
+        return Ok(message);
+    };
+    let device_id = state
+        .devices
+        .lock()
+        .unwrap()
+        .values()
+        .find(|device| device.ip == device_ip)
+        .map_or_else(|| device_ip.to_string(), |device| device.id.clone());
+
+    let data = &mut parsed.msg.data;
+    match parsed.msg.cmd.as_str() {
+        "razer" => return Err("Segment colors must be sent with govee_set_segment_colors".to_string()),
+        "turn" => {
+            lighting::guard_power(app, LightBackend::Govee, &device_id)?;
+            return Ok(message);
+        }
+        "brightness" => {
+            let Some(value) = data.get("value").and_then(|v| v.as_u64()) else {
+                return Ok(message);
+            };
+            let brightness = lighting::guard_brightness(app, LightBackend::Govee, &device_id, value.min(100) as u8);
+            data["value"] = serde_json::json!(brightness.max(1));
+        }
+        "colorwc" => {
+            if let Some(kelvin) = data.get("colorTemInKelvin").and_then(|v| v.as_u64()).filter(|k| *k > 0) {
+                let brightness = state.devices.lock().unwrap().get(&device_id).map_or(100, |d| d.state.brightness);
+                let kelvin = kelvin.min(u16::MAX as u64) as u16;
+                lighting::guard_white_point(app, LightBackend::Govee, &device_id, kelvin, brightness)?;
+            }
+            if data.get("color").is_none() {
+                return Ok(message);
+            }
+            let color = lighting::guard_color(app, LightBackend::Govee, &device_id, parse_color(data.get("color")));
+            data["color"] = serde_json::json!({ "r": color.r, "g": color.g, "b": color.b });
+        }
+        _ => return Ok(message),
+    }
+
+    serde_json::to_string(&parsed).map_err(|e| format!("Failed to serialize command: {}", e))
+}
+
+/// Keep the cached state of a device in line with a raw command sent to it,
+/// so idle fades start from what the light is actually showing
+fn note_sent_command(state: &GoveeState, device_ip: &str, message: &str) {
+    let Ok(message) = serde_json::from_str::<LanMessage>(message) else {
+        return;
+    };
+    let mut devices = state.devices.lock().unwrap();
+    let Some(device) = devices.values_mut().find(|device| device.ip == device_ip) else {
+        return;
+    };
+
+    let data = &message.msg.data;
+    let value = data.get("value").and_then(|v| v.as_u64());
+    match message.msg.cmd.as_str() {
+        "turn" => {
+            if let Some(value) = value {
+                device.state.on = value == 1;
+            }
+        }
+        "brightness" => {
+            if let Some(value) = value {
+                device.state.brightness = value.min(100) as u8;
+            }
+        }
+        "colorwc" => {
+            if data.get("color").is_some() {
+                device.state.color = parse_color(data.get("color"));
+            }
+            if let Some(kelvin) = data.get("colorTemInKelvin").and_then(|v| v.as_u64()).filter(|k| *k > 0) {
+                device.state.color_temperature = kelvin.min(u16::MAX as u64) as u16;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Send a raw LAN message to a device address
+fn send_lan_message(
+    device_ip: &str,
+    message: &str,
+    expect_response: bool,
+    port: u16,
 ) -> Result<serde_json::Value, String> {
     println!("Sending command to {} on port {}", device_ip, port);
     println!("Message: {}", message);
@@ -383,5 +509,807 @@ pub fn govee_get_all_devices(state: State<GoveeState>) -> Vec<GoveeDevice> {
 pub fn govee_clear_devices(state: State<GoveeState>) {
     let mut devices = state.devices.lock().unwrap();
     devices.clear();
+    state.segment_mode.lock().unwrap().clear();
     println!("Cleared all cached Govee devices");
-}
\ No newline at end of file
+}
+
+/// Wrap a razer payload as `BB <len:u16> <payload> <xor>` and encode it as a LAN message
+fn razer_message(payload: &[u8]) -> String {
+    let len = payload.len().saturating_sub(1) as u16;
+    let mut packet = vec![0xBB, (len >> 8) as u8, len as u8];
+    packet.extend_from_slice(payload);
+    let checksum = packet.iter().fold(0u8, |acc, byte| acc ^ byte);
+    packet.push(checksum);
+
+    let message = LanMessage {
+        msg: MessageContent {
+            cmd: "razer".to_string(),
+            data: serde_json::json!({ "pt": BASE64.encode(packet) }),
+        },
+    };
+    serde_json::to_string(&message).unwrap_or_default()
+}
+
+/// Set individual segment colors (index = segment) on a device
+#[tauri::command]
+pub fn govee_set_segment_colors(
+    device_id: String,
+    colors: Vec<RGBColor>,
+    app: AppHandle,
+    state: State<GoveeState>,
+) -> Result<(), String> {
+    let target = lighting::LightTarget::device(LightBackend::Govee, &device_id);
+    set_segment_colors(&state, &device_id, lighting::guard_colors(&app, target, colors))
+}
+
+/// Send segment colors without guarding (callers run them through the limiter)
+pub fn set_segment_colors(state: &GoveeState, device_id: &str, colors: Vec<RGBColor>) -> Result<(), String> {
+    if colors.is_empty() || colors.len() > u8::MAX as usize {
+        return Err(format!("Invalid segment count: {}", colors.len()));
+    }
+
+    let ip = device_ip(state, device_id)?;
+
+    if !state.segment_mode.lock().unwrap().contains(device_id) {
+        send_lan_message(&ip, &razer_message(&[0xB1, 0x01]), false, CONTROL_PORT)?;
+        state.segment_mode.lock().unwrap().insert(device_id.to_string());
+    }
+
+    let mut payload = vec![0xB0, 0x00, colors.len() as u8];
+    for color in &colors {
+        payload.extend_from_slice(&[color.r, color.g, color.b]);
+    }
+    send_lan_message(&ip, &razer_message(&payload), false, CONTROL_PORT)?;
+
+    Ok(())
+}
+
+/// Look up the LAN address of a cached device
+fn device_ip(state: &GoveeState, device_id: &str) -> Result<String, String> {
+    state
+        .devices
+        .lock()
+        .unwrap()
+        .get(device_id)
+        .map(|device| device.ip.clone())
+        .ok_or_else(|| format!("Device not found: {}", device_id))
+}
+
+/// Send a `{cmd, data}` LAN command to a cached device, leaving per-segment mode first
+fn send_device_command(state: &GoveeState, device_id: &str, cmd: &str, data: serde_json::Value) -> Result<(), String> {
+    let ip = device_ip(state, device_id)?;
+
+    if state.segment_mode.lock().unwrap().remove(device_id) {
+        send_lan_message(&ip, &razer_message(&[0xB1, 0x00]), false, CONTROL_PORT)?;
+    }
+
+    let message = LanMessage {
+        msg: MessageContent {
+            cmd: cmd.to_string(),
+            data,
+        },
+    };
+    let message = serde_json::to_string(&message)
+        .map_err(|e| format!("Failed to serialize command: {}", e))?;
+    send_lan_message(&ip, &message, false, CONTROL_PORT)?;
+    Ok(())
+}
+
+/// Set a single color for the whole device, leaving per-segment mode
+#[tauri::command]
+pub fn govee_set_color(device_id: String, color: RGBColor, app: AppHandle, state: State<GoveeState>) -> Result<(), String> {
+    let color = lighting::guard_color(&app, LightBackend::Govee, &device_id, color);
+    set_color(&state, &device_id, color)
+}
+
+/// Send a whole-device color without guarding
+pub fn set_color(state: &GoveeState, device_id: &str, color: RGBColor) -> Result<(), String> {
+    send_device_command(
+        state,
+        device_id,
+        "colorwc",
+        serde_json::json!({
+            "color": { "r": color.r, "g": color.g, "b": color.b },
+            "colorTemInKelvin": 0
+        }),
+    )?;
+
+    if let Some(device) = state.devices.lock().unwrap().get_mut(device_id) {
+        device.state.color = color;
+    }
+    Ok(())
+}
+
+/// Set white color temperature (2000-9000 K) and brightness (1-100)
+#[tauri::command]
+pub fn govee_set_color_temperature(
+    device_id: String,
+    kelvin: u16,
+    brightness: u8,
+    app: AppHandle,
+    state: State<GoveeState>,
+) -> Result<(), String> {
+    let brightness = lighting::guard_temperature(&app, LightBackend::Govee, &device_id, kelvin, brightness);
+    set_color_temperature(&state, &device_id, kelvin, brightness)
+}
+
+/// Send a color temperature without guarding
+pub fn set_color_temperature(state: &GoveeState, device_id: &str, kelvin: u16, brightness: u8) -> Result<(), String> {
+    let kelvin = kelvin.clamp(2000, 9000);
+    let brightness = brightness.clamp(1, 100);
+
+    send_device_command(state, device_id, "colorwc", serde_json::json!({ "colorTemInKelvin": kelvin }))?;
+    send_device_command(state, device_id, "brightness", serde_json::json!({ "value": brightness }))?;
+
+    if let Some(device) = state.devices.lock().unwrap().get_mut(device_id) {
+        device.state.color_temperature = kelvin;
+        device.state.brightness = brightness;
+    }
+    Ok(())
+}
+
+/// Idle policy storage file
+const IDLE_POLICY_FILE: &str = "govee_idle.json";
+/// Audio energy below which the music counts as silent
+const SILENCE_THRESHOLD: f32 = 0.02;
+/// How often the backend advances the idle policy
+const IDLE_TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What to do with the lights once playback pauses or goes silent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum IdleAction {
+    /// Fade every device to one ambient color and brightness (1-100)
+    FadeToAmbient { color: RGBColor, brightness: u8 },
+    /// Return devices to the snapshot taken with `govee_capture_snapshot`
+    RestoreSnapshot,
+    /// Slowly cross-fade through a list of colors
+    AmbientScene {
+        colors: Vec<RGBColor>,
+        #[serde(rename = "periodMs")]
+        period_ms: u32,
+    },
+    /// Fade out and switch devices off
+    PowerOff,
+}
+
+/// Idle and silence policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoveeIdlePolicy {
+    pub enabled: bool,
+    /// Seconds of pause or silence before the action starts
+    #[serde(rename = "idleAfterSecs")]
+    pub idle_after_secs: u32,
+    pub action: IdleAction,
+    /// Fade duration into the idle state
+    #[serde(rename = "fadeMs")]
+    pub fade_ms: u32,
+    /// Brightness ramp duration when music restarts
+    #[serde(rename = "resumeFadeMs")]
+    pub resume_fade_ms: u32,
+}
+
+impl Default for GoveeIdlePolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            idle_after_secs: 30,
+            action: IdleAction::FadeToAmbient {
+                color: RGBColor { r: 255, g: 147, b: 41 },
+                brightness: 30,
+            },
+            fade_ms: 3000,
+            resume_fade_ms: 1500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IdlePhase {
+    #[default]
+    Active,
+    FadingOut,
+    Idle,
+    Resuming,
+}
+
+/// Tracks playback/audio activity and the idle transition in progress
+#[derive(Default)]
+struct IdleTracker {
+    policy: GoveeIdlePolicy,
+    /// None until playback is reported; audio silence still counts then
+    playing: Option<bool>,
+    paused_at: Option<Instant>,
+    /// Whether audio levels are being reported at all
+    audio_seen: bool,
+    last_loud: Option<Instant>,
+    phase: IdlePhase,
+    phase_started: Option<Instant>,
+    /// Colors and brightness devices had when the fade started
+    fade_from: HashMap<String, DeviceState>,
+    /// Brightness devices had when the music came back
+    resume_from: HashMap<String, u8>,
+    snapshot: HashMap<String, DeviceState>,
+}
+
+impl IdleTracker {
+    /// Time the music has been paused or silent
+    fn idle_for(&self, now: Instant) -> Option<Duration> {
+        if self.playing == Some(false) {
+            return self.paused_at.map(|at| now.duration_since(at));
+        }
+        if self.audio_seen {
+            return self.last_loud.map(|at| now.duration_since(at));
+        }
+        None
+    }
+
+    fn report_playback(&mut self, playing: bool, now: Instant) {
+        match (self.playing, playing) {
+            (Some(true), true) | (Some(false), false) => {}
+            (_, true) => self.last_loud = Some(now),
+            (_, false) => self.paused_at = Some(now),
+        }
+        self.playing = Some(playing);
+    }
+
+    fn report_audio_level(&mut self, energy: f32, now: Instant) {
+        self.audio_seen = true;
+        if energy > SILENCE_THRESHOLD || self.last_loud.is_none() {
+            self.last_loud = Some(now);
+        }
+    }
+
+    fn enter(&mut self, phase: IdlePhase, now: Instant) {
+        self.phase = phase;
+        self.phase_started = Some(now);
+    }
+
+    fn phase_progress(&self, now: Instant, duration_ms: u32) -> f32 {
+        let elapsed = self.phase_started.map_or(0.0, |at| now.duration_since(at).as_millis() as f32);
+        if duration_ms == 0 {
+            1.0
+        } else {
+            (elapsed / duration_ms as f32).clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// Current idle status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoveeIdleStatus {
+    pub phase: IdlePhase,
+    pub playing: bool,
+    #[serde(rename = "idleForMs")]
+    pub idle_for_ms: Option<u64>,
+}
+
+fn mix_color(a: &RGBColor, b: &RGBColor, amount: f32) -> RGBColor {
+    let t = amount.clamp(0.0, 1.0);
+    let lerp = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * t).round() as u8;
+    RGBColor {
+        r: lerp(a.r, b.r),
+        g: lerp(a.g, b.g),
+        b: lerp(a.b, b.b),
+    }
+}
+
+fn mix_brightness(a: u8, b: u8, amount: f32) -> u8 {
+    (a as f32 + (b as f32 - a as f32) * amount.clamp(0.0, 1.0)).round() as u8
+}
+
+/// Color of an ambient scene at a point in time
+fn scene_color(colors: &[RGBColor], period_ms: u32, elapsed_ms: f32) -> Option<RGBColor> {
+    let first = colors.first()?;
+    if colors.len() == 1 || period_ms == 0 {
+        return Some(first.clone());
+    }
+
+    let position = elapsed_ms / period_ms as f32;
+    let index = position.floor() as usize % colors.len();
+    let next = (index + 1) % colors.len();
+    Some(mix_color(&colors[index], &colors[next], position.fract()))
+}
+
+fn set_brightness(state: &GoveeState, device_id: &str, brightness: u8) -> Result<(), String> {
+    let brightness = brightness.clamp(1, 100);
+    send_device_command(state, device_id, "brightness", serde_json::json!({ "value": brightness }))?;
+    if let Some(device) = state.devices.lock().unwrap().get_mut(device_id) {
+        device.state.brightness = brightness;
+    }
+    Ok(())
+}
+
+fn set_power(state: &GoveeState, device_id: &str, on: bool) -> Result<(), String> {
+    send_device_command(state, device_id, "turn", serde_json::json!({ "value": on as u8 }))?;
+    if let Some(device) = state.devices.lock().unwrap().get_mut(device_id) {
+        device.state.on = on;
+    }
+    Ok(())
+}
+
+/// Set the idle policy
+#[tauri::command]
+pub fn govee_set_idle_policy(policy: GoveeIdlePolicy, state: State<GoveeState>) -> Result<(), String> {
+    storage::save_json(IDLE_POLICY_FILE, &policy)?;
+    state.idle.lock().unwrap().policy = policy;
+    Ok(())
+}
+
+/// Get the idle policy
+#[tauri::command]
+pub fn govee_get_idle_policy(state: State<GoveeState>) -> GoveeIdlePolicy {
+    state.idle.lock().unwrap().policy.clone()
+}
+
+/// Report playback state changes (playing/paused)
+#[tauri::command]
+pub fn govee_report_playback(playing: bool, state: State<GoveeState>) {
+    state.idle.lock().unwrap().report_playback(playing, Instant::now());
+}
+
+/// Report the current audio energy (0.0 - 1.0) for silence detection
+#[tauri::command]
+pub fn govee_report_audio_level(energy: f32, state: State<GoveeState>) {
+    state.idle.lock().unwrap().report_audio_level(energy, Instant::now());
+}
+
+/// Remember the current state of every device for `RestoreSnapshot`
+#[tauri::command]
+pub fn govee_capture_snapshot(state: State<GoveeState>) -> usize {
+    let snapshot: HashMap<String, DeviceState> = state
+        .devices
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, device)| (id.clone(), device.state.clone()))
+        .collect();
+    let count = snapshot.len();
+    state.idle.lock().unwrap().snapshot = snapshot;
+    count
+}
+
+/// Device change decided by the idle policy
+#[derive(Debug, Clone, PartialEq)]
+enum IdleCommand {
+    Color(String, RGBColor),
+    Brightness(String, u8),
+    Power(String, bool),
+}
+
+impl IdleTracker {
+    /// Move between phases and decide this tick's device changes from the
+    /// devices' current states
+    fn advance(&mut self, now: Instant, devices: &HashMap<String, DeviceState>) -> Vec<IdleCommand> {
+        let mut commands = Vec::new();
+        let is_idle = self.policy.enabled
+            && self
+                .idle_for(now)
+                .is_some_and(|d| d >= Duration::from_secs(self.policy.idle_after_secs as u64));
+
+        match (self.phase, is_idle) {
+            (IdlePhase::Active | IdlePhase::Resuming, true) => {
+                self.fade_from = devices.clone();
+                self.enter(IdlePhase::FadingOut, now);
+            }
+            (IdlePhase::FadingOut | IdlePhase::Idle, false) => {
+                if matches!(self.policy.action, IdleAction::PowerOff) {
+                    for id in devices.keys() {
+                        commands.push(IdleCommand::Power(id.clone(), true));
+                    }
+                }
+                // Ramp up from what the lights show now (mid-fade or idle)
+                self.resume_from = devices
+                    .iter()
+                    .map(|(id, device)| (id.clone(), device.brightness.max(1)))
+                    .collect();
+                self.enter(IdlePhase::Resuming, now);
+            }
+            _ => {}
+        }
+
+        match self.phase {
+            IdlePhase::FadingOut | IdlePhase::Idle => {
+                let progress = self.phase_progress(now, self.policy.fade_ms);
+                let elapsed_ms = self.phase_started.map_or(0.0, |at| now.duration_since(at).as_millis() as f32);
+
+                for id in devices.keys() {
+                    let Some(from) = self.fade_from.get(id) else {
+                        continue;
+                    };
+                    let target = match &self.policy.action {
+                        IdleAction::FadeToAmbient { color, brightness } => Some((color.clone(), *brightness)),
+                        IdleAction::RestoreSnapshot => self
+                            .snapshot
+                            .get(id)
+                            .map(|snapshot| (snapshot.color.clone(), snapshot.brightness)),
+                        IdleAction::AmbientScene { colors, period_ms } => {
+                            scene_color(colors, *period_ms, elapsed_ms).map(|color| (color, from.brightness))
+                        }
+                        IdleAction::PowerOff => Some((from.color.clone(), 1)),
+                    };
+                    let Some((color, brightness)) = target else {
+                        continue;
+                    };
+
+                    // Scenes keep animating after the fade; other actions stop once reached
+                    let animating = matches!(self.policy.action, IdleAction::AmbientScene { .. });
+                    if self.phase == IdlePhase::Idle && !animating {
+                        continue;
+                    }
+
+                    commands.push(IdleCommand::Color(id.clone(), mix_color(&from.color, &color, progress)));
+                    commands.push(IdleCommand::Brightness(
+                        id.clone(),
+                        mix_brightness(from.brightness, brightness, progress),
+                    ));
+                }
+
+                if self.phase == IdlePhase::FadingOut && progress >= 1.0 {
+                    if matches!(self.policy.action, IdleAction::PowerOff) {
+                        for id in devices.keys() {
+                            commands.push(IdleCommand::Power(id.clone(), false));
+                        }
+                    }
+                    self.phase = IdlePhase::Idle;
+                }
+            }
+            IdlePhase::Resuming => {
+                // Ramp brightness back up while the sync takes over the colors
+                let progress = self.phase_progress(now, self.policy.resume_fade_ms);
+                for (id, device) in devices {
+                    let restored = self.fade_from.get(id).map_or(100, |from| from.brightness.max(1));
+                    let start = self.resume_from.get(id).copied().unwrap_or(device.brightness);
+                    commands.push(IdleCommand::Brightness(id.clone(), mix_brightness(start, restored, progress)));
+                }
+                if progress >= 1.0 {
+                    self.enter(IdlePhase::Active, now);
+                }
+            }
+            IdlePhase::Active => {}
+        }
+
+        commands
+    }
+
+    fn status(&self, now: Instant) -> GoveeIdleStatus {
+        GoveeIdleStatus {
+            phase: self.phase,
+            playing: self.playing == Some(true),
+            idle_for_ms: self.idle_for(now).map(|d| d.as_millis() as u64),
+        }
+    }
+}
+
+/// Advance the idle policy: start, continue or undo idle behavior
+fn idle_tick(app: &AppHandle, state: &GoveeState) -> GoveeIdleStatus {
+    let now = Instant::now();
+    let devices: HashMap<String, DeviceState> = state
+        .devices
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, device)| (id.clone(), device.state.clone()))
+        .collect();
+
+    // Decide under the idle lock; the LAN I/O (through the limiter) happens
+    // after it is released
+    let (commands, status) = {
+        let mut idle = state.idle.lock().unwrap();
+        let commands = idle.advance(now, &devices);
+        (commands, idle.status(now))
+    };
+
+    let errors: Vec<String> = commands
+        .into_iter()
+        .filter_map(|command| {
+            let result = match command {
+                IdleCommand::Color(id, color) => {
+                    let color = lighting::guard_color(app, LightBackend::Govee, &id, color);
+                    set_color(state, &id, color)
+                }
+                IdleCommand::Brightness(id, brightness) => {
+                    let brightness = lighting::guard_brightness(app, LightBackend::Govee, &id, brightness);
+                    set_brightness(state, &id, brightness)
+                }
+                IdleCommand::Power(id, on) => {
+                    lighting::guard_power(app, LightBackend::Govee, &id).and_then(|()| set_power(state, &id, on))
+                }
+            };
+            result.err()
+        })
+        .collect();
+    if let Some(error) = errors.first() {
+        println!("Govee idle policy: {} device errors (first: {})", errors.len(), error);
+    }
+
+    status
+}
+
+/// Start the thread that advances the idle policy
+pub fn start_idle_timer(app: &AppHandle) {
+    let app = app.clone();
+    if let Err(e) = std::thread::Builder::new()
+        .name("govee-idle".to_string())
+        .spawn(move || loop {
+            idle_tick(&app, &app.state::<GoveeState>());
+            std::thread::sleep(IDLE_TICK_INTERVAL);
+        })
+    {
+        println!("Failed to start Govee idle thread: {}", e);
+    }
+}
+
+/// Current idle phase and how long the music has been paused or silent
+#[tauri::command]
+pub fn govee_idle_status(state: State<GoveeState>) -> GoveeIdleStatus {
+    state.idle.lock().unwrap().status(Instant::now())
+}
+
+/// Govee cloud API (the key stays in the credential vault; the webview only
+/// sees the results)
+const CLOUD_API_URL: &str = "https://developer-api.govee.com/v1";
+/// Credential vault entry holding the Govee developer API key
+const CLOUD_API_KEY_SECRET: &str = "api_key";
+
+/// Device as listed by the Govee cloud API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoveeCloudDevice {
+    pub device: String,
+    pub model: String,
+    #[serde(rename = "deviceName")]
+    pub device_name: String,
+    pub controllable: bool,
+    pub retrievable: bool,
+    #[serde(rename = "supportCmds", default)]
+    pub support_cmds: Vec<String>,
+}
+
+/// Cloud control command ("turn", "brightness", "color" or "colorTem")
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoveeCloudCommand {
+    pub name: String,
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudResponse {
+    code: u16,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+fn cloud_request(
+    method: reqwest::Method,
+    path: &str,
+    query: &[(&str, &str)],
+    body: Option<serde_json::Value>,
+) -> Result<serde_json::Value, String> {
+    let api_key = credential_vault::vault()
+        .get(CredentialNamespace::Govee, CLOUD_API_KEY_SECRET)?
+        .ok_or("No Govee API key saved")?;
+
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let mut request = client
+        .request(method, format!("{}{}", CLOUD_API_URL, path))
+        .header("Govee-API-Key", api_key)
+        .query(query);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+
+    let response: CloudResponse = request
+        .send()
+        .map_err(|e| format!("Govee cloud request failed: {}", e))?
+        .json()
+        .map_err(|e| format!("Invalid Govee cloud response: {}", e))?;
+    if response.code != 200 {
+        return Err(format!("Govee cloud error {}: {}", response.code, response.message));
+    }
+    Ok(response.data)
+}
+
+/// List the devices registered to the saved API key
+#[tauri::command]
+pub fn govee_cloud_list_devices() -> Result<Vec<GoveeCloudDevice>, String> {
+    let data = cloud_request(reqwest::Method::GET, "/devices", &[], None)?;
+    serde_json::from_value(data.get("devices").cloned().unwrap_or_default())
+        .map_err(|e| format!("Invalid Govee device list: {}", e))
+}
+
+/// Read a device's reported state through the cloud API
+#[tauri::command]
+pub fn govee_cloud_get_state(device: String, model: String) -> Result<serde_json::Value, String> {
+    let data = cloud_request(
+        reqwest::Method::GET,
+        "/devices/state",
+        &[("device", &device), ("model", &model)],
+        None,
+    )?;
+    Ok(data.get("properties").cloned().unwrap_or_default())
+}
+
+/// Control a device through the cloud API (light output goes through
+/// schedule caps and the safety limiter first)
+#[tauri::command]
+pub fn govee_cloud_control(
+    device: String,
+    model: String,
+    cmd: GoveeCloudCommand,
+    app: AppHandle,
+    state: State<GoveeState>,
+) -> Result<(), String> {
+    let cmd = guard_cloud_command(&app, &state, &device, cmd)?;
+    cloud_request(
+        reqwest::Method::PUT,
+        "/devices/control",
+        &[],
+        Some(serde_json::json!({ "device": device, "model": model, "cmd": cmd })),
+    )?;
+    Ok(())
+}
+
+fn guard_cloud_command(app: &AppHandle, state: &GoveeState, device_id: &str, mut cmd: GoveeCloudCommand) -> Result<GoveeCloudCommand, String> {
+    match cmd.name.as_str() {
+        "turn" => lighting::guard_power(app, LightBackend::Govee, device_id)?,
+        "brightness" => {
+            let value = cmd.value.as_u64().ok_or("Brightness must be a number")?;
+            let brightness = lighting::guard_brightness(app, LightBackend::Govee, device_id, value.min(100) as u8);
+            cmd.value = serde_json::json!(brightness.max(1));
+        }
+        "color" => {
+            let color = lighting::guard_color(app, LightBackend::Govee, device_id, parse_color(Some(&cmd.value)));
+            cmd.value = serde_json::json!({ "r": color.r, "g": color.g, "b": color.b });
+        }
+        "colorTem" => {
+            let kelvin = cmd.value.as_u64().ok_or("Color temperature must be a number")?;
+            let brightness = state.devices.lock().unwrap().get(device_id).map_or(100, |d| d.state.brightness);
+            lighting::guard_white_point(app, LightBackend::Govee, device_id, kelvin.min(u16::MAX as u64) as u16, brightness)?;
+        }
+        other => return Err(format!("Unsupported Govee cloud command: {}", other)),
+    }
+    Ok(cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: RGBColor = RGBColor { r: 255, g: 0, b: 0 };
+    const AMBER: RGBColor = RGBColor { r: 255, g: 147, b: 41 };
+
+    fn tracker(action: IdleAction) -> IdleTracker {
+        IdleTracker {
+            policy: GoveeIdlePolicy {
+                enabled: true,
+                idle_after_secs: 10,
+                action,
+                fade_ms: 1000,
+                resume_fade_ms: 1000,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn lamp(brightness: u8, color: RGBColor) -> HashMap<String, DeviceState> {
+        let state = DeviceState {
+            on: true,
+            brightness,
+            color,
+            color_temperature: 4000,
+            mode: "color".to_string(),
+        };
+        HashMap::from([("lamp".to_string(), state)])
+    }
+
+    fn brightness_of(commands: &[IdleCommand]) -> Option<u8> {
+        commands.iter().find_map(|command| match command {
+            IdleCommand::Brightness(_, brightness) => Some(*brightness),
+            _ => None,
+        })
+    }
+
+    fn ambient() -> IdleAction {
+        IdleAction::FadeToAmbient { color: AMBER, brightness: 20 }
+    }
+
+    #[test]
+    fn silence_counts_before_playback_is_reported() {
+        let start = Instant::now();
+        let mut idle = tracker(ambient());
+        assert_eq!(idle.idle_for(start), None);
+
+        idle.report_audio_level(0.5, start);
+        idle.report_audio_level(0.0, start + Duration::from_secs(5));
+
+        assert_eq!(idle.idle_for(start + Duration::from_secs(12)), Some(Duration::from_secs(12)));
+        assert!(!idle.status(start).playing);
+    }
+
+    #[test]
+    fn pause_fades_to_the_idle_action() {
+        let start = Instant::now();
+        let devices = lamp(80, RED);
+        let mut idle = tracker(ambient());
+        idle.report_playback(true, start);
+        idle.report_playback(false, start);
+
+        assert!(idle.advance(start + Duration::from_secs(9), &devices).is_empty());
+        assert_eq!(idle.phase, IdlePhase::Active);
+
+        let fading = start + Duration::from_secs(10);
+        let commands = idle.advance(fading, &devices);
+        assert_eq!(idle.phase, IdlePhase::FadingOut);
+        assert_eq!(brightness_of(&commands), Some(80));
+
+        let halfway = idle.advance(fading + Duration::from_millis(500), &devices);
+        assert_eq!(brightness_of(&halfway), Some(50));
+
+        let done = idle.advance(fading + Duration::from_millis(1000), &devices);
+        assert_eq!(idle.phase, IdlePhase::Idle);
+        assert!(done.contains(&IdleCommand::Color("lamp".to_string(), AMBER)));
+        assert_eq!(brightness_of(&done), Some(20));
+
+        // Reached: nothing more to send while idle
+        assert!(idle.advance(fading + Duration::from_secs(2), &devices).is_empty());
+    }
+
+    #[test]
+    fn resume_ramps_up_from_the_idle_brightness() {
+        let start = Instant::now();
+        let mut idle = tracker(ambient());
+        idle.report_playback(false, start);
+        idle.advance(start + Duration::from_secs(10), &lamp(80, RED));
+        idle.advance(start + Duration::from_secs(11), &lamp(80, RED));
+        assert_eq!(idle.phase, IdlePhase::Idle);
+
+        let resumed = start + Duration::from_secs(20);
+        idle.report_playback(true, resumed);
+        let dimmed = lamp(20, AMBER);
+
+        let commands = idle.advance(resumed, &dimmed);
+        assert_eq!(idle.phase, IdlePhase::Resuming);
+        assert_eq!(brightness_of(&commands), Some(20));
+
+        let halfway = idle.advance(resumed + Duration::from_millis(500), &lamp(20, AMBER));
+        assert_eq!(brightness_of(&halfway), Some(50));
+
+        let done = idle.advance(resumed + Duration::from_millis(1000), &lamp(50, AMBER));
+        assert_eq!(brightness_of(&done), Some(80));
+        assert_eq!(idle.phase, IdlePhase::Active);
+    }
+
+    #[test]
+    fn power_off_switches_devices_off_and_back_on() {
+        let start = Instant::now();
+        let devices = lamp(60, RED);
+        let mut idle = tracker(IdleAction::PowerOff);
+        idle.report_playback(false, start);
+
+        idle.advance(start + Duration::from_secs(10), &devices);
+        let done = idle.advance(start + Duration::from_secs(11), &devices);
+        assert!(done.contains(&IdleCommand::Power("lamp".to_string(), false)));
+
+        idle.report_playback(true, start + Duration::from_secs(20));
+        let resumed = idle.advance(start + Duration::from_secs(20), &lamp(1, RED));
+        assert_eq!(resumed[0], IdleCommand::Power("lamp".to_string(), true));
+        assert_eq!(brightness_of(&resumed), Some(1));
+    }
+
+    #[test]
+    fn disabled_policy_never_goes_idle() {
+        let start = Instant::now();
+        let mut idle = tracker(ambient());
+        idle.policy.enabled = false;
+        idle.report_playback(false, start);
+
+        assert!(idle.advance(start + Duration::from_secs(60), &lamp(80, RED)).is_empty());
+        assert_eq!(idle.phase, IdlePhase::Active);
+    }
+}
@@ -0,0 +1,1169 @@
+// Philips Hue Entertainment Module
+// Handles bridge discovery, link-button pairing, entertainment configuration
+// lookup and color streaming to entertainment areas over DTLS-PSK.
+// Bridge certificates are pinned on first contact; discovery uses normal TLS.
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tauri::{AppHandle, State};
+use webrtc_dtls::cipher_suite::CipherSuiteId;
+use webrtc_dtls::config::{Config as DtlsConfig, ExtendedMasterSecretType};
+use webrtc_dtls::conn::DTLSConn;
+
+use crate::credential_vault::{vault, CredentialNamespace};
+use crate::govee::RGBColor;
+use crate::lighting::{self, LightBackend, LightTarget, TargetFrame};
+
+/// Public N-UPnP discovery endpoint
+const DEFAULT_DISCOVERY_URL: &str = "https://discovery.meethue.com";
+
+/// UDP port the bridge accepts entertainment streams on
+const HUE_STREAM_PORT: u16 = 2100;
+
+/// Bridges drop the stream after 10s of silence, so resend the last frame
+const STREAM_KEEPALIVE: Duration = Duration::from_millis(40);
+
+/// Maximum channels per HueStream v2 message
+const MAX_STREAM_CHANNELS: usize = 20;
+
+/// Hue bridge information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HueBridge {
+    pub id: String,
+    pub name: String,
+    pub ip: String,
+    pub port: u16,
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub paired: bool,
+    /// SHA-256 of the certificate the bridge presented on first contact
+    #[serde(rename = "certificateFingerprint")]
+    pub certificate_fingerprint: String,
+}
+
+/// Application credentials returned by link-button pairing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HueCredentials {
+    username: String,
+    clientkey: String,
+    /// Certificate fingerprint of the bridge the credentials belong to;
+    /// None for bridges paired before certificates were pinned
+    #[serde(default)]
+    certificate: Option<String>,
+}
+
+/// Entertainment area configured in the Hue app
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HueEntertainmentConfiguration {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "configurationType")]
+    pub configuration_type: String,
+    pub status: String,
+    pub channels: Vec<HueChannel>,
+}
+
+/// Streaming channel and its position in the entertainment area (-1..1)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HueChannel {
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    pub position: HuePosition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HuePosition {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+/// Color for one entertainment channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HueChannelColor {
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    pub color: RGBColor,
+}
+
+/// CLIP v2 response envelope
+#[derive(Debug, Deserialize)]
+struct ClipResponse<T> {
+    #[serde(default)]
+    errors: Vec<ClipError>,
+    #[serde(default = "Vec::new")]
+    data: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClipError {
+    description: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClipEntertainmentConfiguration {
+    id: String,
+    #[serde(default)]
+    metadata: Option<ClipMetadata>,
+    #[serde(default)]
+    configuration_type: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    channels: Vec<ClipChannel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClipMetadata {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClipChannel {
+    channel_id: u8,
+    position: HuePosition,
+}
+
+/// Messages for the streaming thread
+enum StreamMessage {
+    Colors(Vec<HueChannelColor>),
+    Stop,
+}
+
+/// Active entertainment stream
+struct HueStream {
+    bridge_id: String,
+    configuration_id: String,
+    sender: tokio::sync::mpsc::UnboundedSender<StreamMessage>,
+    handle: JoinHandle<()>,
+}
+
+/// Hue manager state for Tauri
+#[derive(Default)]
+pub struct HueState {
+    bridges: Arc<Mutex<HashMap<String, HueBridge>>>,
+    stream: Mutex<Option<HueStream>>,
+}
+
+impl HueState {
+    fn bridge(&self, bridge_id: &str) -> Result<HueBridge, String> {
+        self.bridges
+            .lock()
+            .unwrap()
+            .get(bridge_id)
+            .cloned()
+            .ok_or_else(|| format!("Hue bridge {} not found", bridge_id))
+    }
+}
+
+/// Client for the public discovery endpoint (normal certificate validation)
+fn discovery_client() -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+/// Fingerprint of the certificate a bridge presented during the handshake
+type PresentedCertificate = Arc<Mutex<Option<String>>>;
+
+/// Bridges present a certificate no public CA signed, so instead of CA
+/// validation the certificate is pinned by fingerprint: recorded on first
+/// contact and required to match from then on
+#[derive(Debug)]
+struct BridgeCertVerifier {
+    pinned: Option<String>,
+    presented: PresentedCertificate,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for BridgeCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = certificate_fingerprint(end_entity);
+        if self.pinned.as_ref().is_some_and(|pinned| *pinned != fingerprint) {
+            return Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure));
+        }
+        *self.presented.lock().unwrap() = Some(fingerprint);
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn certificate_fingerprint(cert: &CertificateDer<'_>) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Client for bridge calls. Without a pin any certificate is accepted and
+/// its fingerprint reported through the returned slot.
+fn bridge_client(pinned: Option<&str>) -> Result<(reqwest::blocking::Client, PresentedCertificate), String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let presented = Arc::new(Mutex::new(None));
+    let verifier = BridgeCertVerifier {
+        pinned: pinned.map(str::to_string),
+        presented: presented.clone(),
+        provider: provider.clone(),
+    };
+
+    let tls = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("Failed to configure TLS: {}", e))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+
+    let client = reqwest::blocking::Client::builder()
+        .use_preconfigured_tls(tls)
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    Ok((client, presented))
+}
+
+/// Client that only talks to the bridge holding the pinned certificate
+fn pinned_client(bridge: &HueBridge) -> Result<reqwest::blocking::Client, String> {
+    bridge_client(Some(&bridge.certificate_fingerprint)).map(|(client, _)| client)
+}
+
+fn bridge_url(bridge: &HueBridge, path: &str) -> String {
+    format!("https://{}:{}{}", bridge.ip, bridge.port, path)
+}
+
+fn credentials_name(bridge_id: &str) -> String {
+    bridge_id.to_lowercase()
+}
+
+/// Save bridge credentials to the credential vault
+fn save_credentials(bridge_id: &str, credentials: &HueCredentials) -> Result<(), String> {
+    let json = serde_json::to_string(credentials)
+        .map_err(|e| format!("Failed to serialize Hue credentials: {}", e))?;
+
+    vault()
+        .set(CredentialNamespace::Hue, &credentials_name(bridge_id), &json)
+        .map_err(|e| format!("Failed to save Hue credentials: {}", e))
+}
+
+/// Load bridge credentials from the credential vault
+fn load_credentials(bridge_id: &str) -> Result<Option<HueCredentials>, String> {
+    match vault().get(CredentialNamespace::Hue, &credentials_name(bridge_id)) {
+        Ok(Some(json)) => serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| format!("Failed to deserialize Hue credentials: {}", e)),
+        Ok(None) => Ok(None),
+        Err(e) => Err(format!("Failed to load Hue credentials: {}", e)),
+    }
+}
+
+/// Delete bridge credentials from the credential vault
+fn delete_credentials(bridge_id: &str) -> Result<(), String> {
+    vault()
+        .delete(CredentialNamespace::Hue, &credentials_name(bridge_id))
+        .map_err(|e| format!("Failed to delete Hue credentials: {}", e))
+}
+
+fn require_credentials(bridge_id: &str) -> Result<HueCredentials, String> {
+    load_credentials(bridge_id)?
+        .ok_or_else(|| format!("Hue bridge {} is not paired", bridge_id))
+}
+
+/// Fetch the unauthenticated bridge config to learn its id and name, and
+/// check its certificate against the one it was paired with
+fn fetch_bridge(ip: &str, port: u16) -> Result<HueBridge, String> {
+    let (client, presented) = bridge_client(None)?;
+    let url = format!("https://{}:{}/api/0/config", ip, port);
+
+    let config: serde_json::Value = client
+        .get(&url)
+        .send()
+        .and_then(|r| r.json())
+        .map_err(|e| format!("Failed to query bridge at {}: {}", ip, e))?;
+
+    let id = config
+        .get("bridgeid")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| format!("Device at {} did not report a bridge id", ip))?
+        .to_lowercase();
+
+    let certificate_fingerprint = presented
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| format!("Bridge at {} did not present a certificate", ip))?;
+
+    let credentials = load_credentials(&id).ok().flatten();
+    if let Some(credentials) = &credentials {
+        match &credentials.certificate {
+            Some(pinned) if *pinned != certificate_fingerprint => {
+                return Err(format!(
+                    "Hue bridge {} at {} presented a different certificate than when it was paired",
+                    id, ip
+                ));
+            }
+            Some(_) => {}
+            // Paired before certificates were pinned: trust the current one
+            None => save_credentials(
+                &id,
+                &HueCredentials {
+                    certificate: Some(certificate_fingerprint.clone()),
+                    ..credentials.clone()
+                },
+            )?,
+        }
+    }
+    let paired = credentials.is_some();
+
+    Ok(HueBridge {
+        name: config
+            .get("name")
+            .and_then(|v| v.as_str())
+            .unwrap_or("Hue Bridge")
+            .to_string(),
+        api_version: config
+            .get("apiversion")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string(),
+        id,
+        ip: ip.to_string(),
+        port,
+        paired,
+        certificate_fingerprint,
+    })
+}
+
+/// Discover bridges through the Hue N-UPnP endpoint
+#[tauri::command]
+pub fn hue_discover_bridges(
+    discovery_url: Option<String>,
+    state: State<HueState>,
+) -> Result<Vec<HueBridge>, String> {
+    let url = discovery_url.unwrap_or_else(|| DEFAULT_DISCOVERY_URL.to_string());
+    println!("Discovering Hue bridges via {}", url);
+
+    let client = discovery_client()?;
+    let entries: Vec<serde_json::Value> = client
+        .get(&url)
+        .send()
+        .and_then(|r| r.json())
+        .map_err(|e| format!("Hue discovery failed: {}", e))?;
+
+    let mut bridges = Vec::new();
+    for entry in entries {
+        let Some(ip) = entry.get("internalipaddress").and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let port = entry.get("port").and_then(|v| v.as_u64()).unwrap_or(443) as u16;
+
+        match fetch_bridge(ip, port) {
+            Ok(bridge) => {
+                println!("  ✓ Found Hue bridge: {} ({}) at {}", bridge.name, bridge.id, bridge.ip);
+                bridges.push(bridge);
+            }
+            Err(e) => println!("  ! Skipping bridge at {}: {}", ip, e),
+        }
+    }
+
+    let mut state_bridges = state.bridges.lock().unwrap();
+    for bridge in &bridges {
+        state_bridges.insert(bridge.id.clone(), bridge.clone());
+    }
+
+    Ok(bridges)
+}
+
+/// Add a bridge by address ("ip" or "ip:port") when discovery isn't available
+#[tauri::command]
+pub fn hue_add_bridge(address: String, state: State<HueState>) -> Result<HueBridge, String> {
+    let (ip, port) = match address.parse::<SocketAddr>() {
+        Ok(addr) => (addr.ip().to_string(), addr.port()),
+        Err(_) => (address.clone(), 443),
+    };
+
+    let bridge = fetch_bridge(&ip, port)?;
+    state
+        .bridges
+        .lock()
+        .unwrap()
+        .insert(bridge.id.clone(), bridge.clone());
+
+    println!("Added Hue bridge {} at {}", bridge.id, address);
+    Ok(bridge)
+}
+
+/// Pair with a bridge; the link button must have been pressed beforehand
+#[tauri::command]
+pub fn hue_pair_bridge(bridge_id: String, state: State<HueState>) -> Result<HueBridge, String> {
+    pair_bridge(&state, &bridge_id)
+}
+
+fn pair_bridge(state: &HueState, bridge_id: &str) -> Result<HueBridge, String> {
+    let bridge = state.bridge(bridge_id)?;
+    let client = pinned_client(&bridge)?;
+
+    let response: Vec<serde_json::Value> = client
+        .post(bridge_url(&bridge, "/api"))
+        .json(&serde_json::json!({
+            "devicetype": "musicviz#app",
+            "generateclientkey": true,
+        }))
+        .send()
+        .and_then(|r| r.json())
+        .map_err(|e| format!("Pairing request failed: {}", e))?;
+
+    let result = response
+        .first()
+        .ok_or_else(|| "Empty pairing response".to_string())?;
+
+    if let Some(error) = result.get("error") {
+        // Error 101: link button not pressed
+        if error.get("type").and_then(|t| t.as_u64()) == Some(101) {
+            return Err("Press the link button on the Hue bridge, then try again".to_string());
+        }
+        return Err(format!(
+            "Pairing failed: {}",
+            error.get("description").and_then(|d| d.as_str()).unwrap_or("unknown error")
+        ));
+    }
+
+    let mut credentials: HueCredentials = result
+        .get("success")
+        .cloned()
+        .ok_or_else(|| "Unexpected pairing response".to_string())
+        .and_then(|s| serde_json::from_value(s).map_err(|e| format!("Invalid pairing response: {}", e)))?;
+    credentials.certificate = Some(bridge.certificate_fingerprint.clone());
+
+    save_credentials(&bridge.id, &credentials)?;
+
+    let mut bridges = state.bridges.lock().unwrap();
+    let stored = bridges
+        .get_mut(&bridge.id)
+        .ok_or_else(|| format!("Hue bridge {} not found", bridge.id))?;
+    stored.paired = true;
+
+    println!("Paired with Hue bridge {}", bridge.id);
+    Ok(stored.clone())
+}
+
+/// Get all known bridges
+#[tauri::command]
+pub fn hue_get_bridges(state: State<HueState>) -> Vec<HueBridge> {
+    let bridges = state.bridges.lock().unwrap();
+    bridges.values().cloned().collect()
+}
+
+/// Forget a bridge and delete its stored credentials
+#[tauri::command]
+pub fn hue_forget_bridge(bridge_id: String, state: State<HueState>) -> Result<(), String> {
+    let streaming = matches!(&*state.stream.lock().unwrap(), Some(s) if s.bridge_id == bridge_id);
+    if streaming {
+        hue_stop_streaming(state.clone())?;
+    }
+
+    delete_credentials(&bridge_id)?;
+    state.bridges.lock().unwrap().remove(&bridge_id);
+    println!("Forgot Hue bridge {}", bridge_id);
+    Ok(())
+}
+
+/// List entertainment configurations and their channel positions
+#[tauri::command]
+pub fn hue_list_entertainment_configurations(
+    bridge_id: String,
+    state: State<HueState>,
+) -> Result<Vec<HueEntertainmentConfiguration>, String> {
+    list_entertainment_configurations(&state, &bridge_id)
+}
+
+fn list_entertainment_configurations(
+    state: &HueState,
+    bridge_id: &str,
+) -> Result<Vec<HueEntertainmentConfiguration>, String> {
+    let bridge = state.bridge(bridge_id)?;
+    let credentials = require_credentials(&bridge.id)?;
+
+    let response: ClipResponse<ClipEntertainmentConfiguration> = pinned_client(&bridge)?
+        .get(bridge_url(&bridge, "/clip/v2/resource/entertainment_configuration"))
+        .header("hue-application-key", &credentials.username)
+        .send()
+        .and_then(|r| r.json())
+        .map_err(|e| format!("Failed to list entertainment configurations: {}", e))?;
+
+    if let Some(error) = response.errors.first() {
+        return Err(format!("Bridge error: {}", error.description));
+    }
+
+    Ok(response
+        .data
+        .into_iter()
+        .map(|config| HueEntertainmentConfiguration {
+            name: config
+                .metadata
+                .map(|m| m.name)
+                .unwrap_or_else(|| config.id.clone()),
+            id: config.id,
+            configuration_type: config.configuration_type,
+            status: config.status,
+            channels: config
+                .channels
+                .into_iter()
+                .map(|c| HueChannel {
+                    channel_id: c.channel_id,
+                    position: c.position,
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Start or stop an entertainment configuration on the bridge
+fn set_configuration_action(
+    bridge: &HueBridge,
+    credentials: &HueCredentials,
+    configuration_id: &str,
+    action: &str,
+) -> Result<(), String> {
+    let path = format!("/clip/v2/resource/entertainment_configuration/{}", configuration_id);
+    let response: ClipResponse<serde_json::Value> = pinned_client(bridge)?
+        .put(bridge_url(bridge, &path))
+        .header("hue-application-key", &credentials.username)
+        .json(&serde_json::json!({ "action": action }))
+        .send()
+        .and_then(|r| r.json())
+        .map_err(|e| format!("Failed to {} entertainment configuration: {}", action, e))?;
+
+    match response.errors.first() {
+        Some(error) => Err(format!("Bridge error: {}", error.description)),
+        None => Ok(()),
+    }
+}
+
+/// Build a HueStream v2 message
+fn encode_stream_message(sequence: u8, configuration_id: &str, colors: &[HueChannelColor]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(52 + colors.len() * 7);
+    message.extend_from_slice(b"HueStream");
+    message.extend_from_slice(&[0x02, 0x00]); // API version 2.0
+    message.push(sequence);
+    message.extend_from_slice(&[0x00, 0x00]); // Reserved
+    message.push(0x00); // Color space: RGB
+    message.push(0x00); // Reserved
+    message.extend_from_slice(configuration_id.as_bytes());
+
+    for channel in colors.iter().take(MAX_STREAM_CHANNELS) {
+        message.push(channel.channel_id);
+        for component in [channel.color.r, channel.color.g, channel.color.b] {
+            // Scale 8-bit to 16-bit so 0xFF maps to 0xFFFF
+            message.extend_from_slice(&(component as u16 * 257).to_be_bytes());
+        }
+    }
+
+    message
+}
+
+fn decode_client_key(clientkey: &str) -> Result<Vec<u8>, String> {
+    if !clientkey.len().is_multiple_of(2) {
+        return Err("Invalid Hue client key".to_string());
+    }
+
+    (0..clientkey.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&clientkey[i..i + 2], 16).map_err(|_| "Invalid Hue client key".to_string())
+        })
+        .collect()
+}
+
+/// Connect DTLS and forward frames until told to stop
+async fn run_stream(
+    addr: SocketAddr,
+    credentials: HueCredentials,
+    configuration_id: String,
+    mut receiver: tokio::sync::mpsc::UnboundedReceiver<StreamMessage>,
+    ready: mpsc::Sender<Result<(), String>>,
+) {
+    let psk = match decode_client_key(&credentials.clientkey) {
+        Ok(psk) => psk,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    let connect = async {
+        let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(addr).await?;
+
+        let config = DtlsConfig {
+            psk: Some(Arc::new(move |_hint: &[u8]| Ok(psk.clone()))),
+            psk_identity_hint: Some(credentials.username.as_bytes().to_vec()),
+            cipher_suites: vec![CipherSuiteId::Tls_Psk_With_Aes_128_Gcm_Sha256],
+            extended_master_secret: ExtendedMasterSecretType::Request,
+            ..Default::default()
+        };
+
+        DTLSConn::new(Arc::new(socket), config, true, None)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))
+    };
+
+    let conn = match tokio::time::timeout(Duration::from_secs(5), connect).await {
+        Ok(Ok(conn)) => conn,
+        Ok(Err(e)) => {
+            let _ = ready.send(Err(format!("DTLS handshake failed: {}", e)));
+            return;
+        }
+        Err(_) => {
+            let _ = ready.send(Err("DTLS handshake timed out".to_string()));
+            return;
+        }
+    };
+
+    let _ = ready.send(Ok(()));
+    println!("Hue entertainment stream connected to {}", addr);
+
+    let mut sequence: u8 = 0;
+    let mut latest: Vec<HueChannelColor> = Vec::new();
+
+    loop {
+        match tokio::time::timeout(STREAM_KEEPALIVE, receiver.recv()).await {
+            Ok(Some(StreamMessage::Colors(colors))) => latest = colors,
+            Ok(Some(StreamMessage::Stop)) | Ok(None) => break,
+            Err(_) => {
+                // Nothing new; resend the last frame to keep the stream alive
+                if latest.is_empty() {
+                    continue;
+                }
+            }
+        }
+
+        let message = encode_stream_message(sequence, &configuration_id, &latest);
+        sequence = sequence.wrapping_add(1);
+
+        if let Err(e) = conn.write(&message, None).await {
+            println!("Hue stream write failed: {}", e);
+            break;
+        }
+    }
+
+    let _ = conn.close().await;
+    println!("Hue entertainment stream closed");
+}
+
+/// Start streaming to an entertainment configuration
+#[tauri::command]
+pub fn hue_start_streaming(
+    bridge_id: String,
+    configuration_id: String,
+    state: State<HueState>,
+) -> Result<(), String> {
+    if state.stream.lock().unwrap().is_some() {
+        hue_stop_streaming(state.clone())?;
+    }
+
+    let bridge = state.bridge(&bridge_id)?;
+    let credentials = require_credentials(&bridge.id)?;
+
+    set_configuration_action(&bridge, &credentials, &configuration_id, "start")?;
+
+    let addr: SocketAddr = format!("{}:{}", bridge.ip, HUE_STREAM_PORT)
+        .parse()
+        .map_err(|e| format!("Invalid bridge address: {}", e))?;
+
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let stream_config_id = configuration_id.clone();
+    let stream_credentials = credentials.clone();
+
+    let handle = std::thread::Builder::new()
+        .name("hue-stream".to_string())
+        .spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(format!("Failed to start stream runtime: {}", e)));
+                    return;
+                }
+            };
+            runtime.block_on(run_stream(addr, stream_credentials, stream_config_id, receiver, ready_tx));
+        })
+        .map_err(|e| format!("Failed to spawn stream thread: {}", e))?;
+
+    let connected = ready_rx
+        .recv_timeout(Duration::from_secs(10))
+        .unwrap_or_else(|_| Err("DTLS handshake timed out".to_string()));
+
+    if let Err(e) = connected {
+        let _ = handle.join();
+        let _ = set_configuration_action(&bridge, &credentials, &configuration_id, "stop");
+        return Err(e);
+    }
+
+    *state.stream.lock().unwrap() = Some(HueStream {
+        bridge_id: bridge.id,
+        configuration_id,
+        sender,
+        handle,
+    });
+
+    Ok(())
+}
+
+/// Push channel colors to the active stream (through schedule caps and the
+/// safety limiter; channels are targets of the streaming bridge)
+#[tauri::command]
+pub fn hue_send_colors(colors: Vec<HueChannelColor>, app: AppHandle, state: State<HueState>) -> Result<(), String> {
+    let bridge_id = state
+        .stream
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|stream| stream.bridge_id.clone())
+        .ok_or_else(|| "No Hue entertainment stream is active".to_string())?;
+
+    let frames = colors
+        .iter()
+        .map(|channel| TargetFrame {
+            target: LightTarget {
+                backend: LightBackend::Hue,
+                device_id: bridge_id.clone(),
+                segment: Some(channel.channel_id as u32),
+            },
+            colors: vec![channel.color.clone()],
+        })
+        .collect();
+    let colors = colors
+        .into_iter()
+        .zip(lighting::guard_frames(&app, frames))
+        .map(|(channel, frame)| HueChannelColor {
+            color: lighting::average_color(&frame.colors),
+            ..channel
+        })
+        .collect();
+
+    send_colors(&state, colors)
+}
+
+/// Push channel colors without guarding (callers run them through the limiter)
+pub fn send_colors(state: &HueState, colors: Vec<HueChannelColor>) -> Result<(), String> {
+    let stream = state.stream.lock().unwrap();
+    let stream = stream
+        .as_ref()
+        .ok_or_else(|| "No Hue entertainment stream is active".to_string())?;
+
+    stream
+        .sender
+        .send(StreamMessage::Colors(colors))
+        .map_err(|_| "Hue entertainment stream has stopped".to_string())
+}
+
+/// Stop the active stream and release the entertainment configuration
+#[tauri::command]
+pub fn hue_stop_streaming(state: State<HueState>) -> Result<(), String> {
+    let Some(stream) = state.stream.lock().unwrap().take() else {
+        return Ok(());
+    };
+
+    let _ = stream.sender.send(StreamMessage::Stop);
+    let _ = stream.handle.join();
+
+    let bridge = state.bridge(&stream.bridge_id)?;
+    let credentials = require_credentials(&bridge.id)?;
+    set_configuration_action(&bridge, &credentials, &stream.configuration_id, "stop")?;
+
+    println!("Stopped Hue entertainment stream for {}", stream.configuration_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential_vault::{self, CredentialVault};
+    use rustls::pki_types::PrivatePkcs8KeyDer;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use webrtc_util::conn::Listener;
+
+    fn use_memory_vault() {
+        let _ = credential_vault::install(CredentialVault::in_memory());
+    }
+
+    /// Self-signed certificate and its fingerprint
+    fn certificate() -> (rustls::ServerConfig, String) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert = certified.cert.der().clone();
+        let fingerprint = certificate_fingerprint(&cert);
+        let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key.into())
+            .unwrap();
+        (config, fingerprint)
+    }
+
+    /// Stand-in bridge answering every HTTPS request with `body`
+    fn stand_in_bridge(config: rustls::ServerConfig, body: String) -> u16 {
+        recording_bridge(config, body).0
+    }
+
+    /// Stand-in bridge that also records the requests it receives
+    fn recording_bridge(config: rustls::ServerConfig, body: String) -> (u16, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = Arc::new(config);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let Ok(conn) = rustls::ServerConnection::new(config.clone()) else {
+                    continue;
+                };
+                let mut tls = rustls::StreamOwned::new(conn, stream);
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match tls.read(&mut buf) {
+                        Ok(n) if n > 0 => request.extend_from_slice(&buf[..n]),
+                        _ => break,
+                    }
+                }
+                let request = String::from_utf8_lossy(&request).into_owned();
+                let content_length = request
+                    .lines()
+                    .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .unwrap_or(0);
+                let head_len = request.find("\r\n\r\n").map_or(request.len(), |i| i + 4);
+                let mut request = request.into_bytes();
+                while request.len() < head_len + content_length {
+                    match tls.read(&mut buf) {
+                        Ok(n) if n > 0 => request.extend_from_slice(&buf[..n]),
+                        _ => break,
+                    }
+                }
+                recorded.lock().unwrap().push(String::from_utf8_lossy(&request).into_owned());
+
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = tls.write_all(response.as_bytes());
+                tls.conn.send_close_notify();
+                let _ = tls.flush();
+            }
+        });
+
+        (port, requests)
+    }
+
+    fn config_body(bridge_id: &str) -> String {
+        serde_json::json!({ "bridgeid": bridge_id.to_uppercase(), "name": "Stand-in", "apiversion": "1.60.0" }).to_string()
+    }
+
+    #[test]
+    fn bridge_certificate_is_pinned() {
+        use_memory_vault();
+        let (config, fingerprint) = certificate();
+        let port = stand_in_bridge(config, config_body("001788fffe0000a1"));
+
+        let bridge = fetch_bridge("127.0.0.1", port).unwrap();
+        assert_eq!(bridge.id, "001788fffe0000a1");
+        assert_eq!(bridge.certificate_fingerprint, fingerprint);
+
+        let response = pinned_client(&bridge)
+            .unwrap()
+            .get(bridge_url(&bridge, "/api/0/config"))
+            .send();
+        assert!(response.is_ok());
+
+        // Another bridge (or an impostor) on the same address
+        let (other, _) = certificate();
+        let impostor = HueBridge {
+            port: stand_in_bridge(other, config_body("001788fffe0000a1")),
+            ..bridge
+        };
+        let response = pinned_client(&impostor)
+            .unwrap()
+            .get(bridge_url(&impostor, "/api/0/config"))
+            .send();
+        assert!(response.is_err());
+    }
+
+    #[test]
+    fn paired_bridge_must_keep_its_certificate() {
+        use_memory_vault();
+        let bridge_id = "001788fffe0000b2";
+        let (config, fingerprint) = certificate();
+        let port = stand_in_bridge(config, config_body(bridge_id));
+        let credentials = HueCredentials {
+            username: "user".to_string(),
+            clientkey: "00".repeat(16),
+            certificate: Some(fingerprint),
+        };
+        save_credentials(bridge_id, &credentials).unwrap();
+        assert!(fetch_bridge("127.0.0.1", port).unwrap().paired);
+
+        let (other, _) = certificate();
+        let port = stand_in_bridge(other, config_body(bridge_id));
+        let error = fetch_bridge("127.0.0.1", port).unwrap_err();
+        assert!(error.contains("different certificate"), "{}", error);
+        delete_credentials(bridge_id).unwrap();
+    }
+
+    /// HueState that knows a bridge served by the stand-in
+    fn known_bridge(bridge_id: &str, port: u16, fingerprint: String) -> HueState {
+        let state = HueState::default();
+        state.bridges.lock().unwrap().insert(
+            bridge_id.to_string(),
+            HueBridge {
+                id: bridge_id.to_string(),
+                name: "Stand-in".to_string(),
+                ip: "127.0.0.1".to_string(),
+                port,
+                api_version: "1.60.0".to_string(),
+                paired: false,
+                certificate_fingerprint: fingerprint,
+            },
+        );
+        state
+    }
+
+    #[test]
+    fn pairing_stores_credentials_for_the_pinned_certificate() {
+        use_memory_vault();
+        let bridge_id = "001788fffe0000c3";
+        let (config, fingerprint) = certificate();
+        let body = serde_json::json!([{ "success": { "username": "app-user", "clientkey": "0123456789abcdef0123456789abcdef" } }]);
+        let (port, requests) = recording_bridge(config, body.to_string());
+        let state = known_bridge(bridge_id, port, fingerprint.clone());
+
+        let bridge = pair_bridge(&state, bridge_id).unwrap();
+
+        assert!(bridge.paired);
+        assert!(state.bridge(bridge_id).unwrap().paired);
+        let credentials = require_credentials(bridge_id).unwrap();
+        assert_eq!(credentials.username, "app-user");
+        assert_eq!(credentials.clientkey, "0123456789abcdef0123456789abcdef");
+        assert_eq!(credentials.certificate, Some(fingerprint));
+
+        let request = requests.lock().unwrap()[0].clone();
+        assert!(request.starts_with("POST /api HTTP/1.1"), "{}", request);
+        let payload: serde_json::Value = serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(payload, serde_json::json!({ "devicetype": "musicviz#app", "generateclientkey": true }));
+        delete_credentials(bridge_id).unwrap();
+    }
+
+    #[test]
+    fn pairing_reports_the_link_button() {
+        use_memory_vault();
+        let bridge_id = "001788fffe0000d4";
+        let (config, fingerprint) = certificate();
+        let body = serde_json::json!([{ "error": { "type": 101, "address": "", "description": "link button not pressed" } }]);
+        let state = known_bridge(bridge_id, stand_in_bridge(config, body.to_string()), fingerprint);
+
+        let error = pair_bridge(&state, bridge_id).unwrap_err();
+
+        assert_eq!(error, "Press the link button on the Hue bridge, then try again");
+        assert!(!state.bridge(bridge_id).unwrap().paired);
+        assert!(load_credentials(bridge_id).unwrap().is_none());
+
+        let (config, fingerprint) = certificate();
+        let body = serde_json::json!([{ "error": { "type": 7, "description": "invalid value" } }]);
+        let state = known_bridge(bridge_id, stand_in_bridge(config, body.to_string()), fingerprint);
+        assert_eq!(pair_bridge(&state, bridge_id).unwrap_err(), "Pairing failed: invalid value");
+        assert!(pair_bridge(&state, "001788fffe0000ff").is_err());
+    }
+
+    #[test]
+    fn entertainment_configurations_are_listed_with_channel_positions() {
+        use_memory_vault();
+        let bridge_id = "001788fffe0000e5";
+        let (config, fingerprint) = certificate();
+        let body = serde_json::json!({
+            "errors": [],
+            "data": [
+                {
+                    "id": "area-1",
+                    "metadata": { "name": "Living room" },
+                    "configuration_type": "screen",
+                    "status": "inactive",
+                    "channels": [
+                        { "channel_id": 0, "position": { "x": -1.0, "y": 0.5, "z": 0.0 } },
+                        { "channel_id": 1, "position": { "x": 1.0, "y": 0.5, "z": 0.0 } },
+                    ],
+                },
+                { "id": "area-2", "status": "active" },
+            ],
+        });
+        let (port, requests) = recording_bridge(config, body.to_string());
+        let state = known_bridge(bridge_id, port, fingerprint.clone());
+
+        let error = list_entertainment_configurations(&state, bridge_id).unwrap_err();
+        assert_eq!(error, format!("Hue bridge {} is not paired", bridge_id));
+
+        let credentials = HueCredentials {
+            username: "app-user".to_string(),
+            clientkey: "00".repeat(16),
+            certificate: Some(fingerprint),
+        };
+        save_credentials(bridge_id, &credentials).unwrap();
+        let configurations = list_entertainment_configurations(&state, bridge_id).unwrap();
+
+        assert_eq!(configurations.len(), 2);
+        let area = &configurations[0];
+        assert_eq!((area.id.as_str(), area.name.as_str()), ("area-1", "Living room"));
+        assert_eq!((area.configuration_type.as_str(), area.status.as_str()), ("screen", "inactive"));
+        assert_eq!(area.channels.len(), 2);
+        assert_eq!(area.channels[1].channel_id, 1);
+        assert_eq!((area.channels[1].position.x, area.channels[1].position.y), (1.0, 0.5));
+        // Areas without metadata fall back to their id
+        assert_eq!(configurations[1].name, "area-2");
+        assert!(configurations[1].channels.is_empty());
+
+        let request = requests.lock().unwrap()[0].to_lowercase();
+        assert!(request.starts_with("get /clip/v2/resource/entertainment_configuration http/1.1"), "{}", request);
+        assert!(request.contains("hue-application-key: app-user"), "{}", request);
+        delete_credentials(bridge_id).unwrap();
+    }
+
+    #[test]
+    fn entertainment_listing_reports_bridge_errors() {
+        use_memory_vault();
+        let bridge_id = "001788fffe0000f6";
+        let (config, fingerprint) = certificate();
+        let body = serde_json::json!({ "errors": [{ "description": "unauthorized user" }], "data": [] });
+        let state = known_bridge(bridge_id, stand_in_bridge(config, body.to_string()), fingerprint.clone());
+        let credentials = HueCredentials {
+            username: "revoked".to_string(),
+            clientkey: "00".repeat(16),
+            certificate: Some(fingerprint),
+        };
+        save_credentials(bridge_id, &credentials).unwrap();
+
+        let error = list_entertainment_configurations(&state, bridge_id).unwrap_err();
+
+        assert_eq!(error, "Bridge error: unauthorized user");
+        delete_credentials(bridge_id).unwrap();
+    }
+
+    #[test]
+    fn discovery_requires_a_valid_certificate() {
+        let (config, _) = certificate();
+        let port = stand_in_bridge(config, "[]".to_string());
+
+        let response = discovery_client()
+            .unwrap()
+            .get(format!("https://127.0.0.1:{}/", port))
+            .send();
+        assert!(response.is_err());
+    }
+
+    /// Run a stream against a stand-in DTLS-PSK endpoint and return the
+    /// first message it received
+    fn stream_to_stand_in(bridge_key: &str, clientkey: &str) -> Result<Vec<u8>, String> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let bridge_psk = decode_client_key(bridge_key).unwrap();
+
+        runtime.block_on(async move {
+            let config = DtlsConfig {
+                psk: Some(Arc::new(move |_hint: &[u8]| Ok(bridge_psk.clone()))),
+                psk_identity_hint: Some(b"stand-in".to_vec()),
+                cipher_suites: vec![CipherSuiteId::Tls_Psk_With_Aes_128_Gcm_Sha256],
+                extended_master_secret: ExtendedMasterSecretType::Request,
+                ..Default::default()
+            };
+            let listener = webrtc_dtls::listener::listen("127.0.0.1:0", config).await.unwrap();
+            let addr = listener.addr().await.unwrap();
+            let received = tokio::spawn(async move {
+                let (conn, _) = listener.accept().await.map_err(|e| e.to_string())?;
+                let mut buf = vec![0u8; 512];
+                let n = conn.recv(&mut buf).await.map_err(|e| e.to_string())?;
+                buf.truncate(n);
+                Ok::<_, String>(buf)
+            });
+
+            let credentials = HueCredentials {
+                username: "user".to_string(),
+                clientkey: clientkey.to_string(),
+                certificate: None,
+            };
+            let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+            let (ready_tx, ready_rx) = mpsc::channel();
+            let configuration_id = "1a2b3c4d-0000-4000-8000-000000000000".to_string();
+            let stream = tokio::spawn(run_stream(addr, credentials, configuration_id, receiver, ready_tx));
+
+            let ready = tokio::task::spawn_blocking(move || ready_rx.recv().unwrap()).await.unwrap();
+            if let Err(e) = ready {
+                received.abort();
+                return Err(e);
+            }
+            let colors = vec![HueChannelColor {
+                channel_id: 3,
+                color: RGBColor { r: 255, g: 0, b: 128 },
+            }];
+            sender.send(StreamMessage::Colors(colors)).unwrap();
+            let message = received.await.unwrap();
+            sender.send(StreamMessage::Stop).unwrap();
+            stream.await.unwrap();
+            message
+        })
+    }
+
+    #[test]
+    fn stream_sends_colors_over_dtls() {
+        let key = "0123456789abcdef0123456789abcdef";
+        let message = stream_to_stand_in(key, key).unwrap();
+
+        let configuration_id = "1a2b3c4d-0000-4000-8000-000000000000";
+        assert!(message.starts_with(b"HueStream\x02\x00"));
+        assert_eq!(&message[16..52], configuration_id.as_bytes());
+        assert_eq!(&message[52..], &[3, 0xff, 0xff, 0x00, 0x00, 0x80, 0x80]);
+    }
+
+    #[test]
+    fn stream_with_wrong_client_key_fails() {
+        let result = stream_to_stand_in("0123456789abcdef0123456789abcdef", "fedcba9876543210fedcba9876543210");
+        assert!(result.is_err());
+    }
+}
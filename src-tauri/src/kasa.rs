@@ -0,0 +1,708 @@
+// TP-Link Kasa Module
+// Handles UDP broadcast discovery and the XOR-obfuscated JSON protocol on
+// port 9999 for smart bulbs (HSV/brightness) and smart plugs (relay).
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+
+use crate::govee::RGBColor;
+use crate::lighting::{self, LightBackend};
+
+/// Initial key of the autokey XOR cipher
+const INITIAL_KEY: u8 = 171;
+
+/// Largest response accepted from a device (sysinfo is a few kilobytes)
+const MAX_RESPONSE_LEN: usize = 64 * 1024;
+
+/// Kasa device information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KasaDevice {
+    pub id: String,
+    pub name: String,
+    pub model: String,
+    pub ip: String,
+    pub port: u16,
+    pub kind: KasaDeviceKind,
+    #[serde(rename = "colorControl")]
+    pub color_control: bool,
+    pub on: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KasaDeviceKind {
+    Bulb,
+    Plug,
+}
+
+/// Switches a plug only on big musical moments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KasaPlugTrigger {
+    /// Energy (0-1) at which the plug switches on
+    #[serde(rename = "onThreshold")]
+    pub on_threshold: f32,
+    /// Energy (0-1) below which the plug may switch off again
+    #[serde(rename = "offThreshold")]
+    pub off_threshold: f32,
+    /// Minimum time the plug stays on once triggered
+    #[serde(rename = "minOnMs")]
+    pub min_on_ms: u64,
+    /// Minimum time between relay changes, to spare the relay
+    #[serde(rename = "cooldownMs")]
+    pub cooldown_ms: u64,
+}
+
+struct TriggerState {
+    trigger: KasaPlugTrigger,
+    on: bool,
+    last_change: Option<Instant>,
+}
+
+/// Kasa manager state for Tauri
+#[derive(Default)]
+pub struct KasaState {
+    devices: Arc<Mutex<HashMap<String, KasaDevice>>>,
+    triggers: Mutex<HashMap<String, TriggerState>>,
+}
+
+impl KasaState {
+    fn device(&self, device_id: &str) -> Result<KasaDevice, String> {
+        self.devices
+            .lock()
+            .unwrap()
+            .get(device_id)
+            .cloned()
+            .ok_or_else(|| format!("Kasa device {} not found", device_id))
+    }
+
+    fn set_on_flag(&self, device_id: &str, on: bool) {
+        if let Some(device) = self.devices.lock().unwrap().get_mut(device_id) {
+            device.on = on;
+        }
+    }
+}
+
+/// Obfuscate a payload with the autokey XOR cipher
+fn encrypt(payload: &[u8]) -> Vec<u8> {
+    let mut key = INITIAL_KEY;
+    payload
+        .iter()
+        .map(|byte| {
+            key ^= byte;
+            key
+        })
+        .collect()
+}
+
+/// Reverse the autokey XOR cipher
+fn decrypt(payload: &[u8]) -> Vec<u8> {
+    let mut key = INITIAL_KEY;
+    payload
+        .iter()
+        .map(|byte| {
+            let plain = key ^ byte;
+            key = *byte;
+            plain
+        })
+        .collect()
+}
+
+/// Send a request over TCP (length-prefixed) and return the decoded response
+fn send_request(addr: &SocketAddr, request: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let payload = serde_json::to_vec(request).map_err(|e| format!("Failed to serialize request: {}", e))?;
+
+    let mut stream = TcpStream::connect_timeout(addr, Duration::from_secs(2))
+        .map_err(|e| format!("Failed to connect to {}: {}", addr, e))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .map_err(|e| format!("Failed to set timeout: {}", e))?;
+
+    let mut message = (payload.len() as u32).to_be_bytes().to_vec();
+    message.extend(encrypt(&payload));
+    stream
+        .write_all(&message)
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+
+    let mut length = [0u8; 4];
+    stream
+        .read_exact(&mut length)
+        .map_err(|e| format!("Failed to receive response: {}", e))?;
+
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_RESPONSE_LEN {
+        return Err(format!("Response from {} is too large ({} bytes)", addr, length));
+    }
+
+    let mut response = vec![0u8; length];
+    stream
+        .read_exact(&mut response)
+        .map_err(|e| format!("Failed to receive response: {}", e))?;
+
+    serde_json::from_slice(&decrypt(&response)).map_err(|e| format!("Failed to parse response: {}", e))
+}
+
+/// Check the `err_code` of a module/method response
+fn check_response(response: &serde_json::Value, module: &str, method: &str) -> Result<(), String> {
+    let result = response
+        .get(module)
+        .and_then(|m| m.get(method))
+        .ok_or_else(|| format!("Missing {}.{} in response", module, method))?;
+
+    match result.get("err_code").and_then(|c| c.as_i64()) {
+        Some(0) | None => Ok(()),
+        Some(code) => Err(format!(
+            "{}.{} failed ({}): {}",
+            module,
+            method,
+            code,
+            result.get("err_msg").and_then(|m| m.as_str()).unwrap_or("unknown error")
+        )),
+    }
+}
+
+/// Discover Kasa devices with a UDP broadcast
+#[tauri::command]
+pub fn kasa_discover_devices(
+    timeout: u32,
+    broadcast_address: String,
+    port: u16,
+    state: State<KasaState>,
+) -> Result<Vec<KasaDevice>, String> {
+    println!("Starting Kasa discovery on {}:{}...", broadcast_address, port);
+
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("Failed to bind discovery socket: {}", e))?;
+    socket
+        .set_broadcast(true)
+        .map_err(|e| format!("Failed to enable broadcast: {}", e))?;
+    socket
+        .set_read_timeout(Some(Duration::from_millis(timeout as u64)))
+        .map_err(|e| format!("Failed to set socket timeout: {}", e))?;
+
+    let target: SocketAddr = format!("{}:{}", broadcast_address, port)
+        .parse()
+        .map_err(|e| format!("Invalid broadcast address: {}", e))?;
+
+    // UDP requests are not length-prefixed
+    let request = encrypt(br#"{"system":{"get_sysinfo":{}}}"#);
+    socket
+        .send_to(&request, target)
+        .map_err(|e| format!("Failed to send discovery message: {}", e))?;
+
+    let mut found: HashMap<String, KasaDevice> = HashMap::new();
+    let mut buffer = [0u8; 4096];
+    let start = Instant::now();
+
+    while start.elapsed() < Duration::from_millis(timeout as u64) {
+        match socket.recv_from(&mut buffer) {
+            Ok((size, src_addr)) => {
+                let Ok(response) = serde_json::from_slice::<serde_json::Value>(&decrypt(&buffer[..size])) else {
+                    println!("  ! Couldn't decode response from {}", src_addr);
+                    continue;
+                };
+
+                match parse_sysinfo(&response, &src_addr) {
+                    Some(device) => {
+                        println!("  ✓ Found Kasa {:?}: {} ({}) at {}", device.kind, device.name, device.model, device.ip);
+                        found.insert(device.id.clone(), device);
+                    }
+                    None => println!("  ! Response from {} wasn't a supported Kasa device", src_addr),
+                }
+            }
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::WouldBlock && e.kind() != std::io::ErrorKind::TimedOut {
+                    println!("Error receiving response: {}", e);
+                }
+            }
+        }
+    }
+
+    let mut devices = state.devices.lock().unwrap();
+    for device in found.values() {
+        devices.insert(device.id.clone(), device.clone());
+    }
+
+    println!("Discovery complete: {} Kasa devices found", found.len());
+    Ok(found.into_values().collect())
+}
+
+/// Parse a get_sysinfo response
+fn parse_sysinfo(response: &serde_json::Value, src_addr: &SocketAddr) -> Option<KasaDevice> {
+    let info = response.get("system")?.get("get_sysinfo")?;
+
+    let device_type = info
+        .get("mic_type")
+        .or_else(|| info.get("type"))
+        .and_then(|t| t.as_str())
+        .unwrap_or("");
+
+    let (kind, on) = if device_type.contains("SMARTBULB") {
+        let on = info
+            .get("light_state")
+            .and_then(|l| l.get("on_off"))
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0)
+            == 1;
+        (KasaDeviceKind::Bulb, on)
+    } else if device_type.contains("SMARTPLUG") {
+        let on = info.get("relay_state").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+        (KasaDeviceKind::Plug, on)
+    } else {
+        return None;
+    };
+
+    Some(KasaDevice {
+        id: info.get("deviceId")?.as_str()?.to_string(),
+        name: info
+            .get("alias")
+            .and_then(|a| a.as_str())
+            .unwrap_or("Kasa Device")
+            .to_string(),
+        model: info
+            .get("model")
+            .and_then(|m| m.as_str())
+            .unwrap_or("Unknown")
+            .to_string(),
+        ip: src_addr.ip().to_string(),
+        port: src_addr.port(),
+        color_control: info.get("is_color").and_then(|v| v.as_i64()).unwrap_or(0) == 1,
+        kind,
+        on,
+    })
+}
+
+/// Convert RGB to Kasa HSV (hue 0-360, saturation/value 0-100)
+fn rgb_to_hsv(color: &RGBColor) -> (u16, u8, u8) {
+    let r = color.r as f32 / 255.0;
+    let g = color.g as f32 / 255.0;
+    let b = color.b as f32 / 255.0;
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * (((g - b) / delta).rem_euclid(6.0))
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+    (
+        hue.round() as u16 % 360,
+        (saturation * 100.0).round() as u8,
+        (max * 100.0).round() as u8,
+    )
+}
+
+/// Convert Kasa HSV (hue 0-360, saturation/value 0-100) to RGB
+fn hsv_to_rgb(hue: u16, saturation: u8, value: u8) -> RGBColor {
+    let h = (hue % 360) as f32 / 60.0;
+    let s = saturation.min(100) as f32 / 100.0;
+    let v = value.min(100) as f32 / 100.0;
+
+    let c = v * s;
+    let x = c * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match h as u8 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = v - c;
+    let channel = |value: f32| ((value + m) * 255.0).round() as u8;
+    RGBColor {
+        r: channel(r),
+        g: channel(g),
+        b: channel(b),
+    }
+}
+
+fn device_addr(device: &KasaDevice) -> Result<SocketAddr, String> {
+    format!("{}:{}", device.ip, device.port)
+        .parse()
+        .map_err(|e| format!("Invalid device address: {}", e))
+}
+
+/// Send a bulb light state transition
+fn transition_light_state(device: &KasaDevice, light_state: serde_json::Value) -> Result<(), String> {
+    if device.kind != KasaDeviceKind::Bulb {
+        return Err(format!("{} is not a Kasa bulb", device.name));
+    }
+
+    let module = "smartlife.iot.smartbulb.lightingservice";
+    let response = send_request(
+        &device_addr(device)?,
+        &serde_json::json!({ module: { "transition_light_state": light_state } }),
+    )?;
+    check_response(&response, module, "transition_light_state")
+}
+
+/// Set relay (plug) or light (bulb) power
+fn set_device_power(device: &KasaDevice, on: bool) -> Result<(), String> {
+    match device.kind {
+        KasaDeviceKind::Plug => {
+            let response = send_request(
+                &device_addr(device)?,
+                &serde_json::json!({ "system": { "set_relay_state": { "state": on as u8 } } }),
+            )?;
+            check_response(&response, "system", "set_relay_state")
+        }
+        KasaDeviceKind::Bulb => transition_light_state(device, serde_json::json!({ "on_off": on as u8 })),
+    }
+}
+
+/// Turn a bulb or plug on/off
+#[tauri::command]
+pub fn kasa_set_power(device_id: String, on: bool, app: AppHandle, state: State<KasaState>) -> Result<(), String> {
+    lighting::guard_power(&app, LightBackend::Kasa, &device_id)?;
+    let device = state.device(&device_id)?;
+    set_device_power(&device, on)?;
+    state.set_on_flag(&device_id, on);
+    Ok(())
+}
+
+/// Set bulb hue (0-360), saturation (0-100) and brightness (0-100)
+#[tauri::command]
+pub fn kasa_set_bulb_hsv(
+    device_id: String,
+    hue: u16,
+    saturation: u8,
+    brightness: u8,
+    transition_ms: u32,
+    app: AppHandle,
+    state: State<KasaState>,
+) -> Result<(), String> {
+    let color = hsv_to_rgb(hue, saturation, brightness);
+    let color = lighting::guard_color(&app, LightBackend::Kasa, &device_id, color);
+    set_bulb_color(&state, &device_id, color, transition_ms)
+}
+
+/// Send HSV without guarding (callers run it through the limiter)
+fn set_bulb_hsv(
+    state: &KasaState,
+    device_id: &str,
+    (hue, saturation, brightness): (u16, u8, u8),
+    transition_ms: u32,
+) -> Result<(), String> {
+    let device = state.device(device_id)?;
+    if !device.color_control {
+        return Err(format!("{} doesn't support color", device.name));
+    }
+
+    transition_light_state(
+        &device,
+        serde_json::json!({
+            "on_off": 1,
+            "hue": hue.min(360),
+            "saturation": saturation.min(100),
+            "brightness": brightness.min(100),
+            "color_temp": 0,
+            "transition_period": transition_ms,
+        }),
+    )?;
+    state.set_on_flag(device_id, true);
+    Ok(())
+}
+
+/// Set bulb white color temperature (2500-9000 K) and brightness (0-100)
+#[tauri::command]
+pub fn kasa_set_bulb_color_temp(
+    device_id: String,
+    kelvin: u16,
+    brightness: u8,
+    transition_ms: u32,
+    app: AppHandle,
+    state: State<KasaState>,
+) -> Result<(), String> {
+    let brightness = lighting::guard_temperature(&app, LightBackend::Kasa, &device_id, kelvin, brightness);
+    set_bulb_color_temp(&state, &device_id, kelvin, brightness, transition_ms)
+}
+
+/// Send a color temperature without guarding
+pub fn set_bulb_color_temp(
+    state: &KasaState,
+    device_id: &str,
+    kelvin: u16,
+    brightness: u8,
+    transition_ms: u32,
+) -> Result<(), String> {
+    let device = state.device(device_id)?;
+    if device.kind != KasaDeviceKind::Bulb {
+        return Err(format!("{} is not a bulb", device.name));
+    }
+
+    transition_light_state(
+        &device,
+        serde_json::json!({
+            "on_off": 1,
+            "color_temp": kelvin.clamp(2500, 9000),
+            "brightness": brightness.min(100),
+            "transition_period": transition_ms,
+        }),
+    )?;
+    state.set_on_flag(device_id, true);
+    Ok(())
+}
+
+/// Set bulb color from RGB
+#[tauri::command]
+pub fn kasa_set_bulb_color(
+    device_id: String,
+    color: RGBColor,
+    transition_ms: u32,
+    app: AppHandle,
+    state: State<KasaState>,
+) -> Result<(), String> {
+    let color = lighting::guard_color(&app, LightBackend::Kasa, &device_id, color);
+    set_bulb_color(&state, &device_id, color, transition_ms)
+}
+
+/// Send an RGB color without guarding
+pub fn set_bulb_color(state: &KasaState, device_id: &str, color: RGBColor, transition_ms: u32) -> Result<(), String> {
+    set_bulb_hsv(state, device_id, rgb_to_hsv(&color), transition_ms)
+}
+
+/// Set bulb brightness (0-100) without changing its color
+#[tauri::command]
+pub fn kasa_set_bulb_brightness(
+    device_id: String,
+    brightness: u8,
+    transition_ms: u32,
+    app: AppHandle,
+    state: State<KasaState>,
+) -> Result<(), String> {
+    let brightness = lighting::guard_brightness(&app, LightBackend::Kasa, &device_id, brightness);
+    let device = state.device(&device_id)?;
+    transition_light_state(
+        &device,
+        serde_json::json!({
+            "on_off": 1,
+            "brightness": brightness.min(100),
+            "transition_period": transition_ms,
+        }),
+    )?;
+    state.set_on_flag(&device_id, true);
+    Ok(())
+}
+
+/// Attach (or with `None` remove) an energy trigger to a plug
+#[tauri::command]
+pub fn kasa_set_plug_trigger(
+    device_id: String,
+    trigger: Option<KasaPlugTrigger>,
+    state: State<KasaState>,
+) -> Result<(), String> {
+    let device = state.device(&device_id)?;
+    if device.kind != KasaDeviceKind::Plug {
+        return Err(format!("{} is not a Kasa plug", device.name));
+    }
+
+    let mut triggers = state.triggers.lock().unwrap();
+    match trigger {
+        Some(trigger) => {
+            triggers.insert(
+                device_id,
+                TriggerState {
+                    trigger,
+                    on: device.on,
+                    last_change: None,
+                },
+            );
+        }
+        None => {
+            triggers.remove(&device_id);
+        }
+    }
+    Ok(())
+}
+
+/// Feed the current audio energy (0-1); plugs with triggers switch on big moments
+#[tauri::command]
+pub fn kasa_update_energy(energy: f32, state: State<KasaState>) -> Result<Vec<String>, String> {
+    Ok(update_energy(&state, energy))
+}
+
+/// Decide which plugs switch while holding the trigger lock, then switch them
+/// without it so a slow plug doesn't stall other commands
+fn update_energy(state: &KasaState, energy: f32) -> Vec<String> {
+    let now = Instant::now();
+    let mut changes = Vec::new();
+
+    for (device_id, trigger_state) in state.triggers.lock().unwrap().iter_mut() {
+        let trigger = &trigger_state.trigger;
+        let since_change = trigger_state
+            .last_change
+            .map(|t| now.duration_since(t))
+            .unwrap_or(Duration::MAX);
+
+        if since_change < Duration::from_millis(trigger.cooldown_ms) {
+            continue;
+        }
+
+        let want_on = if trigger_state.on {
+            // Hold for the minimum time, then release once energy falls off
+            since_change < Duration::from_millis(trigger.min_on_ms) || energy > trigger.off_threshold
+        } else {
+            energy >= trigger.on_threshold
+        };
+
+        if want_on == trigger_state.on {
+            continue;
+        }
+
+        // Triggers can outlive their device (cleared or rediscovered)
+        let Ok(device) = state.device(device_id) else {
+            continue;
+        };
+
+        // Claim the change now so a concurrent update doesn't switch it again
+        changes.push((device_id.clone(), device, want_on, trigger_state.on, trigger_state.last_change));
+        trigger_state.on = want_on;
+        trigger_state.last_change = Some(now);
+    }
+
+    let mut switched = Vec::new();
+    for (device_id, device, want_on, was_on, last_change) in changes {
+        match set_device_power(&device, want_on) {
+            Ok(()) => {
+                state.set_on_flag(&device_id, want_on);
+                switched.push(device_id);
+            }
+            Err(e) => {
+                println!("Failed to switch plug {}: {}", device_id, e);
+                if let Some(trigger_state) = state.triggers.lock().unwrap().get_mut(&device_id) {
+                    if trigger_state.last_change == Some(now) {
+                        trigger_state.on = was_on;
+                        trigger_state.last_change = last_change;
+                    }
+                }
+            }
+        }
+    }
+
+    switched
+}
+
+/// Get all cached Kasa devices
+#[tauri::command]
+pub fn kasa_get_all_devices(state: State<KasaState>) -> Vec<KasaDevice> {
+    let devices = state.devices.lock().unwrap();
+    devices.values().cloned().collect()
+}
+
+/// Clear cached devices and plug triggers
+#[tauri::command]
+pub fn kasa_clear_devices(state: State<KasaState>) {
+    state.triggers.lock().unwrap().clear();
+    state.devices.lock().unwrap().clear();
+    println!("Cleared all cached Kasa devices");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Stand-in device answering each connection with `reply(request)` as
+    /// raw bytes after the length prefix
+    fn stand_in(reply: fn(&serde_json::Value) -> (u32, Vec<u8>)) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut length = [0u8; 4];
+                if stream.read_exact(&mut length).is_err() {
+                    continue;
+                }
+                let mut request = vec![0u8; u32::from_be_bytes(length) as usize];
+                if stream.read_exact(&mut request).is_err() {
+                    continue;
+                }
+                let request = serde_json::from_slice(&decrypt(&request)).unwrap_or_default();
+                let (length, body) = reply(&request);
+                let _ = stream.write_all(&length.to_be_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+
+        port
+    }
+
+    fn plug(id: &str, port: u16) -> KasaDevice {
+        KasaDevice {
+            id: id.to_string(),
+            name: id.to_string(),
+            model: "HS100".to_string(),
+            ip: "127.0.0.1".to_string(),
+            port,
+            kind: KasaDeviceKind::Plug,
+            color_control: false,
+            on: false,
+        }
+    }
+
+    fn trigger() -> TriggerState {
+        TriggerState {
+            trigger: KasaPlugTrigger {
+                on_threshold: 0.8,
+                off_threshold: 0.3,
+                min_on_ms: 0,
+                cooldown_ms: 0,
+            },
+            on: false,
+            last_change: None,
+        }
+    }
+
+    #[test]
+    fn oversized_response_is_refused() {
+        let port = stand_in(|_| (u32::MAX, Vec::new()));
+        let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        let error = send_request(&addr, &serde_json::json!({ "system": { "get_sysinfo": {} } })).unwrap_err();
+        assert!(error.contains("too large"), "{}", error);
+    }
+
+    #[test]
+    fn energy_switches_known_plugs_and_skips_missing_ones() {
+        let port = stand_in(|_| {
+            let body = encrypt(br#"{"system":{"set_relay_state":{"err_code":0}}}"#);
+            (body.len() as u32, body)
+        });
+        let state = KasaState::default();
+        state.devices.lock().unwrap().insert("plug".to_string(), plug("plug", port));
+        let mut triggers = state.triggers.lock().unwrap();
+        triggers.insert("plug".to_string(), trigger());
+        triggers.insert("gone".to_string(), trigger());
+        drop(triggers);
+
+        assert_eq!(update_energy(&state, 0.9), vec!["plug".to_string()]);
+        assert!(state.device("plug").unwrap().on);
+        assert!(!state.triggers.lock().unwrap()["gone"].on);
+    }
+
+    #[test]
+    fn failed_switch_is_retried() {
+        let port = stand_in(|_| (u32::MAX, Vec::new()));
+        let state = KasaState::default();
+        state.devices.lock().unwrap().insert("plug".to_string(), plug("plug", port));
+        state.triggers.lock().unwrap().insert("plug".to_string(), trigger());
+
+        assert!(update_energy(&state, 0.9).is_empty());
+        let triggers = state.triggers.lock().unwrap();
+        assert!(!triggers["plug"].on);
+        assert!(triggers["plug"].last_change.is_none());
+    }
+}
@@ -0,0 +1,630 @@
+// Control Layers
+//
+// Every source that drives lights (manual control, scenes, canvas sync,
+// effects, external inputs) writes into its own named layer instead of
+// straight to the bulbs. Layers are merged per target in priority order
+// using HTP, LTP or alpha blending, fade in and out when toggled, and the
+// built-in manual layer holds overrides for a limited time. Streaming layers
+// drop their values shortly after their source stops writing, so the layers
+// below them (circadian lighting) take over again.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+
+use crate::govee::RGBColor;
+use crate::lighting::{self, FlushReport, LightTarget, TargetFrame};
+use crate::storage;
+
+const LAYERS_FILE: &str = "layers.json";
+pub const MANUAL_LAYER: &str = "manual";
+/// Lifetime of values on layers fed by a running stream
+const STREAM_TTL_MS: u32 = 2000;
+
+/// How a layer combines with the layers below it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeMode {
+    /// Highest takes precedence (per channel maximum)
+    Htp,
+    /// Latest takes precedence (replaces, most recent write wins on ties)
+    Ltp,
+    /// Blend over the layers below at the layer opacity
+    Alpha,
+}
+
+/// Layer configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerConfig {
+    pub id: String,
+    pub name: String,
+    /// Higher priorities are merged later (on top)
+    pub priority: i32,
+    pub mode: MergeMode,
+    pub enabled: bool,
+    /// Opacity used by alpha layers (0.0 - 1.0)
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    /// Fade duration when the layer is enabled or disabled
+    #[serde(rename = "fadeMs", default)]
+    pub fade_ms: u32,
+    /// Values expire this long after they were written (none: kept until
+    /// cleared)
+    #[serde(rename = "ttlMs", default)]
+    pub ttl_ms: Option<u32>,
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+fn default_layers() -> Vec<LayerConfig> {
+    let layer = |id: &str, name: &str, priority: i32, ttl_ms: Option<u32>| LayerConfig {
+        id: id.to_string(),
+        name: name.to_string(),
+        priority,
+        mode: MergeMode::Ltp,
+        enabled: true,
+        opacity: 1.0,
+        fade_ms: 500,
+        ttl_ms,
+    };
+    let stream = Some(STREAM_TTL_MS);
+
+    vec![
+        layer("circadian", "Circadian (music idle)", 50, None),
+        layer("sync", "Canvas sync", 100, stream),
+        layer("framebuffer", "Framebuffer", 100, stream),
+        layer("effects", "Effects", 200, stream),
+        layer("external", "External inputs", 300, stream),
+        layer("scenes", "Scenes", 400, None),
+        layer(MANUAL_LAYER, "Manual override", 1000, None),
+    ]
+}
+
+/// Color written by a layer for one target
+struct LayerValue {
+    colors: Vec<RGBColor>,
+    updated_at: Instant,
+    expires_at: Option<Instant>,
+}
+
+struct Layer {
+    config: LayerConfig,
+    values: HashMap<LightTarget, LayerValue>,
+    fade_from: f32,
+    fade_started: Instant,
+}
+
+impl Layer {
+    fn new(config: LayerConfig) -> Self {
+        let level = if config.enabled { 1.0 } else { 0.0 };
+        Self {
+            config,
+            values: HashMap::new(),
+            fade_from: level,
+            fade_started: Instant::now(),
+        }
+    }
+
+    /// Current fade level (0.0 - 1.0) towards the enabled state
+    fn fade_level(&self, now: Instant) -> f32 {
+        let target = if self.config.enabled { 1.0 } else { 0.0 };
+        if self.config.fade_ms == 0 {
+            return target;
+        }
+
+        let progress = now.duration_since(self.fade_started).as_secs_f32() / (self.config.fade_ms as f32 / 1000.0);
+        self.fade_from + (target - self.fade_from) * progress.clamp(0.0, 1.0)
+    }
+
+    /// Apply a new config, restarting the fade from the current level
+    fn reconfigure(&mut self, config: LayerConfig) {
+        let now = Instant::now();
+        if config.enabled != self.config.enabled || config.fade_ms != self.config.fade_ms {
+            self.fade_from = self.fade_level(now);
+            self.fade_started = now;
+        }
+        self.config = config;
+    }
+}
+
+/// One layer's share of a computed target state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerContribution {
+    #[serde(rename = "layerId")]
+    pub layer_id: String,
+    pub mode: MergeMode,
+    pub colors: Vec<RGBColor>,
+    /// Effective weight after fade and opacity
+    pub weight: f32,
+    /// Remaining override time, for expiring values
+    #[serde(rename = "expiresInMs")]
+    pub expires_in_ms: Option<u64>,
+}
+
+/// Final merged state of one target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComputedState {
+    pub target: LightTarget,
+    pub colors: Vec<RGBColor>,
+    pub contributions: Vec<LayerContribution>,
+}
+
+/// Layer stack state for Tauri
+pub struct LayersState {
+    layers: Mutex<Vec<Layer>>,
+}
+
+fn is_built_in(layer_id: &str) -> bool {
+    default_layers().iter().any(|layer| layer.id == layer_id)
+}
+
+impl LayersState {
+    pub fn new() -> Self {
+        let mut configs = match storage::load_json::<Vec<LayerConfig>>(LAYERS_FILE) {
+            Ok(Some(configs)) => configs,
+            Ok(None) => Vec::new(),
+            Err(e) => {
+                println!("Failed to load layers: {}", e);
+                Vec::new()
+            }
+        };
+
+        // Built-in layers added since the file was saved, and stream TTLs
+        // for layers saved before they existed
+        for default in default_layers() {
+            match configs.iter_mut().find(|config| config.id == default.id) {
+                Some(config) => config.ttl_ms = config.ttl_ms.or(default.ttl_ms),
+                None => configs.push(default),
+            }
+        }
+
+        Self::with_configs(configs)
+    }
+
+    fn with_configs(configs: Vec<LayerConfig>) -> Self {
+        Self {
+            layers: Mutex::new(configs.into_iter().map(Layer::new).collect()),
+        }
+    }
+
+    /// Store frames in a layer, expiring after `hold` (or the layer TTL)
+    pub fn write(&self, layer_id: &str, frames: Vec<TargetFrame>, hold: Option<Duration>) -> Result<(), String> {
+        let mut layers = self.layers.lock().unwrap();
+        let layer = layers
+            .iter_mut()
+            .find(|layer| layer.config.id == layer_id)
+            .ok_or_else(|| format!("Layer not found: {}", layer_id))?;
+
+        let now = Instant::now();
+        let lifetime = hold.or(layer.config.ttl_ms.map(|ttl| Duration::from_millis(ttl as u64)));
+        for frame in frames {
+            layer.values.insert(
+                frame.target,
+                LayerValue {
+                    colors: frame.colors,
+                    updated_at: now,
+                    expires_at: lifetime.map(|lifetime| now + lifetime),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Merge all layers for every target they drive
+    pub fn compute(&self) -> Vec<ComputedState> {
+        let now = Instant::now();
+        let mut layers = self.layers.lock().unwrap();
+
+        for layer in layers.iter_mut() {
+            layer
+                .values
+                .retain(|_, value| value.expires_at.is_none_or(|expires| expires > now));
+        }
+
+        let mut targets: Vec<&LightTarget> = layers.iter().flat_map(|layer| layer.values.keys()).collect();
+        targets.sort();
+        targets.dedup();
+
+        targets
+            .into_iter()
+            .map(|target| {
+                // Priority order; equal priorities resolve by write time so LTP means latest
+                let mut entries: Vec<(&Layer, &LayerValue)> = layers
+                    .iter()
+                    .filter_map(|layer| layer.values.get(target).map(|value| (layer, value)))
+                    .collect();
+                entries.sort_by_key(|(layer, value)| (layer.config.priority, value.updated_at));
+
+                let mut colors: Vec<RGBColor> = Vec::new();
+                let mut contributions = Vec::new();
+
+                for (layer, value) in entries {
+                    let mut weight = layer.fade_level(now);
+                    if layer.config.mode == MergeMode::Alpha {
+                        weight *= layer.config.opacity.clamp(0.0, 1.0);
+                    }
+                    if weight <= 0.0 {
+                        continue;
+                    }
+
+                    if colors.len() < value.colors.len() {
+                        colors.resize(value.colors.len(), RGBColor { r: 0, g: 0, b: 0 });
+                    }
+                    for (current, color) in colors.iter_mut().zip(&value.colors) {
+                        *current = match layer.config.mode {
+                            MergeMode::Htp => {
+                                let scaled = mix(&RGBColor { r: 0, g: 0, b: 0 }, color, weight);
+                                RGBColor {
+                                    r: current.r.max(scaled.r),
+                                    g: current.g.max(scaled.g),
+                                    b: current.b.max(scaled.b),
+                                }
+                            }
+                            MergeMode::Ltp | MergeMode::Alpha => mix(current, color, weight),
+                        };
+                    }
+
+                    contributions.push(LayerContribution {
+                        layer_id: layer.config.id.clone(),
+                        mode: layer.config.mode,
+                        colors: value.colors.clone(),
+                        weight,
+                        expires_in_ms: value
+                            .expires_at
+                            .map(|expires| expires.saturating_duration_since(now).as_millis() as u64),
+                    });
+                }
+
+                ComputedState {
+                    target: target.clone(),
+                    colors,
+                    contributions,
+                }
+            })
+            .collect()
+    }
+
+    /// Merged frames for every target that has at least one active layer
+    pub fn frames(&self) -> Vec<TargetFrame> {
+        self.compute()
+            .into_iter()
+            .filter(|state| !state.contributions.is_empty())
+            .map(|state| TargetFrame {
+                target: state.target,
+                colors: state.colors,
+            })
+            .collect()
+    }
+
+    fn save(layers: &[Layer]) -> Result<(), String> {
+        let configs: Vec<&LayerConfig> = layers.iter().map(|layer| &layer.config).collect();
+        storage::save_json(LAYERS_FILE, &configs)
+    }
+}
+
+fn mix(a: &RGBColor, b: &RGBColor, amount: f32) -> RGBColor {
+    let t = amount.clamp(0.0, 1.0);
+    let lerp = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * t).round() as u8;
+    RGBColor {
+        r: lerp(a.r, b.r),
+        g: lerp(a.g, b.g),
+        b: lerp(a.b, b.b),
+    }
+}
+
+/// Write frames into a layer and send the merged result for those targets
+pub fn submit(app: &AppHandle, state: &LayersState, layer_id: &str, frames: Vec<TargetFrame>) -> Result<FlushReport, String> {
+    submit_with_hold(app, state, layer_id, frames, None)
+}
+
+fn submit_with_hold(
+    app: &AppHandle,
+    state: &LayersState,
+    layer_id: &str,
+    frames: Vec<TargetFrame>,
+    hold: Option<Duration>,
+) -> Result<FlushReport, String> {
+    let targets: Vec<LightTarget> = frames.iter().map(|frame| frame.target.clone()).collect();
+    state.write(layer_id, frames, hold)?;
+
+    let merged: Vec<TargetFrame> = state
+        .frames()
+        .into_iter()
+        .filter(|frame| targets.contains(&frame.target))
+        .collect();
+    Ok(lighting::send_frames(app, &merged))
+}
+
+/// Get all layer configurations
+#[tauri::command]
+pub fn layers_get(state: State<LayersState>) -> Vec<LayerConfig> {
+    state.layers.lock().unwrap().iter().map(|layer| layer.config.clone()).collect()
+}
+
+/// Create or update a layer (toggling `enabled` fades over `fadeMs`)
+#[tauri::command]
+pub fn layers_set(config: LayerConfig, state: State<LayersState>) -> Result<(), String> {
+    if config.id.is_empty() {
+        return Err("Layer id must not be empty".to_string());
+    }
+
+    let mut layers = state.layers.lock().unwrap();
+    match layers.iter_mut().find(|layer| layer.config.id == config.id) {
+        Some(layer) => layer.reconfigure(config),
+        None => layers.push(Layer::new(config)),
+    }
+
+    LayersState::save(&layers)
+}
+
+/// Enable or disable a layer
+#[tauri::command]
+pub fn layers_set_enabled(layer_id: String, enabled: bool, state: State<LayersState>) -> Result<(), String> {
+    let mut layers = state.layers.lock().unwrap();
+    let layer = layers
+        .iter_mut()
+        .find(|layer| layer.config.id == layer_id)
+        .ok_or_else(|| format!("Layer not found: {}", layer_id))?;
+
+    let mut config = layer.config.clone();
+    config.enabled = enabled;
+    layer.reconfigure(config);
+
+    LayersState::save(&layers)
+}
+
+/// Remove a custom layer (built-in layers can only be disabled)
+#[tauri::command]
+pub fn layers_remove(layer_id: String, state: State<LayersState>) -> Result<(), String> {
+    if is_built_in(&layer_id) {
+        return Err(format!("Built-in layer {} cannot be removed; disable it instead", layer_id));
+    }
+
+    let mut layers = state.layers.lock().unwrap();
+    layers.retain(|layer| layer.config.id != layer_id);
+    LayersState::save(&layers)
+}
+
+/// Clear a layer's values (all targets, or just the given ones)
+#[tauri::command]
+pub fn layers_clear(layer_id: String, targets: Option<Vec<LightTarget>>, state: State<LayersState>) -> Result<(), String> {
+    let mut layers = state.layers.lock().unwrap();
+    let layer = layers
+        .iter_mut()
+        .find(|layer| layer.config.id == layer_id)
+        .ok_or_else(|| format!("Layer not found: {}", layer_id))?;
+
+    match targets {
+        Some(targets) => layer.values.retain(|target, _| !targets.contains(target)),
+        None => layer.values.clear(),
+    }
+    Ok(())
+}
+
+/// Write frames into a layer and send the merged result
+#[tauri::command]
+pub fn layers_submit(
+    layer_id: String,
+    frames: Vec<TargetFrame>,
+    app: AppHandle,
+    state: State<LayersState>,
+) -> Result<FlushReport, String> {
+    submit(&app, &state, &layer_id, frames)
+}
+
+/// Hold a color on targets through the manual layer for `seconds`
+#[tauri::command]
+pub fn layers_manual_override(
+    targets: Vec<LightTarget>,
+    color: RGBColor,
+    seconds: u32,
+    app: AppHandle,
+    state: State<LayersState>,
+) -> Result<FlushReport, String> {
+    let frames: Vec<TargetFrame> = targets
+        .into_iter()
+        .map(|target| TargetFrame {
+            target,
+            colors: vec![color.clone()],
+        })
+        .collect();
+
+    submit_with_hold(&app, &state, MANUAL_LAYER, frames, Some(Duration::from_secs(seconds as u64)))
+}
+
+/// Send the merged state of every target (call each tick so fades and
+/// expiring overrides take effect)
+#[tauri::command]
+pub fn layers_flush(app: AppHandle, state: State<LayersState>) -> FlushReport {
+    lighting::send_frames(&app, &state.frames())
+}
+
+/// Inspect the merged state and per-layer contributions of targets
+#[tauri::command]
+pub fn layers_inspect(target: Option<LightTarget>, state: State<LayersState>) -> Vec<ComputedState> {
+    state
+        .compute()
+        .into_iter()
+        .filter(|computed| target.as_ref().is_none_or(|target| computed.target == *target))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lighting::LightBackend;
+
+    fn rgb(r: u8, g: u8, b: u8) -> RGBColor {
+        RGBColor { r, g, b }
+    }
+
+    fn frame(level: u8) -> TargetFrame {
+        colors_frame(vec![rgb(level, level, level)])
+    }
+
+    fn colors_frame(colors: Vec<RGBColor>) -> TargetFrame {
+        TargetFrame {
+            target: LightTarget::device(LightBackend::Govee, "test"),
+            colors,
+        }
+    }
+
+    fn layer(id: &str, priority: i32, mode: MergeMode) -> LayerConfig {
+        LayerConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            priority,
+            mode,
+            enabled: true,
+            opacity: 1.0,
+            fade_ms: 0,
+            ttl_ms: None,
+        }
+    }
+
+    fn merged(state: &LayersState) -> Vec<RGBColor> {
+        state.frames().remove(0).colors
+    }
+
+    #[test]
+    fn htp_keeps_the_highest_channel() {
+        let state = LayersState::with_configs(vec![layer("base", 0, MergeMode::Ltp), layer("htp", 10, MergeMode::Htp)]);
+
+        state.write("base", vec![colors_frame(vec![rgb(100, 0, 50)])], None).unwrap();
+        state.write("htp", vec![colors_frame(vec![rgb(50, 200, 0)])], None).unwrap();
+
+        assert_eq!(merged(&state), vec![rgb(100, 200, 50)]);
+    }
+
+    #[test]
+    fn ltp_replaces_lower_layers_and_latest_wins_on_ties() {
+        let state = LayersState::with_configs(vec![
+            layer("base", 0, MergeMode::Ltp),
+            layer("first", 10, MergeMode::Ltp),
+            layer("second", 10, MergeMode::Ltp),
+        ]);
+
+        state.write("base", vec![colors_frame(vec![rgb(1, 1, 1), rgb(2, 2, 2), rgb(3, 3, 3)])], None).unwrap();
+        state.write("second", vec![frame(20)], None).unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        state.write("first", vec![frame(10)], None).unwrap();
+
+        // Shorter frames only replace the pixels they cover
+        assert_eq!(merged(&state), vec![rgb(10, 10, 10), rgb(2, 2, 2), rgb(3, 3, 3)]);
+
+        std::thread::sleep(Duration::from_millis(2));
+        state.write("second", vec![frame(20)], None).unwrap();
+        assert_eq!(merged(&state)[0], rgb(20, 20, 20));
+    }
+
+    #[test]
+    fn alpha_blends_at_the_layer_opacity() {
+        let state = LayersState::with_configs(vec![
+            layer("base", 0, MergeMode::Ltp),
+            LayerConfig {
+                opacity: 0.25,
+                ..layer("alpha", 10, MergeMode::Alpha)
+            },
+            LayerConfig {
+                opacity: 0.0,
+                ..layer("hidden", 20, MergeMode::Alpha)
+            },
+        ]);
+
+        state.write("base", vec![colors_frame(vec![rgb(0, 100, 200)])], None).unwrap();
+        state.write("alpha", vec![colors_frame(vec![rgb(200, 100, 0)])], None).unwrap();
+        state.write("hidden", vec![frame(255)], None).unwrap();
+
+        let computed = state.compute().remove(0);
+        assert_eq!(computed.colors, vec![rgb(50, 100, 150)]);
+        let weights: Vec<(&str, f32)> = computed
+            .contributions
+            .iter()
+            .map(|c| (c.layer_id.as_str(), c.weight))
+            .collect();
+        assert_eq!(weights, vec![("base", 1.0), ("alpha", 0.25)]);
+    }
+
+    #[test]
+    fn disabled_layers_fade_out() {
+        let state = LayersState::with_configs(vec![layer("base", 0, MergeMode::Ltp), layer("top", 10, MergeMode::Ltp)]);
+        state.write("base", vec![frame(0)], None).unwrap();
+        state.write("top", vec![frame(200)], None).unwrap();
+
+        let reconfigure = |config: LayerConfig| {
+            let mut layers = state.layers.lock().unwrap();
+            layers.iter_mut().find(|l| l.config.id == "top").unwrap().reconfigure(config);
+        };
+
+        reconfigure(LayerConfig {
+            enabled: false,
+            fade_ms: 60_000,
+            ..layer("top", 10, MergeMode::Ltp)
+        });
+        let fading = merged(&state)[0].r;
+        assert!((190..=200).contains(&fading), "{}", fading);
+
+        reconfigure(LayerConfig {
+            enabled: false,
+            ..layer("top", 10, MergeMode::Ltp)
+        });
+        assert_eq!(merged(&state), vec![rgb(0, 0, 0)]);
+    }
+
+    #[test]
+    fn stale_stream_values_uncover_lower_layers() {
+        let mut configs = default_layers();
+        for config in &mut configs {
+            config.fade_ms = 0;
+            if config.ttl_ms.is_some() {
+                config.ttl_ms = Some(20);
+            }
+        }
+        let state = LayersState::with_configs(configs);
+
+        state.write("circadian", vec![frame(10)], None).unwrap();
+        state.write("sync", vec![frame(200)], None).unwrap();
+        assert_eq!(state.frames()[0].colors[0].r, 200);
+        let expires = state.compute()[0].contributions[1].expires_in_ms;
+        assert!(expires.is_some_and(|ms| ms <= 20), "{:?}", expires);
+
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(state.frames()[0].colors[0].r, 10);
+        assert_eq!(state.compute()[0].contributions.len(), 1);
+    }
+
+    #[test]
+    fn holds_override_the_layer_ttl() {
+        let state = LayersState::with_configs(vec![
+            LayerConfig {
+                ttl_ms: Some(20),
+                ..layer("stream", 0, MergeMode::Ltp)
+            },
+            layer(MANUAL_LAYER, 10, MergeMode::Ltp),
+        ]);
+
+        state.write("stream", vec![frame(10)], Some(Duration::from_secs(60))).unwrap();
+        state.write(MANUAL_LAYER, vec![frame(200)], Some(Duration::from_millis(20))).unwrap();
+        std::thread::sleep(Duration::from_millis(40));
+
+        assert_eq!(merged(&state), vec![rgb(10, 10, 10)]);
+
+        // Layers without a TTL keep their values until cleared
+        state.write(MANUAL_LAYER, vec![frame(200)], None).unwrap();
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(merged(&state), vec![rgb(200, 200, 200)]);
+        assert_eq!(state.compute()[0].contributions[1].expires_in_ms, None);
+    }
+
+    #[test]
+    fn writing_to_an_unknown_layer_fails() {
+        let state = LayersState::with_configs(default_layers());
+
+        assert_eq!(state.write("missing", vec![frame(1)], None).unwrap_err(), "Layer not found: missing");
+        assert!(state.frames().is_empty());
+    }
+}
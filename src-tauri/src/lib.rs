@@ -1,9 +1,76 @@
 // Spotify authentication module
 mod spotify_auth;
 
+// Spotify companion (phone) login for the TV build
+mod spotify_companion;
+
+// Spotify accounts and household profiles
+mod spotify_profiles;
+
+// Background Spotify token refresh
+mod spotify_token_scheduler;
+
+// Typed Spotify Web API client
+mod spotify_api;
+
 // Govee integration module
 mod govee;
 
+// Yeelight integration module
+mod yeelight;
+
+// Philips Hue integration module
+mod hue;
+
+// Nanoleaf integration module
+mod nanoleaf;
+
+// TP-Link Kasa integration module
+mod kasa;
+
+// OpenRGB SDK integration module
+mod openrgb;
+
+// MQTT bridge module (Home Assistant / Zigbee2MQTT)
+mod mqtt;
+
+// WLED realtime output module
+mod wled;
+
+// Shared local storage and lighting types
+mod storage;
+mod lighting;
+
+// Encrypted files for secrets the keyring cannot hold
+mod secure_file;
+
+// Namespaced credential vault for all service secrets
+mod credential_vault;
+
+// Spatial room layout module
+mod room_layout;
+
+// Virtual LED framebuffer module
+mod framebuffer;
+
+// Audio-reactive effect library
+mod effects;
+
+// Control priority and merge layers
+mod layers;
+
+// Photosensitivity safety limiter
+mod safety;
+
+// Device color calibration profiles
+mod calibration;
+
+// Schedules, quiet hours and circadian lighting
+mod schedule;
+
+// Lighting session recording and replay
+mod recorder;
+
 use tauri::{Emitter, Manager};
 use tauri_plugin_deep_link::DeepLinkExt;
 
@@ -15,14 +82,53 @@ fn greet(name: &str) -> String {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Kiosk and demo installs can keep service secrets out of persistent storage
+    if std::env::var_os("MUSICVIZ_EPHEMERAL_CREDENTIALS").is_some() {
+        if let Err(e) = credential_vault::install(credential_vault::CredentialVault::in_memory()) {
+            println!("Failed to use in-memory credentials: {}", e);
+        }
+    }
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_deep_link::init())
         // Initialize Spotify authentication state
         .manage(spotify_auth::PKCEState::new())
         .manage(spotify_auth::SpotifyAuthState::new())
+        .manage(spotify_token_scheduler::TokenRefreshScheduler::new())
+        .manage(spotify_api::SpotifyApiState::new())
         // Initialize Govee state
-        .manage(govee::GoveeState::default())
+        .manage(govee::GoveeState::new())
+        // Initialize Yeelight state
+        .manage(yeelight::YeelightState::default())
+        // Initialize Hue state
+        .manage(hue::HueState::default())
+        // Initialize Nanoleaf state
+        .manage(nanoleaf::NanoleafState::default())
+        // Initialize Kasa state
+        .manage(kasa::KasaState::default())
+        // Initialize OpenRGB state
+        .manage(openrgb::OpenRgbState::default())
+        // Initialize MQTT state
+        .manage(mqtt::MqttState::default())
+        // Initialize room layout state
+        .manage(room_layout::RoomLayoutState::new())
+        // Initialize WLED state
+        .manage(wled::WledState::default())
+        // Initialize framebuffer state
+        .manage(framebuffer::FramebufferState::new())
+        // Initialize effect engine state
+        .manage(effects::EffectsState::new())
+        // Initialize control layer state
+        .manage(layers::LayersState::new())
+        // Initialize safety limiter state
+        .manage(safety::SafetyState::new())
+        // Initialize calibration state
+        .manage(calibration::CalibrationState::new())
+        // Initialize schedule state
+        .manage(schedule::ScheduleState::new())
+        // Initialize session recorder state
+        .manage(recorder::RecorderState::default())
         // Setup deep link handler for OAuth callback
         .setup(|app| {
             let handle = app.handle().clone();
@@ -32,11 +138,34 @@ pub fn run() {
                 for url in event.urls() {
                     println!("Deep link received: {}", url);
 
+                    // Spotify login callbacks carry the authorization code:
+                    // exchange it here instead of forwarding it
+                    if spotify_auth::is_login_callback(&handle, url.as_str()) {
+                        let handle = handle.clone();
+                        let url = url.to_string();
+                        std::thread::spawn(move || {
+                            let _ = spotify_auth::handle_login_callback(&handle, &url);
+                        });
+                        continue;
+                    }
+
                     // Emit event to frontend with the full URL
                     let _ = handle.emit("deep-link", url.as_str());
                 }
             });
 
+            // Refresh Spotify tokens before they expire
+            spotify_token_scheduler::start(app.handle());
+
+            // Dim or restore Govee lights when the music pauses or resumes
+            govee::start_idle_timer(app.handle());
+
+            // Follow the circadian curve while music is idle
+            schedule::start_timer(app.handle());
+
+            // Render audio-reactive effect groups
+            effects::start_timer(app.handle());
+
             // Enable DevTools for debugging in production builds
             #[cfg(not(debug_assertions))]
             {
@@ -51,20 +180,192 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             greet,
             // Spotify auth commands
-            spotify_auth::store_code_verifier,
-            spotify_auth::get_code_verifier,
+            spotify_auth::spotify_begin_login,
+            spotify_auth::spotify_complete_login,
+            spotify_auth::spotify_begin_loopback_login,
+            spotify_auth::spotify_cancel_login,
+            spotify_companion::spotify_begin_companion_login,
             spotify_auth::store_spotify_token,
             spotify_auth::get_spotify_token,
+            spotify_auth::get_valid_access_token,
+            spotify_auth::refresh_spotify_token,
+            spotify_auth::spotify_has_scopes,
+            spotify_auth::spotify_request_scopes,
+            spotify_auth::set_spotify_client_id,
             spotify_auth::is_authenticated,
             spotify_auth::logout,
             spotify_auth::open_url,
             spotify_auth::test_keyring,
+            // Spotify profile commands
+            spotify_profiles::spotify_list_profiles,
+            spotify_profiles::spotify_switch_profile,
+            spotify_profiles::spotify_remove_profile,
+            spotify_profiles::spotify_get_profile_preferences,
+            spotify_profiles::spotify_set_profile_preferences,
+            // Token refresh scheduler commands
+            spotify_token_scheduler::spotify_get_refresh_settings,
+            spotify_token_scheduler::spotify_set_refresh_settings,
+            // Spotify Web API commands
+            spotify_api::spotify_api_get_me,
+            spotify_api::spotify_api_get_playback_state,
+            spotify_api::spotify_api_get_currently_playing,
+            spotify_api::spotify_api_get_queue,
+            spotify_api::spotify_api_add_to_queue,
+            spotify_api::spotify_api_get_devices,
+            spotify_api::spotify_api_transfer_playback,
+            spotify_api::spotify_api_start_playback,
+            spotify_api::spotify_api_get_track,
+            spotify_api::spotify_api_get_tracks,
+            spotify_api::spotify_api_get_audio_features,
+            // Credential vault commands
+            credential_vault::vault_set_secret,
+            credential_vault::vault_delete_secret,
+            credential_vault::vault_has_secret,
+            credential_vault::vault_list_secrets,
             // Govee integration commands
             govee::govee_discover_devices,
             govee::govee_send_lan_command,
             govee::govee_get_device,
             govee::govee_get_all_devices,
             govee::govee_clear_devices,
+            govee::govee_set_color,
+            govee::govee_set_segment_colors,
+            govee::govee_set_color_temperature,
+            govee::govee_set_idle_policy,
+            govee::govee_get_idle_policy,
+            govee::govee_report_playback,
+            govee::govee_report_audio_level,
+            govee::govee_capture_snapshot,
+            govee::govee_idle_status,
+            govee::govee_cloud_list_devices,
+            govee::govee_cloud_get_state,
+            govee::govee_cloud_control,
+            // Yeelight integration commands
+            yeelight::yeelight_discover_devices,
+            yeelight::yeelight_set_rgb,
+            yeelight::yeelight_set_ct,
+            yeelight::yeelight_set_bright,
+            yeelight::yeelight_set_power,
+            yeelight::yeelight_start_music_mode,
+            yeelight::yeelight_stop_music_mode,
+            yeelight::yeelight_get_all_devices,
+            yeelight::yeelight_clear_devices,
+            // Hue integration commands
+            hue::hue_discover_bridges,
+            hue::hue_add_bridge,
+            hue::hue_pair_bridge,
+            hue::hue_get_bridges,
+            hue::hue_forget_bridge,
+            hue::hue_list_entertainment_configurations,
+            hue::hue_start_streaming,
+            hue::hue_send_colors,
+            hue::hue_stop_streaming,
+            // Nanoleaf integration commands
+            nanoleaf::nanoleaf_discover_devices,
+            nanoleaf::nanoleaf_add_device,
+            nanoleaf::nanoleaf_pair_device,
+            nanoleaf::nanoleaf_get_layout,
+            nanoleaf::nanoleaf_place_panels,
+            nanoleaf::nanoleaf_start_streaming,
+            nanoleaf::nanoleaf_send_panel_colors,
+            nanoleaf::nanoleaf_stop_streaming,
+            nanoleaf::nanoleaf_get_all_devices,
+            nanoleaf::nanoleaf_forget_device,
+            // Kasa integration commands
+            kasa::kasa_discover_devices,
+            kasa::kasa_set_power,
+            kasa::kasa_set_bulb_hsv,
+            kasa::kasa_set_bulb_color_temp,
+            kasa::kasa_set_bulb_color,
+            kasa::kasa_set_bulb_brightness,
+            kasa::kasa_set_plug_trigger,
+            kasa::kasa_update_energy,
+            kasa::kasa_get_all_devices,
+            kasa::kasa_clear_devices,
+            // OpenRGB integration commands
+            openrgb::openrgb_connect,
+            openrgb::openrgb_get_controllers,
+            openrgb::openrgb_set_led_colors,
+            openrgb::openrgb_set_zone_led_colors,
+            openrgb::openrgb_set_zone_colors,
+            openrgb::openrgb_disconnect,
+            // MQTT bridge commands
+            mqtt::mqtt_connect,
+            mqtt::mqtt_disconnect,
+            mqtt::mqtt_get_status,
+            mqtt::mqtt_publish_audio_features,
+            mqtt::mqtt_publish_now_playing,
+            mqtt::mqtt_publish_sync_state,
+            mqtt::mqtt_set_zigbee2mqtt_light,
+            // Room layout commands
+            room_layout::room_layout_get,
+            room_layout::room_layout_upsert_room,
+            room_layout::room_layout_remove_room,
+            room_layout::room_layout_set_placement,
+            room_layout::room_layout_remove_placement,
+            room_layout::room_layout_find_by_tag,
+            room_layout::room_layout_sample_field,
+            room_layout::room_layout_sync_field,
+            // WLED commands
+            wled::wled_send_pixels,
+            // Framebuffer commands
+            framebuffer::framebuffer_get_config,
+            framebuffer::framebuffer_set_config,
+            framebuffer::framebuffer_write,
+            framebuffer::framebuffer_preview,
+            framebuffer::framebuffer_flush,
+            framebuffer::framebuffer_present,
+            // Effect library commands
+            effects::effects_list,
+            effects::effects_push_audio_features,
+            effects::effects_get_groups,
+            effects::effects_set_group,
+            effects::effects_remove_group,
+            effects::effects_preview,
+            // Control layer commands
+            layers::layers_get,
+            layers::layers_set,
+            layers::layers_set_enabled,
+            layers::layers_remove,
+            layers::layers_clear,
+            layers::layers_submit,
+            layers::layers_manual_override,
+            layers::layers_flush,
+            layers::layers_inspect,
+            // Safety limiter commands
+            safety::safety_get_settings,
+            safety::safety_set_config,
+            safety::safety_remove_room_config,
+            safety::safety_get_log,
+            safety::safety_clear_log,
+            safety::safety_analyze_visual_frame,
+            // Calibration commands
+            calibration::calibration_get_profiles,
+            calibration::calibration_set_profile,
+            calibration::calibration_remove_profile,
+            calibration::calibration_kelvin_to_rgb,
+            calibration::calibration_begin,
+            calibration::calibration_update_draft,
+            calibration::calibration_next,
+            calibration::calibration_finish,
+            calibration::calibration_cancel,
+            // Schedule commands
+            schedule::schedule_get_settings,
+            schedule::schedule_set_settings,
+            schedule::schedule_report_music_activity,
+            schedule::schedule_get_status,
+            // Session recorder commands
+            recorder::recorder_start,
+            recorder::recorder_stop,
+            recorder::recorder_record_input,
+            recorder::recorder_status,
+            recorder::recorder_info,
+            recorder::recorder_list,
+            recorder::recorder_load,
+            recorder::recorder_delete,
+            recorder::recorder_replay,
+            recorder::recorder_stop_replay,
+            recorder::recorder_diff,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
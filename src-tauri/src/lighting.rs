@@ -0,0 +1,537 @@
+// Shared lighting types and output routing
+//
+// Identifies an individual light output (a device, or one segment of a
+// device) across all of the integration backends, and dispatches batches of
+// colors to the backend modules.
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::calibration::{self, CalibratedOutput, CalibrationState, TemperatureCommand};
+use crate::govee::{self, RGBColor};
+use crate::recorder::RecorderState;
+use crate::room_layout::{RoomLayout, RoomLayoutState};
+use crate::safety::SafetyState;
+use crate::schedule::ScheduleState;
+use crate::{hue, kasa, mqtt, nanoleaf, openrgb, wled, yeelight};
+
+// Highest segment index accepted (segment lists are allocated up to it)
+const MAX_SEGMENT: u32 = 1023;
+// White used to judge brightness-only commands
+const NEUTRAL_WHITE_KELVIN: u16 = 6500;
+
+/// Integration backend that owns a light
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LightBackend {
+    Govee,
+    Yeelight,
+    Hue,
+    Nanoleaf,
+    Kasa,
+    OpenRgb,
+    Zigbee2Mqtt,
+    Wled,
+}
+
+impl LightBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LightBackend::Govee => "govee",
+            LightBackend::Yeelight => "yeelight",
+            LightBackend::Hue => "hue",
+            LightBackend::Nanoleaf => "nanoleaf",
+            LightBackend::Kasa => "kasa",
+            LightBackend::OpenRgb => "openrgb",
+            LightBackend::Zigbee2Mqtt => "zigbee2mqtt",
+            LightBackend::Wled => "wled",
+        }
+    }
+}
+
+/// Backends with a native white color temperature command
+pub fn supports_color_temperature(backend: LightBackend) -> bool {
+    matches!(
+        backend,
+        LightBackend::Govee | LightBackend::Yeelight | LightBackend::Kasa | LightBackend::Zigbee2Mqtt
+    )
+}
+
+/// Model name of a discovered device, where the backend reports one
+pub fn device_model(app: &AppHandle, target: &LightTarget) -> Option<String> {
+    let id = &target.device_id;
+    match target.backend {
+        LightBackend::Govee => govee::govee_get_device(id.clone(), app.state()).map(|device| device.model),
+        LightBackend::Yeelight => yeelight::yeelight_get_all_devices(app.state())
+            .into_iter()
+            .find(|device| &device.id == id)
+            .map(|device| device.model),
+        LightBackend::Kasa => kasa::kasa_get_all_devices(app.state())
+            .into_iter()
+            .find(|device| &device.id == id)
+            .map(|device| device.model),
+        LightBackend::Nanoleaf => nanoleaf::nanoleaf_get_all_devices(app.state())
+            .into_iter()
+            .find(|device| &device.id == id)
+            .map(|device| device.model),
+        _ => None,
+    }
+}
+
+/// A single addressable light: a whole device or one of its segments
+/// (Govee segment, Hue channel, Nanoleaf panel, OpenRGB zone, ...)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct LightTarget {
+    pub backend: LightBackend,
+    /// Backend device id (WLED: host address, OpenRGB: controller index)
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    #[serde(default)]
+    pub segment: Option<u32>,
+}
+
+impl LightTarget {
+    /// A whole device
+    pub fn device(backend: LightBackend, device_id: &str) -> Self {
+        Self {
+            backend,
+            device_id: device_id.to_string(),
+            segment: None,
+        }
+    }
+}
+
+/// Colors for one target; single-color lights use the average of `colors`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetFrame {
+    pub target: LightTarget,
+    pub colors: Vec<RGBColor>,
+}
+
+/// Result of routing a batch of frames to the backends
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlushReport {
+    pub sent: usize,
+    pub errors: Vec<String>,
+}
+
+impl FlushReport {
+    fn record(&mut self, target: &str, result: Result<(), String>) {
+        match result {
+            Ok(()) => self.sent += 1,
+            Err(e) => self.errors.push(format!("{}: {}", target, e)),
+        }
+    }
+}
+
+/// Average a run of pixels down to one color
+pub fn average_color(colors: &[RGBColor]) -> RGBColor {
+    if colors.is_empty() {
+        return RGBColor { r: 0, g: 0, b: 0 };
+    }
+
+    let (r, g, b) = colors.iter().fold((0u32, 0u32, 0u32), |(r, g, b), c| {
+        (r + c.r as u32, g + c.g as u32, b + c.b as u32)
+    });
+    let n = colors.len() as u32;
+    RGBColor {
+        r: (r / n) as u8,
+        g: (g / n) as u8,
+        b: (b / n) as u8,
+    }
+}
+
+/// Route a batch of frames to their backends, merging segments of the same
+/// device into a single message where the protocol allows it. Every frame
+/// passes through schedule brightness caps, device calibration, then the
+/// photosensitivity limiter (so it analyzes what the lights will actually
+/// show), and is recorded when a session recording is running.
+pub fn send_frames(app: &AppHandle, frames: &[TargetFrame]) -> FlushReport {
+    let calibration = app.state::<CalibrationState>();
+    send_frames_with(app, frames, |frames| calibration.apply(app, frames))
+}
+
+/// Like `send_frames`, with a custom calibration stage
+pub fn send_frames_with(
+    app: &AppHandle,
+    frames: &[TargetFrame],
+    calibrate: impl FnOnce(Vec<TargetFrame>) -> CalibratedOutput,
+) -> FlushReport {
+    let layout = app.state::<RoomLayoutState>().snapshot();
+    let capped = app.state::<ScheduleState>().limit(frames.to_vec(), &layout);
+    let output = calibrate(capped);
+    let limited = limit_output(&app.state::<SafetyState>(), &layout, output);
+
+    app.state::<RecorderState>().record_output(&limited);
+    dispatch(app, limited)
+}
+
+/// Send recorded output (already capped and calibrated) back to the lights.
+/// It goes through the limiter again, since a replay can run faster than
+/// the session it was recorded from.
+pub fn send_recorded(app: &AppHandle, output: CalibratedOutput) -> FlushReport {
+    let layout = app.state::<RoomLayoutState>().snapshot();
+    let limited = limit_output(&app.state::<SafetyState>(), &layout, output);
+    dispatch(app, limited)
+}
+
+/// Run calibrated output through the photosensitivity limiter
+fn limit_output(safety: &SafetyState, layout: &RoomLayout, output: CalibratedOutput) -> CalibratedOutput {
+    through_stage(output, |frames| safety.limit(&frames, layout))
+}
+
+/// White a color temperature command renders, as RGB
+fn rendered_white(command: &TemperatureCommand) -> RGBColor {
+    let white = calibration::kelvin_to_rgb(command.kelvin);
+    let scale = |v: u8| (v as f32 * command.brightness.min(100) as f32 / 100.0).round() as u8;
+    RGBColor {
+        r: scale(white.r),
+        g: scale(white.g),
+        b: scale(white.b),
+    }
+}
+
+/// Run a per-frame stage (one output frame per input frame, in order) over
+/// calibrated output. Color temperature commands pass through it as the
+/// white they render and take back the resulting brightness.
+fn through_stage(
+    output: CalibratedOutput,
+    stage: impl FnOnce(Vec<TargetFrame>) -> Vec<TargetFrame>,
+) -> CalibratedOutput {
+    let CalibratedOutput { mut frames, temperatures } = output;
+    let count = frames.len();
+    frames.extend(temperatures.iter().map(|command| TargetFrame {
+        target: command.target.clone(),
+        colors: vec![rendered_white(command)],
+    }));
+
+    let mut frames = stage(frames);
+    let whites = frames.split_off(count.min(frames.len()));
+    let temperatures = temperatures
+        .into_iter()
+        .zip(whites)
+        .map(|(mut command, white)| {
+            // Every black-body white has a full channel, so the peak is the brightness
+            let color = average_color(&white.colors);
+            let peak = color.r.max(color.g).max(color.b);
+            command.brightness = (peak as f32 * 100.0 / 255.0).round() as u8;
+            command
+        })
+        .collect();
+
+    CalibratedOutput { frames, temperatures }
+}
+
+/// Run a direct device command through schedule caps and the
+/// photosensitivity limiter (no calibration: the command asked for exactly
+/// these values)
+pub fn guard_output(app: &AppHandle, output: CalibratedOutput) -> CalibratedOutput {
+    let layout = app.state::<RoomLayoutState>().snapshot();
+    let schedule = app.state::<ScheduleState>();
+    let safety = app.state::<SafetyState>();
+    through_stage(output, |frames| safety.limit_direct(&schedule.limit(frames, &layout), &layout))
+}
+
+/// Guard the frames of a direct command (one output frame per input frame)
+pub fn guard_frames(app: &AppHandle, frames: Vec<TargetFrame>) -> Vec<TargetFrame> {
+    let output = CalibratedOutput {
+        frames,
+        temperatures: Vec::new(),
+    };
+    guard_output(app, output).frames
+}
+
+/// Guard the colors of a direct command to one target
+pub fn guard_colors(app: &AppHandle, target: LightTarget, colors: Vec<RGBColor>) -> Vec<RGBColor> {
+    if colors.is_empty() {
+        return colors;
+    }
+
+    guard_frames(app, vec![TargetFrame { target, colors }])
+        .pop()
+        .map(|frame| frame.colors)
+        .unwrap_or_default()
+}
+
+/// Guard a single color sent to a whole device
+pub fn guard_color(app: &AppHandle, backend: LightBackend, device_id: &str, color: RGBColor) -> RGBColor {
+    let colors = guard_colors(app, LightTarget::device(backend, device_id), vec![color]);
+    average_color(&colors)
+}
+
+/// Guard a white color temperature command, returning the brightness (0-100)
+/// that may be sent
+pub fn guard_temperature(app: &AppHandle, backend: LightBackend, device_id: &str, kelvin: u16, brightness: u8) -> u8 {
+    let output = CalibratedOutput {
+        frames: Vec::new(),
+        temperatures: vec![TemperatureCommand {
+            target: LightTarget::device(backend, device_id),
+            kelvin,
+            brightness,
+        }],
+    };
+    guard_output(app, output)
+        .temperatures
+        .pop()
+        .map_or(brightness, |command| command.brightness)
+}
+
+/// Guard a color temperature change that keeps the current brightness:
+/// refused when the limiter would have to dim it
+pub fn guard_white_point(app: &AppHandle, backend: LightBackend, device_id: &str, kelvin: u16, brightness: u8) -> Result<(), String> {
+    if guard_temperature(app, backend, device_id, kelvin, brightness) < brightness {
+        return Err("Color temperature change held back by the photosensitivity limiter".to_string());
+    }
+    Ok(())
+}
+
+/// Guard a brightness-only change (0-100), judged as neutral white
+pub fn guard_brightness(app: &AppHandle, backend: LightBackend, device_id: &str, brightness: u8) -> u8 {
+    guard_temperature(app, backend, device_id, NEUTRAL_WHITE_KELVIN, brightness)
+}
+
+/// Guard a power switch: refused while the target is at its flash rate
+pub fn guard_power(app: &AppHandle, backend: LightBackend, device_id: &str) -> Result<(), String> {
+    let layout = app.state::<RoomLayoutState>().snapshot();
+    if app
+        .state::<SafetyState>()
+        .allow_switch(&LightTarget::device(backend, device_id), &layout)
+    {
+        Ok(())
+    } else {
+        Err("Power change held back by the photosensitivity limiter".to_string())
+    }
+}
+
+/// Send already processed output straight to the backends (no caps,
+/// limiter or calibration)
+fn dispatch(app: &AppHandle, output: CalibratedOutput) -> FlushReport {
+    let mut report = FlushReport::default();
+    let CalibratedOutput { frames, temperatures } = output;
+
+    for command in temperatures {
+        let device = command.target.device_id.clone();
+        let label = format!("{:?} {}", command.target.backend, device);
+        let result = match command.target.backend {
+            LightBackend::Govee => {
+                govee::set_color_temperature(&app.state(), &device, command.kelvin, command.brightness)
+            }
+            LightBackend::Yeelight => yeelight::set_ct(&app.state(), &device, command.kelvin, 0)
+                .and_then(|()| yeelight::set_bright(&app.state(), &device, command.brightness, 0)),
+            LightBackend::Kasa => {
+                kasa::set_bulb_color_temp(&app.state(), &device, command.kelvin, command.brightness, 0)
+            }
+            LightBackend::Zigbee2Mqtt => {
+                let light = mqtt::Zigbee2MqttLightCommand {
+                    on: None,
+                    color: None,
+                    brightness: Some(command.brightness),
+                    color_temperature: Some(command.kelvin),
+                    transition_ms: None,
+                };
+                mqtt::set_zigbee2mqtt_light(&app.state(), &device, &light)
+            }
+            _ => Err("Color temperature is not supported".to_string()),
+        };
+        report.record(&label, result);
+    }
+
+    // Group by (backend, device) so each device gets one write per flush
+    let mut devices: Vec<((LightBackend, &str), Vec<&TargetFrame>)> = Vec::new();
+    for frame in frames.iter().filter(|frame| !frame.colors.is_empty()) {
+        if frame.target.segment.is_some_and(|segment| segment > MAX_SEGMENT) {
+            report.errors.push(format!(
+                "{:?} {}: segment index above {}",
+                frame.target.backend, frame.target.device_id, MAX_SEGMENT
+            ));
+            continue;
+        }
+
+        let key = (frame.target.backend, frame.target.device_id.as_str());
+        match devices.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, group)) => group.push(frame),
+            None => devices.push((key, vec![frame])),
+        }
+    }
+
+    let mut hue_colors = Vec::new();
+
+    for ((backend, device_id), group) in devices {
+        let device = device_id.to_string();
+        let label = format!("{:?} {}", backend, device_id);
+        let whole = group.iter().find(|frame| frame.target.segment.is_none());
+
+        match backend {
+            LightBackend::Govee => {
+                let segments = segment_colors(&group);
+                let result = match (whole, segments.is_empty()) {
+                    (Some(frame), true) if frame.colors.len() == 1 => {
+                        govee::set_color(&app.state(), &device, frame.colors[0].clone())
+                    }
+                    (Some(frame), true) => govee::set_segment_colors(&app.state(), &device, frame.colors.clone()),
+                    _ => govee::set_segment_colors(&app.state(), &device, segments),
+                };
+                report.record(&label, result);
+            }
+            LightBackend::Wled => {
+                let result = match whole {
+                    Some(frame) => app.state::<wled::WledState>().send(&device, &frame.colors),
+                    None => app.state::<wled::WledState>().send(&device, &segment_colors(&group)),
+                };
+                report.record(&label, result);
+            }
+            LightBackend::Yeelight => {
+                let color = average_color(&group[0].colors);
+                report.record(&label, yeelight::set_rgb(&app.state(), &device, color, 0));
+            }
+            LightBackend::Kasa => {
+                let color = average_color(&group[0].colors);
+                report.record(&label, kasa::set_bulb_color(&app.state(), &device, color, 0));
+            }
+            LightBackend::Zigbee2Mqtt => {
+                let command = mqtt::Zigbee2MqttLightCommand {
+                    on: None,
+                    color: Some(average_color(&group[0].colors)),
+                    brightness: None,
+                    color_temperature: None,
+                    transition_ms: None,
+                };
+                report.record(&label, mqtt::set_zigbee2mqtt_light(&app.state(), &device, &command));
+            }
+            LightBackend::Hue => {
+                // All channels share the active entertainment stream
+                for frame in &group {
+                    match frame.target.segment.and_then(|s| u8::try_from(s).ok()) {
+                        Some(channel_id) => hue_colors.push(hue::HueChannelColor {
+                            channel_id,
+                            color: average_color(&frame.colors),
+                        }),
+                        None => report.errors.push(format!("{}: Hue targets need a channel segment", label)),
+                    }
+                }
+            }
+            LightBackend::Nanoleaf => {
+                let colors = group
+                    .iter()
+                    .filter_map(|frame| {
+                        let panel_id = u16::try_from(frame.target.segment?).ok()?;
+                        Some(nanoleaf::NanoleafPanelColor {
+                            panel_id,
+                            color: average_color(&frame.colors),
+                            transition_time: 0,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                report.record(&label, nanoleaf::send_panel_colors(&app.state(), &device, &colors));
+            }
+            LightBackend::OpenRgb => {
+                let Ok(controller) = device_id.parse::<u32>() else {
+                    report.errors.push(format!("{}: invalid controller index", label));
+                    continue;
+                };
+                for frame in &group {
+                    let result = match frame.target.segment {
+                        Some(zone) => {
+                            openrgb::set_zone_led_colors(&app.state(), controller, zone, &frame.colors)
+                        }
+                        None => openrgb::set_led_colors(&app.state(), controller, &frame.colors),
+                    };
+                    report.record(&label, result);
+                }
+            }
+        }
+    }
+
+    if !hue_colors.is_empty() {
+        let count = hue_colors.len();
+        match hue::send_colors(&app.state(), hue_colors) {
+            Ok(()) => report.sent += count,
+            Err(e) => report.errors.push(format!("Hue: {}", e)),
+        }
+    }
+
+    report
+}
+
+/// Lay out per-segment frames as one color per segment index (indices
+/// above `MAX_SEGMENT` are ignored)
+fn segment_colors(group: &[&TargetFrame]) -> Vec<RGBColor> {
+    let count = group
+        .iter()
+        .filter_map(|frame| frame.target.segment)
+        .filter(|segment| *segment <= MAX_SEGMENT)
+        .map(|segment| segment as usize + 1)
+        .max()
+        .unwrap_or(0);
+
+    let mut colors = vec![RGBColor { r: 0, g: 0, b: 0 }; count];
+    for frame in group {
+        if let Some(segment) = frame.target.segment.filter(|segment| *segment <= MAX_SEGMENT) {
+            colors[segment as usize] = average_color(&frame.colors);
+        }
+    }
+    colors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safety::{SafetyConfig, SafetySettings};
+
+    fn target() -> LightTarget {
+        LightTarget::device(LightBackend::Govee, "test")
+    }
+
+    /// Full swings between consecutive levels
+    fn swings(levels: &[u8], threshold: u8) -> usize {
+        levels.windows(2).filter(|pair| pair[0].abs_diff(pair[1]) > threshold).count()
+    }
+
+    fn allowed_transitions() -> usize {
+        (SafetyConfig::default().max_flashes_per_second * 2.0) as usize
+    }
+
+    #[test]
+    fn replayed_color_strobe_is_limited() {
+        let safety = SafetyState::with_settings(SafetySettings::default());
+        let layout = RoomLayout::default();
+
+        let levels: Vec<u8> = (0..20)
+            .map(|i| {
+                let level = if i % 2 == 0 { 0 } else { 255 };
+                let output = CalibratedOutput {
+                    frames: vec![TargetFrame {
+                        target: target(),
+                        colors: vec![RGBColor { r: level, g: level, b: level }],
+                    }],
+                    temperatures: Vec::new(),
+                };
+                limit_output(&safety, &layout, output).frames[0].colors[0].r
+            })
+            .collect();
+
+        assert!(swings(&levels, 128) <= allowed_transitions(), "{:?}", levels);
+    }
+
+    #[test]
+    fn replayed_temperature_strobe_is_limited() {
+        let safety = SafetyState::with_settings(SafetySettings::default());
+        let layout = RoomLayout::default();
+
+        let levels: Vec<u8> = (0..20)
+            .map(|i| {
+                let output = CalibratedOutput {
+                    frames: Vec::new(),
+                    temperatures: vec![TemperatureCommand {
+                        target: target(),
+                        kelvin: 4000,
+                        brightness: if i % 2 == 0 { 0 } else { 100 },
+                    }],
+                };
+                limit_output(&safety, &layout, output).temperatures[0].brightness
+            })
+            .collect();
+
+        assert!(swings(&levels, 50) <= allowed_transitions(), "{:?}", levels);
+    }
+}
@@ -0,0 +1,820 @@
+// MQTT Bridge Module
+// Publishes audio features, now-playing and light sync state to an MQTT
+// broker, announces them through Home Assistant MQTT discovery, and drives
+// Zigbee2MQTT lights as an output backend.
+
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS, RecvTimeoutError, Transport};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+
+use crate::credential_vault::{vault, CredentialNamespace};
+use crate::govee::RGBColor;
+use crate::lighting::{self, LightBackend};
+
+// Credential vault entry holding the broker password
+const PASSWORD_SECRET: &str = "password";
+
+// How often a connected event loop checks whether it should stop
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// How long a stopping event loop keeps sending queued requests (the offline
+// message and the disconnect) before giving up on them
+const STOP_GRACE: Duration = Duration::from_secs(1);
+
+/// Broker connection settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    pub username: Option<String>,
+    /// Leave empty to use the password stored in the credential vault
+    pub password: Option<String>,
+    #[serde(rename = "useTls", default)]
+    pub use_tls: bool,
+    /// PEM CA certificate for brokers with a private CA
+    #[serde(rename = "caCertificate", default)]
+    pub ca_certificate: Option<String>,
+    /// Root topic for musicViz state, e.g. "musicviz/living_room"
+    #[serde(rename = "baseTopic")]
+    pub base_topic: String,
+    #[serde(rename = "discoveryPrefix", default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+    #[serde(rename = "zigbee2mqttTopic", default = "default_zigbee2mqtt_topic")]
+    pub zigbee2mqtt_topic: String,
+    /// Minimum interval between audio feature messages
+    #[serde(rename = "featureIntervalMs", default = "default_feature_interval")]
+    pub feature_interval_ms: u64,
+}
+
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+fn default_zigbee2mqtt_topic() -> String {
+    "zigbee2mqtt".to_string()
+}
+
+fn default_feature_interval() -> u64 {
+    200
+}
+
+/// Connection status reported to the frontend
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MqttStatus {
+    pub connected: bool,
+    pub broker: Option<String>,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+}
+
+/// Zigbee2MQTT light command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Zigbee2MqttLightCommand {
+    pub on: Option<bool>,
+    pub color: Option<RGBColor>,
+    /// Brightness 0-100, scaled to Zigbee's 0-254
+    pub brightness: Option<u8>,
+    /// White color temperature in Kelvin, sent as mireds
+    #[serde(rename = "colorTemperature", default)]
+    pub color_temperature: Option<u16>,
+    #[serde(rename = "transitionMs")]
+    pub transition_ms: Option<u32>,
+}
+
+/// Open broker session
+struct MqttSession {
+    client: Client,
+    config: MqttConfig,
+    event_loop: JoinHandle<()>,
+    running: Arc<AtomicBool>,
+    last_features: Option<Instant>,
+}
+
+/// MQTT manager state for Tauri
+#[derive(Default)]
+pub struct MqttState {
+    session: Mutex<Option<MqttSession>>,
+    status: Arc<Mutex<MqttStatus>>,
+}
+
+impl MqttConfig {
+    fn topic(&self, suffix: &str) -> String {
+        format!("{}/{}", self.base_topic.trim_end_matches('/'), suffix)
+    }
+
+    /// Node id used for discovery topics and unique ids
+    fn node_id(&self) -> String {
+        self.client_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect()
+    }
+}
+
+/// Home Assistant discovery payloads for the musicViz entities
+fn discovery_messages(config: &MqttConfig) -> Vec<(String, serde_json::Value)> {
+    let node_id = config.node_id();
+    let device = serde_json::json!({
+        "identifiers": [format!("musicviz_{}", node_id)],
+        "name": "musicViz",
+        "manufacturer": "musicViz",
+        "model": "musicViz",
+    });
+    let availability_topic = config.topic("availability");
+
+    let entity = |component: &str, object_id: &str, name: &str, state_topic: String, extra: serde_json::Value| {
+        let mut payload = serde_json::json!({
+            "name": name,
+            "unique_id": format!("musicviz_{}_{}", node_id, object_id),
+            "state_topic": state_topic,
+            "availability_topic": availability_topic,
+            "device": device,
+        });
+        if let (Some(payload), Some(extra)) = (payload.as_object_mut(), extra.as_object()) {
+            payload.extend(extra.clone());
+        }
+        (
+            format!("{}/{}/{}/{}/config", config.discovery_prefix, component, node_id, object_id),
+            payload,
+        )
+    };
+
+    vec![
+        entity(
+            "sensor",
+            "energy",
+            "Audio energy",
+            config.topic("audio"),
+            serde_json::json!({ "value_template": "{{ value_json.energy }}", "state_class": "measurement" }),
+        ),
+        entity(
+            "sensor",
+            "bass",
+            "Bass level",
+            config.topic("audio"),
+            serde_json::json!({ "value_template": "{{ value_json.bass }}", "state_class": "measurement" }),
+        ),
+        entity(
+            "sensor",
+            "tempo",
+            "Tempo",
+            config.topic("audio"),
+            serde_json::json!({
+                "value_template": "{{ value_json.tempo }}",
+                "unit_of_measurement": "BPM",
+                "state_class": "measurement",
+            }),
+        ),
+        entity(
+            "sensor",
+            "now_playing",
+            "Now playing",
+            config.topic("now_playing"),
+            serde_json::json!({
+                "value_template": "{{ value_json.title }}",
+                "json_attributes_topic": config.topic("now_playing"),
+                "icon": "mdi:music",
+            }),
+        ),
+        entity(
+            "binary_sensor",
+            "light_sync",
+            "Light sync",
+            config.topic("sync"),
+            serde_json::json!({
+                "value_template": "{{ 'ON' if value_json.enabled else 'OFF' }}",
+                "json_attributes_topic": config.topic("sync"),
+            }),
+        ),
+    ]
+}
+
+/// Drive the connection; announces discovery on every (re)connect. Stops
+/// once `running` is cleared, whether or not the DISCONNECT went out.
+fn run_event_loop(
+    mut connection: rumqttc::Connection,
+    client: Client,
+    config: MqttConfig,
+    status: Arc<Mutex<MqttStatus>>,
+    running: Arc<AtomicBool>,
+) {
+    let mut connected = false;
+    let mut stop_deadline = None;
+
+    loop {
+        if !running.load(Ordering::SeqCst) {
+            let deadline = *stop_deadline.get_or_insert_with(|| Instant::now() + STOP_GRACE);
+            if !connected || Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        // Poll in short slices while connected so a stop is noticed even when
+        // the disconnect request could not be queued. Connecting is bounded by
+        // the connection timeout and must not be cut short.
+        let event = if connected {
+            match connection.recv_timeout(STOP_POLL_INTERVAL) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match connection.recv() {
+                Ok(event) => event,
+                Err(_) => break,
+            }
+        };
+
+        match event {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                println!("MQTT connected to {}:{}", config.host, config.port);
+                connected = true;
+                {
+                    let mut status = status.lock().unwrap();
+                    status.connected = true;
+                    status.last_error = None;
+                }
+
+                let _ = client.try_publish(config.topic("availability"), QoS::AtLeastOnce, true, "online");
+                for (topic, payload) in discovery_messages(&config) {
+                    let _ = client.try_publish(topic, QoS::AtLeastOnce, true, payload.to_string());
+                }
+            }
+            Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)) => break,
+            Ok(_) => {}
+            Err(e) => {
+                println!("MQTT connection error: {}", e);
+                connected = false;
+                {
+                    let mut status = status.lock().unwrap();
+                    status.connected = false;
+                    status.last_error = Some(e.to_string());
+                }
+
+                // Without a connection the disconnect request never goes out
+                if !running.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                // Back off before the next iteration reconnects
+                std::thread::sleep(Duration::from_secs(2));
+            }
+        }
+    }
+
+    status.lock().unwrap().connected = false;
+    println!("MQTT event loop stopped");
+}
+
+/// Connect to a broker
+#[tauri::command]
+pub fn mqtt_connect(config: MqttConfig, state: State<MqttState>) -> Result<(), String> {
+    mqtt_disconnect(state.clone())?;
+
+    let session = open_session(config, &state.status)?;
+    *state.session.lock().unwrap() = Some(session);
+    Ok(())
+}
+
+fn open_session(config: MqttConfig, status: &Arc<Mutex<MqttStatus>>) -> Result<MqttSession, String> {
+    let mut options = MqttOptions::new(config.client_id.clone(), config.host.clone(), config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    options.set_last_will(LastWill::new(
+        config.topic("availability"),
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+
+    if let Some(username) = &config.username {
+        let password = match &config.password {
+            Some(password) => password.clone(),
+            None => vault()
+                .get(CredentialNamespace::Mqtt, PASSWORD_SECRET)?
+                .unwrap_or_default(),
+        };
+        options.set_credentials(username.clone(), password);
+    }
+
+    if config.use_tls {
+        let transport = match &config.ca_certificate {
+            Some(ca) => Transport::tls(ca.as_bytes().to_vec(), None, None),
+            None => Transport::tls_with_default_config(),
+        };
+        options.set_transport(transport);
+    }
+
+    let (client, connection) = Client::new(options, 64);
+
+    *status.lock().unwrap() = MqttStatus {
+        connected: false,
+        broker: Some(format!("{}:{}", config.host, config.port)),
+        last_error: None,
+    };
+
+    let loop_client = client.clone();
+    let loop_config = config.clone();
+    let loop_status = status.clone();
+    let running = Arc::new(AtomicBool::new(true));
+    let loop_running = running.clone();
+    let event_loop = std::thread::Builder::new()
+        .name("mqtt-event-loop".to_string())
+        .spawn(move || run_event_loop(connection, loop_client, loop_config, loop_status, loop_running))
+        .map_err(|e| format!("Failed to spawn MQTT event loop: {}", e))?;
+
+    Ok(MqttSession {
+        client,
+        config,
+        event_loop,
+        running,
+        last_features: None,
+    })
+}
+
+/// Disconnect from the broker
+#[tauri::command]
+pub fn mqtt_disconnect(state: State<MqttState>) -> Result<(), String> {
+    let Some(session) = state.session.lock().unwrap().take() else {
+        return Ok(());
+    };
+
+    close_session(session);
+    println!("MQTT disconnected");
+    Ok(())
+}
+
+fn close_session(session: MqttSession) {
+    // Publish offline explicitly; the last will only fires on unclean disconnects
+    let _ = session
+        .client
+        .try_publish(session.config.topic("availability"), QoS::AtLeastOnce, true, "offline");
+    if let Err(e) = session.client.try_disconnect() {
+        println!("MQTT disconnect request not queued: {}", e);
+    }
+
+    // The event loop stops on this flag even if the request above failed
+    session.running.store(false, Ordering::SeqCst);
+    let _ = session.event_loop.join();
+}
+
+/// Get broker connection status
+#[tauri::command]
+pub fn mqtt_get_status(state: State<MqttState>) -> MqttStatus {
+    state.status.lock().unwrap().clone()
+}
+
+fn publish(
+    state: &MqttState,
+    topic: impl FnOnce(&MqttConfig) -> String,
+    payload: String,
+    retain: bool,
+) -> Result<(), String> {
+    let session = state.session.lock().unwrap();
+    let session = session.as_ref().ok_or_else(|| "MQTT is not connected".to_string())?;
+
+    session
+        .client
+        .try_publish(topic(&session.config), QoS::AtMostOnce, retain, payload)
+        .map_err(|e| format!("Failed to publish: {}", e))
+}
+
+/// Publish audio features (throttled to `featureIntervalMs`)
+#[tauri::command]
+pub fn mqtt_publish_audio_features(features: serde_json::Value, state: State<MqttState>) -> Result<(), String> {
+    publish_audio_features(&state, &features)
+}
+
+fn publish_audio_features(state: &MqttState, features: &serde_json::Value) -> Result<(), String> {
+    {
+        let mut session = state.session.lock().unwrap();
+        let session = session.as_mut().ok_or_else(|| "MQTT is not connected".to_string())?;
+
+        let interval = Duration::from_millis(session.config.feature_interval_ms);
+        if session.last_features.is_some_and(|t| t.elapsed() < interval) {
+            return Ok(());
+        }
+        session.last_features = Some(Instant::now());
+    }
+
+    publish(state, |c| c.topic("audio"), features.to_string(), false)
+}
+
+/// Publish the now-playing track
+#[tauri::command]
+pub fn mqtt_publish_now_playing(track: serde_json::Value, state: State<MqttState>) -> Result<(), String> {
+    publish_now_playing(&state, &track)
+}
+
+fn publish_now_playing(state: &MqttState, track: &serde_json::Value) -> Result<(), String> {
+    publish(state, |c| c.topic("now_playing"), track.to_string(), true)
+}
+
+/// Publish the light sync state
+#[tauri::command]
+pub fn mqtt_publish_sync_state(sync_state: serde_json::Value, state: State<MqttState>) -> Result<(), String> {
+    publish_sync_state(&state, &sync_state)
+}
+
+fn publish_sync_state(state: &MqttState, sync_state: &serde_json::Value) -> Result<(), String> {
+    publish(state, |c| c.topic("sync"), sync_state.to_string(), true)
+}
+
+/// Build a Zigbee2MQTT `/set` payload
+fn zigbee2mqtt_payload(command: &Zigbee2MqttLightCommand) -> serde_json::Value {
+    let mut payload = serde_json::Map::new();
+
+    if let Some(on) = command.on {
+        payload.insert("state".to_string(), serde_json::json!(if on { "ON" } else { "OFF" }));
+    }
+    if let Some(color) = &command.color {
+        payload.insert(
+            "color".to_string(),
+            serde_json::json!({ "r": color.r, "g": color.g, "b": color.b }),
+        );
+    }
+    if let Some(kelvin) = command.color_temperature {
+        let mireds = 1_000_000 / kelvin.max(1000) as u32;
+        payload.insert("color_temp".to_string(), serde_json::json!(mireds));
+    }
+    if let Some(brightness) = command.brightness {
+        let level = (brightness.min(100) as u32 * 254 / 100) as u8;
+        payload.insert("brightness".to_string(), serde_json::json!(level));
+    }
+    if let Some(transition_ms) = command.transition_ms {
+        // Zigbee2MQTT takes transitions in seconds
+        payload.insert("transition".to_string(), serde_json::json!(transition_ms as f64 / 1000.0));
+    }
+
+    serde_json::Value::Object(payload)
+}
+
+/// Drive a Zigbee2MQTT light via `<zigbee2mqtt>/<name>/set` (through
+/// schedule caps and the safety limiter)
+#[tauri::command]
+pub fn mqtt_set_zigbee2mqtt_light(
+    name: String,
+    mut command: Zigbee2MqttLightCommand,
+    app: AppHandle,
+    state: State<MqttState>,
+) -> Result<(), String> {
+    let backend = LightBackend::Zigbee2Mqtt;
+    if command.on.is_some() {
+        lighting::guard_power(&app, backend, &name)?;
+    }
+    match (command.color.take(), command.color_temperature, command.brightness) {
+        (Some(color), _, Some(brightness)) => {
+            // The light shows the hue of `color` at `brightness`
+            let peak = color.r.max(color.g).max(color.b).max(1) as f32;
+            let scale = |v: u8| (v as f32 * 255.0 / peak * brightness.min(100) as f32 / 100.0).round() as u8;
+            let rendered = RGBColor {
+                r: scale(color.r),
+                g: scale(color.g),
+                b: scale(color.b),
+            };
+            let guarded = lighting::guard_color(&app, backend, &name, rendered);
+            let peak = guarded.r.max(guarded.g).max(guarded.b);
+            command.brightness = Some((peak as f32 * 100.0 / 255.0).round() as u8);
+            command.color = Some(guarded);
+        }
+        (Some(color), _, None) => command.color = Some(lighting::guard_color(&app, backend, &name, color)),
+        (None, Some(kelvin), Some(brightness)) => {
+            command.brightness = Some(lighting::guard_temperature(&app, backend, &name, kelvin, brightness));
+        }
+        (None, Some(kelvin), None) => lighting::guard_white_point(&app, backend, &name, kelvin, 100)?,
+        (None, None, Some(brightness)) => {
+            command.brightness = Some(lighting::guard_brightness(&app, backend, &name, brightness));
+        }
+        (None, None, None) => {}
+    }
+
+    set_zigbee2mqtt_light(&state, &name, &command)
+}
+
+/// Publish a light command without guarding (callers run it through the limiter)
+pub fn set_zigbee2mqtt_light(state: &MqttState, name: &str, command: &Zigbee2MqttLightCommand) -> Result<(), String> {
+    publish(
+        state,
+        |c| format!("{}/{}/set", c.zigbee2mqtt_topic.trim_end_matches('/'), name),
+        zigbee2mqtt_payload(command).to_string(),
+        false,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    const CONNECT: u8 = 1;
+    const PUBLISH: u8 = 3;
+    const DISCONNECT: u8 = 14;
+
+    /// Message received by the stand-in broker
+    #[derive(Debug, Clone)]
+    struct Published {
+        topic: String,
+        payload: String,
+        retain: bool,
+    }
+
+    /// Local broker that accepts the connection and records what it receives
+    struct StandInBroker {
+        port: u16,
+        packets: Arc<Mutex<Vec<u8>>>,
+        published: Arc<Mutex<Vec<Published>>>,
+    }
+
+    impl StandInBroker {
+        /// Wait for a message on `topic` and return the latest one
+        fn wait_for(&self, topic: &str) -> Published {
+            let deadline = Instant::now() + Duration::from_secs(2);
+            loop {
+                let found = self.published.lock().unwrap().iter().rev().find(|p| p.topic == topic).cloned();
+                if let Some(message) = found {
+                    return message;
+                }
+                assert!(Instant::now() < deadline, "broker never saw a publish on {}", topic);
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        }
+
+        fn count(&self, topic: &str) -> usize {
+            self.published.lock().unwrap().iter().filter(|p| p.topic == topic).count()
+        }
+    }
+
+    fn parse_publish(flags: u8, body: &[u8]) -> Option<Published> {
+        let topic_len = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+        let topic = std::str::from_utf8(body.get(2..2 + topic_len)?).ok()?;
+        // QoS 1 and 2 publishes carry a packet id before the payload
+        let payload_start = 2 + topic_len + if flags & 0x06 != 0 { 2 } else { 0 };
+        Some(Published {
+            topic: topic.to_string(),
+            payload: String::from_utf8_lossy(body.get(payload_start..)?).into_owned(),
+            retain: flags & 0x01 != 0,
+        })
+    }
+
+    fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+        let mut header = [0u8; 1];
+        stream.read_exact(&mut header).ok()?;
+
+        let mut length = 0usize;
+        for shift in (0..28).step_by(7) {
+            let mut byte = [0u8; 1];
+            stream.read_exact(&mut byte).ok()?;
+            length |= ((byte[0] & 0x7f) as usize) << shift;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+
+        let mut body = vec![0u8; length];
+        stream.read_exact(&mut body).ok()?;
+        Some((header[0], body))
+    }
+
+    /// Broker that acknowledges the connection but never acknowledges
+    /// publishes or answers pings
+    fn stand_in_broker() -> StandInBroker {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let packets = Arc::new(Mutex::new(Vec::new()));
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let (recorded, received) = (packets.clone(), published.clone());
+
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                while let Some((header, body)) = read_packet(&mut stream) {
+                    let packet_type = header >> 4;
+                    recorded.lock().unwrap().push(packet_type);
+                    match packet_type {
+                        CONNECT => {
+                            let _ = stream.write_all(&[0x20, 0x02, 0x00, 0x00]);
+                        }
+                        PUBLISH => received.lock().unwrap().extend(parse_publish(header & 0x0f, &body)),
+                        _ => {}
+                    }
+                }
+            }
+        });
+
+        StandInBroker {
+            port,
+            packets,
+            published,
+        }
+    }
+
+    fn test_config(port: u16) -> MqttConfig {
+        MqttConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            client_id: "musicviz-test".to_string(),
+            username: None,
+            password: None,
+            use_tls: false,
+            ca_certificate: None,
+            base_topic: "musicviz/test".to_string(),
+            discovery_prefix: default_discovery_prefix(),
+            zigbee2mqtt_topic: default_zigbee2mqtt_topic(),
+            feature_interval_ms: default_feature_interval(),
+        }
+    }
+
+    fn connect(port: u16) -> (MqttSession, Arc<Mutex<MqttStatus>>) {
+        let status = Arc::new(Mutex::new(MqttStatus::default()));
+        let session = open_session(test_config(port), &status).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !status.lock().unwrap().connected {
+            assert!(Instant::now() < deadline, "stand-in broker never connected");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        (session, status)
+    }
+
+    #[test]
+    fn disconnect_reaches_the_broker() {
+        let broker = stand_in_broker();
+        let (session, status) = connect(broker.port);
+
+        close_session(session);
+        assert!(!status.lock().unwrap().connected);
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !broker.packets.lock().unwrap().contains(&DISCONNECT) {
+            assert!(Instant::now() < deadline, "broker never saw DISCONNECT");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn disconnect_does_not_wait_for_a_full_request_queue() {
+        let broker = stand_in_broker();
+        let (session, status) = connect(broker.port);
+
+        // Unacknowledged publishes fill the inflight window, then the queue,
+        // so the disconnect request can't be queued
+        let full = (0..10_000).any(|i| {
+            session
+                .client
+                .try_publish("musicviz/test/flood", QoS::AtLeastOnce, false, i.to_string())
+                .is_err()
+        });
+        assert!(full);
+        assert!(session.client.try_disconnect().is_err());
+
+        let started = Instant::now();
+        close_session(session);
+        assert!(started.elapsed() < Duration::from_secs(3), "took {:?}", started.elapsed());
+        assert!(!status.lock().unwrap().connected);
+    }
+
+    /// MqttState holding a session connected to the stand-in broker
+    fn connected_state(broker: &StandInBroker) -> MqttState {
+        let (session, status) = connect(broker.port);
+        MqttState {
+            session: Mutex::new(Some(session)),
+            status,
+        }
+    }
+
+    #[test]
+    fn connect_announces_availability_and_discovery() {
+        let broker = stand_in_broker();
+        let state = connected_state(&broker);
+
+        let availability = broker.wait_for("musicviz/test/availability");
+        assert_eq!(availability.payload, "online");
+        assert!(availability.retain);
+
+        let energy = broker.wait_for("homeassistant/sensor/musicviz_test/energy/config");
+        assert!(energy.retain);
+        let config: serde_json::Value = serde_json::from_str(&energy.payload).unwrap();
+        assert_eq!(config["unique_id"], "musicviz_musicviz_test_energy");
+        assert_eq!(config["state_topic"], "musicviz/test/audio");
+        assert_eq!(config["availability_topic"], "musicviz/test/availability");
+        assert_eq!(config["value_template"], "{{ value_json.energy }}");
+        assert_eq!(config["device"]["identifiers"][0], "musicviz_musicviz_test");
+
+        for (topic, payload) in discovery_messages(&test_config(broker.port)) {
+            let message = broker.wait_for(&topic);
+            assert_eq!(serde_json::from_str::<serde_json::Value>(&message.payload).unwrap(), payload);
+        }
+        let sync = broker.wait_for("homeassistant/binary_sensor/musicviz_test/light_sync/config");
+        assert!(sync.payload.contains("value_json.enabled"));
+
+        close_session(state.session.lock().unwrap().take().unwrap());
+        assert_eq!(broker.wait_for("musicviz/test/availability").payload, "offline");
+    }
+
+    #[test]
+    fn state_is_published_under_the_base_topic() {
+        let broker = stand_in_broker();
+        let state = connected_state(&broker);
+
+        let features = serde_json::json!({ "energy": 0.5, "bass": 0.8, "tempo": 120 });
+        publish_audio_features(&state, &features).unwrap();
+        let audio = broker.wait_for("musicviz/test/audio");
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&audio.payload).unwrap(), features);
+        assert!(!audio.retain);
+
+        // Features arriving within the interval are dropped
+        publish_audio_features(&state, &serde_json::json!({ "energy": 0.9 })).unwrap();
+
+        let track = serde_json::json!({ "title": "Song", "artist": "Artist" });
+        publish_now_playing(&state, &track).unwrap();
+        let now_playing = broker.wait_for("musicviz/test/now_playing");
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&now_playing.payload).unwrap(), track);
+        assert!(now_playing.retain);
+
+        publish_sync_state(&state, &serde_json::json!({ "enabled": true })).unwrap();
+        let sync = broker.wait_for("musicviz/test/sync");
+        assert_eq!(sync.payload, r#"{"enabled":true}"#);
+        assert!(sync.retain);
+
+        assert_eq!(broker.count("musicviz/test/audio"), 1);
+        close_session(state.session.lock().unwrap().take().unwrap());
+    }
+
+    #[test]
+    fn publishing_without_a_session_fails() {
+        let state = MqttState::default();
+
+        assert_eq!(
+            publish_now_playing(&state, &serde_json::json!({})).unwrap_err(),
+            "MQTT is not connected"
+        );
+        assert!(publish_audio_features(&state, &serde_json::json!({})).is_err());
+    }
+
+    #[test]
+    fn zigbee2mqtt_payload_converts_units() {
+        let payload = zigbee2mqtt_payload(&Zigbee2MqttLightCommand {
+            on: Some(true),
+            color: Some(RGBColor { r: 255, g: 128, b: 0 }),
+            brightness: Some(50),
+            color_temperature: None,
+            transition_ms: Some(1500),
+        });
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "state": "ON",
+                "color": { "r": 255, "g": 128, "b": 0 },
+                "brightness": 127,
+                "transition": 1.5,
+            })
+        );
+
+        let white = zigbee2mqtt_payload(&Zigbee2MqttLightCommand {
+            on: Some(false),
+            color: None,
+            brightness: Some(150),
+            color_temperature: Some(2500),
+            transition_ms: None,
+        });
+        assert_eq!(white, serde_json::json!({ "state": "OFF", "color_temp": 400, "brightness": 254 }));
+
+        // Temperatures below 1000K are clamped instead of overflowing the mired range
+        let clamped = zigbee2mqtt_payload(&Zigbee2MqttLightCommand {
+            on: None,
+            color: None,
+            brightness: None,
+            color_temperature: Some(0),
+            transition_ms: None,
+        });
+        assert_eq!(clamped, serde_json::json!({ "color_temp": 1000 }));
+    }
+
+    #[test]
+    fn zigbee2mqtt_commands_go_to_the_device_set_topic() {
+        let broker = stand_in_broker();
+        let state = connected_state(&broker);
+
+        let command = Zigbee2MqttLightCommand {
+            on: None,
+            color: Some(RGBColor { r: 0, g: 0, b: 255 }),
+            brightness: None,
+            color_temperature: None,
+            transition_ms: Some(0),
+        };
+        set_zigbee2mqtt_light(&state, "Desk Lamp", &command).unwrap();
+
+        let set = broker.wait_for("zigbee2mqtt/Desk Lamp/set");
+        assert!(!set.retain);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&set.payload).unwrap(),
+            serde_json::json!({ "color": { "r": 0, "g": 0, "b": 255 }, "transition": 0.0 })
+        );
+        close_session(state.session.lock().unwrap().take().unwrap());
+    }
+}
@@ -0,0 +1,825 @@
+// Nanoleaf OpenAPI Module
+// Handles mDNS discovery, token pairing, panel layout lookup and per-panel
+// color streaming through External Control (extControl v2 over UDP). Panel
+// layouts can be placed in a room so spatial effects address each panel.
+
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+
+use crate::credential_vault::{vault, CredentialNamespace};
+use crate::govee::RGBColor;
+use crate::lighting::{self, LightBackend, LightTarget, TargetFrame};
+use crate::room_layout::{DevicePlacement, Orientation, RoomLayout, RoomLayoutState, Vec3};
+
+/// mDNS service type advertised by Nanoleaf controllers
+const NANOLEAF_SERVICE_TYPE: &str = "_nanoleafapi._tcp.local.";
+
+/// Default OpenAPI REST port
+const DEFAULT_API_PORT: u16 = 16021;
+
+/// UDP port used by extControl v2
+const EXT_CONTROL_PORT: u16 = 60222;
+
+/// Shape types that carry no LEDs (Rhythm module, controllers, connectors)
+const UNLIT_SHAPE_TYPES: [u16; 5] = [1, 12, 16, 19, 20];
+
+/// Nanoleaf controller information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NanoleafDevice {
+    pub id: String,
+    pub name: String,
+    pub model: String,
+    pub ip: String,
+    pub port: u16,
+    pub paired: bool,
+    pub streaming: bool,
+}
+
+/// Panel layout with positions in Nanoleaf layout units
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NanoleafLayout {
+    #[serde(rename = "globalOrientation")]
+    pub global_orientation: u16,
+    #[serde(rename = "sideLength")]
+    pub side_length: u16,
+    pub panels: Vec<NanoleafPanel>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NanoleafPanel {
+    #[serde(rename = "panelId")]
+    pub panel_id: u16,
+    pub x: i32,
+    pub y: i32,
+    pub orientation: u16,
+    #[serde(rename = "shapeType")]
+    pub shape_type: u16,
+    pub lit: bool,
+}
+
+/// Color for one panel; transition time is in 100ms steps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NanoleafPanelColor {
+    #[serde(rename = "panelId")]
+    pub panel_id: u16,
+    pub color: RGBColor,
+    #[serde(rename = "transitionTime", default)]
+    pub transition_time: u16,
+}
+
+/// OpenAPI panelLayout/layout response
+#[derive(Debug, Deserialize)]
+struct LayoutResponse {
+    #[serde(rename = "sideLength", default)]
+    side_length: u16,
+    #[serde(rename = "positionData", default)]
+    position_data: Vec<PositionData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PositionData {
+    #[serde(rename = "panelId")]
+    panel_id: u16,
+    x: i32,
+    y: i32,
+    o: u16,
+    #[serde(rename = "shapeType", default)]
+    shape_type: u16,
+}
+
+/// Open extControl session
+struct StreamSession {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    previous_effect: Option<String>,
+}
+
+/// Nanoleaf manager state for Tauri
+#[derive(Default)]
+pub struct NanoleafState {
+    devices: Arc<Mutex<HashMap<String, NanoleafDevice>>>,
+    layouts: Arc<Mutex<HashMap<String, NanoleafLayout>>>,
+    streams: Mutex<HashMap<String, StreamSession>>,
+}
+
+impl NanoleafState {
+    fn device(&self, device_id: &str) -> Result<NanoleafDevice, String> {
+        self.devices
+            .lock()
+            .unwrap()
+            .get(device_id)
+            .cloned()
+            .ok_or_else(|| format!("Nanoleaf {} not found", device_id))
+    }
+
+    fn set_streaming_flag(&self, device_id: &str, streaming: bool) {
+        if let Some(device) = self.devices.lock().unwrap().get_mut(device_id) {
+            device.streaming = streaming;
+        }
+    }
+}
+
+fn http_client() -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+fn api_url(device: &NanoleafDevice, token: &str, path: &str) -> String {
+    format!("http://{}:{}/api/v1/{}{}", device.ip, device.port, token, path)
+}
+
+fn token_name(device_id: &str) -> String {
+    device_id.to_lowercase().replace(':', "")
+}
+
+/// Save auth token to the credential vault
+fn save_token(device_id: &str, token: &str) -> Result<(), String> {
+    vault()
+        .set(CredentialNamespace::Nanoleaf, &token_name(device_id), token)
+        .map_err(|e| format!("Failed to save Nanoleaf token: {}", e))
+}
+
+/// Load auth token from the credential vault
+fn load_token(device_id: &str) -> Result<Option<String>, String> {
+    vault()
+        .get(CredentialNamespace::Nanoleaf, &token_name(device_id))
+        .map_err(|e| format!("Failed to load Nanoleaf token: {}", e))
+}
+
+/// Delete auth token from the credential vault
+fn delete_token(device_id: &str) -> Result<(), String> {
+    vault()
+        .delete(CredentialNamespace::Nanoleaf, &token_name(device_id))
+        .map_err(|e| format!("Failed to delete Nanoleaf token: {}", e))
+}
+
+fn require_token(device_id: &str) -> Result<String, String> {
+    load_token(device_id)?.ok_or_else(|| format!("Nanoleaf {} is not paired", device_id))
+}
+
+/// Discover Nanoleaf controllers via mDNS
+#[tauri::command]
+pub fn nanoleaf_discover_devices(timeout: u32, state: State<NanoleafState>) -> Result<Vec<NanoleafDevice>, String> {
+    println!("Starting Nanoleaf mDNS discovery for {} ms...", timeout);
+
+    let daemon = ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS daemon: {}", e))?;
+    let receiver = daemon
+        .browse(NANOLEAF_SERVICE_TYPE)
+        .map_err(|e| format!("Failed to browse for Nanoleaf devices: {}", e))?;
+
+    let mut found: HashMap<String, NanoleafDevice> = HashMap::new();
+    let deadline = Instant::now() + Duration::from_millis(timeout as u64);
+
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let event = match receiver.recv_timeout(remaining) {
+            Ok(event) => event,
+            Err(_) => break,
+        };
+
+        if let ServiceEvent::ServiceResolved(info) = event {
+            let Some(ip) = info.get_addresses_v4().into_iter().next() else {
+                continue;
+            };
+
+            // Instance name is the friendly name, e.g. "Shapes 4A2B._nanoleafapi._tcp.local."
+            let name = info
+                .get_fullname()
+                .split('.')
+                .next()
+                .unwrap_or("Nanoleaf")
+                .to_string();
+            let id = info
+                .get_property_val_str("id")
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| ip.to_string());
+
+            let device = NanoleafDevice {
+                paired: matches!(load_token(&id), Ok(Some(_))),
+                model: info.get_property_val_str("md").unwrap_or("Unknown").to_string(),
+                ip: ip.to_string(),
+                port: info.get_port(),
+                streaming: false,
+                name,
+                id,
+            };
+
+            println!("  ✓ Found Nanoleaf: {} ({}) at {}", device.name, device.model, device.ip);
+            found.insert(device.id.clone(), device);
+        }
+    }
+
+    let _ = daemon.shutdown();
+
+    let streaming: Vec<String> = state.streams.lock().unwrap().keys().cloned().collect();
+    let mut devices = state.devices.lock().unwrap();
+    for device in found.values_mut() {
+        device.streaming = streaming.contains(&device.id);
+        devices.insert(device.id.clone(), device.clone());
+    }
+
+    println!("Discovery complete: {} Nanoleaf devices found", found.len());
+    Ok(found.into_values().collect())
+}
+
+/// Add a controller by address ("ip" or "ip:port") when mDNS isn't available
+#[tauri::command]
+pub fn nanoleaf_add_device(address: String, state: State<NanoleafState>) -> Result<NanoleafDevice, String> {
+    let (ip, port) = match address.parse::<SocketAddr>() {
+        Ok(addr) => (addr.ip().to_string(), addr.port()),
+        Err(_) => (address.clone(), DEFAULT_API_PORT),
+    };
+
+    let device = NanoleafDevice {
+        id: ip.clone(),
+        name: format!("Nanoleaf {}", ip),
+        model: "Unknown".to_string(),
+        paired: matches!(load_token(&ip), Ok(Some(_))),
+        streaming: false,
+        ip,
+        port,
+    };
+
+    state
+        .devices
+        .lock()
+        .unwrap()
+        .insert(device.id.clone(), device.clone());
+    Ok(device)
+}
+
+/// Request an auth token; the power button must be held for 5-7 seconds first
+#[tauri::command]
+pub fn nanoleaf_pair_device(device_id: String, state: State<NanoleafState>) -> Result<NanoleafDevice, String> {
+    let device = state.device(&device_id)?;
+    let url = format!("http://{}:{}/api/v1/new", device.ip, device.port);
+
+    let response = http_client()?
+        .post(&url)
+        .send()
+        .map_err(|e| format!("Pairing request failed: {}", e))?;
+
+    if response.status() == reqwest::StatusCode::FORBIDDEN {
+        return Err("Hold the Nanoleaf power button for 5-7 seconds, then try again".to_string());
+    }
+
+    let body: serde_json::Value = response
+        .error_for_status()
+        .and_then(|r| r.json())
+        .map_err(|e| format!("Pairing failed: {}", e))?;
+
+    let token = body
+        .get("auth_token")
+        .and_then(|t| t.as_str())
+        .ok_or_else(|| "Pairing response did not include a token".to_string())?;
+
+    save_token(&device.id, token)?;
+
+    let mut devices = state.devices.lock().unwrap();
+    let stored = devices
+        .get_mut(&device.id)
+        .ok_or_else(|| format!("Nanoleaf {} not found", device.id))?;
+    stored.paired = true;
+
+    println!("Paired with Nanoleaf {}", device.id);
+    Ok(stored.clone())
+}
+
+/// Fetch panel positions and orientation
+#[tauri::command]
+pub fn nanoleaf_get_layout(device_id: String, state: State<NanoleafState>) -> Result<NanoleafLayout, String> {
+    fetch_layout(&state, &device_id)
+}
+
+fn fetch_layout(state: &NanoleafState, device_id: &str) -> Result<NanoleafLayout, String> {
+    let device = state.device(device_id)?;
+    let token = require_token(&device.id)?;
+    let client = http_client()?;
+
+    let layout: LayoutResponse = client
+        .get(api_url(&device, &token, "/panelLayout/layout"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .map_err(|e| format!("Failed to fetch panel layout: {}", e))?;
+
+    // Older firmware doesn't report orientation; treat it as unrotated
+    let global_orientation = client
+        .get(api_url(&device, &token, "/panelLayout/globalOrientation"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json::<serde_json::Value>())
+        .ok()
+        .and_then(|v| v.get("value").and_then(|o| o.as_u64()))
+        .unwrap_or(0) as u16;
+
+    let layout = NanoleafLayout {
+        global_orientation,
+        side_length: layout.side_length,
+        panels: layout
+            .position_data
+            .into_iter()
+            .map(|p| NanoleafPanel {
+                panel_id: p.panel_id,
+                x: p.x,
+                y: p.y,
+                orientation: p.o,
+                shape_type: p.shape_type,
+                lit: !UNLIT_SHAPE_TYPES.contains(&p.shape_type),
+            })
+            .collect(),
+    };
+
+    state
+        .layouts
+        .lock()
+        .unwrap()
+        .insert(device.id.clone(), layout.clone());
+
+    Ok(layout)
+}
+
+/// How a panel arrangement is mounted in a room
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PanelMount {
+    /// On a wall along the room's x axis (layout y is height)
+    Wall,
+    /// On the ceiling (layout y runs front to back)
+    Ceiling,
+}
+
+/// Where a panel arrangement hangs in a room
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PanelMounting {
+    #[serde(rename = "roomId")]
+    pub room_id: String,
+    /// Middle of the arrangement, in meters
+    pub center: Vec3,
+    /// Largest extent of the arrangement (width or height), in meters
+    pub size: f32,
+    pub mount: PanelMount,
+    /// Tags given to every panel
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Place every lit panel in a room so spatial effects color panels by where
+/// they hang
+#[tauri::command]
+pub fn nanoleaf_place_panels(
+    device_id: String,
+    mounting: PanelMounting,
+    state: State<NanoleafState>,
+    room_layout: State<RoomLayoutState>,
+) -> Result<RoomLayout, String> {
+    let cached = state.layouts.lock().unwrap().get(&device_id).cloned();
+    let layout = match cached {
+        Some(layout) => layout,
+        None => fetch_layout(&state, &device_id)?,
+    };
+
+    let placements = panel_placements(&device_id, &layout, &mounting);
+    room_layout.replace_device_placements(LightBackend::Nanoleaf, &device_id, placements)
+}
+
+/// Room placements for the lit panels of a layout, rotated by the global
+/// orientation and scaled to the mounting size
+fn panel_placements(device_id: &str, layout: &NanoleafLayout, mounting: &PanelMounting) -> Vec<DevicePlacement> {
+    let (sin, cos) = (layout.global_orientation as f32).to_radians().sin_cos();
+    let panels: Vec<(&NanoleafPanel, f32, f32)> = layout
+        .panels
+        .iter()
+        .filter(|panel| panel.lit)
+        .map(|panel| {
+            let (x, y) = (panel.x as f32, panel.y as f32);
+            (panel, x * cos - y * sin, x * sin + y * cos)
+        })
+        .collect();
+
+    let bounds = |coordinate: fn(&(&NanoleafPanel, f32, f32)) -> f32| {
+        panels
+            .iter()
+            .map(coordinate)
+            .fold((f32::MAX, f32::MIN), |(min, max), value| (min.min(value), max.max(value)))
+    };
+    let (min_x, max_x) = bounds(|panel| panel.1);
+    let (min_y, max_y) = bounds(|panel| panel.2);
+    let extent = (max_x - min_x).max(max_y - min_y);
+    let scale = if extent > 0.0 { mounting.size / extent } else { 0.0 };
+    let center = mounting.center;
+
+    panels
+        .iter()
+        .map(|(panel, x, y)| {
+            let dx = (x - (min_x + max_x) / 2.0) * scale;
+            let dy = (y - (min_y + max_y) / 2.0) * scale;
+            let rotation = ((panel.orientation + layout.global_orientation) % 360) as f32;
+            let (position, orientation) = match mounting.mount {
+                PanelMount::Wall => (
+                    Vec3 { x: center.x + dx, y: center.y, z: center.z + dy },
+                    Orientation { roll: rotation, ..Default::default() },
+                ),
+                PanelMount::Ceiling => (
+                    Vec3 { x: center.x + dx, y: center.y + dy, z: center.z },
+                    Orientation { yaw: rotation, ..Default::default() },
+                ),
+            };
+
+            DevicePlacement {
+                target: LightTarget {
+                    backend: LightBackend::Nanoleaf,
+                    device_id: device_id.to_string(),
+                    segment: Some(panel.panel_id as u32),
+                },
+                room_id: mounting.room_id.clone(),
+                position,
+                orientation,
+                tags: mounting.tags.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Enable External Control and open the UDP stream
+#[tauri::command]
+pub fn nanoleaf_start_streaming(device_id: String, state: State<NanoleafState>) -> Result<(), String> {
+    start_streaming(&state, &device_id)
+}
+
+fn start_streaming(state: &NanoleafState, device_id: &str) -> Result<(), String> {
+    if state.streams.lock().unwrap().contains_key(device_id) {
+        return Ok(());
+    }
+
+    let device = state.device(device_id)?;
+    let token = require_token(&device.id)?;
+    let client = http_client()?;
+
+    // Remember the running effect so it can be restored afterwards
+    let previous_effect = client
+        .get(api_url(&device, &token, "/effects/select"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json::<String>())
+        .ok();
+
+    client
+        .put(api_url(&device, &token, "/effects"))
+        .json(&serde_json::json!({
+            "write": {
+                "command": "display",
+                "animType": "extControl",
+                "extControlVersion": "v2"
+            }
+        }))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Failed to enable External Control: {}", e))?;
+
+    let addr: SocketAddr = format!("{}:{}", device.ip, EXT_CONTROL_PORT)
+        .parse()
+        .map_err(|e| format!("Invalid device address: {}", e))?;
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("Failed to create socket: {}", e))?;
+
+    state.streams.lock().unwrap().insert(
+        device.id.clone(),
+        StreamSession {
+            socket,
+            addr,
+            previous_effect,
+        },
+    );
+    state.set_streaming_flag(&device.id, true);
+
+    println!("External Control streaming enabled for {}", device.id);
+    Ok(())
+}
+
+/// Build an extControl v2 frame
+fn encode_panel_frame(colors: &[NanoleafPanelColor]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(2 + colors.len() * 8);
+    frame.extend_from_slice(&(colors.len() as u16).to_be_bytes());
+
+    for panel in colors {
+        frame.extend_from_slice(&panel.panel_id.to_be_bytes());
+        frame.extend_from_slice(&[panel.color.r, panel.color.g, panel.color.b, 0]);
+        frame.extend_from_slice(&panel.transition_time.to_be_bytes());
+    }
+
+    frame
+}
+
+/// Push per-panel colors to a streaming controller (through schedule caps
+/// and the safety limiter)
+#[tauri::command]
+pub fn nanoleaf_send_panel_colors(
+    device_id: String,
+    colors: Vec<NanoleafPanelColor>,
+    app: AppHandle,
+    state: State<NanoleafState>,
+) -> Result<(), String> {
+    let frames = colors
+        .iter()
+        .map(|panel| TargetFrame {
+            target: LightTarget {
+                backend: LightBackend::Nanoleaf,
+                device_id: device_id.clone(),
+                segment: Some(panel.panel_id as u32),
+            },
+            colors: vec![panel.color.clone()],
+        })
+        .collect();
+    let colors: Vec<NanoleafPanelColor> = colors
+        .into_iter()
+        .zip(lighting::guard_frames(&app, frames))
+        .map(|(panel, frame)| NanoleafPanelColor {
+            color: lighting::average_color(&frame.colors),
+            ..panel
+        })
+        .collect();
+
+    send_panel_colors(&state, &device_id, &colors)
+}
+
+/// Push panel colors without guarding (callers run them through the limiter)
+pub fn send_panel_colors(state: &NanoleafState, device_id: &str, colors: &[NanoleafPanelColor]) -> Result<(), String> {
+    let streams = state.streams.lock().unwrap();
+    let session = streams
+        .get(device_id)
+        .ok_or_else(|| format!("Nanoleaf {} is not streaming", device_id))?;
+
+    session
+        .socket
+        .send_to(&encode_panel_frame(colors), session.addr)
+        .map_err(|e| format!("Failed to send panel colors: {}", e))?;
+
+    Ok(())
+}
+
+/// Close the stream and restore the previously selected effect
+#[tauri::command]
+pub fn nanoleaf_stop_streaming(device_id: String, state: State<NanoleafState>) -> Result<(), String> {
+    stop_streaming(&state, &device_id)
+}
+
+fn stop_streaming(state: &NanoleafState, device_id: &str) -> Result<(), String> {
+    let Some(session) = state.streams.lock().unwrap().remove(device_id) else {
+        return Ok(());
+    };
+    state.set_streaming_flag(device_id, false);
+
+    if let Some(effect) = session.previous_effect {
+        let device = state.device(device_id)?;
+        let token = require_token(&device.id)?;
+
+        http_client()?
+            .put(api_url(&device, &token, "/effects"))
+            .json(&serde_json::json!({ "select": effect }))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Failed to restore effect: {}", e))?;
+    }
+
+    println!("External Control streaming stopped for {}", device_id);
+    Ok(())
+}
+
+/// Get all known Nanoleaf controllers
+#[tauri::command]
+pub fn nanoleaf_get_all_devices(state: State<NanoleafState>) -> Vec<NanoleafDevice> {
+    let devices = state.devices.lock().unwrap();
+    devices.values().cloned().collect()
+}
+
+/// Forget a controller and delete its stored token (restoring the effect
+/// it showed before streaming)
+#[tauri::command]
+pub fn nanoleaf_forget_device(device_id: String, state: State<NanoleafState>) -> Result<(), String> {
+    forget_device(&state, &device_id)
+}
+
+fn forget_device(state: &NanoleafState, device_id: &str) -> Result<(), String> {
+    // An unreachable controller can still be forgotten
+    if let Err(e) = stop_streaming(state, device_id) {
+        println!("Warning: {}", e);
+    }
+
+    state.layouts.lock().unwrap().remove(device_id);
+    state.devices.lock().unwrap().remove(device_id);
+    delete_token(device_id)?;
+
+    println!("Forgot Nanoleaf {}", device_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential_vault::{self, CredentialVault};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    type Requests = Arc<Mutex<Vec<String>>>;
+
+    const LAYOUT: &str = r#"{"numPanels":3,"sideLength":150,"positionData":[
+        {"panelId":10,"x":0,"y":0,"o":0,"shapeType":2},
+        {"panelId":20,"x":150,"y":0,"o":60,"shapeType":2},
+        {"panelId":30,"x":75,"y":0,"o":0,"shapeType":12}]}"#;
+
+    /// Stand-in controller answering OpenAPI requests from `routes` (method,
+    /// path, JSON body) and recording each request as "METHOD path body"
+    fn stand_in(routes: Vec<(&'static str, &'static str, &'static str)>) -> (u16, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Requests::default();
+        let recorded = requests.clone();
+
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut data = Vec::new();
+                let mut buf = [0u8; 1024];
+                let header_end = loop {
+                    if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                    match stream.read(&mut buf) {
+                        Ok(n) if n > 0 => data.extend_from_slice(&buf[..n]),
+                        _ => break data.len(),
+                    }
+                };
+                let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+                let length = head
+                    .lines()
+                    .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap_or(0)))
+                    .unwrap_or(0);
+                while data.len() < header_end + length {
+                    match stream.read(&mut buf) {
+                        Ok(n) if n > 0 => data.extend_from_slice(&buf[..n]),
+                        _ => break,
+                    }
+                }
+
+                let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+                let method = request_line.next().unwrap_or_default().to_string();
+                let path = request_line.next().unwrap_or_default().to_string();
+                let body = String::from_utf8_lossy(&data[header_end..]).to_string();
+                recorded.lock().unwrap().push(format!("{} {} {}", method, path, body));
+
+                let response = match routes.iter().find(|(m, p, _)| *m == method && *p == path) {
+                    Some((_, _, body)) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                    None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        (port, requests)
+    }
+
+    fn paired_controller(device_id: &str, port: u16) -> NanoleafState {
+        let _ = credential_vault::install(CredentialVault::in_memory());
+        save_token(device_id, "tok").unwrap();
+
+        let state = NanoleafState::default();
+        state.devices.lock().unwrap().insert(
+            device_id.to_string(),
+            NanoleafDevice {
+                id: device_id.to_string(),
+                name: "Shapes".to_string(),
+                model: "NL42".to_string(),
+                ip: "127.0.0.1".to_string(),
+                port,
+                paired: true,
+                streaming: false,
+            },
+        );
+        state
+    }
+
+    fn wall_mounting() -> PanelMounting {
+        PanelMounting {
+            room_id: "living".to_string(),
+            center: Vec3 { x: 2.0, y: 0.0, z: 1.5 },
+            size: 0.3,
+            mount: PanelMount::Wall,
+            tags: vec!["wall".to_string()],
+        }
+    }
+
+    #[test]
+    fn layout_places_lit_panels_in_the_room() {
+        let (port, _) = stand_in(vec![
+            ("GET", "/api/v1/tok/panelLayout/layout", LAYOUT),
+            ("GET", "/api/v1/tok/panelLayout/globalOrientation", r#"{"value":0,"max":360,"min":0}"#),
+        ]);
+        let state = paired_controller("layout-test", port);
+
+        let layout = fetch_layout(&state, "layout-test").unwrap();
+        assert_eq!(layout.panels.len(), 3);
+        assert!(!layout.panels[2].lit);
+
+        let placements = panel_placements("layout-test", &layout, &wall_mounting());
+        let segments: Vec<Option<u32>> = placements.iter().map(|p| p.target.segment).collect();
+        assert_eq!(segments, vec![Some(10), Some(20)]);
+        assert!((placements[0].position.x - 1.85).abs() < 1e-4);
+        assert!((placements[1].position.x - 2.15).abs() < 1e-4);
+        assert!(placements.iter().all(|p| (p.position.z - 1.5).abs() < 1e-4 && p.position.y == 0.0));
+        assert_eq!(placements[1].orientation.roll, 60.0);
+        assert_eq!(placements[0].tags, vec!["wall".to_string()]);
+    }
+
+    #[test]
+    fn global_orientation_rotates_the_arrangement() {
+        let (port, _) = stand_in(vec![
+            ("GET", "/api/v1/tok/panelLayout/layout", LAYOUT),
+            ("GET", "/api/v1/tok/panelLayout/globalOrientation", r#"{"value":90,"max":360,"min":0}"#),
+        ]);
+        let state = paired_controller("rotated-test", port);
+
+        let layout = fetch_layout(&state, "rotated-test").unwrap();
+        let placements = panel_placements("rotated-test", &layout, &wall_mounting());
+        // A horizontal row turned a quarter stands upright on the wall
+        assert!(placements.iter().all(|p| (p.position.x - 2.0).abs() < 1e-4));
+        assert!((placements[0].position.z - 1.35).abs() < 1e-4);
+        assert!((placements[1].position.z - 1.65).abs() < 1e-4);
+    }
+
+    #[test]
+    fn stream_sends_panel_frames_and_restores_the_effect() {
+        let (port, requests) = stand_in(vec![
+            ("GET", "/api/v1/tok/effects/select", r#""Northern Lights""#),
+            ("PUT", "/api/v1/tok/effects", ""),
+        ]);
+        let state = paired_controller("stream-test", port);
+
+        start_streaming(&state, "stream-test").unwrap();
+        assert!(state.device("stream-test").unwrap().streaming);
+        assert!(requests.lock().unwrap().iter().any(|r| r.starts_with("PUT") && r.contains("extControl")));
+
+        // Point the session at a stand-in extControl receiver
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        state.streams.lock().unwrap().get_mut("stream-test").unwrap().addr = receiver.local_addr().unwrap();
+
+        let colors = [NanoleafPanelColor {
+            panel_id: 20,
+            color: RGBColor { r: 255, g: 128, b: 0 },
+            transition_time: 1,
+        }];
+        send_panel_colors(&state, "stream-test", &colors).unwrap();
+        let mut frame = [0u8; 64];
+        let n = receiver.recv(&mut frame).unwrap();
+        assert_eq!(&frame[..n], &[0, 1, 0, 20, 255, 128, 0, 0, 0, 1]);
+
+        stop_streaming(&state, "stream-test").unwrap();
+        assert!(!state.device("stream-test").unwrap().streaming);
+        let last = requests.lock().unwrap().last().cloned().unwrap();
+        assert!(last.starts_with("PUT /api/v1/tok/effects") && last.contains("Northern Lights"), "{}", last);
+    }
+
+    #[test]
+    fn forgetting_a_streaming_controller_restores_the_effect() {
+        let (port, requests) = stand_in(vec![
+            ("GET", "/api/v1/tok/effects/select", r#""Forest""#),
+            ("PUT", "/api/v1/tok/effects", ""),
+        ]);
+        let state = paired_controller("forget-test", port);
+        start_streaming(&state, "forget-test").unwrap();
+
+        forget_device(&state, "forget-test").unwrap();
+
+        let last = requests.lock().unwrap().last().cloned().unwrap();
+        assert!(last.starts_with("PUT /api/v1/tok/effects") && last.contains("Forest"), "{}", last);
+        assert!(state.streams.lock().unwrap().is_empty());
+        assert!(state.device("forget-test").is_err());
+        assert_eq!(load_token("forget-test").unwrap(), None);
+    }
+
+    #[test]
+    fn unreachable_controller_is_still_forgotten() {
+        let (port, _) = stand_in(vec![
+            ("GET", "/api/v1/tok/effects/select", r#""Forest""#),
+            ("PUT", "/api/v1/tok/effects", ""),
+        ]);
+        let state = paired_controller("unreachable-test", port);
+        start_streaming(&state, "unreachable-test").unwrap();
+        state.devices.lock().unwrap().get_mut("unreachable-test").unwrap().port = 1;
+
+        forget_device(&state, "unreachable-test").unwrap();
+
+        assert!(state.streams.lock().unwrap().is_empty());
+        assert!(state.device("unreachable-test").is_err());
+        assert_eq!(load_token("unreachable-test").unwrap(), None);
+    }
+}
@@ -0,0 +1,682 @@
+// OpenRGB SDK Module
+// Network client for the OpenRGB SDK server (TCP 6742): enumerates
+// controllers, zones and LEDs of PC peripherals and pushes per-LED colors.
+
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, State};
+
+use crate::govee::RGBColor;
+use crate::lighting::{self, LightBackend, LightTarget};
+
+/// Highest SDK protocol version this client understands
+const CLIENT_PROTOCOL_VERSION: u32 = 3;
+
+// SDK packet ids
+const REQUEST_CONTROLLER_COUNT: u32 = 0;
+const REQUEST_CONTROLLER_DATA: u32 = 1;
+const REQUEST_PROTOCOL_VERSION: u32 = 40;
+const SET_CLIENT_NAME: u32 = 50;
+const RGBCONTROLLER_UPDATELEDS: u32 = 1050;
+const RGBCONTROLLER_UPDATEZONELEDS: u32 = 1051;
+const RGBCONTROLLER_SETCUSTOMMODE: u32 = 1100;
+
+/// OpenRGB controller (keyboard, fan hub, RAM stick, ...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenRgbController {
+    pub index: u32,
+    pub name: String,
+    pub vendor: String,
+    pub description: String,
+    pub location: String,
+    #[serde(rename = "deviceType")]
+    pub device_type: i32,
+    pub zones: Vec<OpenRgbZone>,
+    pub leds: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenRgbZone {
+    pub index: u32,
+    pub name: String,
+    #[serde(rename = "zoneType")]
+    pub zone_type: i32,
+    #[serde(rename = "ledsCount")]
+    pub leds_count: u32,
+    /// First LED of this zone in the controller-wide LED list
+    #[serde(rename = "startIndex")]
+    pub start_index: u32,
+}
+
+/// Open SDK connection
+struct OpenRgbConnection {
+    stream: TcpStream,
+    protocol_version: u32,
+    controllers: Vec<OpenRgbController>,
+}
+
+/// OpenRGB manager state for Tauri
+#[derive(Default)]
+pub struct OpenRgbState {
+    connection: Mutex<Option<OpenRgbConnection>>,
+}
+
+impl OpenRgbConnection {
+    fn send_packet(&mut self, device_index: u32, packet_id: u32, data: &[u8]) -> Result<(), String> {
+        let mut packet = Vec::with_capacity(16 + data.len());
+        packet.extend_from_slice(b"ORGB");
+        packet.extend_from_slice(&device_index.to_le_bytes());
+        packet.extend_from_slice(&packet_id.to_le_bytes());
+        packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        packet.extend_from_slice(data);
+
+        self.stream
+            .write_all(&packet)
+            .map_err(|e| format!("Failed to send OpenRGB packet {}: {}", packet_id, e))
+    }
+
+    /// Read packets until one with the expected id arrives
+    fn read_reply(&mut self, packet_id: u32) -> Result<Vec<u8>, String> {
+        loop {
+            let mut header = [0u8; 16];
+            self.stream
+                .read_exact(&mut header)
+                .map_err(|e| format!("Failed to read OpenRGB reply: {}", e))?;
+
+            if &header[0..4] != b"ORGB" {
+                return Err("Invalid OpenRGB packet header".to_string());
+            }
+
+            let id = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+            let size = u32::from_le_bytes([header[12], header[13], header[14], header[15]]);
+
+            let mut data = vec![0u8; size as usize];
+            self.stream
+                .read_exact(&mut data)
+                .map_err(|e| format!("Failed to read OpenRGB reply: {}", e))?;
+
+            // Servers may push DEVICE_LIST_UPDATED notifications at any time
+            if id == packet_id {
+                return Ok(data);
+            }
+        }
+    }
+
+    fn negotiate_protocol(&mut self) -> Result<(), String> {
+        self.send_packet(0, REQUEST_PROTOCOL_VERSION, &CLIENT_PROTOCOL_VERSION.to_le_bytes())?;
+
+        // Protocol 0 servers never answer this request
+        self.protocol_version = match self.read_reply(REQUEST_PROTOCOL_VERSION) {
+            Ok(data) if data.len() >= 4 => {
+                u32::from_le_bytes([data[0], data[1], data[2], data[3]]).min(CLIENT_PROTOCOL_VERSION)
+            }
+            _ => 0,
+        };
+        Ok(())
+    }
+
+    fn fetch_controllers(&mut self) -> Result<(), String> {
+        self.send_packet(0, REQUEST_CONTROLLER_COUNT, &[])?;
+        let data = self.read_reply(REQUEST_CONTROLLER_COUNT)?;
+        let count = Reader::new(&data).u32()?;
+
+        let mut controllers = Vec::with_capacity(count as usize);
+        for index in 0..count {
+            let request = if self.protocol_version >= 1 {
+                self.protocol_version.to_le_bytes().to_vec()
+            } else {
+                Vec::new()
+            };
+            self.send_packet(index, REQUEST_CONTROLLER_DATA, &request)?;
+            let data = self.read_reply(REQUEST_CONTROLLER_DATA)?;
+            controllers.push(parse_controller(index, &data, self.protocol_version)?);
+        }
+
+        self.controllers = controllers;
+        Ok(())
+    }
+
+    fn update_leds(&mut self, controller: u32, colors: &[RGBColor]) -> Result<(), String> {
+        let mut data = Vec::with_capacity(6 + colors.len() * 4);
+        data.extend_from_slice(&((6 + colors.len() * 4) as u32).to_le_bytes());
+        data.extend_from_slice(&(colors.len() as u16).to_le_bytes());
+        for color in colors {
+            data.extend_from_slice(&[color.r, color.g, color.b, 0]);
+        }
+        self.send_packet(controller, RGBCONTROLLER_UPDATELEDS, &data)
+    }
+
+    fn update_zone_leds(&mut self, controller: u32, zone: u32, colors: &[RGBColor]) -> Result<(), String> {
+        let mut data = Vec::with_capacity(10 + colors.len() * 4);
+        data.extend_from_slice(&((10 + colors.len() * 4) as u32).to_le_bytes());
+        data.extend_from_slice(&zone.to_le_bytes());
+        data.extend_from_slice(&(colors.len() as u16).to_le_bytes());
+        for color in colors {
+            data.extend_from_slice(&[color.r, color.g, color.b, 0]);
+        }
+        self.send_packet(controller, RGBCONTROLLER_UPDATEZONELEDS, &data)
+    }
+
+    fn controller(&self, index: u32) -> Result<&OpenRgbController, String> {
+        self.controllers
+            .iter()
+            .find(|c| c.index == index)
+            .ok_or_else(|| format!("OpenRGB controller {} not found", index))
+    }
+}
+
+/// Little-endian reader for SDK payloads
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.offset + len;
+        if end > self.data.len() {
+            return Err("Truncated OpenRGB controller data".to_string());
+        }
+        let slice = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(self.u32()? as i32)
+    }
+
+    /// Length-prefixed, null-terminated string
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u16()? as usize;
+        let bytes = self.take(len)?;
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), String> {
+        self.take(len).map(|_| ())
+    }
+}
+
+/// Parse a REQUEST_CONTROLLER_DATA reply
+fn parse_controller(index: u32, data: &[u8], protocol_version: u32) -> Result<OpenRgbController, String> {
+    let mut r = Reader::new(data);
+    r.u32()?; // data size
+    let device_type = r.i32()?;
+    let name = r.string()?;
+    let vendor = if protocol_version >= 1 { r.string()? } else { String::new() };
+    let description = r.string()?;
+    r.string()?; // version
+    r.string()?; // serial
+    let location = r.string()?;
+
+    let num_modes = r.u16()?;
+    r.i32()?; // active mode
+    for _ in 0..num_modes {
+        r.string()?; // name
+        r.skip(4 * 4)?; // value, flags, speed min/max
+        if protocol_version >= 3 {
+            r.skip(4 * 2)?; // brightness min/max
+        }
+        r.skip(4 * 3)?; // colors min/max, speed
+        if protocol_version >= 3 {
+            r.skip(4)?; // brightness
+        }
+        r.skip(4 * 2)?; // direction, color mode
+        let num_colors = r.u16()? as usize;
+        r.skip(num_colors * 4)?;
+    }
+
+    let num_zones = r.u16()?;
+    let mut zones = Vec::with_capacity(num_zones as usize);
+    let mut start_index = 0;
+    for zone_index in 0..num_zones as u32 {
+        let zone_name = r.string()?;
+        let zone_type = r.i32()?;
+        r.skip(4 * 2)?; // leds min/max
+        let leds_count = r.u32()?;
+        let matrix_len = r.u16()? as usize;
+        r.skip(matrix_len)?;
+
+        zones.push(OpenRgbZone {
+            index: zone_index,
+            name: zone_name,
+            zone_type,
+            leds_count,
+            start_index,
+        });
+        start_index += leds_count;
+    }
+
+    let num_leds = r.u16()?;
+    let mut leds = Vec::with_capacity(num_leds as usize);
+    for _ in 0..num_leds {
+        leds.push(r.string()?);
+        r.u32()?; // value
+    }
+
+    Ok(OpenRgbController {
+        index,
+        name,
+        vendor,
+        description,
+        location,
+        device_type,
+        zones,
+        leds,
+    })
+}
+
+/// Stretch a color array over `count` LEDs
+fn stretch_colors(colors: &[RGBColor], count: usize) -> Vec<RGBColor> {
+    (0..count)
+        .map(|i| colors[i * colors.len() / count].clone())
+        .collect()
+}
+
+/// Connect to an OpenRGB SDK server and enumerate its controllers
+#[tauri::command]
+pub fn openrgb_connect(
+    host: String,
+    port: u16,
+    client_name: String,
+    state: State<OpenRgbState>,
+) -> Result<Vec<OpenRgbController>, String> {
+    connect(&state, &host, port, &client_name)
+}
+
+fn connect(state: &OpenRgbState, host: &str, port: u16, client_name: &str) -> Result<Vec<OpenRgbController>, String> {
+    println!("Connecting to OpenRGB SDK server at {}:{}...", host, port);
+
+    let addr = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Invalid OpenRGB address: {}", e))?
+        .next()
+        .ok_or_else(|| format!("Could not resolve {}", host))?;
+
+    let stream = TcpStream::connect_timeout(&addr, Duration::from_secs(2))
+        .map_err(|e| format!("Failed to connect to OpenRGB at {}: {}", addr, e))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .map_err(|e| format!("Failed to set timeout: {}", e))?;
+    stream
+        .set_nodelay(true)
+        .map_err(|e| format!("Failed to configure connection: {}", e))?;
+
+    let mut connection = OpenRgbConnection {
+        stream,
+        protocol_version: 0,
+        controllers: Vec::new(),
+    };
+
+    connection.negotiate_protocol()?;
+
+    let mut name = client_name.as_bytes().to_vec();
+    name.push(0);
+    connection.send_packet(0, SET_CLIENT_NAME, &name)?;
+
+    connection.fetch_controllers()?;
+
+    // Direct/custom mode is required for per-LED updates to show
+    for index in 0..connection.controllers.len() as u32 {
+        connection.send_packet(index, RGBCONTROLLER_SETCUSTOMMODE, &[])?;
+    }
+
+    println!(
+        "Connected to OpenRGB (protocol {}): {} controllers",
+        connection.protocol_version,
+        connection.controllers.len()
+    );
+
+    let controllers = connection.controllers.clone();
+    *state.connection.lock().unwrap() = Some(connection);
+    Ok(controllers)
+}
+
+/// Get the controllers of the current connection
+#[tauri::command]
+pub fn openrgb_get_controllers(state: State<OpenRgbState>) -> Result<Vec<OpenRgbController>, String> {
+    let connection = state.connection.lock().unwrap();
+    let connection = connection
+        .as_ref()
+        .ok_or_else(|| "Not connected to OpenRGB".to_string())?;
+    Ok(connection.controllers.clone())
+}
+
+/// Set every LED of a controller
+#[tauri::command]
+pub fn openrgb_set_led_colors(
+    controller: u32,
+    colors: Vec<RGBColor>,
+    app: AppHandle,
+    state: State<OpenRgbState>,
+) -> Result<(), String> {
+    let target = LightTarget::device(LightBackend::OpenRgb, &controller.to_string());
+    set_led_colors(&state, controller, &lighting::guard_colors(&app, target, colors))
+}
+
+/// Set controller LEDs without guarding (callers run them through the limiter)
+pub fn set_led_colors(state: &OpenRgbState, controller: u32, colors: &[RGBColor]) -> Result<(), String> {
+    let mut connection = state.connection.lock().unwrap();
+    let connection = connection
+        .as_mut()
+        .ok_or_else(|| "Not connected to OpenRGB".to_string())?;
+
+    let led_count = connection.controller(controller)?.leds.len();
+    if colors.len() != led_count {
+        return Err(format!(
+            "Controller {} has {} LEDs, got {} colors",
+            controller,
+            led_count,
+            colors.len()
+        ));
+    }
+
+    connection.update_leds(controller, colors)
+}
+
+/// Set the LEDs of one zone
+#[tauri::command]
+pub fn openrgb_set_zone_led_colors(
+    controller: u32,
+    zone: u32,
+    colors: Vec<RGBColor>,
+    app: AppHandle,
+    state: State<OpenRgbState>,
+) -> Result<(), String> {
+    let target = LightTarget {
+        backend: LightBackend::OpenRgb,
+        device_id: controller.to_string(),
+        segment: Some(zone),
+    };
+    set_zone_led_colors(&state, controller, zone, &lighting::guard_colors(&app, target, colors))
+}
+
+/// Set zone LEDs without guarding
+pub fn set_zone_led_colors(state: &OpenRgbState, controller: u32, zone: u32, colors: &[RGBColor]) -> Result<(), String> {
+    let mut connection = state.connection.lock().unwrap();
+    let connection = connection
+        .as_mut()
+        .ok_or_else(|| "Not connected to OpenRGB".to_string())?;
+
+    let leds_count = connection
+        .controller(controller)?
+        .zones
+        .iter()
+        .find(|z| z.index == zone)
+        .ok_or_else(|| format!("Zone {} not found on controller {}", zone, controller))?
+        .leds_count;
+
+    if colors.len() != leds_count as usize {
+        return Err(format!("Zone {} has {} LEDs, got {} colors", zone, leds_count, colors.len()));
+    }
+
+    connection.update_zone_leds(controller, zone, colors)
+}
+
+/// Apply a zone color array (as pushed to Govee devices) to every controller,
+/// stretched across each controller's LEDs
+#[tauri::command]
+pub fn openrgb_set_zone_colors(colors: Vec<RGBColor>, app: AppHandle, state: State<OpenRgbState>) -> Result<(), String> {
+    if colors.is_empty() {
+        return Ok(());
+    }
+
+    let mut connection = state.connection.lock().unwrap();
+    let connection = connection
+        .as_mut()
+        .ok_or_else(|| "Not connected to OpenRGB".to_string())?;
+
+    let targets: Vec<(u32, usize)> = connection
+        .controllers
+        .iter()
+        .filter(|c| !c.leds.is_empty())
+        .map(|c| (c.index, c.leds.len()))
+        .collect();
+
+    for (index, led_count) in targets {
+        let target = LightTarget::device(LightBackend::OpenRgb, &index.to_string());
+        let stretched = lighting::guard_colors(&app, target, stretch_colors(&colors, led_count));
+        connection.update_leds(index, &stretched)?;
+    }
+
+    Ok(())
+}
+
+/// Close the SDK connection
+#[tauri::command]
+pub fn openrgb_disconnect(state: State<OpenRgbState>) {
+    if let Some(connection) = state.connection.lock().unwrap().take() {
+        let _ = connection.stream.shutdown(std::net::Shutdown::Both);
+        println!("Disconnected from OpenRGB");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc::{self, Receiver};
+
+    /// (device index, packet id, data) of a packet the mock server received
+    type Packet = (u32, u32, Vec<u8>);
+
+    const DEVICE_LIST_UPDATED: u32 = 100;
+
+    fn put_string(data: &mut Vec<u8>, value: &str) {
+        data.extend_from_slice(&(value.len() as u16 + 1).to_le_bytes());
+        data.extend_from_slice(value.as_bytes());
+        data.push(0);
+    }
+
+    /// REQUEST_CONTROLLER_DATA reply for a controller with one mode and
+    /// `zones` (name, LED count)
+    fn controller_data(protocol_version: u32, name: &str, zones: &[(&str, u32)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&5i32.to_le_bytes()); // keyboard
+        put_string(&mut data, name);
+        if protocol_version >= 1 {
+            put_string(&mut data, "Vendor");
+        }
+        put_string(&mut data, "Description");
+        put_string(&mut data, "1.0");
+        put_string(&mut data, "SN1");
+        put_string(&mut data, "HID: /dev/hidraw0");
+
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&0i32.to_le_bytes());
+        put_string(&mut data, "Direct");
+        data.extend_from_slice(&[0; 16]);
+        if protocol_version >= 3 {
+            data.extend_from_slice(&[0; 8]);
+        }
+        data.extend_from_slice(&[0; 12]);
+        if protocol_version >= 3 {
+            data.extend_from_slice(&[0; 4]);
+        }
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&[255, 0, 0, 0]);
+
+        data.extend_from_slice(&(zones.len() as u16).to_le_bytes());
+        for (zone_name, leds) in zones {
+            put_string(&mut data, zone_name);
+            data.extend_from_slice(&1i32.to_le_bytes());
+            data.extend_from_slice(&leds.to_le_bytes());
+            data.extend_from_slice(&leds.to_le_bytes());
+            data.extend_from_slice(&leds.to_le_bytes());
+            data.extend_from_slice(&0u16.to_le_bytes());
+        }
+
+        let leds: Vec<String> = zones
+            .iter()
+            .flat_map(|(zone_name, leds)| (0..*leds).map(move |i| format!("{} {}", zone_name, i)))
+            .collect();
+        data.extend_from_slice(&(leds.len() as u16).to_le_bytes());
+        for led in &leds {
+            put_string(&mut data, led);
+            data.extend_from_slice(&0u32.to_le_bytes());
+        }
+        data.extend_from_slice(&(leds.len() as u16).to_le_bytes());
+        data.extend(leds.iter().flat_map(|_| [0u8; 4]));
+
+        let mut reply = ((data.len() + 4) as u32).to_le_bytes().to_vec();
+        reply.extend(data);
+        reply
+    }
+
+    fn write_packet(stream: &mut TcpStream, device_index: u32, packet_id: u32, data: &[u8]) {
+        let mut packet = b"ORGB".to_vec();
+        packet.extend_from_slice(&device_index.to_le_bytes());
+        packet.extend_from_slice(&packet_id.to_le_bytes());
+        packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        packet.extend_from_slice(data);
+        stream.write_all(&packet).unwrap();
+    }
+
+    /// Mock SDK server with a keyboard (two zones) and a RAM stick. Servers
+    /// with `protocol_version` None predate the version request and never
+    /// answer it.
+    fn mock_server(protocol_version: Option<u32>) -> (u16, Receiver<Packet>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, received) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0u8; 16];
+            while stream.read_exact(&mut header).is_ok() {
+                assert_eq!(&header[0..4], b"ORGB");
+                let field = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+                let (device_index, packet_id) = (field(4), field(8));
+                let mut data = vec![0u8; field(12) as usize];
+                stream.read_exact(&mut data).unwrap();
+
+                match packet_id {
+                    REQUEST_PROTOCOL_VERSION => {
+                        if let Some(version) = protocol_version {
+                            write_packet(&mut stream, 0, REQUEST_PROTOCOL_VERSION, &version.to_le_bytes());
+                        }
+                    }
+                    REQUEST_CONTROLLER_COUNT => {
+                        write_packet(&mut stream, 0, DEVICE_LIST_UPDATED, &[]);
+                        write_packet(&mut stream, 0, REQUEST_CONTROLLER_COUNT, &2u32.to_le_bytes());
+                    }
+                    REQUEST_CONTROLLER_DATA => {
+                        let requested = data.get(0..4).map_or(0, |v| u32::from_le_bytes(v.try_into().unwrap()));
+                        let reply = match device_index {
+                            0 => controller_data(requested, "Keyboard", &[("Keys", 3), ("Logo", 1)]),
+                            _ => controller_data(requested, "DRAM", &[("Stick", 5)]),
+                        };
+                        write_packet(&mut stream, device_index, REQUEST_CONTROLLER_DATA, &reply);
+                    }
+                    _ => {}
+                }
+                let _ = sender.send((device_index, packet_id, data));
+            }
+        });
+
+        (port, received)
+    }
+
+    /// Next packet with `packet_id`, skipping others
+    fn next_packet(received: &Receiver<Packet>, packet_id: u32) -> Packet {
+        loop {
+            let packet = received.recv_timeout(Duration::from_secs(3)).unwrap();
+            if packet.1 == packet_id {
+                return packet;
+            }
+        }
+    }
+
+    fn colors(values: &[(u8, u8, u8)]) -> Vec<RGBColor> {
+        values.iter().map(|&(r, g, b)| RGBColor { r, g, b }).collect()
+    }
+
+    #[test]
+    fn connect_enumerates_controllers_zones_and_leds() {
+        let (port, received) = mock_server(Some(4));
+        let state = OpenRgbState::default();
+
+        let controllers = connect(&state, "127.0.0.1", port, "musicViz").unwrap();
+
+        assert_eq!(next_packet(&received, REQUEST_PROTOCOL_VERSION).2, 3u32.to_le_bytes());
+        assert_eq!(next_packet(&received, SET_CLIENT_NAME).2, b"musicViz\0");
+        assert_eq!(controllers.len(), 2);
+        let keyboard = &controllers[0];
+        assert_eq!((keyboard.name.as_str(), keyboard.vendor.as_str()), ("Keyboard", "Vendor"));
+        assert_eq!(keyboard.location, "HID: /dev/hidraw0");
+        assert_eq!(keyboard.device_type, 5);
+        assert_eq!(keyboard.leds, vec!["Keys 0", "Keys 1", "Keys 2", "Logo 0"]);
+        assert_eq!(keyboard.zones[1].name, "Logo");
+        assert_eq!((keyboard.zones[1].leds_count, keyboard.zones[1].start_index), (1, 3));
+        assert_eq!(controllers[1].leds.len(), 5);
+
+        // Every controller is switched to custom mode for per-LED updates
+        assert_eq!(next_packet(&received, RGBCONTROLLER_SETCUSTOMMODE).0, 0);
+        assert_eq!(next_packet(&received, RGBCONTROLLER_SETCUSTOMMODE).0, 1);
+    }
+
+    #[test]
+    fn led_updates_are_encoded_per_controller_and_zone() {
+        let (port, received) = mock_server(Some(3));
+        let state = OpenRgbState::default();
+        connect(&state, "127.0.0.1", port, "musicViz").unwrap();
+
+        let error = set_led_colors(&state, 0, &colors(&[(1, 2, 3)])).unwrap_err();
+        assert!(error.contains("has 4 LEDs"), "{}", error);
+        assert!(set_zone_led_colors(&state, 0, 2, &colors(&[(1, 2, 3)])).is_err());
+
+        set_led_colors(&state, 0, &colors(&[(1, 2, 3), (4, 5, 6), (7, 8, 9), (10, 11, 12)])).unwrap();
+        let (device_index, _, data) = next_packet(&received, RGBCONTROLLER_UPDATELEDS);
+        assert_eq!(device_index, 0);
+        assert_eq!(data[0..4], 22u32.to_le_bytes());
+        assert_eq!(data[4..6], 4u16.to_le_bytes());
+        assert_eq!(data[6..], [1, 2, 3, 0, 4, 5, 6, 0, 7, 8, 9, 0, 10, 11, 12, 0]);
+
+        set_zone_led_colors(&state, 0, 1, &colors(&[(0, 0, 255)])).unwrap();
+        let (device_index, _, data) = next_packet(&received, RGBCONTROLLER_UPDATEZONELEDS);
+        assert_eq!(device_index, 0);
+        assert_eq!(data[0..4], 14u32.to_le_bytes());
+        assert_eq!(data[4..8], 1u32.to_le_bytes());
+        assert_eq!(data[8..10], 1u16.to_le_bytes());
+        assert_eq!(data[10..], [0, 0, 255, 0]);
+    }
+
+    #[test]
+    fn protocol_0_servers_are_supported() {
+        let (port, received) = mock_server(None);
+        let state = OpenRgbState::default();
+
+        let controllers = connect(&state, "127.0.0.1", port, "musicViz").unwrap();
+
+        assert_eq!(controllers[0].name, "Keyboard");
+        assert_eq!(controllers[0].vendor, "");
+        assert!(next_packet(&received, REQUEST_CONTROLLER_DATA).2.is_empty());
+    }
+
+    #[test]
+    fn zone_colors_are_stretched_over_the_leds() {
+        let stretched = stretch_colors(&colors(&[(255, 0, 0), (0, 0, 255)]), 5);
+        let reds: Vec<u8> = stretched.iter().map(|color| color.r).collect();
+        assert_eq!(reds, vec![255, 255, 255, 0, 0]);
+    }
+}
@@ -0,0 +1,879 @@
+// Lighting Session Recorder
+//
+// Records every frame leaving the lighting pipeline (after caps, safety
+// limiting and calibration) together with the audio features that drove it,
+// into a compact JSON Lines file: a header line followed by one entry per
+// flush or feature update, with colors packed as hex strings. Recordings can
+// be replayed at their original timing to the real devices (through the
+// photosensitivity limiter again) or to the frontend only, and two
+// recordings can be diffed frame by frame.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::calibration::{CalibratedOutput, TemperatureCommand};
+use crate::effects::{self, AudioFeatures};
+use crate::govee::RGBColor;
+use crate::lighting::{self, LightTarget, TargetFrame};
+use crate::storage;
+
+const RECORDINGS_DIR: &str = "recordings";
+const RECORDING_EXTENSION: &str = "jsonl";
+const FORMAT_VERSION: u32 = 1;
+/// Differences listed in a diff report (the counts cover all of them)
+const MAX_LISTED_DIFFERENCES: usize = 100;
+
+/// First line of a recording file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub version: u32,
+    pub name: String,
+    #[serde(rename = "startedAt")]
+    pub started_at: String,
+}
+
+/// One target's colors, packed as "rrggbb" per pixel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackedFrame {
+    #[serde(rename = "tg")]
+    pub target: LightTarget,
+    #[serde(rename = "c")]
+    pub colors: String,
+}
+
+/// One line of a recording after the header
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "k")]
+pub enum RecordEntry {
+    /// Output of one pipeline flush
+    #[serde(rename = "f")]
+    Frame {
+        /// Milliseconds since the recording started
+        t: u64,
+        #[serde(rename = "fr", default, skip_serializing_if = "Vec::is_empty")]
+        frames: Vec<PackedFrame>,
+        #[serde(rename = "ct", default, skip_serializing_if = "Vec::is_empty")]
+        temperatures: Vec<TemperatureCommand>,
+    },
+    /// Audio features received from the analyzer
+    #[serde(rename = "i")]
+    Input { t: u64, features: AudioFeatures },
+}
+
+impl RecordEntry {
+    fn time_ms(&self) -> u64 {
+        match self {
+            RecordEntry::Frame { t, .. } | RecordEntry::Input { t, .. } => *t,
+        }
+    }
+}
+
+/// A recording loaded from disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub entries: Vec<RecordEntry>,
+}
+
+impl Recording {
+    fn output_frames(&self) -> impl Iterator<Item = (u64, &Vec<PackedFrame>, &Vec<TemperatureCommand>)> {
+        self.entries.iter().filter_map(|entry| match entry {
+            RecordEntry::Frame { t, frames, temperatures } => Some((*t, frames, temperatures)),
+            RecordEntry::Input { .. } => None,
+        })
+    }
+}
+
+/// Summary of a recording file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingInfo {
+    pub name: String,
+    #[serde(rename = "startedAt")]
+    pub started_at: String,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: u64,
+    #[serde(rename = "frameCount")]
+    pub frame_count: usize,
+    #[serde(rename = "inputCount")]
+    pub input_count: usize,
+    #[serde(rename = "sizeBytes")]
+    pub size_bytes: u64,
+}
+
+/// Status of the recorder and player
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecorderStatus {
+    pub recording: Option<String>,
+    #[serde(rename = "recordedEntries")]
+    pub recorded_entries: usize,
+    #[serde(rename = "elapsedMs")]
+    pub elapsed_ms: u64,
+    pub replaying: Option<String>,
+}
+
+/// Where a replay sends its frames
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReplayTarget {
+    /// Send to the real devices (and emit events)
+    Devices,
+    /// Only emit `recorder://frame` events for the frontend simulator
+    Simulated,
+}
+
+/// Replay options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayOptions {
+    pub target: ReplayTarget,
+    /// Playback speed multiplier (1.0 = original timing)
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// Feed recorded audio features back into the effect engine, so the
+    /// effects re-render live (record at the same time to diff the result)
+    #[serde(rename = "replayInputs", default)]
+    pub replay_inputs: bool,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+/// Frame payload emitted during replay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayFrameEvent {
+    pub name: String,
+    #[serde(rename = "timeMs")]
+    pub time_ms: u64,
+    pub frames: Vec<TargetFrame>,
+}
+
+/// A single target that differs between two recordings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameDifference {
+    /// Index of the output frame in both recordings
+    pub index: usize,
+    #[serde(rename = "timeA")]
+    pub time_a: u64,
+    #[serde(rename = "timeB")]
+    pub time_b: u64,
+    pub target: LightTarget,
+    pub expected: Option<Vec<RGBColor>>,
+    pub actual: Option<Vec<RGBColor>>,
+    /// Largest channel difference for this target
+    #[serde(rename = "maxDelta")]
+    pub max_delta: u8,
+}
+
+/// Result of comparing two recordings output frame by output frame
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecordingDiff {
+    #[serde(rename = "framesA")]
+    pub frames_a: usize,
+    #[serde(rename = "framesB")]
+    pub frames_b: usize,
+    #[serde(rename = "mismatchedFrames")]
+    pub mismatched_frames: usize,
+    #[serde(rename = "mismatchedTargets")]
+    pub mismatched_targets: usize,
+    #[serde(rename = "temperatureMismatches")]
+    pub temperature_mismatches: usize,
+    #[serde(rename = "maxDelta")]
+    pub max_delta: u8,
+    /// Largest timing difference between aligned frames
+    #[serde(rename = "maxTimingDriftMs")]
+    pub max_timing_drift_ms: u64,
+    pub identical: bool,
+    pub differences: Vec<FrameDifference>,
+}
+
+struct ActiveRecording {
+    name: String,
+    started: Instant,
+    writer: BufWriter<File>,
+    entries: usize,
+}
+
+impl ActiveRecording {
+    fn write(&mut self, entry: &RecordEntry) -> Result<(), String> {
+        let line = serde_json::to_string(entry).map_err(|e| format!("Failed to serialize entry: {}", e))?;
+        writeln!(self.writer, "{}", line).map_err(|e| format!("Failed to write recording: {}", e))?;
+        self.entries += 1;
+        Ok(())
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
+
+struct Replay {
+    name: String,
+    stop: Arc<AtomicBool>,
+}
+
+/// Recorder state for Tauri
+#[derive(Default)]
+pub struct RecorderState {
+    active: Mutex<Option<ActiveRecording>>,
+    replay: Arc<Mutex<Option<Replay>>>,
+}
+
+impl RecorderState {
+    /// Append one pipeline flush to the running recording
+    pub fn record_output(&self, output: &CalibratedOutput) {
+        let mut active = self.active.lock().unwrap();
+        let Some(recording) = active.as_mut() else {
+            return;
+        };
+
+        let entry = RecordEntry::Frame {
+            t: recording.elapsed_ms(),
+            frames: output.frames.iter().map(pack_frame).collect(),
+            temperatures: output.temperatures.clone(),
+        };
+        if let Err(e) = recording.write(&entry) {
+            println!("Stopping session recording: {}", e);
+            *active = None;
+        }
+    }
+
+    /// Append audio features to the running recording
+    pub fn record_input(&self, features: &AudioFeatures) {
+        let mut active = self.active.lock().unwrap();
+        let Some(recording) = active.as_mut() else {
+            return;
+        };
+
+        let entry = RecordEntry::Input {
+            t: recording.elapsed_ms(),
+            features: features.clone(),
+        };
+        if let Err(e) = recording.write(&entry) {
+            println!("Stopping session recording: {}", e);
+            *active = None;
+        }
+    }
+}
+
+fn pack_frame(frame: &TargetFrame) -> PackedFrame {
+    PackedFrame {
+        target: frame.target.clone(),
+        colors: frame
+            .colors
+            .iter()
+            .map(|c| format!("{:02x}{:02x}{:02x}", c.r, c.g, c.b))
+            .collect(),
+    }
+}
+
+fn unpack_colors(packed: &str) -> Result<Vec<RGBColor>, String> {
+    if !packed.len().is_multiple_of(6) || !packed.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("Invalid packed colors: {}", packed));
+    }
+
+    let channel = |i: usize| {
+        u8::from_str_radix(&packed[i..i + 2], 16).map_err(|e| format!("Invalid packed colors: {}", e))
+    };
+    (0..packed.len())
+        .step_by(6)
+        .map(|i| {
+            Ok(RGBColor {
+                r: channel(i)?,
+                g: channel(i + 2)?,
+                b: channel(i + 4)?,
+            })
+        })
+        .collect()
+}
+
+fn unpack_frame(frame: &PackedFrame) -> Result<TargetFrame, String> {
+    Ok(TargetFrame {
+        target: frame.target.clone(),
+        colors: unpack_colors(&frame.colors)?,
+    })
+}
+
+fn recording_path(name: &str, create: bool) -> Result<PathBuf, String> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("Invalid recording name: {:?}", name));
+    }
+
+    let dir = storage::storage_dir(create)?.join(RECORDINGS_DIR);
+    if create {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    }
+    Ok(dir.join(format!("{}.{}", name, RECORDING_EXTENSION)))
+}
+
+fn load_recording(name: &str) -> Result<Recording, String> {
+    read_recording(&recording_path(name, false)?, name)
+}
+
+fn read_recording(path: &Path, name: &str) -> Result<Recording, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    let mut lines = BufReader::new(file).lines();
+
+    let header_line = lines
+        .next()
+        .ok_or_else(|| format!("Recording {} is empty", name))?
+        .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let header: RecordingHeader =
+        serde_json::from_str(&header_line).map_err(|e| format!("Invalid recording header: {}", e))?;
+    if header.version > FORMAT_VERSION {
+        return Err(format!("Unsupported recording version {}", header.version));
+    }
+
+    let mut entries = Vec::new();
+    for (number, line) in lines.enumerate() {
+        let line = line.map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|e| format!("Invalid entry on line {}: {}", number + 2, e))?;
+        entries.push(entry);
+    }
+
+    Ok(Recording { header, entries })
+}
+
+/// Create a new recording file and write its header (an existing recording
+/// is never overwritten)
+fn create_recording(path: &Path, name: &str) -> Result<ActiveRecording, String> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => format!("Recording {} already exists", name),
+            _ => format!("Failed to create {:?}: {}", path, e),
+        })?;
+    let mut writer = BufWriter::new(file);
+
+    let header = RecordingHeader {
+        version: FORMAT_VERSION,
+        name: name.to_string(),
+        started_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let line = serde_json::to_string(&header).map_err(|e| format!("Failed to serialize header: {}", e))?;
+    writeln!(writer, "{}", line).map_err(|e| format!("Failed to write recording: {}", e))?;
+
+    Ok(ActiveRecording {
+        name: name.to_string(),
+        started: Instant::now(),
+        writer,
+        entries: 0,
+    })
+}
+
+fn channel_delta(a: &[RGBColor], b: &[RGBColor]) -> u8 {
+    if a.len() != b.len() {
+        return u8::MAX;
+    }
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| x.r.abs_diff(y.r).max(x.g.abs_diff(y.g)).max(x.b.abs_diff(y.b)))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Colors of one target in the expected and actual recording
+type ColorPair = (Option<Vec<RGBColor>>, Option<Vec<RGBColor>>);
+
+fn diff_recordings(a: &Recording, b: &Recording, tolerance: u8) -> Result<RecordingDiff, String> {
+    let frames_a: Vec<_> = a.output_frames().collect();
+    let frames_b: Vec<_> = b.output_frames().collect();
+    let mut diff = RecordingDiff {
+        frames_a: frames_a.len(),
+        frames_b: frames_b.len(),
+        ..Default::default()
+    };
+
+    for (index, ((time_a, packed_a, temps_a), (time_b, packed_b, temps_b))) in
+        frames_a.iter().zip(&frames_b).enumerate()
+    {
+        diff.max_timing_drift_ms = diff.max_timing_drift_ms.max(time_a.abs_diff(*time_b));
+
+        let mut targets: BTreeMap<&LightTarget, ColorPair> = BTreeMap::new();
+        for frame in packed_a.iter() {
+            targets.entry(&frame.target).or_default().0 = Some(unpack_colors(&frame.colors)?);
+        }
+        for frame in packed_b.iter() {
+            targets.entry(&frame.target).or_default().1 = Some(unpack_colors(&frame.colors)?);
+        }
+
+        let mut frame_differs = false;
+        for (target, (expected, actual)) in targets {
+            let delta = match (&expected, &actual) {
+                (Some(expected), Some(actual)) => channel_delta(expected, actual),
+                _ => u8::MAX,
+            };
+            if delta <= tolerance {
+                continue;
+            }
+
+            frame_differs = true;
+            diff.mismatched_targets += 1;
+            diff.max_delta = diff.max_delta.max(delta);
+            if diff.differences.len() < MAX_LISTED_DIFFERENCES {
+                diff.differences.push(FrameDifference {
+                    index,
+                    time_a: *time_a,
+                    time_b: *time_b,
+                    target: target.clone(),
+                    expected,
+                    actual,
+                    max_delta: delta,
+                });
+            }
+        }
+
+        let temperatures_match = temps_a.len() == temps_b.len()
+            && temps_a.iter().zip(temps_b.iter()).all(|(x, y)| {
+                x.target == y.target && x.kelvin == y.kelvin && x.brightness == y.brightness
+            });
+        if !temperatures_match {
+            frame_differs = true;
+            diff.temperature_mismatches += 1;
+        }
+
+        if frame_differs {
+            diff.mismatched_frames += 1;
+        }
+    }
+
+    diff.identical = diff.mismatched_frames == 0 && diff.frames_a == diff.frames_b;
+    Ok(diff)
+}
+
+fn run_replay(app: AppHandle, recording: Recording, options: ReplayOptions, stop: Arc<AtomicBool>) {
+    let name = recording.header.name.clone();
+    let speed = if options.speed > 0.0 { options.speed } else { 1.0 };
+    let started = Instant::now();
+
+    for entry in recording.entries {
+        let due = Duration::from_secs_f64(entry.time_ms() as f64 / 1000.0 / speed as f64);
+        while let Some(remaining) = due.checked_sub(started.elapsed()) {
+            if stop.load(Ordering::Relaxed) || remaining.is_zero() {
+                break;
+            }
+            std::thread::sleep(remaining.min(Duration::from_millis(50)));
+        }
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+
+        match entry {
+            RecordEntry::Frame { t, frames, temperatures } => {
+                let frames = match frames.iter().map(unpack_frame).collect::<Result<Vec<_>, _>>() {
+                    Ok(frames) => frames,
+                    Err(e) => {
+                        println!("Skipping recorded frame at {} ms: {}", t, e);
+                        continue;
+                    }
+                };
+
+                if options.target == ReplayTarget::Devices {
+                    let report = lighting::send_recorded(
+                        &app,
+                        CalibratedOutput {
+                            frames: frames.clone(),
+                            temperatures,
+                        },
+                    );
+                    if let Some(error) = report.errors.first() {
+                        println!("Replay frame at {} ms: {}", t, error);
+                    }
+                }
+
+                let _ = app.emit(
+                    "recorder://frame",
+                    ReplayFrameEvent {
+                        name: name.clone(),
+                        time_ms: t,
+                        frames,
+                    },
+                );
+            }
+            RecordEntry::Input { features, .. } => {
+                if options.replay_inputs {
+                    effects::effects_push_audio_features(features, app.state(), app.state(), app.state(), app.state());
+                }
+            }
+        }
+    }
+
+    let _ = app.emit("recorder://replay-finished", &name);
+}
+
+/// Start recording outgoing frames and audio features (the name must not
+/// belong to an existing recording)
+#[tauri::command]
+pub fn recorder_start(name: String, state: State<RecorderState>) -> Result<(), String> {
+    let mut active = state.active.lock().unwrap();
+    if let Some(recording) = active.as_ref() {
+        return Err(format!("Already recording {}", recording.name));
+    }
+
+    let path = recording_path(&name, true)?;
+    *active = Some(create_recording(&path, &name)?);
+    Ok(())
+}
+
+/// Stop the running recording and return its summary
+#[tauri::command]
+pub fn recorder_stop(state: State<RecorderState>) -> Result<RecordingInfo, String> {
+    let mut recording = state
+        .active
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or("No recording in progress")?;
+
+    recording
+        .writer
+        .flush()
+        .map_err(|e| format!("Failed to write recording: {}", e))?;
+    drop(recording.writer);
+
+    recorder_info(recording.name)
+}
+
+/// Record audio features (call alongside `effects_push_audio_features` when
+/// the effect engine is not in use)
+#[tauri::command]
+pub fn recorder_record_input(features: AudioFeatures, state: State<RecorderState>) {
+    state.record_input(&features);
+}
+
+/// Get recorder and player status
+#[tauri::command]
+pub fn recorder_status(state: State<RecorderState>) -> RecorderStatus {
+    let active = state.active.lock().unwrap();
+    let replay = state.replay.lock().unwrap();
+
+    RecorderStatus {
+        recording: active.as_ref().map(|r| r.name.clone()),
+        recorded_entries: active.as_ref().map_or(0, |r| r.entries),
+        elapsed_ms: active.as_ref().map_or(0, |r| r.elapsed_ms()),
+        replaying: replay.as_ref().map(|r| r.name.clone()),
+    }
+}
+
+/// Get the summary of a stored recording
+#[tauri::command]
+pub fn recorder_info(name: String) -> Result<RecordingInfo, String> {
+    let recording = load_recording(&name)?;
+    let path = recording_path(&name, false)?;
+    let size_bytes = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    let frame_count = recording.output_frames().count();
+
+    Ok(RecordingInfo {
+        name: recording.header.name.clone(),
+        started_at: recording.header.started_at.clone(),
+        duration_ms: recording.entries.last().map_or(0, RecordEntry::time_ms),
+        frame_count,
+        input_count: recording.entries.len() - frame_count,
+        size_bytes,
+    })
+}
+
+/// List stored recordings
+#[tauri::command]
+pub fn recorder_list() -> Result<Vec<RecordingInfo>, String> {
+    let dir = storage::storage_dir(false)?.join(RECORDINGS_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to read {:?}: {}", dir, e))?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != RECORDING_EXTENSION {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_string())
+        })
+        .collect();
+    names.sort();
+
+    Ok(names
+        .into_iter()
+        .filter_map(|name| match recorder_info(name.clone()) {
+            Ok(info) => Some(info),
+            Err(e) => {
+                println!("Skipping recording {}: {}", name, e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Load a full recording (header and entries)
+#[tauri::command]
+pub fn recorder_load(name: String) -> Result<Recording, String> {
+    load_recording(&name)
+}
+
+/// Delete a stored recording
+#[tauri::command]
+pub fn recorder_delete(name: String) -> Result<(), String> {
+    let path = recording_path(&name, false)?;
+    fs::remove_file(&path).map_err(|e| format!("Failed to delete {:?}: {}", path, e))
+}
+
+/// Replay a recording at its original timing (scaled by `speed`)
+#[tauri::command]
+pub fn recorder_replay(
+    name: String,
+    options: ReplayOptions,
+    app: AppHandle,
+    state: State<RecorderState>,
+) -> Result<(), String> {
+    let recording = load_recording(&name)?;
+
+    let mut replay = state.replay.lock().unwrap();
+    if let Some(current) = replay.as_ref() {
+        return Err(format!("Already replaying {}", current.name));
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    let slot = state.replay.clone();
+    std::thread::Builder::new()
+        .name("recorder-replay".to_string())
+        .spawn(move || {
+            run_replay(app, recording, options, thread_stop);
+            *slot.lock().unwrap() = None;
+        })
+        .map_err(|e| format!("Failed to spawn replay thread: {}", e))?;
+
+    *replay = Some(Replay { name, stop });
+    Ok(())
+}
+
+/// Stop a running replay
+#[tauri::command]
+pub fn recorder_stop_replay(state: State<RecorderState>) -> bool {
+    match state.replay.lock().unwrap().as_ref() {
+        Some(replay) => {
+            replay.stop.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Compare the output frames of two recordings (`tolerance` is the largest
+/// per-channel difference still treated as equal)
+#[tauri::command]
+pub fn recorder_diff(expected: String, actual: String, tolerance: Option<u8>) -> Result<RecordingDiff, String> {
+    let a = load_recording(&expected)?;
+    let b = load_recording(&actual)?;
+    diff_recordings(&a, &b, tolerance.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lighting::LightBackend;
+
+    fn target(id: &str) -> LightTarget {
+        LightTarget::device(LightBackend::Wled, id)
+    }
+
+    fn rgb(r: u8, g: u8, b: u8) -> RGBColor {
+        RGBColor { r, g, b }
+    }
+
+    fn frame(t: u64, frames: &[(&str, &str)]) -> RecordEntry {
+        RecordEntry::Frame {
+            t,
+            frames: frames
+                .iter()
+                .map(|(id, colors)| PackedFrame {
+                    target: target(id),
+                    colors: colors.to_string(),
+                })
+                .collect(),
+            temperatures: Vec::new(),
+        }
+    }
+
+    fn recording(entries: Vec<RecordEntry>) -> Recording {
+        Recording {
+            header: RecordingHeader {
+                version: FORMAT_VERSION,
+                name: "test".to_string(),
+                started_at: "2024-01-01T00:00:00Z".to_string(),
+            },
+            entries,
+        }
+    }
+
+    /// Path for a recording in a scratch directory of its own
+    fn scratch_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("musicviz-recorder-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(format!("{}.{}", name, RECORDING_EXTENSION))
+    }
+
+    #[test]
+    fn colors_pack_to_hex_and_back() {
+        let colors = vec![rgb(255, 0, 0), rgb(0, 128, 255), rgb(1, 2, 3)];
+        let packed = pack_frame(&TargetFrame {
+            target: target("10.0.0.1"),
+            colors: colors.clone(),
+        });
+
+        assert_eq!(packed.colors, "ff00000080ff010203");
+        assert_eq!(unpack_colors(&packed.colors).unwrap(), colors);
+        assert_eq!(unpack_colors("").unwrap(), Vec::<RGBColor>::new());
+        assert_eq!(unpack_colors("FFA500").unwrap(), vec![rgb(255, 165, 0)]);
+    }
+
+    #[test]
+    fn malformed_packed_colors_are_rejected() {
+        assert!(unpack_colors("fff").is_err());
+        assert!(unpack_colors("zz0000").is_err());
+        assert!(unpack_colors("ff00é0").is_err());
+        assert!(unpack_colors("+f0000").is_err());
+    }
+
+    #[test]
+    fn recording_round_trips_through_the_file() {
+        let path = scratch_path("round-trip");
+        let state = RecorderState::default();
+        *state.active.lock().unwrap() = Some(create_recording(&path, "round-trip").unwrap());
+
+        let output = CalibratedOutput {
+            frames: vec![TargetFrame {
+                target: target("10.0.0.1"),
+                colors: vec![rgb(255, 0, 0), rgb(0, 0, 255)],
+            }],
+            temperatures: vec![TemperatureCommand {
+                target: target("10.0.0.2"),
+                kelvin: 2700,
+                brightness: 128,
+            }],
+        };
+        state.record_input(&AudioFeatures {
+            energy: 0.5,
+            beat: true,
+            ..Default::default()
+        });
+        state.record_output(&output);
+        let mut active = state.active.lock().unwrap().take().unwrap();
+        assert_eq!(active.entries, 2);
+        active.writer.flush().unwrap();
+
+        let loaded = read_recording(&path, "round-trip").unwrap();
+        assert_eq!(loaded.header.name, "round-trip");
+        assert_eq!(loaded.header.version, FORMAT_VERSION);
+        match &loaded.entries[0] {
+            RecordEntry::Input { features, .. } => assert!(features.beat && features.energy == 0.5),
+            other => panic!("expected input, got {:?}", other),
+        }
+        let (_, frames, temperatures) = loaded.output_frames().next().unwrap();
+        assert_eq!(unpack_frame(&frames[0]).unwrap().colors, output.frames[0].colors);
+        assert_eq!(temperatures[0].kelvin, 2700);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn existing_recording_is_not_overwritten() {
+        let path = scratch_path("existing");
+        drop(create_recording(&path, "existing").unwrap());
+        let before = fs::read(&path).unwrap();
+
+        let error = create_recording(&path, "existing").err().unwrap();
+
+        assert_eq!(error, "Recording existing already exists");
+        assert_eq!(fs::read(&path).unwrap(), before);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn identical_recordings_have_no_differences() {
+        let a = recording(vec![
+            frame(0, &[("a", "ff0000")]),
+            RecordEntry::Input { t: 10, features: AudioFeatures::default() },
+            frame(33, &[("a", "00ff00")]),
+        ]);
+        let b = recording(vec![frame(2, &[("a", "ff0000")]), frame(30, &[("a", "00ff00")])]);
+
+        let diff = diff_recordings(&a, &b, 0).unwrap();
+
+        assert!(diff.identical);
+        assert_eq!((diff.frames_a, diff.frames_b), (2, 2));
+        assert_eq!(diff.max_timing_drift_ms, 3);
+        assert!(diff.differences.is_empty());
+    }
+
+    #[test]
+    fn color_differences_respect_the_tolerance() {
+        let a = recording(vec![frame(0, &[("a", "ff0000"), ("b", "000000")])]);
+        let b = recording(vec![frame(0, &[("a", "fa0000"), ("b", "000000")])]);
+
+        assert!(diff_recordings(&a, &b, 5).unwrap().identical);
+
+        let diff = diff_recordings(&a, &b, 4).unwrap();
+        assert!(!diff.identical);
+        assert_eq!((diff.mismatched_frames, diff.mismatched_targets, diff.max_delta), (1, 1, 5));
+        let difference = &diff.differences[0];
+        assert_eq!(difference.target, target("a"));
+        assert_eq!(difference.expected, Some(vec![rgb(255, 0, 0)]));
+        assert_eq!(difference.actual, Some(vec![rgb(250, 0, 0)]));
+    }
+
+    #[test]
+    fn missing_targets_frames_and_temperatures_differ() {
+        let a = recording(vec![frame(0, &[("a", "ff0000"), ("b", "ff0000")]), frame(33, &[])]);
+        let b = recording(vec![frame(0, &[("a", "ff0000")])]);
+
+        let diff = diff_recordings(&a, &b, 0).unwrap();
+        assert!(!diff.identical);
+        assert_eq!(diff.frames_b, 1);
+        assert_eq!(diff.max_delta, u8::MAX);
+        assert_eq!(diff.differences[0].actual, None);
+
+        let warm = RecordEntry::Frame {
+            t: 0,
+            frames: Vec::new(),
+            temperatures: vec![TemperatureCommand { target: target("c"), kelvin: 2700, brightness: 255 }],
+        };
+        let cool = RecordEntry::Frame {
+            t: 0,
+            frames: Vec::new(),
+            temperatures: vec![TemperatureCommand { target: target("c"), kelvin: 6500, brightness: 255 }],
+        };
+        let diff = diff_recordings(&recording(vec![warm]), &recording(vec![cool]), 0).unwrap();
+        assert_eq!((diff.temperature_mismatches, diff.mismatched_frames), (1, 1));
+        assert!(!diff.identical);
+    }
+
+    #[test]
+    fn diff_lists_a_bounded_number_of_differences() {
+        let a = recording((0..150).map(|t| frame(t, &[("a", "000000")])).collect());
+        let b = recording((0..150).map(|t| frame(t, &[("a", "ffffff")])).collect());
+
+        let diff = diff_recordings(&a, &b, 0).unwrap();
+
+        assert_eq!(diff.mismatched_targets, 150);
+        assert_eq!(diff.differences.len(), MAX_LISTED_DIFFERENCES);
+    }
+}
@@ -0,0 +1,582 @@
+// Spatial Room Layout
+//
+// Persists named rooms and the position, orientation and tags of every
+// light (or light segment) so effects can be computed from where a light is
+// instead of its index in the device list. Spatial fields are sampled at each
+// placement to produce per-light colors; the sync engine does so every tick
+// and sends the result through the sync layer.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+use crate::effects::EffectsState;
+use crate::govee::RGBColor;
+use crate::layers::{self, LayersState};
+use crate::lighting::{FlushReport, LightBackend, LightTarget, TargetFrame};
+use crate::storage;
+
+const LAYOUT_FILE: &str = "room_layout.json";
+
+/// Position in meters relative to the room origin (x = left→right,
+/// y = front→back, z = floor→ceiling)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub z: f32,
+}
+
+/// Orientation in degrees
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Orientation {
+    #[serde(default)]
+    pub yaw: f32,
+    #[serde(default)]
+    pub pitch: f32,
+    #[serde(default)]
+    pub roll: f32,
+}
+
+/// Named room with its dimensions in meters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Room {
+    pub id: String,
+    pub name: String,
+    pub width: f32,
+    pub depth: f32,
+    #[serde(default)]
+    pub height: f32,
+}
+
+/// Where a light (or segment) sits within a room
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DevicePlacement {
+    pub target: LightTarget,
+    #[serde(rename = "roomId")]
+    pub room_id: String,
+    pub position: Vec3,
+    #[serde(default)]
+    pub orientation: Orientation,
+    /// Free-form tags such as "floor", "ceiling" or "behind-tv"
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoomLayout {
+    pub rooms: Vec<Room>,
+    pub placements: Vec<DevicePlacement>,
+}
+
+impl RoomLayout {
+    fn room(&self, room_id: &str) -> Option<&Room> {
+        self.rooms.iter().find(|room| room.id == room_id)
+    }
+
+    /// Room a target is placed in (segments fall back to their device's placement)
+    pub fn room_of(&self, target: &LightTarget) -> Option<&str> {
+        self.placements
+            .iter()
+            .find(|placement| placement.target == *target)
+            .or_else(|| {
+                self.placements.iter().find(|placement| {
+                    placement.target.segment.is_none()
+                        && placement.target.backend == target.backend
+                        && placement.target.device_id == target.device_id
+                })
+            })
+            .map(|placement| placement.room_id.as_str())
+    }
+
+    /// Sample a field at every placement, optionally restricted to one room
+    pub fn sample(&self, room_id: Option<&str>, field: &SpatialField, inputs: &FieldInputs) -> Vec<PlacementColor> {
+        self.placements
+            .iter()
+            .filter(|placement| room_id.is_none_or(|id| placement.room_id == id))
+            .map(|placement| {
+                let point = normalized_position(placement, self.room(&placement.room_id));
+                PlacementColor {
+                    target: placement.target.clone(),
+                    color: field.sample(point, &placement.tags, inputs),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Room layout state shared with Tauri commands
+pub struct RoomLayoutState {
+    layout: Mutex<RoomLayout>,
+}
+
+impl RoomLayoutState {
+    pub fn new() -> Self {
+        let layout = match storage::load_json::<RoomLayout>(LAYOUT_FILE) {
+            Ok(layout) => layout.unwrap_or_default(),
+            Err(e) => {
+                println!("Failed to load room layout: {}", e);
+                RoomLayout::default()
+            }
+        };
+
+        Self {
+            layout: Mutex::new(layout),
+        }
+    }
+
+    pub fn snapshot(&self) -> RoomLayout {
+        self.layout.lock().unwrap().clone()
+    }
+
+    /// Replace every placement of a device and its segments at once (used
+    /// when a backend reports where its segments are, e.g. Nanoleaf panels)
+    pub fn replace_device_placements(
+        &self,
+        backend: LightBackend,
+        device_id: &str,
+        placements: Vec<DevicePlacement>,
+    ) -> Result<RoomLayout, String> {
+        self.update(|layout| {
+            if let Some(placement) = placements.iter().find(|placement| layout.room(&placement.room_id).is_none()) {
+                return Err(format!("Room not found: {}", placement.room_id));
+            }
+
+            layout
+                .placements
+                .retain(|placement| placement.target.backend != backend || placement.target.device_id != device_id);
+            layout.placements.extend(placements);
+            Ok(())
+        })
+    }
+
+    /// Change a copy of the layout and keep it only once it has been saved
+    fn update(&self, change: impl FnOnce(&mut RoomLayout) -> Result<(), String>) -> Result<RoomLayout, String> {
+        self.update_with(change, save_layout)
+    }
+
+    fn update_with(
+        &self,
+        change: impl FnOnce(&mut RoomLayout) -> Result<(), String>,
+        save: impl FnOnce(&RoomLayout) -> Result<(), String>,
+    ) -> Result<RoomLayout, String> {
+        let mut layout = self.layout.lock().unwrap();
+        let mut updated = layout.clone();
+        change(&mut updated)?;
+        save(&updated)?;
+
+        *layout = updated.clone();
+        Ok(updated)
+    }
+}
+
+/// Axis of the room used by directional fields
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// Audio band that scales a field's intensity
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioBand {
+    Bass,
+    Mid,
+    Treble,
+    Energy,
+}
+
+/// Per-tick inputs for sampling a field (levels are 0.0 - 1.0)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FieldInputs {
+    #[serde(rename = "timeMs", default)]
+    pub time_ms: f64,
+    #[serde(default)]
+    pub bass: f32,
+    #[serde(default)]
+    pub mid: f32,
+    #[serde(default)]
+    pub treble: f32,
+    #[serde(default)]
+    pub energy: f32,
+}
+
+impl FieldInputs {
+    fn level(&self, band: AudioBand) -> f32 {
+        let level = match band {
+            AudioBand::Bass => self.bass,
+            AudioBand::Mid => self.mid,
+            AudioBand::Treble => self.treble,
+            AudioBand::Energy => self.energy,
+        };
+        level.clamp(0.0, 1.0)
+    }
+}
+
+/// Color and optional audio band applied to lights carrying a tag
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagStyle {
+    pub tag: String,
+    pub color: RGBColor,
+    #[serde(default)]
+    pub band: Option<AudioBand>,
+}
+
+/// Color field defined over normalized room coordinates (0.0 - 1.0 per axis,
+/// whatever the room's size in meters)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SpatialField {
+    /// Same color everywhere
+    Solid { color: RGBColor },
+    /// Linear blend between two colors along an axis
+    Gradient { axis: Axis, from: RGBColor, to: RGBColor },
+    /// Band of color moving along an axis; negative speed reverses direction
+    Sweep {
+        axis: Axis,
+        color: RGBColor,
+        background: RGBColor,
+        /// Band half-width in normalized units
+        width: f32,
+        /// Sweeps per second
+        speed: f32,
+    },
+    /// Color fading out from a point
+    Radial {
+        /// Center in normalized room coordinates (not meters)
+        center: Vec3,
+        /// Distance in normalized units at which `outer` is reached
+        radius: f32,
+        inner: RGBColor,
+        outer: RGBColor,
+    },
+    /// Color by tag (first matching style wins), scaled by an audio band
+    Tags {
+        styles: Vec<TagStyle>,
+        fallback: RGBColor,
+    },
+}
+
+impl SpatialField {
+    /// Sample the field at a normalized point for a light with the given tags
+    pub fn sample(&self, point: Vec3, tags: &[String], inputs: &FieldInputs) -> RGBColor {
+        match self {
+            SpatialField::Solid { color } => color.clone(),
+            SpatialField::Gradient { axis, from, to } => mix(from, to, axis_value(point, *axis)),
+            SpatialField::Sweep { axis, color, background, width, speed } => {
+                let center = (inputs.time_ms / 1000.0 * *speed as f64).rem_euclid(1.0) as f32;
+                let distance = (axis_value(point, *axis) - center).abs();
+                let amount = if *width > 0.0 { 1.0 - distance / width } else { 0.0 };
+                mix(background, color, amount)
+            }
+            SpatialField::Radial { center, radius, inner, outer } => {
+                let dx = point.x - center.x;
+                let dy = point.y - center.y;
+                let dz = point.z - center.z;
+                let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+                let amount = if *radius > 0.0 { distance / radius } else { 1.0 };
+                mix(inner, outer, amount)
+            }
+            SpatialField::Tags { styles, fallback } => styles
+                .iter()
+                .find(|style| tags.iter().any(|tag| tag.eq_ignore_ascii_case(&style.tag)))
+                .map(|style| match style.band {
+                    Some(band) => scale(&style.color, inputs.level(band)),
+                    None => style.color.clone(),
+                })
+                .unwrap_or_else(|| fallback.clone()),
+        }
+    }
+}
+
+/// Color sampled for one placement
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlacementColor {
+    pub target: LightTarget,
+    pub color: RGBColor,
+}
+
+/// Position scaled to 0.0 - 1.0 by room dimensions (0.5 when unknown)
+fn normalized_position(placement: &DevicePlacement, room: Option<&Room>) -> Vec3 {
+    let normalize = |value: f32, size: Option<f32>| match size {
+        Some(size) if size > 0.0 => (value / size).clamp(0.0, 1.0),
+        _ => 0.5,
+    };
+
+    Vec3 {
+        x: normalize(placement.position.x, room.map(|r| r.width)),
+        y: normalize(placement.position.y, room.map(|r| r.depth)),
+        z: normalize(placement.position.z, room.map(|r| r.height)),
+    }
+}
+
+fn axis_value(point: Vec3, axis: Axis) -> f32 {
+    match axis {
+        Axis::X => point.x,
+        Axis::Y => point.y,
+        Axis::Z => point.z,
+    }
+}
+
+fn mix(a: &RGBColor, b: &RGBColor, amount: f32) -> RGBColor {
+    let t = amount.clamp(0.0, 1.0);
+    let lerp = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * t).round() as u8;
+    RGBColor {
+        r: lerp(a.r, b.r),
+        g: lerp(a.g, b.g),
+        b: lerp(a.b, b.b),
+    }
+}
+
+fn scale(color: &RGBColor, amount: f32) -> RGBColor {
+    mix(&RGBColor { r: 0, g: 0, b: 0 }, color, amount)
+}
+
+fn save_layout(layout: &RoomLayout) -> Result<(), String> {
+    storage::save_json(LAYOUT_FILE, layout)
+}
+
+/// Get the full room layout
+#[tauri::command]
+pub fn room_layout_get(state: State<RoomLayoutState>) -> RoomLayout {
+    state.snapshot()
+}
+
+/// Create or update a room
+#[tauri::command]
+pub fn room_layout_upsert_room(room: Room, state: State<RoomLayoutState>) -> Result<RoomLayout, String> {
+    if room.id.is_empty() {
+        return Err("Room id must not be empty".to_string());
+    }
+    if room.width < 0.0 || room.depth < 0.0 || room.height < 0.0 {
+        return Err("Room dimensions must not be negative".to_string());
+    }
+
+    state.update(|layout| {
+        match layout.rooms.iter_mut().find(|existing| existing.id == room.id) {
+            Some(existing) => *existing = room,
+            None => layout.rooms.push(room),
+        }
+        Ok(())
+    })
+}
+
+/// Remove a room together with every placement in it
+#[tauri::command]
+pub fn room_layout_remove_room(room_id: String, state: State<RoomLayoutState>) -> Result<RoomLayout, String> {
+    state.update(|layout| {
+        layout.rooms.retain(|room| room.id != room_id);
+        layout.placements.retain(|placement| placement.room_id != room_id);
+        Ok(())
+    })
+}
+
+/// Place a light (or segment) in a room, replacing any previous placement
+#[tauri::command]
+pub fn room_layout_set_placement(placement: DevicePlacement, state: State<RoomLayoutState>) -> Result<RoomLayout, String> {
+    state.update(|layout| {
+        if layout.room(&placement.room_id).is_none() {
+            return Err(format!("Room not found: {}", placement.room_id));
+        }
+
+        match layout.placements.iter_mut().find(|existing| existing.target == placement.target) {
+            Some(existing) => *existing = placement,
+            None => layout.placements.push(placement),
+        }
+        Ok(())
+    })
+}
+
+/// Remove a light (or segment) from the layout
+#[tauri::command]
+pub fn room_layout_remove_placement(target: LightTarget, state: State<RoomLayoutState>) -> Result<RoomLayout, String> {
+    state.update(|layout| {
+        layout.placements.retain(|placement| placement.target != target);
+        Ok(())
+    })
+}
+
+/// Get all placements carrying a tag
+#[tauri::command]
+pub fn room_layout_find_by_tag(tag: String, state: State<RoomLayoutState>) -> Vec<DevicePlacement> {
+    state
+        .layout
+        .lock()
+        .unwrap()
+        .placements
+        .iter()
+        .filter(|placement| placement.tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)))
+        .cloned()
+        .collect()
+}
+
+/// Sample a spatial field at every placed light (inputs default to the
+/// latest audio features)
+#[tauri::command]
+pub fn room_layout_sample_field(
+    room_id: Option<String>,
+    field: SpatialField,
+    inputs: Option<FieldInputs>,
+    state: State<RoomLayoutState>,
+    effects: State<EffectsState>,
+) -> Vec<PlacementColor> {
+    let inputs = inputs.unwrap_or_else(|| FieldInputs::from(&effects.features()));
+    state
+        .layout
+        .lock()
+        .unwrap()
+        .sample(room_id.as_deref(), &field, &inputs)
+}
+
+/// Sample a spatial field with the latest audio features and send it to the
+/// placed lights through the sync layer (called once per sync tick)
+#[tauri::command]
+pub fn room_layout_sync_field(
+    room_id: Option<String>,
+    field: SpatialField,
+    app: AppHandle,
+    state: State<RoomLayoutState>,
+    effects: State<EffectsState>,
+    layers_state: State<LayersState>,
+) -> Result<FlushReport, String> {
+    let inputs = FieldInputs::from(&effects.features());
+    let frames = state
+        .snapshot()
+        .sample(room_id.as_deref(), &field, &inputs)
+        .into_iter()
+        .map(|placement| TargetFrame {
+            target: placement.target,
+            colors: vec![placement.color],
+        })
+        .collect();
+
+    layers::submit(&app, &layers_state, "sync", frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lighting::LightBackend;
+
+    fn layout() -> RoomLayout {
+        let placement = |id: &str, x: f32| DevicePlacement {
+            target: LightTarget::device(LightBackend::Govee, id),
+            room_id: "living".to_string(),
+            position: Vec3 { x, y: 2.0, z: 0.0 },
+            orientation: Orientation::default(),
+            tags: Vec::new(),
+        };
+
+        RoomLayout {
+            rooms: vec![Room {
+                id: "living".to_string(),
+                name: "Living room".to_string(),
+                width: 6.0,
+                depth: 4.0,
+                height: 0.0,
+            }],
+            placements: vec![placement("left", 0.0), placement("middle", 3.0), placement("right", 6.0)],
+        }
+    }
+
+    fn red(colors: &[PlacementColor]) -> Vec<u8> {
+        colors.iter().map(|placement| placement.color.r).collect()
+    }
+
+    #[test]
+    fn gradient_follows_positions_in_the_room() {
+        let field = SpatialField::Gradient {
+            axis: Axis::X,
+            from: RGBColor { r: 0, g: 0, b: 0 },
+            to: RGBColor { r: 200, g: 0, b: 0 },
+        };
+
+        let colors = layout().sample(None, &field, &FieldInputs::default());
+        assert_eq!(red(&colors), vec![0, 100, 200]);
+    }
+
+    #[test]
+    fn radial_center_is_in_normalized_coordinates() {
+        let field = SpatialField::Radial {
+            center: Vec3 { x: 1.0, y: 0.5, z: 0.5 },
+            radius: 1.0,
+            inner: RGBColor { r: 200, g: 0, b: 0 },
+            outer: RGBColor { r: 0, g: 0, b: 0 },
+        };
+
+        let colors = layout().sample(Some("living"), &field, &FieldInputs::default());
+        assert_eq!(red(&colors), vec![0, 100, 200]);
+    }
+
+    #[test]
+    fn failed_saves_leave_the_layout_unchanged() {
+        let state = RoomLayoutState {
+            layout: Mutex::new(layout()),
+        };
+
+        let error = state
+            .update_with(
+                |layout| {
+                    layout.rooms.clear();
+                    layout.placements.clear();
+                    Ok(())
+                },
+                |_| Err("disk full".to_string()),
+            )
+            .unwrap_err();
+
+        assert_eq!(error, "disk full");
+        assert_eq!(state.snapshot().rooms.len(), 1);
+        assert_eq!(state.snapshot().placements.len(), 3);
+    }
+
+    #[test]
+    fn rejected_changes_are_not_saved() {
+        let state = RoomLayoutState {
+            layout: Mutex::new(layout()),
+        };
+
+        let mut saved = false;
+        let result = state.update_with(
+            |layout| {
+                layout.placements.clear();
+                Err("Room not found: attic".to_string())
+            },
+            |_| {
+                saved = true;
+                Ok(())
+            },
+        );
+
+        assert_eq!(result.unwrap_err(), "Room not found: attic");
+        assert!(!saved);
+        assert_eq!(state.snapshot().placements.len(), 3);
+    }
+
+    #[test]
+    fn saved_changes_are_kept() {
+        let state = RoomLayoutState {
+            layout: Mutex::new(layout()),
+        };
+
+        let updated = state
+            .update_with(
+                |layout| {
+                    layout.placements.retain(|placement| placement.target.device_id != "middle");
+                    Ok(())
+                },
+                |layout| match layout.placements.len() {
+                    2 => Ok(()),
+                    n => Err(format!("saved {} placements", n)),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(updated.placements.len(), 2);
+        assert_eq!(state.snapshot().placements.len(), 2);
+    }
+}
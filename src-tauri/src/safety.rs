@@ -0,0 +1,614 @@
+// Photosensitivity Safety Limiter
+//
+// Last stage of the lighting pipeline. Follows WCAG 2.3 (three flashes)
+// and the Harding guidance: a flash is a pair of opposing relative
+// luminance changes of at least 10% where the darker state is below 0.80,
+// and saturated red transitions count as flashes of their own. When a frame
+// would exceed the flash rate (or a single-frame luminance step cap), the
+// change is pulled back towards the previous output so it is no longer a
+// transition. Direct device commands pass through it as well (without the
+// step cap, as no frames follow to finish a ramp). Enabled by default,
+// configurable per room, and every intervention is logged.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::State;
+
+use crate::govee::RGBColor;
+use crate::lighting::{average_color, LightTarget, TargetFrame};
+use crate::room_layout::RoomLayout;
+use crate::storage;
+
+const SAFETY_FILE: &str = "safety.json";
+/// Window over which flashes are counted
+const FLASH_WINDOW_MS: f64 = 1000.0;
+/// Darker state must be below this relative luminance for a change to count
+const DARK_STATE_LIMIT: f32 = 0.8;
+/// Saturated red: red share of the color (WCAG 2.3 definition)
+const SATURATED_RED_RATIO: f32 = 0.8;
+/// Red flash threshold on the (R - G - B) * 320 scale
+const RED_FLASH_THRESHOLD: f32 = 20.0;
+/// Recent events kept for the log
+const MAX_LOG_EVENTS: usize = 200;
+
+/// Limiter settings (global default or per room)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SafetyConfig {
+    pub enabled: bool,
+    #[serde(rename = "maxFlashesPerSecond")]
+    pub max_flashes_per_second: f32,
+    /// Relative luminance change that counts as a flash transition
+    #[serde(rename = "flashThreshold")]
+    pub flash_threshold: f32,
+    /// Largest relative luminance change allowed in a single frame
+    #[serde(rename = "maxLuminanceStep")]
+    pub max_luminance_step: f32,
+    /// Count saturated red transitions as flashes
+    #[serde(rename = "limitRed")]
+    pub limit_red: bool,
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_flashes_per_second: 3.0,
+            flash_threshold: 0.1,
+            max_luminance_step: 0.5,
+            limit_red: true,
+        }
+    }
+}
+
+impl SafetyConfig {
+    /// Transitions allowed per flash window (a flash is two transitions)
+    fn allowed_transitions(&self) -> usize {
+        (self.max_flashes_per_second * 2.0).floor() as usize
+    }
+}
+
+/// Default settings plus per-room overrides
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SafetySettings {
+    pub default: SafetyConfig,
+    #[serde(default)]
+    pub rooms: HashMap<String, SafetyConfig>,
+}
+
+impl SafetySettings {
+    fn for_room(&self, room_id: Option<&str>) -> &SafetyConfig {
+        room_id
+            .and_then(|room_id| self.rooms.get(room_id))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Why the limiter changed a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InterventionReason {
+    FlashRate,
+    RedFlash,
+    LuminanceStep,
+}
+
+/// One logged intervention
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SafetyEvent {
+    /// Milliseconds since the limiter started
+    #[serde(rename = "timeMs")]
+    pub time_ms: f64,
+    /// Light target id, or visual source name
+    pub source: String,
+    #[serde(rename = "roomId")]
+    pub room_id: Option<String>,
+    pub reason: InterventionReason,
+}
+
+/// Intervention counters and recent events
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SafetyLog {
+    pub total: u64,
+    #[serde(rename = "byRoom")]
+    pub by_room: HashMap<String, u64>,
+    #[serde(rename = "byReason")]
+    pub by_reason: HashMap<String, u64>,
+    pub recent: VecDeque<SafetyEvent>,
+}
+
+impl SafetyLog {
+    fn record(&mut self, event: SafetyEvent) {
+        self.total += 1;
+        let room = event.room_id.clone().unwrap_or_else(|| "unassigned".to_string());
+        *self.by_room.entry(room).or_default() += 1;
+        *self.by_reason.entry(format!("{:?}", event.reason)).or_default() += 1;
+
+        self.recent.push_back(event);
+        while self.recent.len() > MAX_LOG_EVENTS {
+            self.recent.pop_front();
+        }
+    }
+}
+
+/// WCAG relative luminance (0.0 - 1.0)
+fn relative_luminance(color: &RGBColor) -> f32 {
+    let linear = |channel: u8| {
+        let c = channel as f32 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(color.r) + 0.7152 * linear(color.g) + 0.0722 * linear(color.b)
+}
+
+/// Saturated red measure on the WCAG (R - G - B) * 320 scale (0 when not saturated red)
+fn red_level(color: &RGBColor) -> f32 {
+    let (r, g, b) = (color.r as f32 / 255.0, color.g as f32 / 255.0, color.b as f32 / 255.0);
+    let sum = r + g + b;
+    if sum <= 0.0 || r / sum < SATURATED_RED_RATIO {
+        return 0.0;
+    }
+    ((r - g - b) * 320.0).max(0.0)
+}
+
+fn mix(a: &RGBColor, b: &RGBColor, amount: f32) -> RGBColor {
+    let t = amount.clamp(0.0, 1.0);
+    let lerp = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * t).round() as u8;
+    RGBColor {
+        r: lerp(a.r, b.r),
+        g: lerp(a.g, b.g),
+        b: lerp(a.b, b.b),
+    }
+}
+
+/// Follows one signal (luminance or red level) and detects transitions: a
+/// change of at least `threshold` against the running extreme, in the
+/// opposite direction to the previous transition
+#[derive(Debug, Default, Clone)]
+struct TransitionTracker {
+    extreme: f32,
+    /// 1 after a rising transition, -1 after a falling one, 0 before any
+    direction: i8,
+}
+
+impl TransitionTracker {
+    fn reset(&mut self, value: f32) {
+        self.extreme = value;
+        self.direction = 0;
+    }
+
+    fn would_transition(&self, value: f32, threshold: f32, dark_limit: f32) -> bool {
+        let darker = value.min(self.extreme);
+        let falling = self.direction >= 0 && self.extreme - value >= threshold;
+        let rising = self.direction <= 0 && value - self.extreme >= threshold;
+        (falling || rising) && darker < dark_limit
+    }
+
+    /// Advance with a new value, returning whether it was a transition
+    fn update(&mut self, value: f32, threshold: f32, dark_limit: f32) -> bool {
+        if self.would_transition(value, threshold, dark_limit) {
+            self.direction = if value > self.extreme { 1 } else { -1 };
+            self.extreme = value;
+            return true;
+        }
+
+        // Keep following the extreme in the current direction
+        let extends = match self.direction {
+            1 => value > self.extreme,
+            -1 => value < self.extreme,
+            _ => false,
+        };
+        if extends {
+            self.extreme = value;
+        }
+        false
+    }
+}
+
+/// Flash history of one light or visual source
+#[derive(Debug, Default)]
+struct FlashAnalyzer {
+    last: Option<RGBColor>,
+    last_colors: Vec<RGBColor>,
+    luminance: TransitionTracker,
+    red: TransitionTracker,
+    transitions: VecDeque<f64>,
+}
+
+/// Result of running one frame through the analyzer
+struct Verdict {
+    /// Blend factor from the previous output to the requested frame
+    amount: f32,
+    reason: Option<InterventionReason>,
+    transition: bool,
+    red_transition: bool,
+    flashes_per_second: f32,
+}
+
+impl FlashAnalyzer {
+    /// Whether a color would register as a (luminance, red) transition
+    fn would_transition(&self, color: &RGBColor, config: &SafetyConfig) -> (bool, bool) {
+        let luminance = self
+            .luminance
+            .would_transition(relative_luminance(color), config.flash_threshold, DARK_STATE_LIMIT);
+        let red = config.limit_red && self.red.would_transition(red_level(color), RED_FLASH_THRESHOLD, f32::INFINITY);
+        (luminance, red)
+    }
+
+    /// Forget transitions that left the flash window
+    fn expire(&mut self, now_ms: f64) {
+        while self.transitions.front().is_some_and(|&t| now_ms - t > FLASH_WINDOW_MS) {
+            self.transitions.pop_front();
+        }
+    }
+
+    /// Decide how much of the requested change may pass and update history
+    fn analyze(&mut self, requested: &RGBColor, now_ms: f64, config: &SafetyConfig) -> Verdict {
+        self.expire(now_ms);
+
+        let Some(last) = self.last.clone() else {
+            self.luminance.reset(relative_luminance(requested));
+            self.red.reset(red_level(requested));
+            self.last = Some(requested.clone());
+            return Verdict {
+                amount: 1.0,
+                reason: None,
+                transition: false,
+                red_transition: false,
+                flashes_per_second: 0.0,
+            };
+        };
+
+        let mut amount = 1.0f32;
+        let mut reason = None;
+
+        // Cap the single-frame luminance step
+        let step = (relative_luminance(requested) - relative_luminance(&last)).abs();
+        if step > config.max_luminance_step {
+            amount = config.max_luminance_step / step;
+            reason = Some(InterventionReason::LuminanceStep);
+        }
+
+        // Hold back changes that would exceed the flash rate
+        let allowed = config.allowed_transitions();
+        let (luminance, red) = self.would_transition(&mix(&last, requested, amount), config);
+        if (luminance || red) && self.transitions.len() >= allowed {
+            reason = Some(if luminance {
+                InterventionReason::FlashRate
+            } else {
+                InterventionReason::RedFlash
+            });
+
+            // Largest blend that stays below both transition thresholds
+            let (mut low, mut high) = (0.0f32, amount);
+            for _ in 0..12 {
+                let mid = (low + high) / 2.0;
+                let (l, r) = self.would_transition(&mix(&last, requested, mid), config);
+                if l || r {
+                    high = mid;
+                } else {
+                    low = mid;
+                }
+            }
+            amount = low;
+        }
+
+        let output = mix(&last, requested, amount);
+        let transition = self
+            .luminance
+            .update(relative_luminance(&output), config.flash_threshold, DARK_STATE_LIMIT);
+        let red_transition = config.limit_red && self.red.update(red_level(&output), RED_FLASH_THRESHOLD, f32::INFINITY);
+        if transition || red_transition {
+            self.transitions.push_back(now_ms);
+        }
+        self.last = Some(output);
+
+        Verdict {
+            amount,
+            reason,
+            transition,
+            red_transition,
+            flashes_per_second: self.transitions.len() as f32 / 2.0,
+        }
+    }
+}
+
+/// Safety limiter state for Tauri
+pub struct SafetyState {
+    settings: Mutex<SafetySettings>,
+    analyzers: Mutex<HashMap<LightTarget, FlashAnalyzer>>,
+    visuals: Mutex<HashMap<String, FlashAnalyzer>>,
+    log: Mutex<SafetyLog>,
+    started: Instant,
+}
+
+impl SafetyState {
+    pub fn new() -> Self {
+        let settings = match storage::load_json::<SafetySettings>(SAFETY_FILE) {
+            Ok(settings) => settings.unwrap_or_default(),
+            Err(e) => {
+                println!("Failed to load safety settings: {}", e);
+                SafetySettings::default()
+            }
+        };
+
+        Self::with_settings(settings)
+    }
+
+    pub(crate) fn with_settings(settings: SafetySettings) -> Self {
+        Self {
+            settings: Mutex::new(settings),
+            analyzers: Mutex::new(HashMap::new()),
+            visuals: Mutex::new(HashMap::new()),
+            log: Mutex::new(SafetyLog::default()),
+            started: Instant::now(),
+        }
+    }
+
+    fn now_ms(&self) -> f64 {
+        self.started.elapsed().as_secs_f64() * 1000.0
+    }
+
+    /// Limit a batch of frames about to be sent to the lights
+    pub fn limit(&self, frames: &[TargetFrame], layout: &RoomLayout) -> Vec<TargetFrame> {
+        self.limit_with(frames, layout, true)
+    }
+
+    /// Limit one-shot device commands. No further frames follow to finish a
+    /// ramp, so only the flash rate applies, not the single-frame step cap.
+    pub fn limit_direct(&self, frames: &[TargetFrame], layout: &RoomLayout) -> Vec<TargetFrame> {
+        self.limit_with(frames, layout, false)
+    }
+
+    /// Whether a power switch may go ahead. Switching counts as a full
+    /// transition, so it is refused once the flash rate is used up.
+    pub fn allow_switch(&self, target: &LightTarget, layout: &RoomLayout) -> bool {
+        let now = self.now_ms();
+        let settings = self.settings.lock().unwrap();
+        let room_id = layout.room_of(target);
+        let config = settings.for_room(room_id);
+        if !config.enabled {
+            return true;
+        }
+
+        let mut analyzers = self.analyzers.lock().unwrap();
+        let analyzer = analyzers.entry(target.clone()).or_default();
+        analyzer.expire(now);
+        if analyzer.transitions.len() >= config.allowed_transitions() {
+            self.log.lock().unwrap().record(SafetyEvent {
+                time_ms: now,
+                source: format!("{:?}:{}", target.backend, target.device_id),
+                room_id: room_id.map(str::to_string),
+                reason: InterventionReason::FlashRate,
+            });
+            return false;
+        }
+
+        analyzer.transitions.push_back(now);
+        true
+    }
+
+    fn limit_with(&self, frames: &[TargetFrame], layout: &RoomLayout, step_cap: bool) -> Vec<TargetFrame> {
+        let now = self.now_ms();
+        let settings = self.settings.lock().unwrap();
+        let mut analyzers = self.analyzers.lock().unwrap();
+        let mut log = self.log.lock().unwrap();
+
+        frames
+            .iter()
+            .map(|frame| {
+                let room_id = layout.room_of(&frame.target);
+                let config = settings.for_room(room_id);
+                if !config.enabled || frame.colors.is_empty() {
+                    return frame.clone();
+                }
+
+                let uncapped;
+                let config = if step_cap {
+                    config
+                } else {
+                    uncapped = SafetyConfig {
+                        max_luminance_step: f32::INFINITY,
+                        ..config.clone()
+                    };
+                    &uncapped
+                };
+
+                let analyzer = analyzers.entry(frame.target.clone()).or_default();
+                let verdict = analyzer.analyze(&average_color(&frame.colors), now, config);
+
+                let colors = if verdict.amount >= 1.0 {
+                    frame.colors.clone()
+                } else {
+                    let fallback = analyzer.last_colors.last().cloned().unwrap_or(RGBColor { r: 0, g: 0, b: 0 });
+                    frame
+                        .colors
+                        .iter()
+                        .enumerate()
+                        .map(|(i, color)| mix(analyzer.last_colors.get(i).unwrap_or(&fallback), color, verdict.amount))
+                        .collect()
+                };
+                analyzer.last_colors = colors.clone();
+
+                if let Some(reason) = verdict.reason {
+                    log.record(SafetyEvent {
+                        time_ms: now,
+                        source: format!("{:?}:{}", frame.target.backend, frame.target.device_id),
+                        room_id: room_id.map(str::to_string),
+                        reason,
+                    });
+                }
+
+                TargetFrame {
+                    target: frame.target.clone(),
+                    colors,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Analysis of one visualization frame
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VisualFrameAnalysis {
+    /// Frame changes luminance enough to count as a flash transition
+    pub transition: bool,
+    #[serde(rename = "redTransition")]
+    pub red_transition: bool,
+    /// Flashes counted over the last second
+    #[serde(rename = "flashesPerSecond")]
+    pub flashes_per_second: f32,
+    /// How much of the change from the previous frame is safe (1.0 = all)
+    #[serde(rename = "safeAmount")]
+    pub safe_amount: f32,
+    /// Average color the visual should be limited to
+    #[serde(rename = "limitedColor")]
+    pub limited_color: RGBColor,
+    pub reason: Option<InterventionReason>,
+}
+
+/// Get the default and per-room limiter settings
+#[tauri::command]
+pub fn safety_get_settings(state: State<SafetyState>) -> SafetySettings {
+    state.settings.lock().unwrap().clone()
+}
+
+/// Set the default limiter config, or a room override when `room_id` is given
+#[tauri::command]
+pub fn safety_set_config(config: SafetyConfig, room_id: Option<String>, state: State<SafetyState>) -> Result<(), String> {
+    if config.max_flashes_per_second <= 0.0 || config.flash_threshold <= 0.0 || config.max_luminance_step <= 0.0 {
+        return Err("Safety limits must be positive".to_string());
+    }
+
+    let mut settings = state.settings.lock().unwrap();
+    match room_id {
+        Some(room_id) => {
+            settings.rooms.insert(room_id, config);
+        }
+        None => settings.default = config,
+    }
+    storage::save_json(SAFETY_FILE, &*settings)
+}
+
+/// Remove a room override so the room uses the default config again
+#[tauri::command]
+pub fn safety_remove_room_config(room_id: String, state: State<SafetyState>) -> Result<(), String> {
+    let mut settings = state.settings.lock().unwrap();
+    settings.rooms.remove(&room_id);
+    storage::save_json(SAFETY_FILE, &*settings)
+}
+
+/// Get the intervention log
+#[tauri::command]
+pub fn safety_get_log(state: State<SafetyState>) -> SafetyLog {
+    state.log.lock().unwrap().clone()
+}
+
+/// Reset the intervention log
+#[tauri::command]
+pub fn safety_clear_log(state: State<SafetyState>) {
+    *state.log.lock().unwrap() = SafetyLog::default();
+}
+
+/// Run a visualization frame (its average color) through the same analysis
+#[tauri::command]
+pub fn safety_analyze_visual_frame(
+    source: String,
+    color: RGBColor,
+    time_ms: f64,
+    state: State<SafetyState>,
+) -> VisualFrameAnalysis {
+    let config = state.settings.lock().unwrap().default.clone();
+    let mut visuals = state.visuals.lock().unwrap();
+    let analyzer = visuals.entry(source.clone()).or_default();
+
+    let previous = analyzer.last.clone().unwrap_or_else(|| color.clone());
+    let verdict = if config.enabled {
+        analyzer.analyze(&color, time_ms, &config)
+    } else {
+        Verdict {
+            amount: 1.0,
+            reason: None,
+            transition: false,
+            red_transition: false,
+            flashes_per_second: 0.0,
+        }
+    };
+
+    if let Some(reason) = verdict.reason {
+        state.log.lock().unwrap().record(SafetyEvent {
+            time_ms: state.now_ms(),
+            source,
+            room_id: None,
+            reason,
+        });
+    }
+
+    VisualFrameAnalysis {
+        transition: verdict.transition,
+        red_transition: verdict.red_transition,
+        flashes_per_second: verdict.flashes_per_second,
+        safe_amount: verdict.amount,
+        limited_color: mix(&previous, &color, verdict.amount),
+        reason: verdict.reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lighting::LightBackend;
+
+    fn target() -> LightTarget {
+        LightTarget::device(LightBackend::Govee, "test")
+    }
+
+    fn frame(level: u8) -> TargetFrame {
+        TargetFrame {
+            target: target(),
+            colors: vec![RGBColor { r: level, g: level, b: level }],
+        }
+    }
+
+    #[test]
+    fn step_cap_applies_to_frames_but_not_direct_commands() {
+        let layout = RoomLayout::default();
+
+        let state = SafetyState::with_settings(SafetySettings::default());
+        state.limit(&[frame(0)], &layout);
+        assert!(state.limit(&[frame(255)], &layout)[0].colors[0].r < 255);
+
+        let state = SafetyState::with_settings(SafetySettings::default());
+        state.limit_direct(&[frame(0)], &layout);
+        assert_eq!(state.limit_direct(&[frame(255)], &layout)[0].colors[0].r, 255);
+    }
+
+    #[test]
+    fn direct_strobe_is_held_back() {
+        let layout = RoomLayout::default();
+        let state = SafetyState::with_settings(SafetySettings::default());
+
+        let levels: Vec<u8> = (0..20)
+            .map(|i| state.limit_direct(&[frame(if i % 2 == 0 { 0 } else { 255 })], &layout)[0].colors[0].r)
+            .collect();
+        let swings = levels.windows(2).filter(|pair| pair[0].abs_diff(pair[1]) > 128).count();
+        assert!(swings <= SafetyConfig::default().allowed_transitions(), "{:?}", levels);
+    }
+
+    #[test]
+    fn power_switches_stop_at_flash_rate() {
+        let layout = RoomLayout::default();
+        let state = SafetyState::with_settings(SafetySettings::default());
+
+        let allowed = SafetyConfig::default().allowed_transitions();
+        for _ in 0..allowed {
+            assert!(state.allow_switch(&target(), &layout));
+        }
+        assert!(!state.allow_switch(&target(), &layout));
+        assert_eq!(state.log.lock().unwrap().total, 1);
+    }
+}
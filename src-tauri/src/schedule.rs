@@ -0,0 +1,625 @@
+// Lighting Schedules
+//
+// Weekday/time rules (quiet hours) that cap brightness and disable strobe
+// type effects for device groups, plus a circadian color temperature curve
+// that takes over the lights while music is idle. Rules are evaluated in a
+// configurable IANA timezone (system local time by default).
+
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, NaiveTime, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+use crate::calibration::kelvin_to_rgb;
+use crate::govee::RGBColor;
+use crate::layers::{self, LayersState};
+use crate::lighting::{FlushReport, LightTarget, TargetFrame};
+use crate::room_layout::{RoomLayout, RoomLayoutState};
+use crate::storage;
+
+const SCHEDULE_FILE: &str = "schedule.json";
+const CIRCADIAN_LAYER: &str = "circadian";
+/// Audio energy above which music counts as playing
+const MUSIC_ENERGY_THRESHOLD: f32 = 0.02;
+/// How often the backend re-evaluates the circadian curve
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Named set of lights: explicit targets plus everything in the listed rooms
+/// or carrying the listed tags
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceGroup {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub targets: Vec<LightTarget>,
+    #[serde(default)]
+    pub rooms: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl DeviceGroup {
+    fn contains(&self, target: &LightTarget, layout: &RoomLayout) -> bool {
+        if self.targets.contains(target) {
+            return true;
+        }
+
+        let same_device = |other: &LightTarget| {
+            other.backend == target.backend && other.device_id == target.device_id
+        };
+        if self.targets.iter().any(|t| t.segment.is_none() && same_device(t)) {
+            return true;
+        }
+
+        layout.placements.iter().any(|placement| {
+            (placement.target == *target || (placement.target.segment.is_none() && same_device(&placement.target)))
+                && (self.rooms.contains(&placement.room_id)
+                    || placement.tags.iter().any(|tag| self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))))
+        })
+    }
+
+    /// Every target the group resolves to
+    fn resolve(&self, layout: &RoomLayout) -> Vec<LightTarget> {
+        let mut targets = self.targets.clone();
+        for placement in &layout.placements {
+            if self.contains(&placement.target, layout) && !targets.contains(&placement.target) {
+                targets.push(placement.target.clone());
+            }
+        }
+        targets
+    }
+}
+
+/// Quiet hours / brightness rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleRule {
+    pub id: String,
+    pub name: String,
+    pub enabled: bool,
+    /// Days the rule starts on (empty = every day)
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// Local start time "HH:MM"
+    pub start: String,
+    /// Local end time "HH:MM"; earlier than start wraps past midnight
+    pub end: String,
+    /// Group ids the rule applies to (empty = all lights)
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Brightness cap (0.0 - 1.0)
+    #[serde(rename = "maxBrightness", default)]
+    pub max_brightness: Option<f32>,
+    #[serde(rename = "disableStrobe", default)]
+    pub disable_strobe: bool,
+}
+
+impl ScheduleRule {
+    fn is_active(&self, now: NaiveDateTime) -> Result<bool, String> {
+        if !self.enabled {
+            return Ok(false);
+        }
+
+        let start = parse_time(&self.start)?;
+        let end = parse_time(&self.end)?;
+        let time = now.time();
+        let on_day = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+
+        Ok(if start <= end {
+            on_day(now.weekday()) && time >= start && time < end
+        } else if time >= start {
+            on_day(now.weekday())
+        } else {
+            // After midnight: the window started the day before
+            time < end && on_day(now.weekday().pred())
+        })
+    }
+}
+
+/// Point on the circadian curve
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircadianPoint {
+    /// Local time "HH:MM"
+    pub time: String,
+    pub kelvin: u16,
+    /// Brightness 0.0 - 1.0
+    pub brightness: f32,
+}
+
+/// Color temperature curve followed while music is idle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircadianConfig {
+    pub enabled: bool,
+    /// Group ids to drive (empty = every placed light)
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Seconds without music before the curve takes over
+    #[serde(rename = "idleSeconds")]
+    pub idle_seconds: u32,
+    pub curve: Vec<CircadianPoint>,
+}
+
+impl Default for CircadianConfig {
+    fn default() -> Self {
+        let point = |time: &str, kelvin: u16, brightness: f32| CircadianPoint {
+            time: time.to_string(),
+            kelvin,
+            brightness,
+        };
+
+        Self {
+            enabled: false,
+            groups: Vec::new(),
+            idle_seconds: 300,
+            curve: vec![
+                point("06:00", 2700, 0.4),
+                point("09:00", 4000, 0.8),
+                point("12:00", 5500, 1.0),
+                point("17:00", 4000, 0.8),
+                point("20:00", 2700, 0.5),
+                point("23:00", 2200, 0.2),
+            ],
+        }
+    }
+}
+
+impl CircadianConfig {
+    /// Interpolated (kelvin, brightness) at a local time, wrapping at midnight
+    fn sample(&self, time: NaiveTime) -> Result<Option<(u16, f32)>, String> {
+        let mut points = self
+            .curve
+            .iter()
+            .map(|point| Ok((minutes(parse_time(&point.time)?), point)))
+            .collect::<Result<Vec<_>, String>>()?;
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let (Some(first), Some(last)) = (points.first(), points.last()) else {
+            return Ok(None);
+        };
+
+        let now = minutes(time);
+        let (before, after) = match points.iter().position(|(at, _)| *at > now) {
+            Some(0) | None => (*last, *first),
+            Some(index) => (points[index - 1], points[index]),
+        };
+
+        let span = (after.0 - before.0).rem_euclid(24.0 * 60.0);
+        let t = if span > 0.0 {
+            (now - before.0).rem_euclid(24.0 * 60.0) / span
+        } else {
+            0.0
+        };
+        let kelvin = before.1.kelvin as f32 + (after.1.kelvin as f32 - before.1.kelvin as f32) * t;
+        let brightness = before.1.brightness + (after.1.brightness - before.1.brightness) * t;
+
+        Ok(Some((kelvin.round() as u16, brightness.clamp(0.0, 1.0))))
+    }
+}
+
+/// All schedule configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScheduleSettings {
+    /// IANA timezone such as "Europe/Berlin" (system local time when unset)
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub groups: Vec<DeviceGroup>,
+    #[serde(default)]
+    pub rules: Vec<ScheduleRule>,
+    #[serde(default)]
+    pub circadian: CircadianConfig,
+}
+
+impl ScheduleSettings {
+    fn validate(&self) -> Result<(), String> {
+        self.local_now()?;
+        for rule in &self.rules {
+            parse_time(&rule.start)?;
+            parse_time(&rule.end)?;
+            if let Some(group) = rule.groups.iter().find(|id| !self.groups.iter().any(|g| &g.id == *id)) {
+                return Err(format!("Rule {} references unknown group {}", rule.name, group));
+            }
+        }
+        for point in &self.circadian.curve {
+            parse_time(&point.time)?;
+        }
+        Ok(())
+    }
+
+    fn local_now(&self) -> Result<NaiveDateTime, String> {
+        self.local_time(Utc::now())
+    }
+
+    /// Wall-clock time in the configured timezone at an instant
+    fn local_time(&self, at: DateTime<Utc>) -> Result<NaiveDateTime, String> {
+        match &self.timezone {
+            Some(name) => {
+                let tz: Tz = name.parse().map_err(|_| format!("Unknown timezone: {}", name))?;
+                Ok(at.with_timezone(&tz).naive_local())
+            }
+            None => Ok(at.with_timezone(&Local).naive_local()),
+        }
+    }
+
+    fn active_rules(&self, now: NaiveDateTime) -> Vec<&ScheduleRule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.is_active(now).unwrap_or(false))
+            .collect()
+    }
+
+    fn rule_applies(&self, rule: &ScheduleRule, target: &LightTarget, layout: &RoomLayout) -> bool {
+        rule.groups.is_empty()
+            || self
+                .groups
+                .iter()
+                .filter(|group| rule.groups.contains(&group.id))
+                .any(|group| group.contains(target, layout))
+    }
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| format!("Invalid time (expected HH:MM): {}", value))
+}
+
+fn minutes(time: NaiveTime) -> f32 {
+    time.hour() as f32 * 60.0 + time.minute() as f32 + time.second() as f32 / 60.0
+}
+
+/// Current schedule evaluation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleStatus {
+    #[serde(rename = "localTime")]
+    pub local_time: String,
+    pub timezone: String,
+    #[serde(rename = "activeRules")]
+    pub active_rules: Vec<String>,
+    #[serde(rename = "musicIdle")]
+    pub music_idle: bool,
+    #[serde(rename = "circadianActive")]
+    pub circadian_active: bool,
+    #[serde(rename = "circadianKelvin")]
+    pub circadian_kelvin: Option<u16>,
+    #[serde(rename = "circadianBrightness")]
+    pub circadian_brightness: Option<f32>,
+}
+
+/// Schedule state for Tauri
+pub struct ScheduleState {
+    settings: Mutex<ScheduleSettings>,
+    /// Whether the player last reported music playing
+    playing: Mutex<bool>,
+    last_music: Mutex<Instant>,
+    circadian_active: Mutex<bool>,
+}
+
+impl ScheduleState {
+    pub fn new() -> Self {
+        let settings = match storage::load_json::<ScheduleSettings>(SCHEDULE_FILE) {
+            Ok(settings) => settings.unwrap_or_default(),
+            Err(e) => {
+                println!("Failed to load schedule: {}", e);
+                ScheduleSettings::default()
+            }
+        };
+        Self::with_settings(settings)
+    }
+
+    pub(crate) fn with_settings(settings: ScheduleSettings) -> Self {
+        Self {
+            settings: Mutex::new(settings),
+            playing: Mutex::new(false),
+            last_music: Mutex::new(Instant::now()),
+            circadian_active: Mutex::new(false),
+        }
+    }
+
+    /// Record audio activity from the analyzer
+    pub fn note_audio_energy(&self, energy: f32) {
+        if energy > MUSIC_ENERGY_THRESHOLD {
+            *self.last_music.lock().unwrap() = Instant::now();
+        }
+    }
+
+    fn music_idle(&self, settings: &ScheduleSettings) -> bool {
+        !*self.playing.lock().unwrap()
+            && self.last_music.lock().unwrap().elapsed() >= Duration::from_secs(settings.circadian.idle_seconds as u64)
+    }
+
+    /// Apply active brightness caps to outgoing frames
+    pub fn limit(&self, frames: Vec<TargetFrame>, layout: &RoomLayout) -> Vec<TargetFrame> {
+        let settings = self.settings.lock().unwrap();
+        let Ok(now) = settings.local_now() else {
+            return frames;
+        };
+        let caps: Vec<(&ScheduleRule, f32)> = settings
+            .active_rules(now)
+            .into_iter()
+            .filter_map(|rule| rule.max_brightness.map(|cap| (rule, cap.clamp(0.0, 1.0))))
+            .collect();
+        if caps.is_empty() {
+            return frames;
+        }
+
+        frames
+            .into_iter()
+            .map(|mut frame| {
+                let cap = caps
+                    .iter()
+                    .filter(|(rule, _)| settings.rule_applies(rule, &frame.target, layout))
+                    .map(|(_, cap)| *cap)
+                    .fold(1.0f32, f32::min);
+
+                if cap < 1.0 {
+                    let limit = cap * 255.0;
+                    for color in frame.colors.iter_mut() {
+                        let peak = color.r.max(color.g).max(color.b) as f32;
+                        if peak > limit {
+                            let scale = |v: u8| (v as f32 * limit / peak).round() as u8;
+                            *color = RGBColor { r: scale(color.r), g: scale(color.g), b: scale(color.b) };
+                        }
+                    }
+                }
+                frame
+            })
+            .collect()
+    }
+
+    /// Whether strobe-type effects may run on these targets right now
+    pub fn strobe_allowed(&self, targets: &[LightTarget], layout: &RoomLayout) -> bool {
+        let settings = self.settings.lock().unwrap();
+        let Ok(now) = settings.local_now() else {
+            return true;
+        };
+
+        !settings.active_rules(now).into_iter().any(|rule| {
+            rule.disable_strobe && targets.iter().any(|target| settings.rule_applies(rule, target, layout))
+        })
+    }
+}
+
+/// Get schedule configuration
+#[tauri::command]
+pub fn schedule_get_settings(state: State<ScheduleState>) -> ScheduleSettings {
+    state.settings.lock().unwrap().clone()
+}
+
+/// Replace schedule configuration
+#[tauri::command]
+pub fn schedule_set_settings(settings: ScheduleSettings, state: State<ScheduleState>) -> Result<(), String> {
+    settings.validate()?;
+    storage::save_json(SCHEDULE_FILE, &settings)?;
+    *state.settings.lock().unwrap() = settings;
+    Ok(())
+}
+
+/// Report music playing/paused (e.g. from the Spotify player state)
+#[tauri::command]
+pub fn schedule_report_music_activity(playing: bool, state: State<ScheduleState>) {
+    // Pausing starts the idle countdown; playing holds it off entirely
+    *state.playing.lock().unwrap() = playing;
+    *state.last_music.lock().unwrap() = Instant::now();
+}
+
+/// Evaluate rules and the circadian curve at the current local time
+#[tauri::command]
+pub fn schedule_get_status(state: State<ScheduleState>) -> Result<ScheduleStatus, String> {
+    let settings = state.settings.lock().unwrap();
+    let now = settings.local_now()?;
+    let circadian = settings.circadian.sample(now.time())?;
+
+    Ok(ScheduleStatus {
+        local_time: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+        timezone: settings.timezone.clone().unwrap_or_else(|| "local".to_string()),
+        active_rules: settings.active_rules(now).into_iter().map(|rule| rule.id.clone()).collect(),
+        music_idle: state.music_idle(&settings),
+        circadian_active: *state.circadian_active.lock().unwrap(),
+        circadian_kelvin: circadian.map(|(kelvin, _)| kelvin),
+        circadian_brightness: circadian.map(|(_, brightness)| brightness),
+    })
+}
+
+/// Drive the circadian layer while music is idle and release it when music
+/// returns
+fn tick(app: &AppHandle, state: &ScheduleState) -> Result<FlushReport, String> {
+    let layers_state = app.state::<LayersState>();
+    let (idle, frames) = {
+        let settings = state.settings.lock().unwrap();
+        let idle = settings.circadian.enabled && state.music_idle(&settings);
+        if !idle {
+            (false, Vec::new())
+        } else {
+            let now = settings.local_now()?;
+            let Some((kelvin, brightness)) = settings.circadian.sample(now.time())? else {
+                return Ok(FlushReport::default());
+            };
+
+            let layout = app.state::<RoomLayoutState>().snapshot();
+            let mut targets: Vec<LightTarget> = if settings.circadian.groups.is_empty() {
+                layout.placements.iter().map(|placement| placement.target.clone()).collect()
+            } else {
+                settings
+                    .groups
+                    .iter()
+                    .filter(|group| settings.circadian.groups.contains(&group.id))
+                    .flat_map(|group| group.resolve(&layout))
+                    .collect()
+            };
+            targets.sort();
+            targets.dedup();
+
+            let white = kelvin_to_rgb(kelvin);
+            let scale = |v: u8| (v as f32 * brightness).round() as u8;
+            let color = RGBColor { r: scale(white.r), g: scale(white.g), b: scale(white.b) };
+            let frames = targets
+                .into_iter()
+                .map(|target| TargetFrame {
+                    target,
+                    colors: vec![color.clone()],
+                })
+                .collect();
+            (true, frames)
+        }
+    };
+
+    let mut active = state.circadian_active.lock().unwrap();
+    if !idle {
+        if *active {
+            *active = false;
+            layers::layers_clear(CIRCADIAN_LAYER.to_string(), None, layers_state)?;
+        }
+        return Ok(FlushReport::default());
+    }
+
+    *active = true;
+    layers::submit(app, &layers_state, CIRCADIAN_LAYER, frames)
+}
+
+/// Evaluate the schedule once per second for the lifetime of the app
+pub fn start_timer(app: &AppHandle) {
+    let app = app.clone();
+    if let Err(e) = std::thread::Builder::new()
+        .name("schedule".to_string())
+        .spawn(move || loop {
+            if let Err(e) = tick(&app, &app.state::<ScheduleState>()) {
+                println!("Schedule tick failed: {}", e);
+            }
+            std::thread::sleep(TICK_INTERVAL);
+        })
+    {
+        println!("Failed to start schedule thread: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+
+    fn rule(days: Vec<Weekday>, start: &str, end: &str) -> ScheduleRule {
+        ScheduleRule {
+            id: "quiet".to_string(),
+            name: "Quiet hours".to_string(),
+            enabled: true,
+            days,
+            start: start.to_string(),
+            end: end.to_string(),
+            groups: Vec::new(),
+            max_brightness: Some(0.3),
+            disable_strobe: true,
+        }
+    }
+
+    /// 2024-01-05 is a Friday
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn same_day_window_is_half_open() {
+        let rule = rule(Vec::new(), "09:00", "17:00");
+        assert!(!rule.is_active(at(5, 8, 59)).unwrap());
+        assert!(rule.is_active(at(5, 9, 0)).unwrap());
+        assert!(!rule.is_active(at(5, 17, 0)).unwrap());
+    }
+
+    #[test]
+    fn rule_wraps_past_midnight() {
+        let rule = rule(Vec::new(), "22:00", "07:00");
+        assert!(!rule.is_active(at(5, 21, 59)).unwrap());
+        assert!(rule.is_active(at(5, 22, 0)).unwrap());
+        assert!(rule.is_active(at(6, 3, 0)).unwrap());
+        assert!(!rule.is_active(at(6, 7, 0)).unwrap());
+    }
+
+    #[test]
+    fn wrapped_window_belongs_to_the_day_it_started() {
+        // Friday night only: the early hours of Saturday are still covered,
+        // the early hours of Friday (Thursday's window) are not
+        let rule = rule(vec![Weekday::Fri], "22:00", "07:00");
+        assert!(rule.is_active(at(5, 23, 0)).unwrap());
+        assert!(rule.is_active(at(6, 6, 59)).unwrap());
+        assert!(!rule.is_active(at(5, 6, 0)).unwrap());
+        assert!(!rule.is_active(at(6, 23, 0)).unwrap());
+    }
+
+    #[test]
+    fn disabled_and_malformed_rules() {
+        let mut disabled = rule(Vec::new(), "00:00", "23:59");
+        disabled.enabled = false;
+        assert!(!disabled.is_active(at(5, 12, 0)).unwrap());
+
+        assert!(rule(Vec::new(), "25:00", "07:00").is_active(at(5, 12, 0)).is_err());
+    }
+
+    #[test]
+    fn rules_are_evaluated_in_the_configured_timezone() {
+        let settings = ScheduleSettings {
+            timezone: Some("America/New_York".to_string()),
+            rules: vec![rule(vec![Weekday::Fri], "22:00", "07:00")],
+            ..Default::default()
+        };
+
+        // Saturday 02:00 UTC is Friday 21:00 in New York (EST)
+        let local = settings.local_time(Utc.with_ymd_and_hms(2024, 1, 6, 2, 0, 0).unwrap()).unwrap();
+        assert_eq!(local, at(5, 21, 0));
+        assert!(settings.active_rules(local).is_empty());
+
+        // Saturday 04:00 UTC is Friday 23:00 in New York
+        let local = settings.local_time(Utc.with_ymd_and_hms(2024, 1, 6, 4, 0, 0).unwrap()).unwrap();
+        assert_eq!(settings.active_rules(local).len(), 1);
+
+        let unknown = ScheduleSettings {
+            timezone: Some("Mars/Olympus_Mons".to_string()),
+            ..Default::default()
+        };
+        assert!(unknown.validate().is_err());
+    }
+
+    #[test]
+    fn circadian_curve_hits_its_points() {
+        let circadian = CircadianConfig::default();
+        assert_eq!(circadian.sample(time(12, 0)).unwrap(), Some((5500, 1.0)));
+        assert_eq!(circadian.sample(time(6, 0)).unwrap(), Some((2700, 0.4)));
+    }
+
+    #[test]
+    fn circadian_curve_interpolates_between_points() {
+        let circadian = CircadianConfig::default();
+        let (kelvin, brightness) = circadian.sample(time(10, 30)).unwrap().unwrap();
+        assert_eq!(kelvin, 4750);
+        assert!((brightness - 0.9).abs() < 1e-4);
+    }
+
+    #[test]
+    fn circadian_curve_interpolates_across_midnight() {
+        // 23:00 (2200K, 0.2) to 06:00 (2700K, 0.4) spans seven hours
+        let circadian = CircadianConfig::default();
+
+        let (kelvin, brightness) = circadian.sample(time(23, 0)).unwrap().unwrap();
+        assert_eq!(kelvin, 2200);
+        assert!((brightness - 0.2).abs() < 1e-4);
+
+        // 3.5 hours in, before and after midnight
+        let (kelvin, brightness) = circadian.sample(time(2, 30)).unwrap().unwrap();
+        assert_eq!(kelvin, 2450);
+        assert!((brightness - 0.3).abs() < 1e-4);
+
+        let (kelvin, _) = circadian.sample(time(0, 0)).unwrap().unwrap();
+        assert_eq!(kelvin, 2271);
+    }
+
+    #[test]
+    fn empty_curve_has_no_sample() {
+        let circadian = CircadianConfig {
+            curve: Vec::new(),
+            ..Default::default()
+        };
+        assert_eq!(circadian.sample(time(12, 0)).unwrap(), None);
+    }
+}
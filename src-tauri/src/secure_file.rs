@@ -0,0 +1,502 @@
+// Encrypted file storage for secrets
+// Used when the OS keyring is unavailable. Files are sealed with
+// XChaCha20-Poly1305; the key lives in the keyring when possible, otherwise
+// in a device key file readable only by the current user. A device key is
+// mixed with the OS machine id, so a copy of the config directory (backup,
+// sync folder) can't be decrypted elsewhere. Writes go to a temporary file
+// that is fsynced and renamed over the target.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use keyring::Entry;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::storage;
+
+// Keychain entry holding the file encryption key
+const KEYRING_SERVICE: &str = "musicViz";
+const KEYRING_KEY_ACCOUNT: &str = "file_encryption_key";
+
+// Device key file used when the keyring cannot hold the key
+const DEVICE_KEY_FILE: &str = "device.key";
+
+// Envelope versions
+//   1: sealed with the stored key as is
+//   2: a device key file is bound to the machine id first
+const ENVELOPE_VERSION: u32 = 2;
+const LEGACY_ENVELOPE_VERSION: u32 = 1;
+const ENVELOPE_ALGORITHM: &str = "xchacha20poly1305";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+/// On-disk format of an encrypted file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Envelope {
+    v: u32,
+    alg: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Contents of a secret file
+pub enum SecretFile {
+    /// Decrypted contents of an encrypted file
+    Encrypted(Vec<u8>),
+    /// A file written before encryption; migrate it with `write_encrypted`
+    /// after `wipe_plaintext`
+    Plaintext(Vec<u8>),
+}
+
+/// Encrypt `plaintext` and atomically write it to the storage directory.
+/// The file name is bound to the ciphertext, so files cannot be swapped.
+pub fn write_encrypted(file_name: &str, plaintext: &[u8]) -> Result<(), String> {
+    let json = seal(&encryption_key()?.current()?, file_name, plaintext)?;
+    let path = storage::storage_dir(true)?.join(file_name);
+    write_atomic(&path, &json)
+}
+
+/// Read a secret file from the storage directory, decrypting it if it is
+/// encrypted. Returns None when the file does not exist.
+pub fn read(file_name: &str) -> Result<Option<SecretFile>, String> {
+    let path = storage::storage_dir(false)?.join(file_name);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let envelope = match serde_json::from_slice::<Envelope>(&contents) {
+        Ok(envelope) => envelope,
+        Err(_) => return Ok(Some(SecretFile::Plaintext(contents))),
+    };
+
+    let legacy = envelope.v == LEGACY_ENVELOPE_VERSION;
+    let plaintext = open(&encryption_key()?, file_name, &envelope)
+        .map_err(|e| format!("Failed to decrypt {:?}: {}", path, e))?;
+
+    // Seal files from before machine binding again with the bound key
+    if legacy {
+        write_encrypted(file_name, &plaintext)?;
+        println!("Re-encrypted {:?} with the machine-bound key", path);
+    }
+
+    Ok(Some(SecretFile::Encrypted(plaintext)))
+}
+
+/// Envelope JSON sealing `plaintext` to `file_name` with `key`
+fn seal(key: &[u8; KEY_LEN], file_name: &str, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let cipher = XChaCha20Poly1305::new(&Key::from(*key));
+
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(
+            &XNonce::from(nonce),
+            Payload {
+                msg: plaintext,
+                aad: file_name.as_bytes(),
+            },
+        )
+        .map_err(|_| format!("Failed to encrypt {}", file_name))?;
+
+    let envelope = Envelope {
+        v: ENVELOPE_VERSION,
+        alg: ENVELOPE_ALGORITHM.to_string(),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    };
+    serde_json::to_vec(&envelope).map_err(|e| format!("Failed to serialize {}: {}", file_name, e))
+}
+
+/// Decrypt an envelope read from `file_name` (v1 envelopes use the stored
+/// key as is)
+fn open(key: &FileKey, file_name: &str, envelope: &Envelope) -> Result<Vec<u8>, String> {
+    let legacy = envelope.v == LEGACY_ENVELOPE_VERSION;
+    if (envelope.v != ENVELOPE_VERSION && !legacy) || envelope.alg != ENVELOPE_ALGORITHM {
+        return Err(format!("unsupported format (v{} {})", envelope.v, envelope.alg));
+    }
+    let nonce: [u8; NONCE_LEN] = STANDARD
+        .decode(&envelope.nonce)
+        .ok()
+        .and_then(|nonce| nonce.try_into().ok())
+        .ok_or_else(|| "invalid nonce".to_string())?;
+    let ciphertext = STANDARD
+        .decode(&envelope.ciphertext)
+        .map_err(|e| format!("invalid ciphertext: {}", e))?;
+
+    let key = if legacy { key.stored } else { key.current()? };
+    XChaCha20Poly1305::new(&Key::from(key))
+        .decrypt(
+            &XNonce::from(nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: file_name.as_bytes(),
+            },
+        )
+        .map_err(|_| "wrong key or tampered file".to_string())
+}
+
+/// Overwrite a plaintext secret file with zeros before it is replaced
+pub fn wipe_plaintext(file_name: &str) -> Result<(), String> {
+    wipe(&storage::storage_dir(false)?.join(file_name))
+}
+
+fn wipe(path: &Path) -> Result<(), String> {
+    let len = fs::metadata(path)
+        .map_err(|e| format!("Failed to stat {:?}: {}", path, e))?
+        .len();
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open {:?} for wiping: {}", path, e))?;
+    file.write_all(&vec![0u8; len as usize])
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Failed to wipe {:?}: {}", path, e))
+}
+
+/// Delete a secret file, wiping it first
+pub fn delete(file_name: &str) -> Result<(), String> {
+    let path = storage::storage_dir(false)?.join(file_name);
+    if !path.exists() {
+        return Ok(());
+    }
+
+    if let Err(e) = wipe_plaintext(file_name) {
+        println!("Warning: {}", e);
+    }
+    fs::remove_file(&path).map_err(|e| format!("Failed to delete {:?}: {}", path, e))
+}
+
+/// Write `contents` to a temporary file readable only by the current user,
+/// fsync it and rename it over `path`
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("Invalid path {:?}", path))?
+        .to_string_lossy()
+        .into_owned();
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name));
+
+    let mut file = create_private(&tmp_path)?;
+    let written = file.write_all(contents).and_then(|_| file.sync_all());
+    drop(file);
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(format!("Failed to write {:?}: {}", tmp_path, e));
+    }
+
+    fs::rename(&tmp_path, path).map_err(|e| {
+        let _ = fs::remove_file(&tmp_path);
+        format!("Failed to replace {:?}: {}", path, e)
+    })?;
+
+    // Persist the rename itself
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        if let Err(e) = File::open(dir).and_then(|dir| dir.sync_all()) {
+            println!("Warning: Failed to sync directory {:?}: {}", dir, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Create (truncating) a file with 0600 permissions
+fn create_private(path: &Path) -> Result<File, String> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let file = options
+        .open(path)
+        .map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+
+    // mode() only applies to newly created files
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to restrict permissions of {:?}: {}", path, e))?;
+    }
+
+    Ok(file)
+}
+
+/// Stored file encryption key and where it came from
+struct FileKey {
+    stored: [u8; KEY_LEN],
+    device_file: bool,
+}
+
+impl FileKey {
+    /// Key for current envelopes: a device key file only works on the
+    /// machine that wrote it
+    fn current(&self) -> Result<[u8; KEY_LEN], String> {
+        if !self.device_file {
+            return Ok(self.stored);
+        }
+        Ok(bind_key(&self.stored, &machine_id()?))
+    }
+}
+
+fn bind_key(stored: &[u8; KEY_LEN], machine_id: &str) -> [u8; KEY_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"musicViz device key v2\0");
+    hasher.update(machine_id.as_bytes());
+    hasher.update([0]);
+    hasher.update(stored);
+    hasher.finalize().into()
+}
+
+/// Identifier of this OS installation
+fn machine_id() -> Result<String, String> {
+    #[cfg(target_os = "linux")]
+    {
+        ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .map(|id| id.trim().to_string())
+            .find(|id| !id.is_empty())
+            .ok_or_else(|| "No machine id found in /etc/machine-id".to_string())
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let output = std::process::Command::new("ioreg")
+            .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+            .output()
+            .map_err(|e| format!("Failed to read the platform UUID: {}", e))?;
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find(|line| line.contains("IOPlatformUUID"))
+            .and_then(|line| line.split('"').nth(3))
+            .map(|id| id.to_string())
+            .ok_or_else(|| "No platform UUID reported by ioreg".to_string())
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+        let output = std::process::Command::new("reg")
+            .args(["query", r"HKLM\SOFTWARE\Microsoft\Cryptography", "/v", "MachineGuid"])
+            .creation_flags(CREATE_NO_WINDOW)
+            .output()
+            .map_err(|e| format!("Failed to read the machine GUID: {}", e))?;
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find(|line| line.contains("MachineGuid"))
+            .and_then(|line| line.split_whitespace().last())
+            .map(|id| id.to_string())
+            .ok_or_else(|| "No machine GUID in the registry".to_string())
+    }
+
+    // App storage on mobile is private to the app and excluded from backups
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        Ok(String::new())
+    }
+}
+
+/// File encryption key: from the keyring, else from the device key file,
+/// generating one on first use
+fn encryption_key() -> Result<FileKey, String> {
+    let entry = Entry::new(KEYRING_SERVICE, KEYRING_KEY_ACCOUNT).ok();
+
+    let mut keyring_error = None;
+    if let Some(entry) = &entry {
+        match entry.get_password() {
+            Ok(encoded) => {
+                let stored =
+                    decode_key(&encoded).ok_or_else(|| "Invalid file encryption key in keyring".to_string())?;
+                return Ok(FileKey { stored, device_file: false });
+            }
+            Err(keyring::Error::NoEntry) => {}
+            Err(e) => keyring_error = Some(e),
+        }
+    }
+
+    // A device key file already in use keeps working even if the keyring
+    // became available later
+    let key_path = storage::storage_dir(true)?.join(DEVICE_KEY_FILE);
+    if key_path.exists() {
+        let encoded = fs::read_to_string(&key_path)
+            .map_err(|e| format!("Failed to read device key {:?}: {}", key_path, e))?;
+        let stored = decode_key(&encoded).ok_or_else(|| format!("Invalid device key in {:?}", key_path))?;
+        return Ok(FileKey { stored, device_file: true });
+    }
+
+    // The keyring may hold a key it can't hand out right now (locked,
+    // unreachable): a new one would orphan every file sealed with it
+    if let Some(e) = keyring_error {
+        return Err(format!("Failed to read file encryption key from keyring: {}", e));
+    }
+
+    let mut key = [0u8; KEY_LEN];
+    OsRng.fill_bytes(&mut key);
+    let encoded = STANDARD.encode(key);
+
+    if let Some(entry) = &entry {
+        // Read back through a new entry: a keyring that does not persist
+        // across entries would lose the key with the next launch
+        let stored = entry
+            .set_password(&encoded)
+            .and_then(|_| Entry::new(KEYRING_SERVICE, KEYRING_KEY_ACCOUNT))
+            .and_then(|check| check.get_password())
+            .map(|read_back| read_back == encoded);
+        if let Ok(true) = stored {
+            println!("Stored file encryption key in keyring");
+            return Ok(FileKey { stored: key, device_file: false });
+        }
+    }
+
+    println!("Keyring unavailable, storing file encryption key in {:?}", key_path);
+    write_atomic(&key_path, encoded.as_bytes())?;
+    Ok(FileKey { stored: key, device_file: true })
+}
+
+fn decode_key(encoded: &str) -> Option<[u8; KEY_LEN]> {
+    STANDARD.decode(encoded.trim()).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const DEVICE_KEY: FileKey = FileKey { stored: [9u8; KEY_LEN], device_file: true };
+
+    /// Empty scratch directory for one test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("musicviz-secure-file-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn envelope(json: &[u8]) -> Envelope {
+        serde_json::from_slice(json).unwrap()
+    }
+
+    #[test]
+    fn sealed_file_round_trips() {
+        let sealed = seal(&DEVICE_KEY.current().unwrap(), "tokens.json", b"refresh-token").unwrap();
+
+        assert!(!String::from_utf8_lossy(&sealed).contains("refresh-token"));
+        let envelope = envelope(&sealed);
+        assert_eq!(envelope.v, ENVELOPE_VERSION);
+        assert_eq!(open(&DEVICE_KEY, "tokens.json", &envelope).unwrap(), b"refresh-token");
+    }
+
+    #[test]
+    fn tampered_or_moved_files_are_rejected() {
+        let sealed = envelope(&seal(&DEVICE_KEY.current().unwrap(), "tokens.json", b"refresh-token").unwrap());
+
+        let mut ciphertext = STANDARD.decode(&sealed.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        let tampered = Envelope { ciphertext: STANDARD.encode(ciphertext), ..sealed.clone() };
+        assert!(open(&DEVICE_KEY, "tokens.json", &tampered).is_err());
+
+        // The file name is authenticated, so a copy under another name fails
+        assert!(open(&DEVICE_KEY, "credentials.vault", &sealed).is_err());
+
+        let other_key = FileKey { stored: [1u8; KEY_LEN], device_file: true };
+        assert!(open(&other_key, "tokens.json", &sealed).is_err());
+
+        let future = Envelope { v: ENVELOPE_VERSION + 1, ..sealed.clone() };
+        assert!(open(&DEVICE_KEY, "tokens.json", &future).unwrap_err().contains("unsupported format"));
+    }
+
+    #[test]
+    fn v1_envelope_opens_with_the_stored_key() {
+        let mut legacy = envelope(&seal(&DEVICE_KEY.stored, "tokens.json", b"refresh-token").unwrap());
+        legacy.v = LEGACY_ENVELOPE_VERSION;
+        assert_eq!(open(&DEVICE_KEY, "tokens.json", &legacy).unwrap(), b"refresh-token");
+
+        // The same ciphertext claiming v2 needs the machine-bound key
+        legacy.v = ENVELOPE_VERSION;
+        assert!(open(&DEVICE_KEY, "tokens.json", &legacy).is_err());
+    }
+
+    #[test]
+    fn plaintext_file_is_wiped_and_sealed() {
+        let dir = scratch_dir("migrate");
+        let path = dir.join("spotify_tokens.json");
+        let plaintext = br#"{"access_token":"a","refresh_token":"r"}"#;
+        fs::write(&path, plaintext).unwrap();
+
+        // Not an envelope: read() reports it as plaintext
+        assert!(serde_json::from_slice::<Envelope>(&fs::read(&path).unwrap()).is_err());
+
+        wipe(&path).unwrap();
+        let wiped = fs::read(&path).unwrap();
+        assert_eq!(wiped.len(), plaintext.len());
+        assert!(wiped.iter().all(|b| *b == 0));
+
+        write_atomic(&path, &seal(&DEVICE_KEY.current().unwrap(), "spotify_tokens.json", plaintext).unwrap()).unwrap();
+        let sealed = envelope(&fs::read(&path).unwrap());
+        assert_eq!(sealed.v, ENVELOPE_VERSION);
+        assert_eq!(open(&DEVICE_KEY, "spotify_tokens.json", &sealed).unwrap(), plaintext);
+        assert!(!dir.join(".spotify_tokens.json.tmp").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn written_files_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("permissions");
+        let path = dir.join("credentials.vault");
+        write_atomic(&path, b"sealed").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        // Replacing a file that was readable by others tightens it too
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_atomic(&path, b"sealed again").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read(&path).unwrap(), b"sealed again");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn device_key_is_bound_to_the_machine() {
+        let stored = [7u8; KEY_LEN];
+        let here = bind_key(&stored, "0f1e2d3c4b5a69788796a5b4c3d2e1f0");
+        let elsewhere = bind_key(&stored, "ffeeddccbbaa99887766554433221100");
+
+        assert_eq!(here, bind_key(&stored, "0f1e2d3c4b5a69788796a5b4c3d2e1f0"));
+        assert_ne!(here, elsewhere);
+        assert_ne!(here, stored);
+    }
+
+    #[test]
+    fn keyring_key_is_used_as_is() {
+        let key = FileKey { stored: [3u8; KEY_LEN], device_file: false };
+        assert_eq!(key.current().unwrap(), [3u8; KEY_LEN]);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+    #[test]
+    fn machine_id_is_stable() {
+        if let Ok(id) = machine_id() {
+            assert!(!id.is_empty());
+            assert_eq!(machine_id().unwrap(), id);
+        }
+    }
+}
@@ -0,0 +1,779 @@
+// Spotify Web API Client
+// Typed access to the Web API endpoints the app uses (/me, playback state,
+// currently playing, queue, devices, tracks, audio features). Requests carry
+// the access token from SpotifyAuthState, retry once after a forced refresh
+// on 401, honor 429 Retry-After and share one request budget so bursts from
+// different features cannot trip Spotify's rate limit. Models keep the Web
+// API's field names. Commands run the blocking client on a worker thread so
+// budget and Retry-After waits never stall the main thread.
+
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+use crate::spotify_auth::SpotifyAuthState;
+use crate::spotify_profiles::api_base;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Request budget: at most this many requests per rolling window
+const BUDGET_WINDOW: Duration = Duration::from_secs(30);
+const BUDGET_MAX_REQUESTS: usize = 100;
+
+// Wait at most this long for the budget or a Retry-After before failing
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(10);
+// Retry-After when Spotify sends a 429 without one
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+const MAX_RATE_LIMIT_RETRIES: usize = 2;
+
+// Most ids the batch endpoints accept per request
+const MAX_TRACK_IDS: usize = 50;
+const MAX_AUDIO_FEATURE_IDS: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Image {
+    pub url: String,
+    pub height: Option<u32>,
+    pub width: Option<u32>,
+}
+
+/// Current user (/me)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    pub country: Option<String>,
+    /// Subscription level ("premium", "free", ...)
+    pub product: Option<String>,
+    #[serde(default)]
+    pub images: Vec<Image>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimplifiedArtist {
+    pub id: Option<String>,
+    pub name: String,
+    pub uri: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimplifiedAlbum {
+    pub id: Option<String>,
+    pub name: String,
+    pub uri: Option<String>,
+    #[serde(default)]
+    pub images: Vec<Image>,
+    pub release_date: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Track {
+    /// None for local files
+    pub id: Option<String>,
+    pub name: String,
+    pub uri: String,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub explicit: bool,
+    pub popularity: Option<u32>,
+    #[serde(default)]
+    pub artists: Vec<SimplifiedArtist>,
+    pub album: Option<SimplifiedAlbum>,
+    pub preview_url: Option<String>,
+    #[serde(default)]
+    pub is_local: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimplifiedShow {
+    pub id: String,
+    pub name: String,
+    pub publisher: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Episode {
+    pub id: String,
+    pub name: String,
+    pub uri: String,
+    pub duration_ms: u64,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub images: Vec<Image>,
+    pub show: Option<SimplifiedShow>,
+}
+
+/// Something that can be playing or queued
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PlayableItem {
+    Track(Track),
+    Episode(Episode),
+}
+
+/// A Spotify Connect device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub device_type: String,
+    #[serde(default)]
+    pub is_active: bool,
+    #[serde(default)]
+    pub is_private_session: bool,
+    #[serde(default)]
+    pub is_restricted: bool,
+    pub volume_percent: Option<u32>,
+    #[serde(default)]
+    pub supports_volume: bool,
+}
+
+/// Album, playlist, artist or show being played
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybackContext {
+    #[serde(rename = "type")]
+    pub context_type: String,
+    pub uri: String,
+}
+
+/// Full playback state (/me/player)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybackState {
+    pub device: Device,
+    pub repeat_state: String,
+    pub shuffle_state: bool,
+    pub context: Option<PlaybackContext>,
+    pub timestamp: u64,
+    pub progress_ms: Option<u64>,
+    pub is_playing: bool,
+    pub item: Option<PlayableItem>,
+    pub currently_playing_type: String,
+}
+
+/// Currently playing item (/me/player/currently-playing)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurrentlyPlaying {
+    pub context: Option<PlaybackContext>,
+    pub timestamp: u64,
+    pub progress_ms: Option<u64>,
+    pub is_playing: bool,
+    pub item: Option<PlayableItem>,
+    pub currently_playing_type: String,
+}
+
+/// Playing item and upcoming queue (/me/player/queue)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Queue {
+    pub currently_playing: Option<PlayableItem>,
+    #[serde(default)]
+    pub queue: Vec<PlayableItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioFeatures {
+    pub id: String,
+    pub danceability: f32,
+    pub energy: f32,
+    /// Pitch class (-1 if no key was detected)
+    pub key: i32,
+    pub loudness: f32,
+    /// 1 major, 0 minor
+    pub mode: i32,
+    pub speechiness: f32,
+    pub acousticness: f32,
+    pub instrumentalness: f32,
+    pub liveness: f32,
+    pub valence: f32,
+    pub tempo: f32,
+    pub time_signature: u32,
+    pub duration_ms: u64,
+}
+
+/// Body options of /me/player/play
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StartPlaybackOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uris: Option<Vec<String>>,
+    /// {"position": n} or {"uri": "..."}
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Devices {
+    devices: Vec<Device>,
+}
+
+#[derive(Deserialize)]
+struct Tracks {
+    tracks: Vec<Option<Track>>,
+}
+
+#[derive(Deserialize)]
+struct AudioFeaturesList {
+    audio_features: Vec<Option<AudioFeatures>>,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    error: ApiErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorDetail {
+    message: String,
+}
+
+/// Requests in the current window and any Retry-After block
+#[derive(Default)]
+struct RequestBudget {
+    sent: VecDeque<Instant>,
+    blocked_until: Option<Instant>,
+}
+
+impl RequestBudget {
+    /// Take a slot, or return how long to wait for one
+    fn reserve(&mut self, now: Instant) -> Result<(), Duration> {
+        if let Some(until) = self.blocked_until {
+            if until > now {
+                return Err(until - now);
+            }
+            self.blocked_until = None;
+        }
+
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= BUDGET_WINDOW)
+        {
+            self.sent.pop_front();
+        }
+
+        if self.sent.len() >= BUDGET_MAX_REQUESTS {
+            return Err(BUDGET_WINDOW - now.duration_since(self.sent[0]));
+        }
+
+        self.sent.push_back(now);
+        Ok(())
+    }
+}
+
+/// Web API client state
+pub struct SpotifyApiState {
+    client: Client,
+    base: String,
+    budget: Mutex<RequestBudget>,
+}
+
+impl SpotifyApiState {
+    pub fn new() -> Self {
+        Self::with_base(api_base())
+    }
+
+    fn with_base(base: String) -> Self {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_else(|e| {
+                println!("Failed to configure Spotify API client: {}", e);
+                Client::new()
+            });
+
+        SpotifyApiState {
+            client,
+            base,
+            budget: Mutex::new(RequestBudget::default()),
+        }
+    }
+
+    /// Wait for a slot in the request budget
+    fn acquire(&self) -> Result<(), String> {
+        loop {
+            let wait = match self.budget.lock().unwrap().reserve(Instant::now()) {
+                Ok(()) => return Ok(()),
+                Err(wait) => wait,
+            };
+            if wait > MAX_RATE_LIMIT_WAIT {
+                return Err(format!(
+                    "Spotify API rate limit reached; retry in {}s",
+                    wait.as_secs().max(1)
+                ));
+            }
+            thread::sleep(wait);
+        }
+    }
+
+    /// Hold all requests until `retry_after` has passed
+    fn block_for(&self, retry_after: Duration) {
+        let until = Instant::now() + retry_after;
+        let mut budget = self.budget.lock().unwrap();
+        if budget.blocked_until.is_none_or(|blocked| blocked < until) {
+            budget.blocked_until = Some(until);
+        }
+    }
+
+    /// Send a request, returning the body of a successful response (None
+    /// for 204 No Content)
+    fn send(
+        &self,
+        auth: &SpotifyAuthState,
+        method: Method,
+        path: &str,
+        build: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> Result<Option<String>, String> {
+        let url = format!("{}{}", self.base, path);
+        let mut refreshed = false;
+        let mut rate_limited = 0;
+
+        loop {
+            self.acquire()?;
+            let token = auth.valid_access_token()?;
+
+            let response = build(self.client.request(method.clone(), &url))
+                .bearer_auth(&token)
+                .send()
+                .map_err(|e| format!("Spotify API request failed: {}", e))?;
+            let status = response.status();
+
+            if status == StatusCode::UNAUTHORIZED && !refreshed {
+                // Token revoked or expired early: refresh once and retry
+                println!("Spotify API returned 401 for {}; refreshing token", path);
+                auth.force_refresh()?;
+                refreshed = true;
+                continue;
+            }
+
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs)
+                    .unwrap_or(DEFAULT_RETRY_AFTER);
+                self.block_for(retry_after);
+
+                println!("Spotify API rate limited; retry after {}s", retry_after.as_secs());
+                if rate_limited < MAX_RATE_LIMIT_RETRIES && retry_after <= MAX_RATE_LIMIT_WAIT {
+                    rate_limited += 1;
+                    continue;
+                }
+                return Err(format!(
+                    "Spotify API rate limit reached; retry in {}s",
+                    retry_after.as_secs().max(1)
+                ));
+            }
+
+            if status == StatusCode::NO_CONTENT {
+                return Ok(None);
+            }
+
+            let body = response
+                .text()
+                .map_err(|e| format!("Failed to read Spotify API response: {}", e))?;
+            if status.is_success() {
+                return Ok(Some(body));
+            }
+
+            let message = serde_json::from_str::<ApiErrorBody>(&body)
+                .map(|body| body.error.message)
+                .unwrap_or_else(|_| status.canonical_reason().unwrap_or("Unknown error").to_string());
+            return Err(format!("Spotify API error {} for {}: {}", status.as_u16(), path, message));
+        }
+    }
+
+    /// GET a JSON document (None for 204 No Content)
+    fn get_json<T: DeserializeOwned>(
+        &self,
+        auth: &SpotifyAuthState,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Option<T>, String> {
+        match self.send(auth, Method::GET, path, |request| request.query(query))? {
+            Some(body) => serde_json::from_str(&body)
+                .map(Some)
+                .map_err(|e| format!("Invalid Spotify API response for {}: {}", path, e)),
+            None => Ok(None),
+        }
+    }
+
+    /// GET a JSON document that must have a body
+    fn get_required<T: DeserializeOwned>(
+        &self,
+        auth: &SpotifyAuthState,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, String> {
+        self.get_json(auth, path, query)?
+            .ok_or_else(|| format!("Empty Spotify API response for {}", path))
+    }
+
+    pub fn me(&self, auth: &SpotifyAuthState) -> Result<User, String> {
+        self.get_required(auth, "/me", &[])
+    }
+
+    /// None when nothing is playing on any device
+    pub fn playback_state(&self, auth: &SpotifyAuthState) -> Result<Option<PlaybackState>, String> {
+        self.get_json(auth, "/me/player", &[("additional_types", "track,episode")])
+    }
+
+    /// None when nothing is playing
+    pub fn currently_playing(&self, auth: &SpotifyAuthState) -> Result<Option<CurrentlyPlaying>, String> {
+        self.get_json(
+            auth,
+            "/me/player/currently-playing",
+            &[("additional_types", "track,episode")],
+        )
+    }
+
+    pub fn queue(&self, auth: &SpotifyAuthState) -> Result<Queue, String> {
+        self.get_required(auth, "/me/player/queue", &[])
+    }
+
+    pub fn add_to_queue(&self, auth: &SpotifyAuthState, uri: &str, device_id: Option<&str>) -> Result<(), String> {
+        let mut query = vec![("uri", uri)];
+        if let Some(device_id) = device_id {
+            query.push(("device_id", device_id));
+        }
+        self.send(auth, Method::POST, "/me/player/queue", |request| request.query(&query))
+            .map(|_| ())
+    }
+
+    pub fn devices(&self, auth: &SpotifyAuthState) -> Result<Vec<Device>, String> {
+        self.get_required::<Devices>(auth, "/me/player/devices", &[])
+            .map(|list| list.devices)
+    }
+
+    pub fn transfer_playback(&self, auth: &SpotifyAuthState, device_id: &str, play: bool) -> Result<(), String> {
+        let body = serde_json::json!({ "device_ids": [device_id], "play": play });
+        self.send(auth, Method::PUT, "/me/player", |request| request.json(&body))
+            .map(|_| ())
+    }
+
+    pub fn start_playback(
+        &self,
+        auth: &SpotifyAuthState,
+        device_id: Option<&str>,
+        options: &StartPlaybackOptions,
+    ) -> Result<(), String> {
+        let query: Vec<(&str, &str)> = device_id.map(|id| ("device_id", id)).into_iter().collect();
+        self.send(auth, Method::PUT, "/me/player/play", |request| {
+            request.query(&query).json(options)
+        })
+        .map(|_| ())
+    }
+
+    pub fn track(&self, auth: &SpotifyAuthState, track_id: &str) -> Result<Track, String> {
+        self.get_required(auth, &format!("/tracks/{}", checked_id(track_id)?), &[])
+    }
+
+    /// Tracks in the order of `track_ids` (None for unknown ids)
+    pub fn tracks(&self, auth: &SpotifyAuthState, track_ids: &[String]) -> Result<Vec<Option<Track>>, String> {
+        let mut tracks = Vec::with_capacity(track_ids.len());
+        for chunk in track_ids.chunks(MAX_TRACK_IDS) {
+            let ids = joined_ids(chunk)?;
+            tracks.extend(self.get_required::<Tracks>(auth, "/tracks", &[("ids", &ids)])?.tracks);
+        }
+        Ok(tracks)
+    }
+
+    /// Audio features in the order of `track_ids` (None for unknown ids)
+    pub fn audio_features(
+        &self,
+        auth: &SpotifyAuthState,
+        track_ids: &[String],
+    ) -> Result<Vec<Option<AudioFeatures>>, String> {
+        let mut features = Vec::with_capacity(track_ids.len());
+        for chunk in track_ids.chunks(MAX_AUDIO_FEATURE_IDS) {
+            let ids = joined_ids(chunk)?;
+            features.extend(
+                self.get_required::<AudioFeaturesList>(auth, "/audio-features", &[("ids", &ids)])?
+                    .audio_features,
+            );
+        }
+        Ok(features)
+    }
+}
+
+impl Default for SpotifyApiState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spotify ids are base62; reject anything that could alter the path
+fn checked_id(id: &str) -> Result<&str, String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Invalid Spotify id: {:?}", id));
+    }
+    Ok(id)
+}
+
+fn joined_ids(ids: &[String]) -> Result<String, String> {
+    let ids = ids
+        .iter()
+        .map(|id| checked_id(id))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ids.join(","))
+}
+
+/// Run a Web API call on a blocking worker thread
+async fn run_blocking<T, F>(app: AppHandle, call: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&SpotifyApiState, &SpotifyAuthState) -> Result<T, String> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(move || {
+        call(&app.state::<SpotifyApiState>(), &app.state::<SpotifyAuthState>())
+    })
+    .await
+    .map_err(|e| format!("Spotify API task failed: {}", e))?
+}
+
+/// Get the current user's profile
+#[tauri::command]
+pub async fn spotify_api_get_me(app: AppHandle) -> Result<User, String> {
+    run_blocking(app, |api, auth| api.me(auth)).await
+}
+
+/// Get the playback state (None when nothing is playing)
+#[tauri::command]
+pub async fn spotify_api_get_playback_state(app: AppHandle) -> Result<Option<PlaybackState>, String> {
+    run_blocking(app, |api, auth| api.playback_state(auth)).await
+}
+
+/// Get the currently playing item (None when nothing is playing)
+#[tauri::command]
+pub async fn spotify_api_get_currently_playing(app: AppHandle) -> Result<Option<CurrentlyPlaying>, String> {
+    run_blocking(app, |api, auth| api.currently_playing(auth)).await
+}
+
+/// Get the playing item and the queue after it
+#[tauri::command]
+pub async fn spotify_api_get_queue(app: AppHandle) -> Result<Queue, String> {
+    run_blocking(app, |api, auth| api.queue(auth)).await
+}
+
+/// Add a track or episode URI to the queue
+#[tauri::command]
+pub async fn spotify_api_add_to_queue(app: AppHandle, uri: String, device_id: Option<String>) -> Result<(), String> {
+    run_blocking(app, move |api, auth| api.add_to_queue(auth, &uri, device_id.as_deref())).await
+}
+
+/// List Spotify Connect devices
+#[tauri::command]
+pub async fn spotify_api_get_devices(app: AppHandle) -> Result<Vec<Device>, String> {
+    run_blocking(app, |api, auth| api.devices(auth)).await
+}
+
+/// Move playback to a device
+#[tauri::command]
+pub async fn spotify_api_transfer_playback(app: AppHandle, device_id: String, play: Option<bool>) -> Result<(), String> {
+    run_blocking(app, move |api, auth| {
+        api.transfer_playback(auth, &device_id, play.unwrap_or(true))
+    })
+    .await
+}
+
+/// Start or resume playback, optionally with new content
+#[tauri::command]
+pub async fn spotify_api_start_playback(
+    app: AppHandle,
+    device_id: Option<String>,
+    options: Option<StartPlaybackOptions>,
+) -> Result<(), String> {
+    run_blocking(app, move |api, auth| {
+        api.start_playback(auth, device_id.as_deref(), &options.unwrap_or_default())
+    })
+    .await
+}
+
+/// Get a track
+#[tauri::command]
+pub async fn spotify_api_get_track(app: AppHandle, track_id: String) -> Result<Track, String> {
+    run_blocking(app, move |api, auth| api.track(auth, &track_id)).await
+}
+
+/// Get several tracks
+#[tauri::command]
+pub async fn spotify_api_get_tracks(app: AppHandle, track_ids: Vec<String>) -> Result<Vec<Option<Track>>, String> {
+    run_blocking(app, move |api, auth| api.tracks(auth, &track_ids)).await
+}
+
+/// Get audio features of several tracks
+#[tauri::command]
+pub async fn spotify_api_get_audio_features(
+    app: AppHandle,
+    track_ids: Vec<String>,
+) -> Result<Vec<Option<AudioFeatures>>, String> {
+    run_blocking(app, move |api, auth| api.audio_features(auth, &track_ids)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential_vault::{self, CredentialVault};
+    use crate::spotify_auth::{now_secs, SpotifyToken};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    type Requests = Arc<Mutex<Vec<String>>>;
+    /// (method, path, authorization) -> (status, extra headers, body)
+    type Responder = dyn Fn(&str, &str, &str) -> (u16, &'static str, String) + Send + Sync;
+
+    /// Stand-in HTTP server answering with `respond` and recording each
+    /// request as "METHOD path authorization"
+    fn stand_in(respond: Box<Responder>) -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Requests::default();
+        let recorded = requests.clone();
+
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut data = Vec::new();
+                let mut buf = [0u8; 1024];
+                let header_end = loop {
+                    if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                    match stream.read(&mut buf) {
+                        Ok(n) if n > 0 => data.extend_from_slice(&buf[..n]),
+                        _ => break data.len(),
+                    }
+                };
+                let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+                let length = head
+                    .lines()
+                    .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap_or(0)))
+                    .unwrap_or(0);
+                while data.len() < header_end + length {
+                    match stream.read(&mut buf) {
+                        Ok(n) if n > 0 => data.extend_from_slice(&buf[..n]),
+                        _ => break,
+                    }
+                }
+
+                let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+                let method = request_line.next().unwrap_or_default().to_string();
+                let path = request_line.next().unwrap_or_default().to_string();
+                let authorization = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("authorization: ").or_else(|| line.strip_prefix("Authorization: ")))
+                    .unwrap_or_default()
+                    .to_string();
+                recorded.lock().unwrap().push(format!("{} {} {}", method, path, authorization));
+
+                let (status, headers, body) = respond(&method, &path, &authorization);
+                let response = format!(
+                    "HTTP/1.1 {} X\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    headers,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        (base, requests)
+    }
+
+    /// Auth state with `access_token` for `user_id`, refreshed by a stand-in
+    /// token endpoint that hands out "refreshed"
+    fn signed_in(user_id: &str, access_token: &str) -> (SpotifyAuthState, Requests) {
+        let _ = credential_vault::install(CredentialVault::in_memory());
+        let (accounts, refreshes) = stand_in(Box::new(|_, _, _| {
+            (200, "", r#"{"access_token":"refreshed","expires_in":3600}"#.to_string())
+        }));
+        let token = SpotifyToken {
+            access_token: access_token.to_string(),
+            refresh_token: Some("refresh".to_string()),
+            expires_at: now_secs() + 3600,
+            scopes: None,
+            version: 1,
+        };
+        let auth = SpotifyAuthState::with_token(user_id, token, format!("{}/api/token", accounts));
+        (auth, refreshes)
+    }
+
+    #[test]
+    fn rejected_token_is_refreshed_once_and_retried() {
+        let (auth, refreshes) = signed_in("api-401", "revoked");
+        let (base, requests) = stand_in(Box::new(|_, _, authorization| match authorization {
+            "Bearer refreshed" => (200, "", r#"{"id":"api-401","display_name":"Alice"}"#.to_string()),
+            _ => (401, "", r#"{"error":{"status":401,"message":"The access token expired"}}"#.to_string()),
+        }));
+        let api = SpotifyApiState::with_base(format!("{}/v1", base));
+
+        let user = api.me(&auth).unwrap();
+
+        assert_eq!(user.id, "api-401");
+        assert_eq!(refreshes.lock().unwrap().len(), 1);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["GET /v1/me Bearer revoked", "GET /v1/me Bearer refreshed"]
+        );
+    }
+
+    #[test]
+    fn rate_limit_waits_for_retry_after() {
+        let (auth, _) = signed_in("api-429", "valid");
+        let calls = Arc::new(Mutex::new(0));
+        let (base, _) = stand_in(Box::new(move |_, _, _| {
+            let mut calls = calls.lock().unwrap();
+            *calls += 1;
+            if *calls == 1 {
+                return (429, "Retry-After: 1\r\n", r#"{"error":{"status":429,"message":"rate"}}"#.to_string());
+            }
+            (200, "", r#"{"devices":[{"id":"d1","name":"TV","type":"TV","is_active":true}]}"#.to_string())
+        }));
+        let api = SpotifyApiState::with_base(format!("{}/v1", base));
+
+        let started = Instant::now();
+        let devices = api.devices(&auth).unwrap();
+
+        assert!(started.elapsed() >= Duration::from_millis(900));
+        assert_eq!(devices[0].device_type, "TV");
+    }
+
+    #[test]
+    fn no_content_means_nothing_is_playing() {
+        let (auth, _) = signed_in("api-204", "valid");
+        let (base, requests) = stand_in(Box::new(|_, _, _| (204, "", String::new())));
+        let api = SpotifyApiState::with_base(format!("{}/v1", base));
+
+        assert!(api.currently_playing(&auth).unwrap().is_none());
+        assert_eq!(
+            requests.lock().unwrap()[0],
+            "GET /v1/me/player/currently-playing?additional_types=track%2Cepisode Bearer valid"
+        );
+    }
+
+    #[test]
+    fn api_errors_carry_spotify_message() {
+        let (auth, _) = signed_in("api-404", "valid");
+        let (base, _) = stand_in(Box::new(|_, _, _| {
+            (404, "", r#"{"error":{"status":404,"message":"Player command failed: No active device found"}}"#.to_string())
+        }));
+        let api = SpotifyApiState::with_base(format!("{}/v1", base));
+
+        let error = api.start_playback(&auth, None, &StartPlaybackOptions::default()).unwrap_err();
+
+        assert!(error.contains("404") && error.contains("No active device found"), "{}", error);
+        assert!(api.track(&auth, "../me").is_err());
+    }
+}
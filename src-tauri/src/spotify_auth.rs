@@ -1,48 +1,363 @@
 // Spotify OAuth and Token Management
-// Handles secure storage of OAuth tokens and PKCE code verifier
+// Runs the PKCE authorization flow (the code verifier never leaves Rust),
+// handles secure storage of OAuth tokens, and keeps the access token fresh
+// with the PKCE refresh_token grant
 
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
-use tauri::State;
+use tauri::{AppHandle, Emitter, Manager, State};
 use keyring::Entry;
-use std::fs;
-use std::path::PathBuf;
-use std::env;
-
-// Keychain service and account names
-const KEYRING_SERVICE: &str = "musicViz";
-const KEYRING_ACCOUNT: &str = "spotify_tokens";
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+
+use crate::spotify_profiles::{fetch_profile, ProfileStore, SpotifyProfile};
+use crate::spotify_token_scheduler::TokenRefreshScheduler;
+use crate::credential_vault::{vault, CredentialNamespace};
+use crate::secure_file;
+
+// Credential vault entry names (profiles use "tokens:<user id>"; the bare
+// name holds a token from before profiles or not yet identified)
+const TOKEN_SECRET: &str = "tokens";
+// Token files written before the credential vault (migrated on load)
+const TOKEN_FILE_PREFIX: &str = "spotify_token";
+
+// Spotify accounts token endpoint (override with SPOTIFY_TOKEN_ENDPOINT)
+const TOKEN_ENDPOINT: &str = "https://accounts.spotify.com/api/token";
+// Refresh this many seconds before the access token expires
+const REFRESH_MARGIN_SECS: u64 = 60;
+const TOKEN_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Spotify authorize endpoint and the app's deep link redirect
+const AUTHORIZE_ENDPOINT: &str = "https://accounts.spotify.com/authorize";
+pub const DEFAULT_REDIRECT_URI: &str = "musicviz://callback";
+// How long a started login waits for its callback
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(600);
+
+// Event emitted to the frontend when a login completes, fails or is cleared
+pub const AUTH_CHANGED_EVENT: &str = "spotify://auth-changed";
+
+// Scopes requested when the caller does not pass its own
+const DEFAULT_SCOPES: &[&str] = &[
+    "user-read-playback-state",
+    "user-modify-playback-state",
+    "user-read-currently-playing",
+    "streaming",
+    "user-read-email",
+    "user-read-private",
+    "user-library-read",
+    "user-top-read",
+];
+
+/// A login started with `spotify_begin_login`, waiting for its callback
+struct PendingLogin {
+    code_verifier: String,
+    /// CSRF value the callback must echo back
+    state: String,
+    redirect_uri: String,
+    client_id: String,
+    /// Requested scopes (assumed granted if the response omits them)
+    scopes: Vec<String>,
+    started: Instant,
+}
 
-/// Temporary storage for PKCE code_verifier during OAuth flow
+/// Temporary storage for the PKCE login in progress (never exposed to the webview)
 pub struct PKCEState {
-    code_verifier: Mutex<Option<String>>,
+    pending: Mutex<Option<PendingLogin>>,
 }
 
 impl PKCEState {
     pub fn new() -> Self {
         Self {
-            code_verifier: Mutex::new(None),
+            pending: Mutex::new(None),
         }
     }
 }
 
+/// Payload of `spotify://auth-changed`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthChangedEvent {
+    pub authenticated: bool,
+    pub error: Option<String>,
+    /// Spotify user id of the active profile
+    #[serde(rename = "userId")]
+    pub user_id: Option<String>,
+}
+
+/// Emit `spotify://auth-changed`
+pub fn emit_auth_changed(app: &AppHandle, authenticated: bool, error: Option<String>, user_id: Option<String>) {
+    let event = AuthChangedEvent {
+        authenticated,
+        error,
+        user_id,
+    };
+    if let Err(e) = app.emit(AUTH_CHANGED_EVENT, event) {
+        println!("Failed to emit {}: {}", AUTH_CHANGED_EVENT, e);
+    }
+}
+
+/// Random URL-safe string from `bytes` random bytes
+fn random_token(bytes: usize) -> String {
+    let mut buffer = vec![0u8; bytes];
+    rand::thread_rng().fill_bytes(&mut buffer);
+    URL_SAFE_NO_PAD.encode(buffer)
+}
+
+/// S256 code challenge for a verifier
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Whether `url` is a callback to `redirect_uri` (same scheme, host and path)
+fn is_redirect_to(url: &Url, redirect_uri: &str) -> bool {
+    match Url::parse(redirect_uri) {
+        Ok(redirect) => {
+            url.scheme() == redirect.scheme()
+                && url.host_str() == redirect.host_str()
+                && url.port() == redirect.port()
+                && url.path().trim_end_matches('/') == redirect.path().trim_end_matches('/')
+        }
+        Err(_) => false,
+    }
+}
+
+// Version of the stored token format
+//   0: access token, refresh token and expiry
+//   1: adds the granted scopes
+const TOKEN_SCHEMA_VERSION: u32 = 1;
+
 /// Spotify access and refresh tokens
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SpotifyToken {
     pub access_token: String,
     pub refresh_token: Option<String>,
     pub expires_at: u64,
+    /// Granted scopes; None when unknown (stored before scopes were
+    /// recorded, until the next refresh reports them)
+    #[serde(default)]
+    pub scopes: Option<Vec<String>>,
+    /// Storage format version
+    #[serde(default)]
+    pub version: u32,
+}
+
+/// Access token as handed to the webview; the refresh token never leaves Rust
+#[derive(Debug, Clone, Serialize)]
+pub struct SpotifyAccessToken {
+    pub access_token: String,
+    pub expires_at: u64,
+}
+
+impl From<SpotifyToken> for SpotifyAccessToken {
+    fn from(token: SpotifyToken) -> Self {
+        Self {
+            access_token: token.access_token,
+            expires_at: token.expires_at,
+        }
+    }
+}
+
+impl SpotifyToken {
+    /// Whether the access token is usable for at least the refresh margin
+    fn is_fresh(&self) -> bool {
+        now_secs() + REFRESH_MARGIN_SECS < self.expires_at
+    }
+
+    /// Parse a stored token, upgrading older formats. Also returns whether
+    /// the token was upgraded (and should be stored again).
+    fn from_stored(json: &[u8]) -> Result<(SpotifyToken, bool), String> {
+        let mut value: serde_json::Value =
+            serde_json::from_slice(json).map_err(|e| format!("Failed to deserialize token: {}", e))?;
+        let fields = value
+            .as_object_mut()
+            .ok_or_else(|| "Failed to deserialize token: not an object".to_string())?;
+
+        let version = fields.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+        if version > TOKEN_SCHEMA_VERSION as u64 {
+            return Err(format!("Stored Spotify token has unsupported format version {}", version));
+        }
+        if version < 1 {
+            // Scopes were not recorded: unknown until the next refresh
+            fields.insert("scopes".to_string(), serde_json::Value::Null);
+        }
+        fields.insert("version".to_string(), TOKEN_SCHEMA_VERSION.into());
+
+        let token = serde_json::from_value(value).map_err(|e| format!("Failed to deserialize token: {}", e))?;
+        Ok((token, version < TOKEN_SCHEMA_VERSION as u64))
+    }
+}
+
+/// Split a space-separated scope string
+fn parse_scopes(scope: &str) -> Vec<String> {
+    scope.split_whitespace().map(str::to_string).collect()
+}
+
+/// Spotify scopes look like "user-read-playback-state"
+fn is_valid_scope(scope: &str) -> bool {
+    !scope.is_empty() && scope.chars().all(|c| c.is_ascii_lowercase() || c == '-')
+}
+
+/// Granted scopes compared with the ones a feature needs
+#[derive(Debug, Clone, Serialize)]
+pub struct ScopeStatus {
+    /// Scopes of the current token; None when unknown
+    pub granted: Option<Vec<String>>,
+    /// Requested scopes not granted (all of them when unknown)
+    pub missing: Vec<String>,
+    #[serde(rename = "hasAll")]
+    pub has_all: bool,
+}
+
+/// Successful token endpoint response
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    /// Space-separated granted scopes
+    #[serde(default)]
+    scope: Option<String>,
+}
+
+/// Token endpoint error body
+#[derive(Debug, Deserialize)]
+struct TokenErrorResponse {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+/// Why a token request did not produce a token
+pub enum TokenRequestError {
+    /// Spotify rejected the grant (revoked refresh token, used or expired
+    /// code): log in again
+    Rejected(String),
+    /// Network or server failure: the stored token is still worth retrying
+    Failed(String),
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// POST a grant to the token endpoint (PKCE: no client secret)
+fn request_token(endpoint: &str, form: &[(&str, &str)]) -> Result<TokenResponse, TokenRequestError> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(TOKEN_REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| TokenRequestError::Failed(format!("Failed to create HTTP client: {}", e)))?;
+
+    let response = client
+        .post(endpoint)
+        .form(form)
+        .send()
+        .map_err(|e| TokenRequestError::Failed(format!("Token request failed: {}", e)))?;
+
+    let status = response.status();
+    let body = response
+        .text()
+        .map_err(|e| TokenRequestError::Failed(format!("Failed to read token response: {}", e)))?;
+
+    if status.is_success() {
+        return serde_json::from_str(&body)
+            .map_err(|e| TokenRequestError::Failed(format!("Invalid token response: {}", e)));
+    }
+
+    match serde_json::from_str::<TokenErrorResponse>(&body) {
+        Ok(error) => {
+            let message = format!(
+                "Token request failed: {}",
+                error.error_description.as_deref().unwrap_or(&error.error)
+            );
+            if error.error == "invalid_grant" {
+                Err(TokenRequestError::Rejected(message))
+            } else {
+                Err(TokenRequestError::Failed(message))
+            }
+        }
+        Err(_) => Err(TokenRequestError::Failed(format!("Token request failed with HTTP {}", status))),
+    }
+}
+
+/// Perform the PKCE refresh_token grant
+fn request_token_refresh(
+    endpoint: &str,
+    client_id: &str,
+    refresh_token: &str,
+) -> Result<TokenResponse, TokenRequestError> {
+    request_token(
+        endpoint,
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", client_id),
+        ],
+    )
+}
+
+/// Exchange an authorization code with its PKCE verifier
+fn request_code_exchange(endpoint: &str, login: &PendingLogin, code: &str) -> Result<TokenResponse, TokenRequestError> {
+    request_token(
+        endpoint,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &login.redirect_uri),
+            ("client_id", &login.client_id),
+            ("code_verifier", &login.code_verifier),
+        ],
+    )
 }
 
 /// Token storage state
 pub struct SpotifyAuthState {
     token: Mutex<Option<SpotifyToken>>,
+    /// Held while a refresh is in flight so concurrent callers share it
+    refresh_lock: Mutex<()>,
+    /// Client ID registered by the frontend for the refresh grant
+    client_id: Mutex<Option<String>>,
+    token_endpoint: String,
+    /// Known accounts, active-profile pointer and preferences
+    profiles: Mutex<ProfileStore>,
+    /// Whether an unidentified token has been matched to its account yet
+    identify_attempted: AtomicBool,
+    /// Whether profile changes are written to disk (off for test states)
+    persist_profiles: bool,
+}
+
+/// Credential vault entry for a profile's tokens
+fn token_secret_name(user_id: Option<&str>) -> String {
+    match user_id {
+        Some(id) => format!("{}:{}", TOKEN_SECRET, id),
+        None => TOKEN_SECRET.to_string(),
+    }
+}
+
+/// Token file written for a profile before the credential vault
+fn legacy_token_file_name(user_id: Option<&str>) -> String {
+    match user_id {
+        Some(id) => format!("{}_{}.json", TOKEN_FILE_PREFIX, id),
+        None => format!("{}.json", TOKEN_FILE_PREFIX),
+    }
 }
 
 impl SpotifyAuthState {
     pub fn new() -> Self {
-        // Try to load persisted tokens on startup (keyring or file fallback)
-        let token = match Self::load_persisted_token() {
+        let profiles = ProfileStore::load();
+
+        // Try to load persisted tokens of the active profile on startup
+        // (keyring or file fallback)
+        let token = match Self::load_persisted_token(profiles.active.as_deref()) {
             Ok(Some(t)) => {
                 println!("Loaded persisted Spotify tokens");
                 Some(t)
@@ -57,237 +372,806 @@ impl SpotifyAuthState {
             }
         };
 
+        let token_endpoint = std::env::var("SPOTIFY_TOKEN_ENDPOINT")
+            .unwrap_or_else(|_| TOKEN_ENDPOINT.to_string());
+
         Self {
             token: Mutex::new(token),
+            refresh_lock: Mutex::new(()),
+            client_id: Mutex::new(None),
+            token_endpoint,
+            profiles: Mutex::new(profiles),
+            identify_attempted: AtomicBool::new(false),
+            persist_profiles: true,
         }
     }
 
-    /// Save tokens to OS keyring
-    fn save_to_keyring(token: &SpotifyToken) -> Result<(), String> {
-        println!("Attempting to save tokens to keyring...");
-        println!("  Service: {}, Account: {}", KEYRING_SERVICE, KEYRING_ACCOUNT);
-        println!("  Token expires at: {}", token.expires_at);
+    /// State holding `token` for the profile `user_id`, refreshed against
+    /// `token_endpoint`
+    #[cfg(test)]
+    pub(crate) fn with_token(user_id: &str, token: SpotifyToken, token_endpoint: String) -> Self {
+        Self {
+            token: Mutex::new(Some(token)),
+            refresh_lock: Mutex::new(()),
+            client_id: Mutex::new(Some("test-client".to_string())),
+            token_endpoint,
+            profiles: Mutex::new(ProfileStore {
+                active: Some(user_id.to_string()),
+                ..Default::default()
+            }),
+            identify_attempted: AtomicBool::new(true),
+            persist_profiles: false,
+        }
+    }
 
-        let entry = Entry::new(KEYRING_SERVICE, KEYRING_ACCOUNT)
-            .map_err(|e| {
-                let err_msg = format!("Failed to create keyring entry: {:?}", e);
-                println!("Keyring error (create): {}", err_msg);
-                println!("  This may be a permission issue or keyring not available");
-                err_msg
-            })?;
+    /// Spotify user id of the active profile
+    pub fn active_user(&self) -> Option<String> {
+        self.profiles.lock().unwrap().active.clone()
+    }
 
-        let json = serde_json::to_string(token)
-            .map_err(|e| format!("Failed to serialize token: {}", e))?;
+    /// Snapshot of the profile store
+    pub fn profiles(&self) -> ProfileStore {
+        self.profiles.lock().unwrap().clone()
+    }
 
-        println!("Serialized token (length: {} bytes), attempting to save to keyring...", json.len());
+    /// Modify and persist the profile store
+    pub fn update_profiles<T>(&self, update: impl FnOnce(&mut ProfileStore) -> Result<T, String>) -> Result<T, String> {
+        let mut store = self.profiles.lock().unwrap();
+        let result = update(&mut store)?;
+        if self.persist_profiles {
+            store.save()?;
+        }
+        Ok(result)
+    }
+
+    /// Make a known profile active, loading its stored tokens
+    pub fn switch_profile(&self, user_id: &str) -> Result<SpotifyProfile, String> {
+        let _refreshing = self.refresh_lock.lock().unwrap();
+
+        if self.profiles.lock().unwrap().get(user_id).is_none() {
+            return Err(format!("Unknown Spotify profile: {}", user_id));
+        }
+        let token = Self::load_persisted_token(Some(user_id))?
+            .ok_or_else(|| format!("No stored tokens for {}; please log in again", user_id))?;
+
+        let profile = self.update_profiles(|store| {
+            store.active = Some(user_id.to_string());
+            let profile = store
+                .profiles
+                .iter_mut()
+                .find(|profile| profile.user_id == user_id)
+                .ok_or_else(|| format!("Unknown Spotify profile: {}", user_id))?;
+            profile.last_used_at = now_secs();
+            Ok(profile.clone())
+        })?;
+        *self.token.lock().unwrap() = Some(token);
 
-        entry.set_password(&json)
-            .map_err(|e| {
-                let err_msg = format!("Failed to save to keyring: {:?}", e);
-                println!("Keyring save error: {}", err_msg);
-                println!("  Platform: {}", std::env::consts::OS);
-                println!("  Check if keychain/credential manager is accessible");
-                err_msg
-            })?;
+        println!("Switched to Spotify profile {}", user_id);
+        Ok(profile)
+    }
+
+    /// Forget a profile: its tokens, entry and preferences
+    pub fn remove_profile(&self, user_id: &str) -> Result<(), String> {
+        let _refreshing = self.refresh_lock.lock().unwrap();
+
+        let was_active = self.active_user().as_deref() == Some(user_id);
+        let removed = self.update_profiles(|store| {
+            store.preferences.remove(user_id);
+            Ok(store.remove(user_id))
+        })?;
+        if !removed {
+            return Err(format!("Unknown Spotify profile: {}", user_id));
+        }
 
-        // Verify it was saved by immediately trying to read it back
-        match entry.get_password() {
-            Ok(_) => println!("Successfully saved and verified tokens in keyring"),
-            Err(e) => println!("Warning: Saved but couldn't verify: {:?}", e),
+        Self::delete_persisted_token(Some(user_id));
+        if was_active {
+            *self.token.lock().unwrap() = None;
         }
 
+        println!("Removed Spotify profile {}", user_id);
         Ok(())
     }
 
-    /// Load tokens from OS keyring
-    fn load_from_keyring() -> Result<Option<SpotifyToken>, String> {
-        println!("Attempting to load tokens from keyring...");
-        println!("  Service: {}, Account: {}", KEYRING_SERVICE, KEYRING_ACCOUNT);
-
-        let entry = Entry::new(KEYRING_SERVICE, KEYRING_ACCOUNT)
-            .map_err(|e| {
-                let err_msg = format!("Failed to create keyring entry for loading: {:?}", e);
-                println!("Keyring error (load/create): {}", err_msg);
-                err_msg
-            })?;
-
-        match entry.get_password() {
-            Ok(json) => {
-                println!("Found token in keyring (length: {} bytes)", json.len());
-                let token: SpotifyToken = serde_json::from_str(&json)
-                    .map_err(|e| {
-                        println!("Failed to deserialize token from keyring: {}", e);
-                        format!("Failed to deserialize token: {}", e)
-                    })?;
-                println!("Successfully loaded token from keyring, expires at: {}", token.expires_at);
-                Ok(Some(token))
-            }
-            Err(keyring::Error::NoEntry) => {
-                println!("No token found in keyring (NoEntry)");
-                Ok(None)
+    /// Store a token obtained by a login under the account it belongs to
+    /// (call with `refresh_lock` held)
+    fn adopt_login_token(&self, token: SpotifyToken) {
+        match fetch_profile(&token.access_token) {
+            Ok(mut profile) => {
+                let user_id = profile.user_id.clone();
+                profile.last_used_at = now_secs();
+                let saved = self.update_profiles(|store| {
+                    store.upsert(profile);
+                    store.active = Some(user_id.clone());
+                    Ok(())
+                });
+                if let Err(e) = saved {
+                    println!("Warning: Failed to save Spotify profiles: {}", e);
+                }
+                self.set_token(token);
+                println!("Logged in Spotify profile {}", user_id);
             }
             Err(e) => {
-                println!("Failed to load from keyring: {:?}", e);
-                println!("  Platform: {}", std::env::consts::OS);
-                Err(format!("Failed to load from keyring: {:?}", e))
+                // Keep the session; the account is identified on a later use
+                println!("Warning: Could not identify Spotify account: {}", e);
+                if let Err(e) = self.update_profiles(|store| {
+                    store.active = None;
+                    Ok(())
+                }) {
+                    println!("Warning: Failed to save Spotify profiles: {}", e);
+                }
+                self.identify_attempted.store(false, Ordering::Relaxed);
+                self.set_token(token);
             }
         }
     }
 
-    /// Delete tokens from OS keyring
-    fn delete_from_keyring() -> Result<(), String> {
-        let entry = Entry::new(KEYRING_SERVICE, KEYRING_ACCOUNT)
-            .map_err(|e| format!("Failed to create keyring entry: {}", e))?;
+    /// Move a token without a profile (saved before profiles existed, or
+    /// whose account lookup failed) to the account it belongs to
+    fn identify_unassigned_token(&self, access_token: &str) {
+        if self.active_user().is_some() || self.identify_attempted.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let _refreshing = self.refresh_lock.lock().unwrap();
+        let Some(token) = self.token.lock().unwrap().clone() else {
+            return;
+        };
+        if token.access_token != access_token {
+            return;
+        }
 
-        match entry.delete_credential() {
-            Ok(()) => {
-                println!("Deleted tokens from keyring");
-                Ok(())
+        println!("Identifying the Spotify account of the stored token...");
+        match fetch_profile(access_token) {
+            Ok(profile) => {
+                let user_id = profile.user_id.clone();
+                let saved = self.update_profiles(|store| {
+                    store.upsert(profile);
+                    store.active = Some(user_id.clone());
+                    Ok(())
+                });
+                match saved {
+                    Ok(()) => {
+                        Self::persist_token(Some(&user_id), &token);
+                        Self::delete_persisted_token(None);
+                        println!("Migrated stored Spotify token to profile {}", user_id);
+                    }
+                    Err(e) => println!("Warning: Failed to save Spotify profiles: {}", e),
+                }
             }
-            Err(keyring::Error::NoEntry) => Ok(()), // Already deleted
-            Err(e) => Err(format!("Failed to delete from keyring: {}", e))
+            Err(e) => println!("Warning: Could not identify Spotify account: {}", e),
         }
     }
 
-    /// Determine local fallback storage path (within user config directory)
-    fn storage_dir(create: bool) -> Result<PathBuf, String> {
-        let mut dir = Self::base_config_dir()?;
-        dir.push("musicViz");
+    /// Client ID for the refresh grant: the one registered by the frontend,
+    /// else SPOTIFY_CLIENT_ID at runtime, else VITE_SPOTIFY_CLIENT_ID at build
+    fn client_id(&self) -> Option<String> {
+        self.client_id
+            .lock()
+            .unwrap()
+            .clone()
+            .or_else(|| std::env::var("SPOTIFY_CLIENT_ID").ok())
+            .or_else(|| option_env!("VITE_SPOTIFY_CLIENT_ID").map(str::to_string))
+    }
 
-        if create {
-            if let Err(e) = fs::create_dir_all(&dir) {
-                return Err(format!("Failed to create storage directory {:?}: {}", dir, e));
+    /// In-memory token, hydrated from keyring or file storage if empty
+    fn current_token(&self) -> Option<SpotifyToken> {
+        let active = self.active_user();
+        let mut token = self.token.lock().unwrap();
+
+        if token.is_none() {
+            match Self::load_persisted_token(active.as_deref()) {
+                Ok(Some(persisted)) => {
+                    println!("Loaded persisted Spotify token from storage on demand");
+                    *token = Some(persisted);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    println!("Failed to load Spotify token from storage on demand: {}", err);
+                }
             }
         }
 
-        Ok(dir)
+        token.clone()
     }
 
-    /// Determine platform-specific base configuration directory
-    fn base_config_dir() -> Result<PathBuf, String> {
-        #[cfg(target_os = "macos")]
-        {
-            if let Ok(home) = env::var("HOME") {
-                return Ok(PathBuf::from(home).join("Library").join("Application Support"));
-            }
+    /// Replace the active profile's token in memory and persist it to
+    /// keyring and file
+    fn set_token(&self, token: SpotifyToken) {
+        let active = self.active_user();
+        *self.token.lock().unwrap() = Some(token.clone());
+        Self::persist_token(active.as_deref(), &token);
+    }
+
+    /// Forget the active profile's token in memory and the credential vault
+    fn clear_token(&self) {
+        let active = self.active_user();
+        *self.token.lock().unwrap() = None;
+        Self::delete_persisted_token(active.as_deref());
+    }
+
+    /// Persist a profile's token to the credential vault
+    fn persist_token(user_id: Option<&str>, token: &SpotifyToken) {
+        // Don't fail the operation if persistence fails - memory storage still works
+        if let Err(e) = Self::save_to_vault(user_id, token) {
+            println!("Warning: Failed to persist token: {}", e);
         }
+    }
 
-        #[cfg(target_os = "windows")]
-        {
-            if let Ok(roaming) = env::var("APPDATA") {
-                return Ok(PathBuf::from(roaming));
-            }
+    /// Delete a profile's token from the credential vault and any token
+    /// file left from before the vault
+    fn delete_persisted_token(user_id: Option<&str>) {
+        if let Err(e) = vault().delete(CredentialNamespace::Spotify, &token_secret_name(user_id)) {
+            println!("Warning: Failed to delete tokens: {}", e);
         }
 
-        #[cfg(all(not(target_os = "macos"), not(target_os = "windows")))]
-        {
-            if let Ok(home) = env::var("HOME") {
-                return Ok(PathBuf::from(home).join(".config"));
-            }
+        if let Err(e) = secure_file::delete(&legacy_token_file_name(user_id)) {
+            println!("Warning: Failed to delete token file: {}", e);
         }
+    }
 
-        Err("Unable to determine configuration directory".to_string())
+    /// Return a fresh access token, refreshing it first when it is expired or
+    /// about to expire. Concurrent callers wait for a single refresh.
+    pub fn valid_access_token(&self) -> Result<String, String> {
+        let access_token = self.fresh_access_token()?;
+        self.identify_unassigned_token(&access_token);
+        Ok(access_token)
     }
 
-    /// Persist token to local file as fallback when keyring is unavailable
-    fn save_to_file(token: &SpotifyToken) -> Result<(), String> {
-        let dir = Self::storage_dir(true)?;
-        let path = dir.join("spotify_token.json");
+    fn fresh_access_token(&self) -> Result<String, String> {
+        let token = self
+            .current_token()
+            .ok_or_else(|| "Not authenticated with Spotify".to_string())?;
+        if token.is_fresh() {
+            return Ok(token.access_token);
+        }
 
-        let json = serde_json::to_string(token)
-            .map_err(|e| format!("Failed to serialize token for file storage: {}", e))?;
+        let _refreshing = self.refresh_lock.lock().unwrap();
 
-        fs::write(&path, json)
-            .map_err(|e| format!("Failed to write token file {:?}: {}", path, e))?;
+        // Another caller may have refreshed while we waited
+        let token = self
+            .current_token()
+            .ok_or_else(|| "Not authenticated with Spotify".to_string())?;
+        if token.is_fresh() {
+            return Ok(token.access_token);
+        }
 
-        println!("Persisted token to file: {:?}", path);
-        Ok(())
+        self.refresh(&token).map(|token| token.access_token)
     }
 
-    /// Load token from local file fallback
-    fn load_from_file() -> Result<Option<SpotifyToken>, String> {
-        let dir = match Self::storage_dir(false) {
-            Ok(dir) => dir,
-            Err(err) => {
-                println!("File storage unavailable: {}", err);
-                return Ok(None);
-            }
-        };
+    /// Refresh now, even if the access token is still valid. Callers that
+    /// raced with another refresh receive its result instead of refreshing
+    /// twice.
+    pub fn force_refresh(&self) -> Result<SpotifyToken, String> {
+        let before = self
+            .current_token()
+            .ok_or_else(|| "Not authenticated with Spotify".to_string())?;
+
+        let _refreshing = self.refresh_lock.lock().unwrap();
+
+        let token = self
+            .current_token()
+            .ok_or_else(|| "Not authenticated with Spotify".to_string())?;
+        if token.access_token != before.access_token {
+            return Ok(token);
+        }
 
-        let path = dir.join("spotify_token.json");
+        self.refresh(&token)
+    }
+
+    /// Run the refresh grant and store the result (call with `refresh_lock` held)
+    fn refresh(&self, token: &SpotifyToken) -> Result<SpotifyToken, String> {
+        self.try_refresh(token).map_err(|e| match e {
+            TokenRequestError::Rejected(e) => format!("{} (please log in again)", e),
+            TokenRequestError::Failed(e) => e,
+        })
+    }
 
-        if !path.exists() {
-            println!("No token file found at {:?}", path);
+    /// Refresh the token if it expires within `margin_secs` (used by the
+    /// background scheduler). Returns None when no refresh was due.
+    pub fn refresh_if_expiring(&self, margin_secs: u64) -> Result<Option<SpotifyToken>, TokenRequestError> {
+        let _refreshing = self.refresh_lock.lock().unwrap();
+
+        let Some(token) = self.current_token() else {
+            return Ok(None);
+        };
+        if token.expires_at > now_secs() + margin_secs {
             return Ok(None);
         }
 
-        let json = fs::read_to_string(&path)
-            .map_err(|e| format!("Failed to read token file {:?}: {}", path, e))?;
+        self.try_refresh(&token).map(Some)
+    }
+
+    /// Scopes of the current token, refreshing once to learn them when the
+    /// token was stored before scopes were recorded
+    pub fn granted_scopes(&self) -> Result<Option<Vec<String>>, String> {
+        let token = self
+            .current_token()
+            .ok_or_else(|| "Not authenticated with Spotify".to_string())?;
+        if token.scopes.is_some() || token.refresh_token.is_none() {
+            return Ok(token.scopes);
+        }
 
-        let token: SpotifyToken = serde_json::from_str(&json)
-            .map_err(|e| format!("Failed to deserialize token file {:?}: {}", path, e))?;
+        println!("Granted Spotify scopes unknown; refreshing to learn them");
+        Ok(self.force_refresh()?.scopes)
+    }
 
-        println!("Loaded Spotify token from file storage (expires at: {})", token.expires_at);
-        Ok(Some(token))
+    /// Compare granted scopes with `required`
+    pub fn scope_status(&self, required: &[String]) -> Result<ScopeStatus, String> {
+        let granted = self.granted_scopes()?;
+        let missing: Vec<String> = required
+            .iter()
+            .filter(|scope| !granted.as_ref().is_some_and(|granted| granted.contains(scope)))
+            .cloned()
+            .collect();
+
+        Ok(ScopeStatus {
+            has_all: missing.is_empty(),
+            granted,
+            missing,
+        })
     }
 
-    /// Delete token file fallback
-    fn delete_file() -> Result<(), String> {
-        let dir = match Self::storage_dir(false) {
-            Ok(dir) => dir,
-            Err(err) => {
-                println!("File storage unavailable for deletion: {}", err);
-                return Ok(());
+    /// Expiry of the active token and whether it can be refreshed
+    pub fn refresh_schedule(&self) -> Option<(u64, bool)> {
+        self.current_token()
+            .map(|token| (token.expires_at, token.refresh_token.is_some()))
+    }
+
+    fn try_refresh(&self, token: &SpotifyToken) -> Result<SpotifyToken, TokenRequestError> {
+        let refresh_token = token.refresh_token.clone().ok_or_else(|| {
+            TokenRequestError::Rejected("Spotify token expired and no refresh token is available".to_string())
+        })?;
+        let client_id = self
+            .client_id()
+            .ok_or_else(|| TokenRequestError::Failed("Spotify client ID is not configured".to_string()))?;
+
+        println!("Refreshing Spotify access token...");
+        match request_token_refresh(&self.token_endpoint, &client_id, &refresh_token) {
+            Ok(response) => {
+                let refreshed = SpotifyToken {
+                    access_token: response.access_token,
+                    // Spotify may rotate the refresh token; keep the old one otherwise
+                    refresh_token: response.refresh_token.or(Some(refresh_token)),
+                    expires_at: now_secs() + response.expires_in,
+                    scopes: response.scope.as_deref().map(parse_scopes).or_else(|| token.scopes.clone()),
+                    version: TOKEN_SCHEMA_VERSION,
+                };
+                self.set_token(refreshed.clone());
+                println!("Refreshed Spotify token (expires at: {})", refreshed.expires_at);
+                Ok(refreshed)
+            }
+            Err(TokenRequestError::Rejected(e)) => {
+                println!("{}; clearing stored tokens", e);
+                self.clear_token();
+                Err(TokenRequestError::Rejected(e))
             }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Validate a callback URL against the pending login and exchange its
+    /// code for tokens
+    fn complete_login(&self, pkce: &PKCEState, callback_url: &str) -> Result<(), String> {
+        let url = Url::parse(callback_url).map_err(|e| format!("Invalid callback URL: {}", e))?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
         };
 
-        let path = dir.join("spotify_token.json");
+        // A callback that doesn't match leaves the pending login in place, so
+        // a stray or forged URL can't cancel the real one
+        let login = {
+            let mut pending = pkce.pending.lock().unwrap();
+            let login = pending
+                .as_ref()
+                .ok_or_else(|| "No Spotify login in progress".to_string())?;
+
+            if login.started.elapsed() > LOGIN_TIMEOUT {
+                *pending = None;
+                return Err("Spotify login expired; please try again".to_string());
+            }
+            if !is_redirect_to(&url, &login.redirect_uri) {
+                return Err(format!("Unexpected callback URL: {}", url.path()));
+            }
+            if param("state").as_deref() != Some(login.state.as_str()) {
+                return Err("Spotify login state mismatch; ignoring callback".to_string());
+            }
+
+            // One-time use: a second callback for the same login is rejected
+            pending.take().ok_or_else(|| "No Spotify login in progress".to_string())?
+        };
 
-        if path.exists() {
-            fs::remove_file(&path)
-                .map_err(|e| format!("Failed to delete token file {:?}: {}", path, e))?;
-            println!("Deleted token file at {:?}", path);
+        if let Some(error) = param("error") {
+            return Err(format!("Spotify authorization error: {}", error));
         }
+        let code = param("code").ok_or_else(|| "No authorization code in callback URL".to_string())?;
+
+        let response = request_code_exchange(&self.token_endpoint, &login, &code).map_err(|e| match e {
+            TokenRequestError::Rejected(e) | TokenRequestError::Failed(e) => e,
+        })?;
 
+        let _refreshing = self.refresh_lock.lock().unwrap();
+        let expires_at = now_secs() + response.expires_in;
+        *self.client_id.lock().unwrap() = Some(login.client_id);
+        self.adopt_login_token(SpotifyToken {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+            expires_at,
+            scopes: Some(response.scope.as_deref().map(parse_scopes).unwrap_or(login.scopes)),
+            version: TOKEN_SCHEMA_VERSION,
+        });
+
+        println!("Completed Spotify login (expires at: {})", expires_at);
         Ok(())
     }
 
-    /// Load token from keyring or file fallback
-    fn load_persisted_token() -> Result<Option<SpotifyToken>, String> {
-        match Self::load_from_keyring() {
-            Ok(Some(token)) => return Ok(Some(token)),
-            Ok(None) => {
-                println!("Keyring empty, checking file storage");
+    /// Save tokens to the credential vault
+    fn save_to_vault(user_id: Option<&str>, token: &SpotifyToken) -> Result<(), String> {
+        let json = serde_json::to_string(token)
+            .map_err(|e| format!("Failed to serialize token: {}", e))?;
+
+        vault().set(CredentialNamespace::Spotify, &token_secret_name(user_id), &json)?;
+        println!("Persisted Spotify token (expires at: {})", token.expires_at);
+        Ok(())
+    }
+
+    /// Load tokens from the credential vault
+    fn load_from_vault(user_id: Option<&str>) -> Result<Option<SpotifyToken>, String> {
+        match vault().get(CredentialNamespace::Spotify, &token_secret_name(user_id))? {
+            Some(json) => {
+                let (token, upgraded) = SpotifyToken::from_stored(json.as_bytes())?;
+                println!("Loaded Spotify token (expires at: {})", token.expires_at);
+                if upgraded {
+                    println!("Upgrading stored Spotify token to format v{}", TOKEN_SCHEMA_VERSION);
+                    Self::save_to_vault(user_id, &token)?;
+                }
+                Ok(Some(token))
             }
+            None => Ok(None),
+        }
+    }
+
+    /// Load a token file written before the credential vault (encrypted,
+    /// or plaintext from older versions)
+    fn load_legacy_file(file_name: &str) -> Result<Option<SpotifyToken>, String> {
+        let json = match secure_file::read(file_name)? {
+            Some(secure_file::SecretFile::Encrypted(json)) => json,
+            Some(secure_file::SecretFile::Plaintext(json)) => json,
+            None => return Ok(None),
+        };
+
+        SpotifyToken::from_stored(&json)
+            .map(|(token, _)| Some(token))
+            .map_err(|e| format!("{} ({})", e, file_name))
+    }
+
+    /// Load a profile's token from the credential vault, moving a token
+    /// file left from before the vault into it
+    fn load_persisted_token(user_id: Option<&str>) -> Result<Option<SpotifyToken>, String> {
+        if let Some(token) = Self::load_from_vault(user_id)? {
+            return Ok(Some(token));
+        }
+
+        let file_name = legacy_token_file_name(user_id);
+        let token = match Self::load_legacy_file(&file_name) {
+            Ok(Some(token)) => token,
+            Ok(None) => return Ok(None),
             Err(err) => {
-                println!("Keyring load failed: {}", err);
+                println!("Failed to read token file {}: {}", file_name, err);
+                return Ok(None);
             }
+        };
+
+        println!("Moving Spotify token file {} to the credential vault", file_name);
+        Self::save_to_vault(user_id, &token)?;
+        if let Err(e) = secure_file::delete(&file_name) {
+            println!("Warning: Failed to delete token file: {}", e);
         }
+        Ok(Some(token))
+    }
+}
 
-        Self::load_from_file()
+/// Generate verifier, S256 challenge and CSRF state, remember them as the
+/// pending login and build the authorize URL. Returns the URL and state.
+pub fn begin_login(
+    pkce: &PKCEState,
+    auth: &SpotifyAuthState,
+    redirect_uri: String,
+    scopes: Option<Vec<String>>,
+) -> Result<(String, String), String> {
+    let client_id = auth
+        .client_id()
+        .ok_or_else(|| "Spotify client ID is not configured".to_string())?;
+    let scopes = scopes.unwrap_or_else(|| DEFAULT_SCOPES.iter().map(|scope| scope.to_string()).collect());
+    if let Some(invalid) = scopes.iter().find(|scope| !is_valid_scope(scope)) {
+        return Err(format!("Invalid Spotify scope: {:?}", invalid));
     }
+    let scope = scopes.join(" ");
+
+    let code_verifier = random_token(64);
+    let state = random_token(16);
+
+    let mut url = Url::parse(AUTHORIZE_ENDPOINT).map_err(|e| format!("Invalid authorize URL: {}", e))?;
+    url.query_pairs_mut()
+        .append_pair("client_id", &client_id)
+        .append_pair("response_type", "code")
+        .append_pair("redirect_uri", &redirect_uri)
+        .append_pair("scope", &scope)
+        .append_pair("code_challenge_method", "S256")
+        .append_pair("code_challenge", &code_challenge(&code_verifier))
+        .append_pair("state", &state)
+        .append_pair("show_dialog", "true");
+
+    // Starting a new login replaces any unfinished one
+    *pkce.pending.lock().unwrap() = Some(PendingLogin {
+        code_verifier,
+        state: state.clone(),
+        redirect_uri,
+        client_id,
+        scopes,
+        started: Instant::now(),
+    });
+
+    Ok((url.to_string(), state))
 }
 
-/// Store PKCE code_verifier temporarily during OAuth flow
+/// Start the PKCE login: generate verifier, S256 challenge and CSRF state,
+/// build the authorize URL and (unless `open_browser` is false) open it.
+/// Returns the authorize URL; the verifier stays in Rust.
 #[tauri::command]
-pub fn store_code_verifier(state: State<PKCEState>, code_verifier: String) -> Result<(), String> {
-    let len = code_verifier.len();
-    let mut verifier = state.code_verifier.lock().unwrap();
-    *verifier = Some(code_verifier);
-    println!("Stored code_verifier (length: {})", len);
-    Ok(())
+pub fn spotify_begin_login(
+    pkce: State<PKCEState>,
+    auth: State<SpotifyAuthState>,
+    redirect_uri: Option<String>,
+    scopes: Option<Vec<String>>,
+    open_browser: Option<bool>,
+) -> Result<String, String> {
+    let redirect_uri = redirect_uri.unwrap_or_else(|| DEFAULT_REDIRECT_URI.to_string());
+    let (url, _) = begin_login(&pkce, &auth, redirect_uri, scopes)?;
+
+    if open_browser.unwrap_or(true) {
+        open_url(url.clone())?;
+    }
+
+    println!("Started Spotify login");
+    Ok(url)
 }
 
-/// Retrieve PKCE code_verifier for token exchange
+/// Whether a deep link is the login callback (handled in Rust, not forwarded
+/// to the webview)
+pub fn is_login_callback(app: &AppHandle, url: &str) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+
+    let pkce = app.state::<PKCEState>();
+    let pending = pkce.pending.lock().unwrap();
+    let redirect_uri = pending
+        .as_ref()
+        .map_or(DEFAULT_REDIRECT_URI, |login| login.redirect_uri.as_str());
+    is_redirect_to(&url, redirect_uri)
+}
+
+/// Finish a login from its callback URL and emit `spotify://auth-changed`
+pub fn handle_login_callback(app: &AppHandle, callback_url: &str) -> Result<(), String> {
+    let auth = app.state::<SpotifyAuthState>();
+    let result = auth.complete_login(&app.state::<PKCEState>(), callback_url);
+
+    if let Err(e) = &result {
+        println!("Spotify login failed: {}", e);
+    }
+
+    emit_auth_changed(app, result.is_ok(), result.clone().err(), auth.active_user());
+    if result.is_ok() {
+        app.state::<TokenRefreshScheduler>().reschedule();
+    }
+
+    result
+}
+
+/// Finish a login from a callback URL received outside the deep link
+/// handler (e.g. the web callback page)
 #[tauri::command]
-pub fn get_code_verifier(state: State<PKCEState>) -> Result<String, String> {
-    let mut verifier = state.code_verifier.lock().unwrap();
-    let code = verifier
-        .take() // Take and clear (one-time use)
-        .ok_or_else(|| "No code verifier found".to_string())?;
+pub fn spotify_complete_login(app: AppHandle, callback_url: String) -> Result<(), String> {
+    handle_login_callback(&app, &callback_url)
+}
 
-    println!("Retrieved code_verifier (length: {})", code.len());
-    Ok(code)
+/// Abandon the login in progress (listeners for it shut down)
+#[tauri::command]
+pub fn spotify_cancel_login(pkce: State<PKCEState>) -> bool {
+    pkce.pending.lock().unwrap().take().is_some()
+}
+
+/// Whether `state` still identifies the pending login
+pub fn is_pending_login(app: &AppHandle, state: &str) -> bool {
+    app.state::<PKCEState>()
+        .pending
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|login| login.state == state)
+}
+
+/// Page shown in the browser tab after the loopback redirect
+fn loopback_page(result: &Result<(), String>) -> String {
+    let (title, message) = match result {
+        Ok(()) => (
+            "Connected to Spotify",
+            "musicViz is now connected. You can close this tab.".to_string(),
+        ),
+        Err(e) => (
+            "Spotify login failed",
+            format!("{}. You can close this tab and try again.", e.replace('<', "&lt;")),
+        ),
+    };
+
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title></head>\
+         <body style=\"font-family: sans-serif; text-align: center; padding-top: 4em\">\
+         <h2>{title}</h2><p>{message}</p></body></html>"
+    )
+}
+
+/// Drop the pending login identified by `state` (if it is still pending)
+/// and report the failure via `spotify://auth-changed`
+pub fn abandon_login(app: &AppHandle, state: &str, error: &str) {
+    {
+        let pkce = app.state::<PKCEState>();
+        let mut pending = pkce.pending.lock().unwrap();
+        if pending.as_ref().is_some_and(|login| login.state == state) {
+            *pending = None;
+        }
+    }
+
+    emit_auth_changed(app, false, Some(error.to_string()), None);
+}
+
+/// Minimal HTTP request read by the login listeners
+pub struct HttpRequest {
+    pub method: String,
+    /// Path and query ("/callback?code=...")
+    pub target: String,
+    pub body: String,
+}
+
+// Largest request body the login listeners accept
+const MAX_REQUEST_BODY: usize = 8 * 1024;
+
+/// Read one HTTP request (request line, headers and a small body)
+pub fn read_http_request(stream: &mut TcpStream) -> Option<HttpRequest> {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok()?;
+            }
+        }
+    }
+    if content_length > MAX_REQUEST_BODY {
+        return None;
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).ok()?;
+
+    Some(HttpRequest {
+        method,
+        target,
+        body: String::from_utf8(body).ok()?,
+    })
+}
+
+/// Write an HTML response and close the exchange
+pub fn write_http_response(stream: &mut TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+/// Serve the loopback redirect until the callback arrives, the login is
+/// replaced, or it times out
+fn run_loopback_listener(app: AppHandle, listener: TcpListener, redirect_uri: String, state: String) {
+    let started = Instant::now();
+
+    loop {
+        if !is_pending_login(&app, &state) {
+            println!("Spotify loopback listener stopped: login replaced or finished");
+            return;
+        }
+
+        if started.elapsed() > LOGIN_TIMEOUT {
+            println!("Spotify loopback login timed out");
+            abandon_login(&app, &state, "Spotify login timed out");
+            return;
+        }
+
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            Err(e) => {
+                println!("Spotify loopback listener failed: {}", e);
+                return;
+            }
+        };
+
+        // Accepted sockets inherit non-blocking mode on some platforms
+        let _ = stream.set_nonblocking(false);
+        let Some(request) = read_http_request(&mut stream).filter(|request| request.method == "GET") else {
+            continue;
+        };
+
+        let callback_url = Url::parse(&redirect_uri).and_then(|base| base.join(&request.target));
+        let Some(callback_url) = callback_url.ok().filter(|url| is_redirect_to(url, &redirect_uri)) else {
+            // Browsers also ask for /favicon.ico
+            write_http_response(&mut stream, "404 Not Found", "");
+            continue;
+        };
+
+        let result = handle_login_callback(&app, callback_url.as_str());
+        write_http_response(&mut stream, "200 OK", &loopback_page(&result));
+        return;
+    }
+}
+
+/// Start the PKCE login with a one-shot HTTP listener on 127.0.0.1 as the
+/// redirect URI (for systems where the musicviz:// deep link is unreliable).
+/// `port` 0 or None picks an ephemeral port; the URI
+/// `http://127.0.0.1:<port>/callback` must be allowed in the Spotify app
+/// settings. Completion is reported via `spotify://auth-changed`.
+#[tauri::command]
+pub fn spotify_begin_loopback_login(
+    app: AppHandle,
+    pkce: State<PKCEState>,
+    auth: State<SpotifyAuthState>,
+    port: Option<u16>,
+    scopes: Option<Vec<String>>,
+    open_browser: Option<bool>,
+) -> Result<String, String> {
+    let listener = TcpListener::bind(("127.0.0.1", port.unwrap_or(0)))
+        .map_err(|e| format!("Failed to start loopback listener: {}", e))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Failed to configure loopback listener: {}", e))?;
+    let port = listener
+        .local_addr()
+        .map_err(|e| format!("Failed to read loopback address: {}", e))?
+        .port();
+
+    let redirect_uri = format!("http://127.0.0.1:{}/callback", port);
+    let (url, state) = begin_login(&pkce, &auth, redirect_uri.clone(), scopes)?;
+
+    std::thread::Builder::new()
+        .name("spotify-loopback".to_string())
+        .spawn(move || run_loopback_listener(app, listener, redirect_uri, state))
+        .map_err(|e| format!("Failed to spawn loopback listener: {}", e))?;
+
+    if open_browser.unwrap_or(true) {
+        open_url(url.clone())?;
+    }
+
+    println!("Started Spotify loopback login on port {}", port);
+    Ok(url)
 }
 
 /// Store Spotify access and refresh tokens securely
@@ -297,145 +1181,164 @@ pub fn store_spotify_token(
     access_token: String,
     refresh_token: Option<String>,
     expires_in: u64,
+    scope: Option<String>,
 ) -> Result<(), String> {
-    let expires_at = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
-        + expires_in;
+    let expires_at = now_secs() + expires_in;
 
     let token = SpotifyToken {
         access_token,
         refresh_token,
         expires_at,
+        scopes: scope.as_deref().map(parse_scopes),
+        version: TOKEN_SCHEMA_VERSION,
     };
 
-    // Save to memory
-    let mut state_token = state.token.lock().unwrap();
-    *state_token = Some(token.clone());
-
+    state.set_token(token);
     println!("Stored Spotify token (expires at: {})", expires_at);
 
-    // Persist to OS keychain
-    if let Err(e) = SpotifyAuthState::save_to_keyring(&token) {
-        println!("Warning: Failed to persist token to keyring: {}", e);
-        // Don't fail the operation if persistence fails - memory storage still works
-    }
+    Ok(())
+}
 
-    // Persist to file fallback
-    if let Err(e) = SpotifyAuthState::save_to_file(&token) {
-        println!("Warning: Failed to persist token to file storage: {}", e);
+/// Register the Spotify client ID used for the refresh_token grant
+#[tauri::command]
+pub fn set_spotify_client_id(state: State<SpotifyAuthState>, client_id: String) -> Result<(), String> {
+    if client_id.trim().is_empty() {
+        return Err("Client ID must not be empty".to_string());
     }
 
+    *state.client_id.lock().unwrap() = Some(client_id);
     Ok(())
 }
 
-/// Retrieve stored Spotify token
-#[tauri::command]
-pub fn get_spotify_token(
-    state: State<SpotifyAuthState>,
-) -> Result<Option<SpotifyToken>, String> {
-    let mut token = state.token.lock().unwrap();
-
-    // Hydrate in-memory token from keyring if empty
-    if token.is_none() {
-        match SpotifyAuthState::load_persisted_token() {
-            Ok(Some(persisted)) => {
-                println!("Loaded persisted Spotify token from storage on demand");
-                *token = Some(persisted);
-            }
-            Ok(None) => {
-                println!("No persisted Spotify token found in storage");
-            }
-            Err(err) => {
-                println!("Failed to load Spotify token from storage on demand: {}", err);
-            }
+/// Run a token operation on a blocking worker thread (refreshes go over the
+/// network and wait on each other)
+async fn run_blocking<T, F>(app: AppHandle, call: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&SpotifyAuthState) -> Result<T, String> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(move || call(&app.state::<SpotifyAuthState>()))
+        .await
+        .map_err(|e| format!("Spotify auth task failed: {}", e))?
+}
+
+/// Stored access token, refreshed first if it has expired. None when there
+/// is no usable session.
+fn usable_token(state: &SpotifyAuthState) -> Result<Option<SpotifyAccessToken>, String> {
+    match state.valid_access_token() {
+        Ok(_) => Ok(state.current_token().map(SpotifyAccessToken::from)),
+        // A token that is still stored means the refresh can be retried
+        Err(e) if state.current_token().is_some_and(|t| t.refresh_token.is_some()) => Err(e),
+        Err(e) => {
+            println!("No usable Spotify token: {}", e);
+            Ok(None)
         }
     }
+}
 
-    if let Some(ref t) = *token {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+/// Retrieve the stored access token, refreshed first if it has expired.
+/// Returns None when there is no usable session.
+#[tauri::command]
+pub async fn get_spotify_token(app: AppHandle) -> Result<Option<SpotifyAccessToken>, String> {
+    run_blocking(app, usable_token).await
+}
 
-        if now >= t.expires_at {
-            println!(
-                "Token expired (expired at: {}, now: {}), returning stored token for frontend refresh",
-                t.expires_at,
-                now
-            );
+/// Get an access token that is valid for at least the next minute,
+/// refreshing it if needed
+#[tauri::command]
+pub async fn get_valid_access_token(app: AppHandle) -> Result<String, String> {
+    run_blocking(app, |state| state.valid_access_token()).await
+}
 
-            if t.refresh_token.is_none() {
-                println!("Token expired and no refresh token available; returning None");
-                return Ok(None);
-            }
-        }
-    }
+/// Refresh the access token now
+#[tauri::command]
+pub async fn refresh_spotify_token(app: AppHandle) -> Result<SpotifyAccessToken, String> {
+    run_blocking(app, |state| state.force_refresh().map(SpotifyAccessToken::from)).await
+}
 
-    Ok(token.clone())
+/// Report whether the current token has the given scopes (refreshing once to
+/// learn them when they are unknown)
+#[tauri::command]
+pub async fn spotify_has_scopes(app: AppHandle, scopes: Vec<String>) -> Result<ScopeStatus, String> {
+    run_blocking(app, move |state| state.scope_status(&scopes)).await
 }
 
-/// Check if user is authenticated
+/// Re-authorize with additional scopes when the current token lacks some of
+/// `scopes` (granted ones are requested again so none are lost). Returns the
+/// authorize URL, or None when everything is already granted; completion
+/// arrives as `spotify://auth-changed` like a normal login.
 #[tauri::command]
-pub fn is_authenticated(state: State<SpotifyAuthState>) -> Result<bool, String> {
-    let mut token = state.token.lock().unwrap();
-
-    if token.is_none() {
-        match SpotifyAuthState::load_persisted_token() {
-            Ok(Some(persisted)) => {
-                println!("Auth check hydrated Spotify token from storage");
-                *token = Some(persisted);
-            }
-            Ok(None) => {
-                println!("Auth check: no Spotify token available in storage");
-                return Ok(false);
-            }
-            Err(err) => {
-                println!("Auth check failed to load token from storage: {}", err);
-                return Err(err);
-            }
-        }
-    }
+pub async fn spotify_request_scopes(
+    app: AppHandle,
+    scopes: Vec<String>,
+    redirect_uri: Option<String>,
+    open_browser: Option<bool>,
+) -> Result<Option<String>, String> {
+    let handle = app.clone();
+    run_blocking(app, move |auth| {
+        request_scopes(&handle.state::<PKCEState>(), auth, scopes, redirect_uri, open_browser)
+    })
+    .await
+}
 
-    if let Some(ref t) = *token {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+fn request_scopes(
+    pkce: &PKCEState,
+    auth: &SpotifyAuthState,
+    scopes: Vec<String>,
+    redirect_uri: Option<String>,
+    open_browser: Option<bool>,
+) -> Result<Option<String>, String> {
+    let status = auth.scope_status(&scopes)?;
+    if status.has_all {
+        return Ok(None);
+    }
 
-        if now < t.expires_at {
-            return Ok(true);
+    let mut requested: Vec<String> = DEFAULT_SCOPES.iter().map(|scope| scope.to_string()).collect();
+    for scope in status.granted.into_iter().flatten().chain(scopes) {
+        if !requested.contains(&scope) {
+            requested.push(scope);
         }
+    }
 
-        if t.refresh_token.is_some() {
-            println!(
-                "Auth check: token expired but refresh token available; treating as authenticated so frontend can refresh"
-            );
-            return Ok(true);
-        }
+    let redirect_uri = redirect_uri.unwrap_or_else(|| DEFAULT_REDIRECT_URI.to_string());
+    let (url, _) = begin_login(pkce, auth, redirect_uri, Some(requested))?;
 
-        println!("Auth check: token expired and no refresh token present");
-        return Ok(false);
+    if open_browser.unwrap_or(true) {
+        open_url(url.clone())?;
     }
 
-    Ok(false)
+    println!("Requesting additional Spotify scopes: {}", status.missing.join(" "));
+    Ok(Some(url))
+}
+
+/// Check if user is authenticated (a valid access token is available,
+/// refreshing if needed)
+#[tauri::command]
+pub async fn is_authenticated(app: AppHandle) -> Result<bool, String> {
+    run_blocking(app, |state| match usable_token(state) {
+        Ok(token) => Ok(token.is_some()),
+        Err(e) => {
+            println!("Auth check failed to refresh token: {}", e);
+            Err(e)
+        }
+    })
+    .await
 }
 
-/// Clear stored tokens (logout)
+/// Clear stored tokens of the active profile and forget the profile
+/// (its preferences are kept for the next login)
 #[tauri::command]
-pub fn logout(state: State<SpotifyAuthState>) -> Result<(), String> {
-    // Clear from memory
-    let mut token = state.token.lock().unwrap();
-    *token = None;
+pub fn logout(state: State<SpotifyAuthState>, scheduler: State<TokenRefreshScheduler>) -> Result<(), String> {
+    scheduler.cancel();
 
-    // Clear from keyring
-    if let Err(e) = SpotifyAuthState::delete_from_keyring() {
-        println!("Warning: Failed to delete tokens from keyring: {}", e);
-    }
+    let _refreshing = state.refresh_lock.lock().unwrap();
+    state.clear_token();
 
-    if let Err(e) = SpotifyAuthState::delete_file() {
-        println!("Warning: Failed to delete token file: {}", e);
+    if let Some(user_id) = state.active_user() {
+        state.update_profiles(|store| {
+            store.remove(&user_id);
+            Ok(())
+        })?;
     }
 
     println!("Cleared Spotify tokens");
@@ -592,3 +1495,292 @@ pub fn test_keyring() -> Result<String, String> {
 
     Ok("Keyring operations working correctly!".to_string())
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::credential_vault::{self, CredentialVault};
+    use std::sync::Arc;
+
+    pub(crate) type Requests = Arc<Mutex<Vec<String>>>;
+
+    /// Stand-in token endpoint answering with `responses` in turn (status,
+    /// JSON body) after `delay`, recording each form body
+    pub(crate) fn token_endpoint(responses: Vec<(u16, &'static str)>, delay: Duration) -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/api/token", listener.local_addr().unwrap());
+        let requests = Requests::default();
+        let recorded = requests.clone();
+
+        std::thread::spawn(move || {
+            let mut responses = responses.into_iter();
+            for mut stream in listener.incoming().flatten() {
+                let mut data = Vec::new();
+                let mut buf = [0u8; 1024];
+                let header_end = loop {
+                    if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                    match stream.read(&mut buf) {
+                        Ok(n) if n > 0 => data.extend_from_slice(&buf[..n]),
+                        _ => break data.len(),
+                    }
+                };
+                let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+                let length = head
+                    .lines()
+                    .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap_or(0)))
+                    .unwrap_or(0);
+                while data.len() < header_end + length {
+                    match stream.read(&mut buf) {
+                        Ok(n) if n > 0 => data.extend_from_slice(&buf[..n]),
+                        _ => break,
+                    }
+                }
+                recorded.lock().unwrap().push(String::from_utf8_lossy(&data[header_end..]).to_string());
+
+                std::thread::sleep(delay);
+                let (status, body) = responses.next().unwrap_or((500, "{}"));
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        (endpoint, requests)
+    }
+
+    pub(crate) fn signed_in(user_id: &str, expires_at: u64, endpoint: String) -> SpotifyAuthState {
+        let _ = credential_vault::install(CredentialVault::in_memory());
+        let token = SpotifyToken {
+            access_token: "old".to_string(),
+            refresh_token: Some("r1".to_string()),
+            expires_at,
+            scopes: None,
+            version: TOKEN_SCHEMA_VERSION,
+        };
+        SpotifyAuthState::with_token(user_id, token, endpoint)
+    }
+
+    #[test]
+    fn fresh_token_is_not_refreshed() {
+        let (endpoint, requests) = token_endpoint(vec![], Duration::ZERO);
+        let auth = signed_in("auth-fresh", now_secs() + 3600, endpoint);
+
+        assert_eq!(auth.valid_access_token().unwrap(), "old");
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn concurrent_callers_share_one_refresh() {
+        let (endpoint, requests) = token_endpoint(
+            vec![(200, r#"{"access_token":"new","token_type":"Bearer","expires_in":3600,"refresh_token":"r2"}"#)],
+            Duration::from_millis(200),
+        );
+        let auth = Arc::new(signed_in("auth-concurrent", now_secs(), endpoint));
+
+        let callers: Vec<_> = (0..8)
+            .map(|_| {
+                let auth = auth.clone();
+                std::thread::spawn(move || auth.valid_access_token())
+            })
+            .collect();
+        for caller in callers {
+            assert_eq!(caller.join().unwrap().unwrap(), "new");
+        }
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0], "grant_type=refresh_token&refresh_token=r1&client_id=test-client");
+    }
+
+    #[test]
+    fn rotated_refresh_token_is_persisted() {
+        let (endpoint, requests) = token_endpoint(
+            vec![
+                (200, r#"{"access_token":"new","expires_in":3600,"refresh_token":"r2","scope":"user-read-email"}"#),
+                (200, r#"{"access_token":"newer","expires_in":3600}"#),
+            ],
+            Duration::ZERO,
+        );
+        let auth = signed_in("auth-rotate", now_secs(), endpoint);
+
+        assert_eq!(auth.valid_access_token().unwrap(), "new");
+        let stored = SpotifyAuthState::load_persisted_token(Some("auth-rotate")).unwrap().unwrap();
+        assert_eq!(stored.access_token, "new");
+        assert_eq!(stored.refresh_token.as_deref(), Some("r2"));
+        assert_eq!(stored.scopes, Some(vec!["user-read-email".to_string()]));
+
+        // Without a new refresh token in the response the rotated one is kept
+        let token = auth.force_refresh().unwrap();
+        assert_eq!(token.access_token, "newer");
+        assert_eq!(token.refresh_token.as_deref(), Some("r2"));
+        assert!(requests.lock().unwrap()[1].contains("refresh_token=r2"));
+    }
+
+    #[test]
+    fn rejected_refresh_token_signs_out() {
+        let (endpoint, _) = token_endpoint(
+            vec![(400, r#"{"error":"invalid_grant","error_description":"Refresh token revoked"}"#)],
+            Duration::ZERO,
+        );
+        let auth = signed_in("auth-revoked", now_secs(), endpoint);
+        SpotifyAuthState::persist_token(Some("auth-revoked"), &auth.current_token().unwrap());
+
+        let error = auth.valid_access_token().unwrap_err();
+
+        assert!(error.contains("Refresh token revoked") && error.contains("log in again"), "{}", error);
+        assert!(auth.current_token().is_none());
+        assert!(SpotifyAuthState::load_persisted_token(Some("auth-revoked")).unwrap().is_none());
+    }
+
+    #[test]
+    fn failed_refresh_keeps_the_token() {
+        let (endpoint, _) = token_endpoint(
+            vec![(503, r#"{"error":"server_error","error_description":"Try again later"}"#)],
+            Duration::ZERO,
+        );
+        let auth = signed_in("auth-unavailable", now_secs(), endpoint);
+
+        assert!(auth.valid_access_token().is_err());
+        assert_eq!(auth.current_token().unwrap().refresh_token.as_deref(), Some("r1"));
+    }
+
+    /// Pending login plus the callback URL Spotify would redirect to
+    fn pending_login(auth: &SpotifyAuthState) -> (PKCEState, String) {
+        let pkce = PKCEState::new();
+        let (_, state) = begin_login(&pkce, auth, DEFAULT_REDIRECT_URI.to_string(), None).unwrap();
+        (pkce, format!("{}?code=abc&state={}", DEFAULT_REDIRECT_URI, state))
+    }
+
+    #[test]
+    fn mismatched_callback_keeps_the_login_pending() {
+        let (endpoint, requests) = token_endpoint(
+            vec![(400, r#"{"error":"invalid_grant","error_description":"Invalid authorization code"}"#)],
+            Duration::ZERO,
+        );
+        let auth = signed_in("auth-login-state", now_secs() + 3600, endpoint);
+        let (pkce, callback) = pending_login(&auth);
+
+        let forged = format!("{}?code=abc&state=forged", DEFAULT_REDIRECT_URI);
+        let error = auth.complete_login(&pkce, &forged).unwrap_err();
+        assert!(error.contains("state mismatch"), "{}", error);
+        let error = auth.complete_login(&pkce, "https://example.com/callback?code=abc").unwrap_err();
+        assert!(error.contains("Unexpected callback URL"), "{}", error);
+        assert!(pkce.pending.lock().unwrap().is_some());
+        assert!(requests.lock().unwrap().is_empty());
+
+        // The genuine callback still reaches the token endpoint
+        let error = auth.complete_login(&pkce, &callback).unwrap_err();
+        assert!(error.contains("Invalid authorization code"), "{}", error);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn expired_login_is_discarded() {
+        let (endpoint, requests) = token_endpoint(vec![], Duration::ZERO);
+        let auth = signed_in("auth-login-expired", now_secs() + 3600, endpoint);
+        let (pkce, callback) = pending_login(&auth);
+        pkce.pending.lock().unwrap().as_mut().unwrap().started =
+            Instant::now().checked_sub(LOGIN_TIMEOUT + Duration::from_secs(1)).unwrap();
+
+        let error = auth.complete_login(&pkce, &callback).unwrap_err();
+        assert!(error.contains("expired"), "{}", error);
+        assert!(pkce.pending.lock().unwrap().is_none());
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn replayed_callback_is_rejected() {
+        let (endpoint, requests) = token_endpoint(
+            vec![(400, r#"{"error":"invalid_grant","error_description":"Invalid authorization code"}"#)],
+            Duration::ZERO,
+        );
+        let auth = signed_in("auth-login-replay", now_secs() + 3600, endpoint);
+        let (pkce, callback) = pending_login(&auth);
+
+        assert!(auth.complete_login(&pkce, &callback).is_err());
+        let error = auth.complete_login(&pkce, &callback).unwrap_err();
+
+        assert_eq!(error, "No Spotify login in progress");
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("grant_type=authorization_code&code=abc&"), "{}", requests[0]);
+    }
+
+    #[test]
+    fn stored_token_formats() {
+        let v0 = br#"{"access_token":"a","refresh_token":"r","expires_at":1700000000}"#;
+        let (token, upgraded) = SpotifyToken::from_stored(v0).unwrap();
+        assert!(upgraded);
+        assert_eq!(token.version, TOKEN_SCHEMA_VERSION);
+        assert_eq!(token.scopes, None);
+
+        let v1 = br#"{"access_token":"a","refresh_token":"r","expires_at":1700000000,"scopes":["streaming"],"version":1}"#;
+        let (token, upgraded) = SpotifyToken::from_stored(v1).unwrap();
+        assert!(!upgraded);
+        assert_eq!(token.scopes, Some(vec!["streaming".to_string()]));
+
+        let future = br#"{"access_token":"a","expires_at":1700000000,"version":2}"#;
+        assert!(SpotifyToken::from_stored(future).unwrap_err().contains("unsupported format version 2"));
+    }
+
+    #[test]
+    fn v0_token_is_resaved_and_learns_its_scopes() {
+        let _ = credential_vault::install(CredentialVault::in_memory());
+        let v0 = format!(r#"{{"access_token":"a","refresh_token":"r1","expires_at":{}}}"#, now_secs() + 3600);
+        vault().set(CredentialNamespace::Spotify, "tokens:auth-v0", &v0).unwrap();
+
+        let token = SpotifyAuthState::load_persisted_token(Some("auth-v0")).unwrap().unwrap();
+        assert_eq!(token.scopes, None);
+        assert_eq!(token.version, TOKEN_SCHEMA_VERSION);
+        let stored = vault().get(CredentialNamespace::Spotify, "tokens:auth-v0").unwrap().unwrap();
+        assert!(stored.contains(r#""version":1"#), "{}", stored);
+
+        // Unknown scopes are learned from one refresh and stored with the token
+        let (endpoint, requests) = token_endpoint(
+            vec![(200, r#"{"access_token":"b","expires_in":3600,"scope":"streaming user-read-email"}"#)],
+            Duration::ZERO,
+        );
+        let auth = SpotifyAuthState::with_token("auth-v0", token, endpoint);
+        let status = auth
+            .scope_status(&["streaming".to_string(), "user-top-read".to_string()])
+            .unwrap();
+
+        assert_eq!(status.granted, Some(vec!["streaming".to_string(), "user-read-email".to_string()]));
+        assert_eq!(status.missing, vec!["user-top-read".to_string()]);
+        assert!(!status.has_all);
+        assert!(auth.scope_status(&["streaming".to_string()]).unwrap().has_all);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        let stored = SpotifyAuthState::load_persisted_token(Some("auth-v0")).unwrap().unwrap();
+        assert_eq!(stored.scopes, status.granted);
+    }
+
+    #[test]
+    fn requesting_scopes_keeps_the_granted_ones() {
+        let (endpoint, _) = token_endpoint(vec![], Duration::ZERO);
+        let auth = signed_in("auth-scopes", now_secs() + 3600, endpoint);
+        let mut token = auth.current_token().unwrap();
+        token.scopes = Some(vec!["playlist-read-private".to_string()]);
+        *auth.token.lock().unwrap() = Some(token);
+        let pkce = PKCEState::new();
+
+        let granted = request_scopes(&pkce, &auth, vec!["playlist-read-private".to_string()], None, Some(false));
+        assert_eq!(granted.unwrap(), None);
+        assert!(pkce.pending.lock().unwrap().is_none());
+
+        let url = request_scopes(&pkce, &auth, vec!["user-follow-read".to_string()], None, Some(false))
+            .unwrap()
+            .unwrap();
+        let scopes = pkce.pending.lock().unwrap().as_ref().unwrap().scopes.clone();
+        assert!(scopes.contains(&"playlist-read-private".to_string()));
+        assert!(scopes.contains(&"user-follow-read".to_string()));
+        assert!(DEFAULT_SCOPES.iter().all(|scope| scopes.contains(&scope.to_string())));
+        assert!(url.contains("user-follow-read"));
+    }
+}
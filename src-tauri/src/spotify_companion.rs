@@ -0,0 +1,623 @@
+// Spotify Companion Login (TV)
+//
+// Lets a phone complete the Spotify login for the TV build. The app serves a
+// short-lived pairing page on the LAN, advertised by QR code together with a
+// one-time pairing code. Once the phone proves it knows the code it is sent
+// to the Spotify authorize page (PKCE challenge generated here). Spotify
+// redirects the phone back to the TV's own /callback, which asks for the
+// pairing code again before the code is exchanged in Rust. Sessions expire,
+// are single use and abort after too many wrong pairing codes.
+
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rand::Rng;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+
+use crate::spotify_auth::{self, PKCEState, SpotifyAuthState};
+
+// Path of the redirect target on the pairing endpoint
+const COMPANION_CALLBACK_PATH: &str = "/callback";
+// How long the pairing page stays up
+const COMPANION_TIMEOUT: Duration = Duration::from_secs(300);
+// Wrong pairing codes tolerated before the session is aborted
+const MAX_PAIRING_ATTEMPTS: u32 = 5;
+const PAIRING_CODE_DIGITS: usize = 6;
+
+/// Companion login details for the TV screen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompanionLogin {
+    /// Pairing page URL (encoded in the QR code)
+    pub url: String,
+    /// One-time code to type on the phone if the QR code is not used
+    #[serde(rename = "pairingCode")]
+    pub pairing_code: String,
+    /// QR code for `url` as an SVG document
+    #[serde(rename = "qrSvg")]
+    pub qr_svg: String,
+    #[serde(rename = "expiresInSecs")]
+    pub expires_in_secs: u64,
+}
+
+/// One companion session, owned by its listener thread
+struct CompanionSession {
+    pairing_code: String,
+    attempts: u32,
+    /// CSRF state of the pending PKCE login
+    state: String,
+    redirect_uri: String,
+    authorize_url: String,
+    expires: Instant,
+}
+
+enum Outcome {
+    /// Keep serving
+    Continue,
+    /// The session is over (success, exchange failure or lockout)
+    Finished,
+}
+
+impl CompanionSession {
+    /// Check a pairing code, counting failures (constant-time compare)
+    fn verify(&mut self, code: Option<&str>) -> Result<(), String> {
+        let candidate = code.unwrap_or("").trim().as_bytes();
+        let expected = self.pairing_code.as_bytes();
+        let matches = candidate.len() == expected.len()
+            && candidate.iter().zip(expected).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0;
+
+        if matches {
+            return Ok(());
+        }
+
+        self.attempts += 1;
+        Err(format!(
+            "Wrong pairing code ({} of {} attempts used)",
+            self.attempts, MAX_PAIRING_ATTEMPTS
+        ))
+    }
+
+    fn locked_out(&self) -> bool {
+        self.attempts >= MAX_PAIRING_ATTEMPTS
+    }
+}
+
+/// The pending PKCE login a companion session completes (the app in
+/// production)
+trait LoginHandoff {
+    /// Whether the login with this CSRF state is still the pending one
+    fn is_pending(&self, state: &str) -> bool;
+    /// Exchange the code in a redirect URL
+    fn complete(&self, callback_url: &str) -> Result<(), String>;
+    fn abandon(&self, state: &str, error: &str);
+}
+
+impl LoginHandoff for AppHandle {
+    fn is_pending(&self, state: &str) -> bool {
+        spotify_auth::is_pending_login(self, state)
+    }
+
+    fn complete(&self, callback_url: &str) -> Result<(), String> {
+        spotify_auth::handle_login_callback(self, callback_url)
+    }
+
+    fn abandon(&self, state: &str, error: &str) {
+        spotify_auth::abandon_login(self, state, error)
+    }
+}
+
+fn random_pairing_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..PAIRING_CODE_DIGITS)
+        .map(|_| char::from(b'0' + rng.gen_range(0..10)))
+        .collect()
+}
+
+/// LAN address other devices can reach (no packets are sent)
+fn lan_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(8, 8, 8, 8), 80)).ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn page(title: &str, content: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <title>{title}</title></head>\
+         <body style=\"font-family: sans-serif; max-width: 28em; margin: 2em auto; padding: 0 1em\">\
+         <h2>{title}</h2>{content}</body></html>",
+        title = escape_html(title)
+    )
+}
+
+fn message_page(title: &str, message: &str) -> String {
+    page(title, &format!("<p>{}</p>", escape_html(message)))
+}
+
+/// Step 1: enter (or confirm) the pairing code shown on the TV
+fn pairing_page(prefill: &str, error: Option<&str>) -> String {
+    let error = error
+        .map(|e| format!("<p style=\"color: #c00\">{}</p>", escape_html(e)))
+        .unwrap_or_default();
+
+    page(
+        "Connect musicViz to Spotify",
+        &format!(
+            "{error}<form method=\"post\" action=\"/pair\">\
+             <p>Enter the pairing code shown on your TV:</p>\
+             <input name=\"pairing\" value=\"{}\" inputmode=\"numeric\" autocomplete=\"off\" \
+             style=\"font-size: 1.5em; width: 8em\">\
+             <p><button type=\"submit\">Continue</button></p></form>",
+            escape_html(prefill)
+        ),
+    )
+}
+
+/// Step 2: sign in with Spotify; the redirect comes back to /callback, or
+/// the code is pasted here when the redirect page only shows it
+fn login_page(session: &CompanionSession, pairing: &str) -> String {
+    page(
+        "Sign in to Spotify",
+        &format!(
+            "<p><a href=\"{}\">Open the Spotify login</a> and approve musicViz. You will be brought back here.</p>\
+             <p>If the login ends on a page showing a code instead, paste the code (or the whole address) here:</p>\
+             <form method=\"post\" action=\"/complete\">\
+             <input type=\"hidden\" name=\"pairing\" value=\"{}\">\
+             <input name=\"code\" autocomplete=\"off\" style=\"width: 100%\">\
+             <p><button type=\"submit\">Finish</button></p></form>",
+            escape_html(&session.authorize_url),
+            escape_html(pairing)
+        ),
+    )
+}
+
+/// Step 3: confirm the redirect from Spotify with the pairing code
+fn callback_page(code: &str, state: &str, error: Option<&str>) -> String {
+    let error = error
+        .map(|e| format!("<p style=\"color: #c00\">{}</p>", escape_html(e)))
+        .unwrap_or_default();
+
+    page(
+        "Finish connecting musicViz",
+        &format!(
+            "{error}<form method=\"post\" action=\"/complete\">\
+             <input type=\"hidden\" name=\"code\" value=\"{}\">\
+             <input type=\"hidden\" name=\"state\" value=\"{}\">\
+             <p>Enter the pairing code shown on your TV once more:</p>\
+             <input name=\"pairing\" inputmode=\"numeric\" autocomplete=\"off\" style=\"font-size: 1.5em; width: 8em\">\
+             <p><button type=\"submit\">Finish</button></p></form>",
+            escape_html(code),
+            escape_html(state)
+        ),
+    )
+}
+
+/// Parse an application/x-www-form-urlencoded body or query string
+fn form_value(encoded: &str, name: &str) -> Option<String> {
+    Url::parse(&format!("http://form/?{}", encoded))
+        .ok()?
+        .query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// The authorization code and state from what the user pasted: either the
+/// bare code or the full redirect address
+fn pasted_code(pasted: &str) -> (Option<String>, Option<String>) {
+    let pasted = pasted.trim();
+    if pasted.is_empty() {
+        return (None, None);
+    }
+
+    match Url::parse(pasted) {
+        Ok(url) => {
+            let param = |name: &str| {
+                url.query_pairs()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+            };
+            (param("code"), param("state"))
+        }
+        Err(_) => (Some(pasted.to_string()), None),
+    }
+}
+
+/// Exchange the code through the shared PKCE path
+fn finish_login(
+    login: &impl LoginHandoff,
+    session: &CompanionSession,
+    code: &str,
+    state: Option<String>,
+) -> Result<(), String> {
+    let mut callback = Url::parse(&session.redirect_uri).map_err(|e| format!("Invalid redirect URI: {}", e))?;
+    callback
+        .query_pairs_mut()
+        .append_pair("code", code)
+        .append_pair("state", state.as_deref().unwrap_or(&session.state));
+
+    login.complete(callback.as_str())
+}
+
+fn handle_request(login: &impl LoginHandoff, session: &mut CompanionSession, stream: &mut TcpStream) -> Outcome {
+    let Some(request) = spotify_auth::read_http_request(stream) else {
+        return Outcome::Continue;
+    };
+    let (path, query) = request
+        .target
+        .split_once('?')
+        .unwrap_or((request.target.as_str(), ""));
+
+    match (request.method.as_str(), path) {
+        ("GET", "/") | ("GET", "/pair") => {
+            let prefill = form_value(query, "code").unwrap_or_default();
+            spotify_auth::write_http_response(stream, "200 OK", &pairing_page(&prefill, None));
+            Outcome::Continue
+        }
+        ("POST", "/pair") => {
+            let pairing = form_value(&request.body, "pairing").unwrap_or_default();
+            match session.verify(Some(&pairing)) {
+                Ok(()) => {
+                    spotify_auth::write_http_response(stream, "200 OK", &login_page(session, &pairing));
+                    Outcome::Continue
+                }
+                Err(e) => reject(stream, session, &pairing_page("", Some(&e))),
+            }
+        }
+        ("POST", "/complete") => {
+            let pairing = form_value(&request.body, "pairing");
+            let (code, pasted_state) = pasted_code(&form_value(&request.body, "code").unwrap_or_default());
+            let state = form_value(&request.body, "state").or(pasted_state);
+
+            // A relay page posting only the redirect gets the confirmation form
+            if let (None, Some(code), Some(state)) = (&pairing, &code, &state) {
+                spotify_auth::write_http_response(stream, "200 OK", &callback_page(code, state, None));
+                return Outcome::Continue;
+            }
+
+            if let Err(e) = session.verify(pairing.as_deref()) {
+                // Keep the code from the redirect so a retry needs no new login
+                let retry = match (&code, &state) {
+                    (Some(code), Some(state)) => callback_page(code, state, Some(&e)),
+                    _ => pairing_page("", Some(&e)),
+                };
+                return reject(stream, session, &retry);
+            }
+
+            let Some(code) = code else {
+                let page = login_page(session, pairing.as_deref().unwrap_or(""));
+                spotify_auth::write_http_response(stream, "400 Bad Request", &page);
+                return Outcome::Continue;
+            };
+
+            let result = finish_login(login, session, &code, state);
+            respond_finished(stream, &result);
+            Outcome::Finished
+        }
+        // Spotify's redirect only shows the confirmation form; finishing
+        // takes the pairing code like /complete from the login page
+        ("GET", COMPANION_CALLBACK_PATH) => {
+            if let Some(error) = form_value(query, "error") {
+                let page = message_page("Spotify login failed", &format!("Spotify reported: {}", error));
+                spotify_auth::write_http_response(stream, "200 OK", &page);
+                return Outcome::Continue;
+            }
+
+            let (Some(code), Some(state)) = (form_value(query, "code"), form_value(query, "state")) else {
+                let page = message_page("Spotify login failed", "The callback is missing its code.");
+                spotify_auth::write_http_response(stream, "400 Bad Request", &page);
+                return Outcome::Continue;
+            };
+
+            spotify_auth::write_http_response(stream, "200 OK", &callback_page(&code, &state, None));
+            Outcome::Continue
+        }
+        _ => {
+            spotify_auth::write_http_response(stream, "404 Not Found", "");
+            Outcome::Continue
+        }
+    }
+}
+
+/// Answer a wrong pairing code with `retry_page`, ending the session after
+/// too many
+fn reject(stream: &mut TcpStream, session: &CompanionSession, retry_page: &str) -> Outcome {
+    if session.locked_out() {
+        let page = message_page("Pairing locked", "Too many wrong pairing codes. Start the login again on the TV.");
+        spotify_auth::write_http_response(stream, "403 Forbidden", &page);
+        return Outcome::Finished;
+    }
+
+    spotify_auth::write_http_response(stream, "403 Forbidden", retry_page);
+    Outcome::Continue
+}
+
+fn respond_finished(stream: &mut TcpStream, result: &Result<(), String>) {
+    let page = match result {
+        Ok(()) => message_page("Connected to Spotify", "musicViz on your TV is now connected. You can close this tab."),
+        Err(e) => message_page("Spotify login failed", &format!("{}. Start the login again on the TV.", e)),
+    };
+    spotify_auth::write_http_response(stream, "200 OK", &page);
+}
+
+/// Serve the pairing page until the login finishes, is replaced, locks out
+/// or expires
+fn run_companion_listener(login: impl LoginHandoff, listener: TcpListener, mut session: CompanionSession) {
+    loop {
+        if !login.is_pending(&session.state) {
+            println!("Spotify companion listener stopped: login replaced or finished");
+            return;
+        }
+
+        if Instant::now() >= session.expires {
+            println!("Spotify companion login expired");
+            login.abandon(&session.state, "Companion login expired");
+            return;
+        }
+
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            Err(e) => {
+                println!("Spotify companion listener failed: {}", e);
+                login.abandon(&session.state, "Companion login listener failed");
+                return;
+            }
+        };
+
+        // Accepted sockets inherit non-blocking mode on some platforms
+        let _ = stream.set_nonblocking(false);
+        if let Outcome::Finished = handle_request(&login, &mut session, &mut stream) {
+            if session.locked_out() {
+                println!("Spotify companion login locked after {} wrong pairing codes", session.attempts);
+                login.abandon(&session.state, "Too many wrong pairing codes");
+            }
+            return;
+        }
+    }
+}
+
+/// Start a companion login for the TV: serve a pairing page on the LAN and
+/// return its URL, QR code and one-time pairing code. `bind_address`
+/// defaults to all interfaces (use 127.0.0.1 for local testing) and `port`
+/// 0 or None picks an ephemeral port. Spotify redirects to this endpoint's
+/// /callback unless `redirect_uri` names another page; either must be
+/// registered in the Spotify app settings, so use a fixed port. Completion
+/// is reported via `spotify://auth-changed`.
+#[tauri::command]
+pub fn spotify_begin_companion_login(
+    app: AppHandle,
+    pkce: State<PKCEState>,
+    auth: State<SpotifyAuthState>,
+    bind_address: Option<String>,
+    port: Option<u16>,
+    redirect_uri: Option<String>,
+    scopes: Option<Vec<String>>,
+) -> Result<CompanionLogin, String> {
+    let bind_ip: IpAddr = match bind_address {
+        Some(address) => address
+            .parse()
+            .map_err(|e| format!("Invalid bind address {}: {}", address, e))?,
+        None => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    };
+
+    let listener = TcpListener::bind((bind_ip, port.unwrap_or(0)))
+        .map_err(|e| format!("Failed to start companion listener: {}", e))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Failed to configure companion listener: {}", e))?;
+    let port = listener
+        .local_addr()
+        .map_err(|e| format!("Failed to read companion address: {}", e))?
+        .port();
+
+    let host = if bind_ip.is_unspecified() {
+        lan_address().ok_or_else(|| "Unable to determine the LAN address".to_string())?
+    } else {
+        bind_ip
+    };
+
+    let endpoint = std::net::SocketAddr::new(host, port);
+    let redirect_uri = redirect_uri.unwrap_or_else(|| format!("http://{}{}", endpoint, COMPANION_CALLBACK_PATH));
+    let (authorize_url, state) = spotify_auth::begin_login(&pkce, &auth, redirect_uri.clone(), scopes)?;
+
+    let pairing_code = random_pairing_code();
+    let url = format!("http://{}/pair?code={}", endpoint, pairing_code);
+    let qr_svg = QrCode::new(url.as_bytes())
+        .map_err(|e| format!("Failed to create QR code: {}", e))?
+        .render::<svg::Color>()
+        .min_dimensions(240, 240)
+        .build();
+
+    let session = CompanionSession {
+        pairing_code: pairing_code.clone(),
+        attempts: 0,
+        state,
+        redirect_uri,
+        authorize_url,
+        expires: Instant::now() + COMPANION_TIMEOUT,
+    };
+
+    std::thread::Builder::new()
+        .name("spotify-companion".to_string())
+        .spawn(move || run_companion_listener(app, listener, session))
+        .map_err(|e| format!("Failed to spawn companion listener: {}", e))?;
+
+    println!("Started Spotify companion login at {}", url);
+    Ok(CompanionLogin {
+        url,
+        pairing_code,
+        qr_svg,
+        expires_in_secs: COMPANION_TIMEOUT.as_secs(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use std::thread::JoinHandle;
+
+    const PAIRING_CODE: &str = "123456";
+
+    /// Pending login recording completed callback URLs and abandon reasons
+    #[derive(Clone, Default)]
+    struct FakeLogin {
+        completed: Arc<Mutex<Vec<String>>>,
+        abandoned: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl LoginHandoff for FakeLogin {
+        fn is_pending(&self, _state: &str) -> bool {
+            self.completed.lock().unwrap().is_empty() && self.abandoned.lock().unwrap().is_empty()
+        }
+
+        fn complete(&self, callback_url: &str) -> Result<(), String> {
+            self.completed.lock().unwrap().push(callback_url.to_string());
+            Ok(())
+        }
+
+        fn abandon(&self, _state: &str, error: &str) {
+            self.abandoned.lock().unwrap().push(error.to_string());
+        }
+    }
+
+    /// Serve a companion session on loopback that expires after `expires_in`
+    fn serve(expires_in: Duration) -> (SocketAddr, FakeLogin, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let login = FakeLogin::default();
+
+        let session = CompanionSession {
+            pairing_code: PAIRING_CODE.to_string(),
+            attempts: 0,
+            state: "csrf".to_string(),
+            redirect_uri: format!("http://{}{}", addr, COMPANION_CALLBACK_PATH),
+            authorize_url: "https://accounts.spotify.com/authorize?client_id=c&state=csrf".to_string(),
+            expires: Instant::now() + expires_in,
+        };
+        let served = login.clone();
+        let handle = std::thread::spawn(move || run_companion_listener(served, listener, session));
+
+        (addr, login, handle)
+    }
+
+    /// Send one request; None once the listener is gone
+    fn request(addr: SocketAddr, method: &str, target: &str, body: &str) -> Option<(u16, String)> {
+        let mut stream = TcpStream::connect_timeout(&addr, Duration::from_secs(1)).ok()?;
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            target,
+            addr,
+            body.len(),
+            body
+        )
+        .ok()?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).ok()?;
+        let status = response.split_whitespace().nth(1)?.parse().ok()?;
+        Some((status, response))
+    }
+
+    #[test]
+    fn pairing_code_unlocks_the_login_page() {
+        let (addr, login, _) = serve(COMPANION_TIMEOUT);
+
+        let (status, page) = request(addr, "GET", "/pair?code=123456", "").unwrap();
+        assert_eq!(status, 200);
+        assert!(page.contains("value=\"123456\""));
+
+        let (status, page) = request(addr, "POST", "/pair", "pairing=123456").unwrap();
+        assert_eq!(status, 200);
+        assert!(page.contains("https://accounts.spotify.com/authorize?client_id=c&amp;state=csrf"));
+        assert!(login.completed.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn callback_needs_the_pairing_code_and_is_single_use() {
+        let (addr, login, handle) = serve(COMPANION_TIMEOUT);
+
+        // The redirect alone only shows the confirmation form
+        let (status, page) = request(addr, "GET", "/callback?code=abc&state=csrf", "").unwrap();
+        assert_eq!(status, 200);
+        assert!(page.contains("name=\"code\" value=\"abc\""));
+        let (status, _) = request(addr, "POST", "/complete", "code=abc&state=csrf").unwrap();
+        assert_eq!(status, 200);
+        assert!(login.completed.lock().unwrap().is_empty());
+
+        let (status, page) = request(addr, "POST", "/complete", "code=abc&state=csrf&pairing=000000").unwrap();
+        assert_eq!(status, 403);
+        assert!(page.contains("1 of 5") && page.contains("value=\"abc\""));
+
+        let (status, page) = request(addr, "POST", "/complete", "code=abc&state=csrf&pairing=123456").unwrap();
+        assert_eq!(status, 200);
+        assert!(page.contains("Connected to Spotify"));
+        assert_eq!(
+            *login.completed.lock().unwrap(),
+            vec![format!("http://{}/callback?code=abc&state=csrf", addr)]
+        );
+
+        handle.join().unwrap();
+        assert!(request(addr, "POST", "/complete", "code=abc&state=csrf&pairing=123456").is_none());
+        assert_eq!(login.completed.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn pasted_redirect_address_is_accepted() {
+        let (addr, login, _) = serve(COMPANION_TIMEOUT);
+        let pasted = "https%3A%2F%2Fexample.com%2Fdone%3Fcode%3Dxyz%26state%3Dcsrf";
+
+        let (status, _) = request(addr, "POST", "/complete", &format!("pairing=123456&code={}", pasted)).unwrap();
+
+        assert_eq!(status, 200);
+        assert!(login.completed.lock().unwrap()[0].ends_with("?code=xyz&state=csrf"));
+    }
+
+    #[test]
+    fn wrong_pairing_codes_lock_the_session() {
+        let (addr, login, handle) = serve(COMPANION_TIMEOUT);
+
+        for attempt in 1..MAX_PAIRING_ATTEMPTS {
+            let (status, page) = request(addr, "POST", "/pair", &format!("pairing=00000{}", attempt)).unwrap();
+            assert_eq!(status, 403);
+            assert!(page.contains(&format!("{} of 5", attempt)), "{}", page);
+        }
+        let (status, page) = request(addr, "POST", "/complete", "code=abc&state=csrf&pairing=999999").unwrap();
+        assert_eq!(status, 403);
+        assert!(page.contains("Pairing locked"));
+
+        handle.join().unwrap();
+        assert_eq!(*login.abandoned.lock().unwrap(), vec!["Too many wrong pairing codes"]);
+        assert!(request(addr, "POST", "/pair", "pairing=123456").is_none());
+        assert!(login.completed.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn expired_session_is_abandoned() {
+        let (addr, login, handle) = serve(Duration::from_millis(300));
+
+        handle.join().unwrap();
+
+        assert_eq!(*login.abandoned.lock().unwrap(), vec!["Companion login expired"]);
+        assert!(request(addr, "POST", "/pair", "pairing=123456").is_none());
+    }
+}
@@ -0,0 +1,399 @@
+// Spotify Profiles
+//
+// Household support: every Spotify account that logs in becomes a profile
+// keyed by its Spotify user id (read from /me after login). Tokens are
+// stored per profile by the auth module; the active-profile pointer and
+// per-profile preferences persist in spotify_profiles.json.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+use crate::spotify_auth::{self, SpotifyAuthState};
+use crate::spotify_token_scheduler::TokenRefreshScheduler;
+use crate::storage;
+
+const PROFILES_FILE: &str = "spotify_profiles.json";
+
+// Spotify Web API base URL (override with SPOTIFY_API_BASE)
+const API_BASE: &str = "https://api.spotify.com/v1";
+const API_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A Spotify account known to this device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpotifyProfile {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    #[serde(rename = "displayName")]
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    #[serde(rename = "imageUrl")]
+    pub image_url: Option<String>,
+    /// Subscription level ("premium", "free", ...)
+    pub product: Option<String>,
+    #[serde(rename = "addedAt")]
+    pub added_at: u64,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: u64,
+}
+
+/// Persisted profile list, active-profile pointer and preferences
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileStore {
+    pub active: Option<String>,
+    pub profiles: Vec<SpotifyProfile>,
+    /// Free-form preferences per user id (kept when a profile logs out)
+    #[serde(default)]
+    pub preferences: BTreeMap<String, serde_json::Value>,
+}
+
+impl ProfileStore {
+    pub fn load() -> Self {
+        match storage::load_json::<ProfileStore>(PROFILES_FILE) {
+            Ok(store) => store.unwrap_or_default(),
+            Err(e) => {
+                println!("Failed to load Spotify profiles: {}", e);
+                ProfileStore::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(PROFILES_FILE, self)
+    }
+
+    pub fn get(&self, user_id: &str) -> Option<&SpotifyProfile> {
+        self.profiles.iter().find(|profile| profile.user_id == user_id)
+    }
+
+    /// Add or update a profile, keeping when it was first added
+    pub fn upsert(&mut self, mut profile: SpotifyProfile) {
+        match self.profiles.iter_mut().find(|p| p.user_id == profile.user_id) {
+            Some(existing) => {
+                profile.added_at = existing.added_at;
+                *existing = profile;
+            }
+            None => self.profiles.push(profile),
+        }
+    }
+
+    /// Remove a profile (and the active pointer if it pointed at it)
+    pub fn remove(&mut self, user_id: &str) -> bool {
+        let before = self.profiles.len();
+        self.profiles.retain(|profile| profile.user_id != user_id);
+        if self.active.as_deref() == Some(user_id) {
+            self.active = None;
+        }
+        self.profiles.len() != before
+    }
+}
+
+/// Profiles and the active one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileList {
+    pub active: Option<String>,
+    pub profiles: Vec<SpotifyProfile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MeImage {
+    url: String,
+}
+
+/// Subset of the /me response
+#[derive(Debug, Deserialize)]
+struct MeResponse {
+    id: String,
+    display_name: Option<String>,
+    email: Option<String>,
+    #[serde(default)]
+    images: Vec<MeImage>,
+    product: Option<String>,
+}
+
+pub fn api_base() -> String {
+    std::env::var("SPOTIFY_API_BASE").unwrap_or_else(|_| API_BASE.to_string())
+}
+
+/// Spotify user ids are used in keyring accounts and file names
+pub fn is_valid_user_id(user_id: &str) -> bool {
+    !user_id.is_empty()
+        && user_id.len() <= 128
+        && user_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Fetch the account behind an access token
+pub fn fetch_profile(access_token: &str) -> Result<SpotifyProfile, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(API_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let response = client
+        .get(format!("{}/me", api_base()))
+        .bearer_auth(access_token)
+        .send()
+        .map_err(|e| format!("Failed to fetch Spotify profile: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Failed to fetch Spotify profile: HTTP {}", response.status()));
+    }
+
+    let me: MeResponse = response
+        .json()
+        .map_err(|e| format!("Invalid Spotify profile response: {}", e))?;
+    if !is_valid_user_id(&me.id) {
+        return Err(format!("Unexpected Spotify user id: {:?}", me.id));
+    }
+
+    let now = spotify_auth::now_secs();
+    Ok(SpotifyProfile {
+        user_id: me.id,
+        display_name: me.display_name,
+        email: me.email,
+        image_url: me.images.into_iter().next().map(|image| image.url),
+        product: me.product,
+        added_at: now,
+        last_used_at: now,
+    })
+}
+
+/// List known Spotify profiles
+#[tauri::command]
+pub fn spotify_list_profiles(auth: State<SpotifyAuthState>) -> ProfileList {
+    let store = auth.profiles();
+    ProfileList {
+        active: store.active,
+        profiles: store.profiles,
+    }
+}
+
+/// Run a profile change on a blocking worker thread (it waits for a token
+/// refresh in flight and goes to the credential vault)
+async fn run_blocking<T, F>(app: &AppHandle, call: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&SpotifyAuthState) -> Result<T, String> + Send + 'static,
+{
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || call(&app.state::<SpotifyAuthState>()))
+        .await
+        .map_err(|e| format!("Spotify profile task failed: {}", e))?
+}
+
+/// Make another known profile the active one
+#[tauri::command]
+pub async fn spotify_switch_profile(app: AppHandle, user_id: String) -> Result<SpotifyProfile, String> {
+    let switched = user_id.clone();
+    let profile = run_blocking(&app, move |auth| auth.switch_profile(&switched)).await?;
+    app.state::<TokenRefreshScheduler>().reschedule();
+    spotify_auth::emit_auth_changed(&app, true, None, Some(user_id));
+    Ok(profile)
+}
+
+/// Forget a profile: its tokens, entry and preferences
+#[tauri::command]
+pub async fn spotify_remove_profile(app: AppHandle, user_id: String) -> Result<(), String> {
+    let was_active = app.state::<SpotifyAuthState>().active_user().as_deref() == Some(user_id.as_str());
+    if was_active {
+        app.state::<TokenRefreshScheduler>().cancel();
+    }
+    run_blocking(&app, move |auth| auth.remove_profile(&user_id)).await?;
+
+    if was_active {
+        spotify_auth::emit_auth_changed(&app, false, None, None);
+    }
+    Ok(())
+}
+
+/// Get preferences of a profile (the active one by default)
+#[tauri::command]
+pub fn spotify_get_profile_preferences(
+    auth: State<SpotifyAuthState>,
+    user_id: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let user_id = user_id
+        .or_else(|| auth.active_user())
+        .ok_or_else(|| "No active Spotify profile".to_string())?;
+
+    Ok(auth
+        .profiles()
+        .preferences
+        .get(&user_id)
+        .cloned()
+        .unwrap_or_else(|| serde_json::json!({})))
+}
+
+/// Replace preferences of a profile (the active one by default)
+#[tauri::command]
+pub fn spotify_set_profile_preferences(
+    auth: State<SpotifyAuthState>,
+    user_id: Option<String>,
+    preferences: serde_json::Value,
+) -> Result<(), String> {
+    let user_id = user_id
+        .or_else(|| auth.active_user())
+        .ok_or_else(|| "No active Spotify profile".to_string())?;
+
+    auth.update_profiles(|store| {
+        if store.get(&user_id).is_none() {
+            return Err(format!("Unknown Spotify profile: {}", user_id));
+        }
+        store.preferences.insert(user_id.clone(), preferences);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential_vault::{self, vault, CredentialNamespace, CredentialVault};
+    use crate::spotify_auth::SpotifyToken;
+
+    fn profile(user_id: &str, added_at: u64) -> SpotifyProfile {
+        SpotifyProfile {
+            user_id: user_id.to_string(),
+            display_name: Some(user_id.to_uppercase()),
+            email: None,
+            image_url: None,
+            product: Some("premium".to_string()),
+            added_at,
+            last_used_at: added_at,
+        }
+    }
+
+    fn token(access_token: &str) -> SpotifyToken {
+        SpotifyToken {
+            access_token: access_token.to_string(),
+            refresh_token: Some(format!("{}-refresh", access_token)),
+            expires_at: spotify_auth::now_secs() + 3600,
+            scopes: None,
+            version: 1,
+        }
+    }
+
+    fn store_tokens(user_id: &str, token: &SpotifyToken) {
+        let json = serde_json::to_string(token).unwrap();
+        vault()
+            .set(CredentialNamespace::Spotify, &format!("tokens:{}", user_id), &json)
+            .unwrap();
+    }
+
+    fn stored_tokens(user_id: &str) -> Option<String> {
+        vault().get(CredentialNamespace::Spotify, &format!("tokens:{}", user_id)).unwrap()
+    }
+
+    /// Signed in as `active`, with every user in `users` known and their
+    /// tokens in the vault
+    fn household(active: &str, users: &[&str]) -> SpotifyAuthState {
+        let _ = credential_vault::install(CredentialVault::in_memory());
+        for user_id in users {
+            store_tokens(user_id, &token(&format!("{}-access", user_id)));
+        }
+        let auth = SpotifyAuthState::with_token(
+            active,
+            token(&format!("{}-access", active)),
+            "http://127.0.0.1:9/api/token".to_string(),
+        );
+        auth.update_profiles(|store| {
+            for user_id in users {
+                store.upsert(profile(user_id, 100));
+            }
+            Ok(())
+        })
+        .unwrap();
+        auth
+    }
+
+    #[test]
+    fn upsert_keeps_when_a_profile_was_added() {
+        let mut store = ProfileStore::default();
+        store.upsert(profile("alice", 100));
+        store.upsert(profile("bob", 200));
+
+        let mut again = profile("alice", 500);
+        again.display_name = Some("Alice".to_string());
+        store.upsert(again);
+
+        assert_eq!(store.profiles.len(), 2);
+        let alice = store.get("alice").unwrap();
+        assert_eq!(alice.added_at, 100);
+        assert_eq!(alice.last_used_at, 500);
+        assert_eq!(alice.display_name.as_deref(), Some("Alice"));
+    }
+
+    #[test]
+    fn removing_the_active_profile_clears_the_pointer() {
+        let mut store = ProfileStore::default();
+        store.upsert(profile("alice", 100));
+        store.upsert(profile("bob", 100));
+        store.active = Some("bob".to_string());
+
+        assert!(store.remove("alice"));
+        assert_eq!(store.active.as_deref(), Some("bob"));
+        assert!(store.remove("bob"));
+        assert_eq!(store.active, None);
+        assert!(!store.remove("bob"));
+        assert!(store.profiles.is_empty());
+    }
+
+    #[test]
+    fn switching_loads_the_profiles_own_tokens() {
+        let auth = household("profiles-alice", &["profiles-alice", "profiles-bob"]);
+
+        let switched = auth.switch_profile("profiles-bob").unwrap();
+
+        assert_eq!(switched.user_id, "profiles-bob");
+        assert!(switched.last_used_at > 100);
+        assert_eq!(auth.active_user().as_deref(), Some("profiles-bob"));
+        assert_eq!(auth.valid_access_token().unwrap(), "profiles-bob-access");
+        assert!(stored_tokens("profiles-alice").unwrap().contains("profiles-alice-access"));
+
+        auth.switch_profile("profiles-alice").unwrap();
+        assert_eq!(auth.valid_access_token().unwrap(), "profiles-alice-access");
+    }
+
+    #[test]
+    fn switching_needs_a_known_profile_with_tokens() {
+        let auth = household("profiles-carol", &["profiles-carol"]);
+        auth.update_profiles(|store| {
+            store.upsert(profile("profiles-dave", 100));
+            Ok(())
+        })
+        .unwrap();
+
+        let error = auth.switch_profile("profiles-erin").unwrap_err();
+        assert!(error.contains("Unknown Spotify profile"), "{}", error);
+        let error = auth.switch_profile("profiles-dave").unwrap_err();
+        assert!(error.contains("log in again"), "{}", error);
+
+        assert_eq!(auth.active_user().as_deref(), Some("profiles-carol"));
+        assert_eq!(auth.valid_access_token().unwrap(), "profiles-carol-access");
+    }
+
+    #[test]
+    fn removing_a_profile_deletes_only_its_tokens() {
+        let auth = household("profiles-frank", &["profiles-frank", "profiles-grace"]);
+        auth.update_profiles(|store| {
+            store.preferences.insert("profiles-grace".to_string(), serde_json::json!({ "theme": "dark" }));
+            Ok(())
+        })
+        .unwrap();
+
+        auth.remove_profile("profiles-grace").unwrap();
+        assert!(stored_tokens("profiles-grace").is_none());
+        assert!(!auth.profiles().preferences.contains_key("profiles-grace"));
+        assert!(stored_tokens("profiles-frank").is_some());
+        assert_eq!(auth.valid_access_token().unwrap(), "profiles-frank-access");
+
+        // Removing the active profile signs out
+        auth.remove_profile("profiles-frank").unwrap();
+        assert!(stored_tokens("profiles-frank").is_none());
+        assert_eq!(auth.active_user(), None);
+        assert!(auth.valid_access_token().is_err());
+        assert!(auth.remove_profile("profiles-frank").is_err());
+    }
+}
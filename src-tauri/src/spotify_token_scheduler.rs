@@ -0,0 +1,480 @@
+// Spotify Token Refresh Scheduler
+// Background thread that refreshes the Spotify access token a configurable
+// margin before it expires, so long listening sessions never stall on a 401.
+// Network failures are retried with exponential backoff; a rejected refresh
+// token asks the frontend to log in again. Sleeps are capped so a system
+// resume (wall clock jumping ahead of the monotonic clock) is noticed quickly.
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::spotify_auth::{now_secs, SpotifyAuthState, TokenRequestError};
+use crate::storage;
+
+const SETTINGS_FILE: &str = "spotify_refresh.json";
+
+pub const TOKEN_REFRESHED_EVENT: &str = "spotify://token-refreshed";
+pub const REAUTH_REQUIRED_EVENT: &str = "spotify://reauth-required";
+
+// Refresh this long before expiry by default (access tokens last an hour)
+const DEFAULT_MARGIN_SECS: u64 = 300;
+const MIN_MARGIN_SECS: u64 = 60;
+const MAX_MARGIN_SECS: u64 = 1800;
+
+// Longest sleep between checks, bounding how late a resume is noticed
+const MAX_SLEEP: Duration = Duration::from_secs(30);
+
+// Least time between two refreshes, in case Spotify hands out tokens that
+// expire within the margin
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+// Retry delays after network failures
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+// Wall clock running ahead of the monotonic clock by more than this
+// means the system was suspended
+const CLOCK_JUMP_TOLERANCE: Duration = Duration::from_secs(5);
+
+/// Scheduler settings (persisted)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshSettings {
+    /// Seconds before expiry to refresh
+    #[serde(rename = "marginSecs")]
+    pub margin_secs: u64,
+}
+
+impl Default for RefreshSettings {
+    fn default() -> Self {
+        RefreshSettings {
+            margin_secs: DEFAULT_MARGIN_SECS,
+        }
+    }
+}
+
+/// Payload of `spotify://token-refreshed`
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenRefreshedEvent {
+    #[serde(rename = "expiresAt")]
+    pub expires_at: u64,
+    #[serde(rename = "userId")]
+    pub user_id: Option<String>,
+}
+
+/// Payload of `spotify://reauth-required`
+#[derive(Debug, Clone, Serialize)]
+pub struct ReauthRequiredEvent {
+    pub reason: String,
+    #[serde(rename = "userId")]
+    pub user_id: Option<String>,
+}
+
+#[derive(Default)]
+struct Control {
+    started: bool,
+    /// Bumped on cancel so results of an abandoned refresh are dropped
+    generation: u64,
+    /// Set to make the thread re-evaluate the schedule now
+    wake: bool,
+}
+
+/// State shared with the refresh thread
+pub struct TokenRefreshScheduler {
+    settings: Mutex<RefreshSettings>,
+    control: Mutex<Control>,
+    wake: Condvar,
+}
+
+impl TokenRefreshScheduler {
+    pub fn new() -> Self {
+        let settings = match storage::load_json::<RefreshSettings>(SETTINGS_FILE) {
+            Ok(settings) => settings.unwrap_or_default(),
+            Err(e) => {
+                println!("Failed to load token refresh settings: {}", e);
+                RefreshSettings::default()
+            }
+        };
+        Self::with_settings(settings)
+    }
+
+    fn with_settings(settings: RefreshSettings) -> Self {
+        TokenRefreshScheduler {
+            settings: Mutex::new(settings),
+            control: Mutex::new(Control::default()),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// Re-evaluate the schedule now (after a login or profile switch)
+    pub fn reschedule(&self) {
+        self.control.lock().unwrap().wake = true;
+        self.wake.notify_all();
+    }
+
+    /// Drop pending retries and any refresh result in flight (logout)
+    pub fn cancel(&self) {
+        let mut control = self.control.lock().unwrap();
+        control.generation += 1;
+        control.wake = true;
+        self.wake.notify_all();
+    }
+
+    fn generation(&self) -> u64 {
+        self.control.lock().unwrap().generation
+    }
+
+    fn margin_secs(&self) -> u64 {
+        self.settings.lock().unwrap().margin_secs
+    }
+
+    /// Sleep for `timeout` unless woken. Returns true if the wall clock
+    /// jumped ahead (system resumed from suspend).
+    fn sleep(&self, timeout: Duration) -> bool {
+        let started = Instant::now();
+        let started_wall = now_secs();
+
+        let control = self.control.lock().unwrap();
+        let (mut control, _) = self
+            .wake
+            .wait_timeout_while(control, timeout, |control| !control.wake)
+            .unwrap();
+        control.wake = false;
+        drop(control);
+
+        let wall_elapsed = Duration::from_secs(now_secs().saturating_sub(started_wall));
+        let jumped = clock_jumped(wall_elapsed, started.elapsed());
+        if jumped {
+            println!(
+                "Wall clock jumped {}s ahead (system resumed?); checking Spotify token",
+                (wall_elapsed - started.elapsed()).as_secs()
+            );
+        }
+        jumped
+    }
+}
+
+/// Whether the wall clock ran ahead of the monotonic clock, which stops
+/// while the system is suspended
+fn clock_jumped(wall_elapsed: Duration, monotonic_elapsed: Duration) -> bool {
+    wall_elapsed > monotonic_elapsed + CLOCK_JUMP_TOLERANCE
+}
+
+/// Retry delays after network failures: doubling up to MAX_BACKOFF
+struct Backoff {
+    next: Duration,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Backoff { next: INITIAL_BACKOFF }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_BACKOFF);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.next = INITIAL_BACKOFF;
+    }
+}
+
+/// What the frontend is told after a pass of the refresh thread
+#[derive(Debug, PartialEq)]
+enum Notice {
+    /// New access token expiring at this time
+    Refreshed(u64),
+    ReauthRequired(String),
+}
+
+/// State the refresh thread keeps between passes
+struct RefreshLoop {
+    backoff: Backoff,
+    /// Expiry of a token without refresh token we already reported
+    reported_expiry: Option<u64>,
+}
+
+impl RefreshLoop {
+    fn new() -> Self {
+        RefreshLoop {
+            backoff: Backoff::new(),
+            reported_expiry: None,
+        }
+    }
+
+    /// Refresh the token if it is due. Returns how long to sleep before the
+    /// next pass and what to report (nothing when a cancel happened while
+    /// the refresh was in flight).
+    fn step(&mut self, scheduler: &TokenRefreshScheduler, auth: &SpotifyAuthState) -> (Duration, Option<Notice>) {
+        let generation = scheduler.generation();
+        let margin = scheduler.margin_secs();
+
+        let Some((expires_at, refreshable)) = auth.refresh_schedule() else {
+            return (MAX_SLEEP, None);
+        };
+        let due_at = expires_at.saturating_sub(margin);
+        let now = now_secs();
+
+        if due_at > now {
+            return (Duration::from_secs(due_at - now).min(MAX_SLEEP), None);
+        }
+        if !refreshable {
+            // Nothing to refresh with: report once when it expires
+            if expires_at <= now && self.reported_expiry != Some(expires_at) {
+                self.reported_expiry = Some(expires_at);
+                return (MAX_SLEEP, Some(Notice::ReauthRequired("Spotify session expired".to_string())));
+            }
+            return (MAX_SLEEP, None);
+        }
+
+        let (delay, notice) = match auth.refresh_if_expiring(margin) {
+            Ok(Some(token)) => {
+                self.backoff.reset();
+                (MIN_REFRESH_INTERVAL, Some(Notice::Refreshed(token.expires_at)))
+            }
+            // Refreshed elsewhere in the meantime
+            Ok(None) => (Duration::ZERO, None),
+            Err(TokenRequestError::Rejected(reason)) => {
+                self.backoff.reset();
+                (MAX_SLEEP, Some(Notice::ReauthRequired(reason)))
+            }
+            Err(TokenRequestError::Failed(e)) => {
+                let delay = self.backoff.next_delay();
+                println!("Spotify token refresh failed: {}; retrying in {}s", e, delay.as_secs());
+                (delay, None)
+            }
+        };
+
+        if scheduler.generation() != generation {
+            return (delay, None);
+        }
+        (delay, notice)
+    }
+}
+
+impl Default for TokenRefreshScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Start the refresh thread (once)
+pub fn start(app: &AppHandle) {
+    {
+        let scheduler = app.state::<TokenRefreshScheduler>();
+        let mut control = scheduler.control.lock().unwrap();
+        if control.started {
+            return;
+        }
+        control.started = true;
+    }
+
+    let app = app.clone();
+    if let Err(e) = thread::Builder::new()
+        .name("spotify-token-refresh".to_string())
+        .spawn(move || run(app))
+    {
+        println!("Failed to start Spotify token refresh thread: {}", e);
+    }
+}
+
+fn run(app: AppHandle) {
+    let scheduler = app.state::<TokenRefreshScheduler>();
+    let auth = app.state::<SpotifyAuthState>();
+    let mut refresh = RefreshLoop::new();
+
+    println!("Spotify token refresh scheduler started");
+    loop {
+        let (delay, notice) = refresh.step(&scheduler, &auth);
+        match notice {
+            Some(Notice::Refreshed(expires_at)) => emit_token_refreshed(&app, expires_at, auth.active_user()),
+            Some(Notice::ReauthRequired(reason)) => emit_reauth_required(&app, reason, auth.active_user()),
+            None => {}
+        }
+
+        let generation = scheduler.generation();
+        let resumed = scheduler.sleep(delay);
+        if resumed || scheduler.generation() != generation {
+            refresh.backoff.reset();
+        }
+    }
+}
+
+fn emit_token_refreshed(app: &AppHandle, expires_at: u64, user_id: Option<String>) {
+    let event = TokenRefreshedEvent { expires_at, user_id };
+    if let Err(e) = app.emit(TOKEN_REFRESHED_EVENT, event) {
+        println!("Failed to emit {}: {}", TOKEN_REFRESHED_EVENT, e);
+    }
+}
+
+fn emit_reauth_required(app: &AppHandle, reason: String, user_id: Option<String>) {
+    println!("Spotify login required: {}", reason);
+    let event = ReauthRequiredEvent { reason, user_id };
+    if let Err(e) = app.emit(REAUTH_REQUIRED_EVENT, event) {
+        println!("Failed to emit {}: {}", REAUTH_REQUIRED_EVENT, e);
+    }
+}
+
+/// Get scheduler settings
+#[tauri::command]
+pub fn spotify_get_refresh_settings(scheduler: State<TokenRefreshScheduler>) -> RefreshSettings {
+    scheduler.settings.lock().unwrap().clone()
+}
+
+/// Update scheduler settings (the margin is clamped to 60..=1800 seconds)
+#[tauri::command]
+pub fn spotify_set_refresh_settings(
+    settings: RefreshSettings,
+    scheduler: State<TokenRefreshScheduler>,
+) -> Result<RefreshSettings, String> {
+    let settings = RefreshSettings {
+        margin_secs: settings.margin_secs.clamp(MIN_MARGIN_SECS, MAX_MARGIN_SECS),
+    };
+    storage::save_json(SETTINGS_FILE, &settings)?;
+    *scheduler.settings.lock().unwrap() = settings.clone();
+    scheduler.reschedule();
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spotify_auth::tests::{signed_in, token_endpoint};
+    use std::sync::Arc;
+
+    fn scheduler() -> TokenRefreshScheduler {
+        TokenRefreshScheduler::with_settings(RefreshSettings::default())
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_five_minutes() {
+        let mut backoff = Backoff::new();
+        let delays: Vec<u64> = (0..8).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![5, 10, 20, 40, 80, 160, 300, 300]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), INITIAL_BACKOFF);
+    }
+
+    #[test]
+    fn clock_jump_needs_more_than_the_tolerance() {
+        let minute = Duration::from_secs(60);
+        assert!(!clock_jumped(minute, minute));
+        assert!(!clock_jumped(minute + CLOCK_JUMP_TOLERANCE, minute));
+        assert!(clock_jumped(minute + CLOCK_JUMP_TOLERANCE + Duration::from_secs(1), minute));
+        // A wall clock set back is not a resume
+        assert!(!clock_jumped(Duration::ZERO, minute));
+    }
+
+    #[test]
+    fn token_is_refreshed_within_the_margin() {
+        let (endpoint, requests) = token_endpoint(
+            vec![(200, r#"{"access_token":"new","expires_in":3600}"#)],
+            Duration::ZERO,
+        );
+        let auth = signed_in("scheduler-due", now_secs() + DEFAULT_MARGIN_SECS + 120, endpoint);
+        let scheduler = scheduler();
+        let mut refresh = RefreshLoop::new();
+
+        // Two minutes before it is due: sleep until then (capped)
+        assert_eq!(refresh.step(&scheduler, &auth), (MAX_SLEEP, None));
+        assert!(requests.lock().unwrap().is_empty());
+
+        scheduler.settings.lock().unwrap().margin_secs = DEFAULT_MARGIN_SECS + 180;
+        let (delay, notice) = refresh.step(&scheduler, &auth);
+        assert!(matches!(notice, Some(Notice::Refreshed(expires_at)) if expires_at >= now_secs() + 3590));
+        assert_eq!(delay, MIN_REFRESH_INTERVAL);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn short_lived_tokens_are_not_refreshed_back_to_back() {
+        // Every new token already expires within the margin
+        let (endpoint, requests) = token_endpoint(
+            vec![(200, r#"{"access_token":"a","expires_in":60}"#)],
+            Duration::ZERO,
+        );
+        let auth = signed_in("scheduler-short", now_secs(), endpoint);
+        let scheduler = scheduler();
+        let mut refresh = RefreshLoop::new();
+
+        let (delay, notice) = refresh.step(&scheduler, &auth);
+        assert!(matches!(notice, Some(Notice::Refreshed(_))));
+        assert_eq!(delay, MIN_REFRESH_INTERVAL);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn failures_back_off_and_rejection_resets() {
+        let (endpoint, requests) = token_endpoint(
+            vec![
+                (503, r#"{"error":"server_error"}"#),
+                (503, r#"{"error":"server_error"}"#),
+                (503, r#"{"error":"server_error"}"#),
+                (400, r#"{"error":"invalid_grant","error_description":"Refresh token revoked"}"#),
+            ],
+            Duration::ZERO,
+        );
+        let auth = signed_in("scheduler-backoff", now_secs(), endpoint);
+        let scheduler = scheduler();
+        let mut refresh = RefreshLoop::new();
+
+        let delays: Vec<u64> = (0..3).map(|_| refresh.step(&scheduler, &auth).0.as_secs()).collect();
+        assert_eq!(delays, vec![5, 10, 20]);
+
+        let (delay, notice) = refresh.step(&scheduler, &auth);
+        assert_eq!(delay, MAX_SLEEP);
+        assert!(matches!(notice, Some(Notice::ReauthRequired(reason)) if reason.contains("Refresh token revoked")));
+        assert_eq!(refresh.backoff.next_delay(), INITIAL_BACKOFF);
+        assert_eq!(requests.lock().unwrap().len(), 4);
+
+        // Signed out now: nothing left to refresh
+        assert_eq!(refresh.step(&scheduler, &auth), (MAX_SLEEP, None));
+    }
+
+    #[test]
+    fn cancel_drops_the_refresh_in_flight() {
+        let (endpoint, requests) = token_endpoint(
+            vec![(200, r#"{"access_token":"new","expires_in":3600}"#)],
+            Duration::from_millis(300),
+        );
+        let auth = Arc::new(signed_in("scheduler-cancel", now_secs(), endpoint));
+        let scheduler = Arc::new(scheduler());
+
+        let pass = {
+            let (auth, scheduler) = (auth.clone(), scheduler.clone());
+            thread::spawn(move || RefreshLoop::new().step(&scheduler, &auth))
+        };
+        while requests.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        scheduler.cancel();
+
+        let (_, notice) = pass.join().unwrap();
+        assert_eq!(notice, None);
+    }
+
+    #[test]
+    fn cancel_wakes_the_sleeping_thread() {
+        let scheduler = Arc::new(scheduler());
+        let sleeper = {
+            let scheduler = scheduler.clone();
+            thread::spawn(move || {
+                let started = Instant::now();
+                scheduler.sleep(Duration::from_secs(10));
+                started.elapsed()
+            })
+        };
+
+        thread::sleep(Duration::from_millis(50));
+        let generation = scheduler.generation();
+        scheduler.cancel();
+
+        assert!(sleeper.join().unwrap() < Duration::from_secs(5));
+        assert_eq!(scheduler.generation(), generation + 1);
+    }
+}
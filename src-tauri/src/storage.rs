@@ -0,0 +1,74 @@
+// Local configuration storage shared by modules that persist state to disk
+// (Spotify token fallback, room layout, ...)
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Determine local storage path (within user config directory)
+pub fn storage_dir(create: bool) -> Result<PathBuf, String> {
+    let mut dir = base_config_dir()?;
+    dir.push("musicViz");
+
+    if create {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            return Err(format!("Failed to create storage directory {:?}: {}", dir, e));
+        }
+    }
+
+    Ok(dir)
+}
+
+/// Determine platform-specific base configuration directory
+fn base_config_dir() -> Result<PathBuf, String> {
+    #[cfg(target_os = "macos")]
+    {
+        if let Ok(home) = env::var("HOME") {
+            return Ok(PathBuf::from(home).join("Library").join("Application Support"));
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        if let Ok(roaming) = env::var("APPDATA") {
+            return Ok(PathBuf::from(roaming));
+        }
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(target_os = "windows")))]
+    {
+        if let Ok(home) = env::var("HOME") {
+            return Ok(PathBuf::from(home).join(".config"));
+        }
+    }
+
+    Err("Unable to determine configuration directory".to_string())
+}
+
+/// Load a JSON document from the storage directory, if present
+pub fn load_json<T: DeserializeOwned>(file_name: &str) -> Result<Option<T>, String> {
+    let path = storage_dir(false)?.join(file_name);
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let json = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+
+    serde_json::from_str(&json)
+        .map(Some)
+        .map_err(|e| format!("Failed to deserialize {:?}: {}", path, e))
+}
+
+/// Write a JSON document to the storage directory
+pub fn save_json<T: Serialize>(file_name: &str, value: &T) -> Result<(), String> {
+    let path = storage_dir(true)?.join(file_name);
+
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize {:?}: {}", path, e))?;
+
+    fs::write(&path, json)
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
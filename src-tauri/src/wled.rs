@@ -0,0 +1,78 @@
+// WLED Realtime UDP Module
+//
+// Pushes per-pixel colors to WLED controllers using the realtime UDP
+// protocol (DNRGB) on port 21324. WLED returns to its normal effect once
+// packets stop arriving for the configured timeout.
+
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+use crate::govee::RGBColor;
+use crate::lighting::{self, LightBackend, LightTarget};
+
+const WLED_REALTIME_PORT: u16 = 21324;
+const PROTOCOL_DNRGB: u8 = 4;
+/// Maximum LEDs per DNRGB packet
+const LEDS_PER_PACKET: usize = 489;
+/// Seconds WLED waits after the last packet before resuming its own effect
+const REALTIME_TIMEOUT_SECS: u8 = 2;
+
+/// WLED output state for Tauri
+#[derive(Default)]
+pub struct WledState {
+    socket: Mutex<Option<UdpSocket>>,
+}
+
+impl WledState {
+    /// Send pixels without guarding (callers run them through the limiter)
+    pub fn send(&self, address: &str, colors: &[RGBColor]) -> Result<(), String> {
+        let target = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, WLED_REALTIME_PORT)
+        };
+        let addr = target
+            .to_socket_addrs()
+            .map_err(|e| format!("Invalid WLED address {}: {}", target, e))?
+            .next()
+            .ok_or_else(|| format!("Could not resolve WLED address {}", target))?;
+
+        let mut socket = self.socket.lock().unwrap();
+        if socket.is_none() {
+            *socket = Some(
+                UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("Failed to create socket: {}", e))?,
+            );
+        }
+        let socket = socket.as_ref().unwrap();
+
+        for (chunk_index, chunk) in colors.chunks(LEDS_PER_PACKET).enumerate() {
+            let start = (chunk_index * LEDS_PER_PACKET) as u16;
+            let mut packet = Vec::with_capacity(4 + chunk.len() * 3);
+            packet.extend_from_slice(&[PROTOCOL_DNRGB, REALTIME_TIMEOUT_SECS]);
+            packet.extend_from_slice(&start.to_be_bytes());
+            for color in chunk {
+                packet.extend_from_slice(&[color.r, color.g, color.b]);
+            }
+
+            socket
+                .send_to(&packet, addr)
+                .map_err(|e| format!("Failed to send WLED pixels: {}", e))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Set pixel colors on a WLED controller (address is host or host:port),
+/// through schedule caps and the safety limiter
+#[tauri::command]
+pub fn wled_send_pixels(
+    address: String,
+    colors: Vec<RGBColor>,
+    app: AppHandle,
+    state: State<WledState>,
+) -> Result<(), String> {
+    let colors = lighting::guard_colors(&app, LightTarget::device(LightBackend::Wled, &address), colors);
+    state.send(&address, &colors)
+}
@@ -0,0 +1,731 @@
+// Yeelight LAN Communication Module
+// Handles SSDP-style discovery, the JSON-over-TCP control API and
+// "music mode", where the bulb connects back to us so commands can be
+// streamed without the 60 commands/minute rate limit.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+
+use crate::govee::RGBColor;
+use crate::lighting::{self, LightBackend};
+
+/// Default control port advertised by Yeelight bulbs
+const DEFAULT_CONTROL_PORT: u16 = 55443;
+
+/// Bulbs treat "smooth" transitions shorter than this as invalid
+const MIN_SMOOTH_DURATION_MS: u32 = 30;
+
+/// How long to wait for a bulb to connect back after `set_music`
+const MUSIC_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Yeelight device information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YeelightDevice {
+    pub id: String,
+    pub name: String,
+    pub model: String,
+    pub ip: String,
+    pub port: u16,
+    #[serde(rename = "firmwareVersion")]
+    pub firmware_version: String,
+    pub support: Vec<String>,
+    pub state: YeelightDeviceState,
+    #[serde(rename = "musicMode")]
+    pub music_mode: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YeelightDeviceState {
+    pub on: bool,
+    pub brightness: u8,
+    pub color: RGBColor,
+    #[serde(rename = "colorTemperature")]
+    pub color_temperature: u16,
+    #[serde(rename = "colorMode")]
+    pub color_mode: u8,
+}
+
+/// Control API request
+#[derive(Debug, Serialize)]
+struct YeelightCommand<'a> {
+    id: u32,
+    method: &'a str,
+    params: serde_json::Value,
+}
+
+/// Reverse connection opened by a bulb in music mode
+struct MusicSession {
+    stream: TcpStream,
+    next_id: u32,
+}
+
+/// Yeelight manager state for Tauri
+#[derive(Default)]
+pub struct YeelightState {
+    devices: Arc<Mutex<HashMap<String, YeelightDevice>>>,
+    music_sessions: Arc<Mutex<HashMap<String, MusicSession>>>,
+}
+
+/// Discover Yeelight bulbs on the local network
+#[tauri::command]
+pub fn yeelight_discover_devices(
+    timeout: u32,
+    multicast_group: String,
+    discovery_port: u16,
+    state: State<YeelightState>,
+) -> Result<Vec<YeelightDevice>, String> {
+    discover_devices(&state, timeout, &multicast_group, discovery_port)
+}
+
+fn discover_devices(
+    state: &YeelightState,
+    timeout: u32,
+    multicast_group: &str,
+    discovery_port: u16,
+) -> Result<Vec<YeelightDevice>, String> {
+    println!("Starting Yeelight device discovery...");
+    println!("  Multicast: {}:{}", multicast_group, discovery_port);
+
+    // Responses are sent unicast back to the port the search came from
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .map_err(|e| format!("Failed to bind discovery socket: {}", e))?;
+
+    socket
+        .set_read_timeout(Some(Duration::from_millis(timeout as u64)))
+        .map_err(|e| format!("Failed to set socket timeout: {}", e))?;
+
+    let search_addr: SocketAddr = format!("{}:{}", multicast_group, discovery_port)
+        .parse()
+        .map_err(|e| format!("Invalid multicast address: {}", e))?;
+
+    let search_msg = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nST: wifi_bulb\r\n",
+        search_addr
+    );
+
+    socket
+        .send_to(search_msg.as_bytes(), search_addr)
+        .map_err(|e| format!("Failed to send discovery message: {}", e))?;
+
+    println!("Sent discovery message, waiting for responses...");
+
+    let mut found: HashMap<String, YeelightDevice> = HashMap::new();
+    let mut buffer = [0u8; 2048];
+    let start = Instant::now();
+
+    while start.elapsed() < Duration::from_millis(timeout as u64) {
+        match socket.recv_from(&mut buffer) {
+            Ok((size, src_addr)) => {
+                let response = String::from_utf8_lossy(&buffer[..size]);
+                match parse_discovery_response(&response, &src_addr) {
+                    Some(device) => {
+                        println!("  ✓ Found Yeelight: {} ({}) at {}", device.name, device.model, device.ip);
+                        found.insert(device.id.clone(), device);
+                    }
+                    None => println!("  ! Response from {} wasn't a Yeelight advertisement", src_addr),
+                }
+            }
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::WouldBlock
+                    && e.kind() != std::io::ErrorKind::TimedOut
+                {
+                    println!("Error receiving response: {}", e);
+                }
+            }
+        }
+    }
+
+    // Bulbs answer several times; keep music mode flags from earlier sessions
+    let active_sessions: Vec<String> = state.music_sessions.lock().unwrap().keys().cloned().collect();
+    let mut state_devices = state.devices.lock().unwrap();
+    for device in found.values_mut() {
+        device.music_mode = active_sessions.contains(&device.id);
+        state_devices.insert(device.id.clone(), device.clone());
+    }
+
+    println!("Discovery complete: {} Yeelight devices found", found.len());
+    Ok(found.into_values().collect())
+}
+
+/// Parse an SSDP-style discovery response or advertisement
+fn parse_discovery_response(response: &str, src_addr: &SocketAddr) -> Option<YeelightDevice> {
+    let mut headers: HashMap<String, String> = HashMap::new();
+    for line in response.lines().skip(1) {
+        if let Some((key, value)) = line.split_once(':') {
+            headers.insert(key.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    // Location: yeelight://192.168.1.239:55443
+    let location = headers.get("location")?.strip_prefix("yeelight://")?;
+    let (ip, port) = match location.rsplit_once(':') {
+        Some((ip, port)) => (ip.to_string(), port.parse().unwrap_or(DEFAULT_CONTROL_PORT)),
+        None => (src_addr.ip().to_string(), DEFAULT_CONTROL_PORT),
+    };
+
+    let id = headers.get("id")?.clone();
+    let model = headers.get("model").cloned().unwrap_or_else(|| "Unknown".to_string());
+    let name = headers
+        .get("name")
+        .filter(|n| !n.is_empty())
+        .cloned()
+        .unwrap_or_else(|| format!("Yeelight {}", model));
+
+    let header_u32 = |key: &str| headers.get(key).and_then(|v| v.parse::<u32>().ok());
+    let rgb = header_u32("rgb").unwrap_or(0xFFFFFF);
+
+    Some(YeelightDevice {
+        id,
+        name,
+        model,
+        ip,
+        port,
+        firmware_version: headers.get("fw_ver").cloned().unwrap_or_default(),
+        support: headers
+            .get("support")
+            .map(|s| s.split_whitespace().map(|m| m.to_string()).collect())
+            .unwrap_or_default(),
+        state: YeelightDeviceState {
+            on: headers.get("power").map(|p| p == "on").unwrap_or(false),
+            brightness: header_u32("bright").unwrap_or(100).min(100) as u8,
+            color: RGBColor {
+                r: ((rgb >> 16) & 0xFF) as u8,
+                g: ((rgb >> 8) & 0xFF) as u8,
+                b: (rgb & 0xFF) as u8,
+            },
+            color_temperature: header_u32("ct").unwrap_or(4000) as u16,
+            color_mode: header_u32("color_mode").unwrap_or(1) as u8,
+        },
+        music_mode: false,
+    })
+}
+
+/// Build transition parameters ("sudden" or "smooth" + duration)
+fn transition_params(duration_ms: u32) -> (&'static str, u32) {
+    if duration_ms < MIN_SMOOTH_DURATION_MS {
+        ("sudden", MIN_SMOOTH_DURATION_MS)
+    } else {
+        ("smooth", duration_ms)
+    }
+}
+
+impl YeelightState {
+    /// Look up the control address of a discovered bulb
+    fn device_addr(&self, device_id: &str) -> Result<SocketAddr, String> {
+        let devices = self.devices.lock().unwrap();
+        let device = devices
+            .get(device_id)
+            .ok_or_else(|| format!("Yeelight {} not found", device_id))?;
+
+        format!("{}:{}", device.ip, device.port)
+            .parse()
+            .map_err(|e| format!("Invalid device address: {}", e))
+    }
+
+    /// Send a command, preferring the music mode connection when one is open
+    fn send(&self, device_id: &str, method: &str, params: serde_json::Value) -> Result<serde_json::Value, String> {
+        {
+            let mut sessions = self.music_sessions.lock().unwrap();
+            if let Some(session) = sessions.get_mut(device_id) {
+                match send_music_command(session, method, params.clone()) {
+                    Ok(()) => return Ok(serde_json::json!(["ok"])),
+                    Err(e) => {
+                        // The bulb dropped the connection; fall back to the control API
+                        println!("Music mode connection to {} lost: {}", device_id, e);
+                        sessions.remove(device_id);
+                        self.set_music_flag(device_id, false);
+                    }
+                }
+            }
+        }
+
+        let addr = self.device_addr(device_id)?;
+        send_control_command(&addr, method, params)
+    }
+
+    fn set_music_flag(&self, device_id: &str, enabled: bool) {
+        if let Some(device) = self.devices.lock().unwrap().get_mut(device_id) {
+            device.music_mode = enabled;
+        }
+    }
+}
+
+/// Send a command over a fresh control connection and wait for its result
+fn send_control_command(
+    addr: &SocketAddr,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let mut stream = connect_control(addr)?;
+    control_request(&mut stream, method, params)
+}
+
+/// Open a control API connection to a bulb
+fn connect_control(addr: &SocketAddr) -> Result<TcpStream, String> {
+    let stream = TcpStream::connect_timeout(addr, Duration::from_secs(2))
+        .map_err(|e| format!("Failed to connect to {}: {}", addr, e))?;
+
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .map_err(|e| format!("Failed to set timeout: {}", e))?;
+
+    Ok(stream)
+}
+
+/// Send a command on a control connection and wait for the matching result line
+fn control_request(
+    stream: &mut TcpStream,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    write_command(stream, 1, method, params)?;
+
+    // Bulbs may interleave "props" notifications before the result line
+    let reader_stream = stream
+        .try_clone()
+        .map_err(|e| format!("Failed to clone control connection: {}", e))?;
+    let mut reader = BufReader::new(reader_stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to receive response: {}", e))?;
+        if read == 0 {
+            return Err("Connection closed before response".to_string());
+        }
+
+        let response: serde_json::Value = match serde_json::from_str(line.trim()) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        if response.get("id").and_then(|id| id.as_u64()) != Some(1) {
+            continue;
+        }
+
+        if let Some(error) = response.get("error") {
+            return Err(format!("Yeelight {} failed: {}", method, error));
+        }
+
+        return Ok(response.get("result").cloned().unwrap_or(serde_json::Value::Null));
+    }
+}
+
+/// Send a command over an open music mode connection (the bulb never replies)
+fn send_music_command(session: &mut MusicSession, method: &str, params: serde_json::Value) -> Result<(), String> {
+    let id = session.next_id;
+    session.next_id = session.next_id.wrapping_add(1);
+    write_command(&mut session.stream, id, method, params)
+}
+
+fn write_command(stream: &mut TcpStream, id: u32, method: &str, params: serde_json::Value) -> Result<(), String> {
+    let command = YeelightCommand { id, method, params };
+    let mut line = serde_json::to_string(&command)
+        .map_err(|e| format!("Failed to serialize command: {}", e))?;
+    line.push_str("\r\n");
+
+    stream
+        .write_all(line.as_bytes())
+        .map_err(|e| format!("Failed to send command: {}", e))
+}
+
+/// Set bulb color
+#[tauri::command]
+pub fn yeelight_set_rgb(
+    device_id: String,
+    color: RGBColor,
+    duration_ms: u32,
+    app: AppHandle,
+    state: State<YeelightState>,
+) -> Result<(), String> {
+    let color = lighting::guard_color(&app, LightBackend::Yeelight, &device_id, color);
+    set_rgb(&state, &device_id, color, duration_ms)
+}
+
+/// Send a color without guarding (callers run it through the limiter)
+pub fn set_rgb(state: &YeelightState, device_id: &str, color: RGBColor, duration_ms: u32) -> Result<(), String> {
+    let rgb = ((color.r as u32) << 16) | ((color.g as u32) << 8) | color.b as u32;
+    let (effect, duration) = transition_params(duration_ms);
+
+    state.send(device_id, "set_rgb", serde_json::json!([rgb, effect, duration]))?;
+
+    if let Some(device) = state.devices.lock().unwrap().get_mut(device_id) {
+        device.state.color = color;
+    }
+    Ok(())
+}
+
+/// Set white color temperature (1700-6500 K)
+#[tauri::command]
+pub fn yeelight_set_ct(
+    device_id: String,
+    kelvin: u16,
+    duration_ms: u32,
+    app: AppHandle,
+    state: State<YeelightState>,
+) -> Result<(), String> {
+    let brightness = state.devices.lock().unwrap().get(&device_id).map_or(100, |d| d.state.brightness);
+    lighting::guard_white_point(&app, LightBackend::Yeelight, &device_id, kelvin, brightness)?;
+    set_ct(&state, &device_id, kelvin, duration_ms)
+}
+
+/// Send a color temperature without guarding
+pub fn set_ct(state: &YeelightState, device_id: &str, kelvin: u16, duration_ms: u32) -> Result<(), String> {
+    let kelvin = kelvin.clamp(1700, 6500);
+    let (effect, duration) = transition_params(duration_ms);
+
+    state.send(device_id, "set_ct_abx", serde_json::json!([kelvin, effect, duration]))?;
+
+    if let Some(device) = state.devices.lock().unwrap().get_mut(device_id) {
+        device.state.color_temperature = kelvin;
+    }
+    Ok(())
+}
+
+/// Set bulb brightness (1-100)
+#[tauri::command]
+pub fn yeelight_set_bright(
+    device_id: String,
+    brightness: u8,
+    duration_ms: u32,
+    app: AppHandle,
+    state: State<YeelightState>,
+) -> Result<(), String> {
+    let brightness = lighting::guard_brightness(&app, LightBackend::Yeelight, &device_id, brightness);
+    set_bright(&state, &device_id, brightness, duration_ms)
+}
+
+/// Send a brightness without guarding
+pub fn set_bright(state: &YeelightState, device_id: &str, brightness: u8, duration_ms: u32) -> Result<(), String> {
+    let brightness = brightness.clamp(1, 100);
+    let (effect, duration) = transition_params(duration_ms);
+
+    state.send(device_id, "set_bright", serde_json::json!([brightness, effect, duration]))?;
+
+    if let Some(device) = state.devices.lock().unwrap().get_mut(device_id) {
+        device.state.brightness = brightness;
+    }
+    Ok(())
+}
+
+/// Turn bulb on/off
+#[tauri::command]
+pub fn yeelight_set_power(
+    device_id: String,
+    on: bool,
+    duration_ms: u32,
+    app: AppHandle,
+    state: State<YeelightState>,
+) -> Result<(), String> {
+    lighting::guard_power(&app, LightBackend::Yeelight, &device_id)?;
+    let (effect, duration) = transition_params(duration_ms);
+    let power = if on { "on" } else { "off" };
+
+    state.send(&device_id, "set_power", serde_json::json!([power, effect, duration]))?;
+
+    if let Some(device) = state.devices.lock().unwrap().get_mut(&device_id) {
+        device.state.on = on;
+    }
+    Ok(())
+}
+
+/// Enable music mode: the bulb connects back to us and accepts unthrottled commands
+#[tauri::command]
+pub fn yeelight_start_music_mode(device_id: String, state: State<YeelightState>) -> Result<(), String> {
+    start_music_mode(&state, &device_id)
+}
+
+fn start_music_mode(state: &YeelightState, device_id: &str) -> Result<(), String> {
+    if state.music_sessions.lock().unwrap().contains_key(device_id) {
+        println!("Music mode already active for {}", device_id);
+        return Ok(());
+    }
+
+    let addr = state.device_addr(device_id)?;
+
+    let mut control = connect_control(&addr)?;
+
+    // Listen on the local interface that reaches the bulb so it can call us back
+    let local_ip = control
+        .local_addr()
+        .map_err(|e| format!("Failed to determine local address for {}: {}", addr, e))?
+        .ip();
+
+    let listener = TcpListener::bind((local_ip, 0))
+        .map_err(|e| format!("Failed to bind music mode listener: {}", e))?;
+    let listen_addr = listener
+        .local_addr()
+        .map_err(|e| format!("Failed to read listener address: {}", e))?;
+
+    println!("Starting Yeelight music mode for {} (callback {})", device_id, listen_addr);
+
+    control_request(
+        &mut control,
+        "set_music",
+        serde_json::json!([1, listen_addr.ip().to_string(), listen_addr.port()]),
+    )?;
+
+    let stream = accept_with_timeout(&listener, MUSIC_CONNECT_TIMEOUT)?;
+    stream
+        .set_nodelay(true)
+        .map_err(|e| format!("Failed to configure music mode connection: {}", e))?;
+
+    state
+        .music_sessions
+        .lock()
+        .unwrap()
+        .insert(device_id.to_string(), MusicSession { stream, next_id: 1 });
+    state.set_music_flag(device_id, true);
+
+    println!("Music mode active for {}", device_id);
+    Ok(())
+}
+
+/// Wait for the bulb's reverse connection
+fn accept_with_timeout(listener: &TcpListener, timeout: Duration) -> Result<TcpStream, String> {
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Failed to configure listener: {}", e))?;
+
+    let start = Instant::now();
+    loop {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                println!("Bulb connected back from {}", peer);
+                stream
+                    .set_nonblocking(false)
+                    .map_err(|e| format!("Failed to configure music mode connection: {}", e))?;
+                return Ok(stream);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                if start.elapsed() >= timeout {
+                    return Err("Timed out waiting for bulb to connect for music mode".to_string());
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            Err(e) => return Err(format!("Failed to accept music mode connection: {}", e)),
+        }
+    }
+}
+
+/// Disable music mode and return to the rate-limited control API
+#[tauri::command]
+pub fn yeelight_stop_music_mode(device_id: String, state: State<YeelightState>) -> Result<(), String> {
+    stop_music_mode(&state, &device_id)
+}
+
+fn stop_music_mode(state: &YeelightState, device_id: &str) -> Result<(), String> {
+    let session = state.music_sessions.lock().unwrap().remove(device_id);
+    state.set_music_flag(device_id, false);
+
+    if let Some(session) = session {
+        let _ = session.stream.shutdown(std::net::Shutdown::Both);
+    }
+
+    // Bulbs also leave music mode when the connection drops, but say so explicitly
+    let addr = state.device_addr(device_id)?;
+    if let Err(e) = send_control_command(&addr, "set_music", serde_json::json!([0])) {
+        println!("Warning: Failed to send set_music off to {}: {}", device_id, e);
+    }
+
+    println!("Music mode stopped for {}", device_id);
+    Ok(())
+}
+
+/// Get all cached Yeelight devices
+#[tauri::command]
+pub fn yeelight_get_all_devices(state: State<YeelightState>) -> Vec<YeelightDevice> {
+    let devices = state.devices.lock().unwrap();
+    devices.values().cloned().collect()
+}
+
+/// Clear cached devices and close any music mode connections
+#[tauri::command]
+pub fn yeelight_clear_devices(state: State<YeelightState>) {
+    let mut sessions = state.music_sessions.lock().unwrap();
+    for (_, session) in sessions.drain() {
+        let _ = session.stream.shutdown(std::net::Shutdown::Both);
+    }
+    state.devices.lock().unwrap().clear();
+    println!("Cleared all cached Yeelight devices");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    /// (connection, method, params) of a command the fake bulb received
+    type Received = (&'static str, String, Value);
+
+    /// Fake bulb answering discovery and control requests. Methods in
+    /// `failing` are answered with an error; set_music connects back and
+    /// reports commands from the music connection too.
+    struct FakeBulb {
+        discovery_port: u16,
+        received: Receiver<Received>,
+    }
+
+    impl FakeBulb {
+        fn start(failing: &'static [&'static str]) -> Self {
+            let control = TcpListener::bind("127.0.0.1:0").unwrap();
+            let control_port = control.local_addr().unwrap().port();
+            let discovery = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let discovery_port = discovery.local_addr().unwrap().port();
+            let (sender, received) = mpsc::channel();
+
+            std::thread::spawn(move || {
+                let mut buffer = [0u8; 1024];
+                while let Ok((size, from)) = discovery.recv_from(&mut buffer) {
+                    if !String::from_utf8_lossy(&buffer[..size]).contains("ST: wifi_bulb") {
+                        continue;
+                    }
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nLocation: yeelight://127.0.0.1:{}\r\nid: 0x0000000007e7bd12\r\n\
+                         model: color\r\nfw_ver: 18\r\nsupport: set_rgb set_ct_abx set_bright set_power set_music\r\n\
+                         power: on\r\nbright: 50\r\ncolor_mode: 2\r\nct: 2700\r\nrgb: 16711680\r\nname: desk\r\n",
+                        control_port
+                    );
+                    let _ = discovery.send_to(response.as_bytes(), from);
+                }
+            });
+
+            std::thread::spawn(move || {
+                for stream in control.incoming().flatten() {
+                    let sender = sender.clone();
+                    std::thread::spawn(move || answer_control(stream, sender, failing));
+                }
+            });
+
+            FakeBulb { discovery_port, received }
+        }
+
+        fn next(&self) -> Received {
+            self.received.recv_timeout(Duration::from_secs(2)).unwrap()
+        }
+    }
+
+    fn answer_control(stream: TcpStream, sender: Sender<Received>, failing: &[&str]) {
+        let mut writer = stream.try_clone().unwrap();
+        for line in BufReader::new(stream).lines().map_while(Result::ok) {
+            let command: Value = serde_json::from_str(&line).unwrap();
+            let method = command["method"].as_str().unwrap().to_string();
+            let params = command["params"].clone();
+
+            // Bulbs push property notifications on every control connection
+            let _ = writer.write_all(b"{\"method\":\"props\",\"params\":{\"power\":\"on\"}}\r\n");
+            let reply = if failing.contains(&method.as_str()) {
+                json!({ "id": command["id"], "error": { "code": -1, "message": "unsupported method" } })
+            } else {
+                json!({ "id": command["id"], "result": ["ok"] })
+            };
+            let _ = writer.write_all(format!("{}\r\n", reply).as_bytes());
+
+            if method == "set_music" && params[0] == 1 {
+                let callback = format!("{}:{}", params[1].as_str().unwrap(), params[2]);
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    let music = TcpStream::connect(callback).unwrap();
+                    for line in BufReader::new(music).lines().map_while(Result::ok) {
+                        let command: Value = serde_json::from_str(&line).unwrap();
+                        let method = command["method"].as_str().unwrap().to_string();
+                        let _ = sender.send(("music", method, command["params"].clone()));
+                    }
+                });
+            }
+            let _ = sender.send(("control", method, params));
+        }
+    }
+
+    const BULB_ID: &str = "0x0000000007e7bd12";
+
+    fn discovered(bulb: &FakeBulb) -> YeelightState {
+        let state = YeelightState::default();
+        discover_devices(&state, 300, "127.0.0.1", bulb.discovery_port).unwrap();
+        state
+    }
+
+    #[test]
+    fn discovery_reads_the_advertised_state() {
+        let bulb = FakeBulb::start(&[]);
+        let state = YeelightState::default();
+
+        let devices = discover_devices(&state, 300, "127.0.0.1", bulb.discovery_port).unwrap();
+
+        assert_eq!(devices.len(), 1);
+        let device = &devices[0];
+        assert_eq!(device.id, BULB_ID);
+        assert_eq!(device.name, "desk");
+        assert_eq!(device.ip, "127.0.0.1");
+        assert_eq!(device.firmware_version, "18");
+        assert!(device.support.contains(&"set_music".to_string()));
+        assert!(device.state.on);
+        assert_eq!(device.state.brightness, 50);
+        assert_eq!((device.state.color.r, device.state.color.g, device.state.color.b), (255, 0, 0));
+        assert_eq!(device.state.color_temperature, 2700);
+        assert!(state.device_addr(BULB_ID).is_ok());
+    }
+
+    #[test]
+    fn commands_go_over_the_control_connection() {
+        let bulb = FakeBulb::start(&[]);
+        let state = discovered(&bulb);
+
+        set_rgb(&state, BULB_ID, RGBColor { r: 0, g: 128, b: 255 }, 200).unwrap();
+        set_ct(&state, BULB_ID, 9000, 0).unwrap();
+        set_bright(&state, BULB_ID, 0, 100).unwrap();
+
+        assert_eq!(bulb.next(), ("control", "set_rgb".to_string(), json!([0x0080FF, "smooth", 200])));
+        assert_eq!(bulb.next(), ("control", "set_ct_abx".to_string(), json!([6500, "sudden", 30])));
+        assert_eq!(bulb.next(), ("control", "set_bright".to_string(), json!([1, "smooth", 100])));
+
+        let devices = state.devices.lock().unwrap();
+        let device = &devices[BULB_ID];
+        assert_eq!((device.state.color.r, device.state.color.g, device.state.color.b), (0, 128, 255));
+        assert_eq!(device.state.color_temperature, 6500);
+        assert_eq!(device.state.brightness, 1);
+    }
+
+    #[test]
+    fn bulb_errors_are_reported() {
+        let bulb = FakeBulb::start(&["set_ct_abx"]);
+        let state = discovered(&bulb);
+
+        let error = set_ct(&state, BULB_ID, 4000, 0).unwrap_err();
+
+        assert!(error.contains("set_ct_abx") && error.contains("unsupported method"), "{}", error);
+        assert_eq!(bulb.next(), ("control", "set_ct_abx".to_string(), json!([4000, "sudden", 30])));
+        // The rejected value is not recorded; the advertised 2700K stays
+        assert_eq!(state.devices.lock().unwrap()[BULB_ID].state.color_temperature, 2700);
+        assert!(set_rgb(&YeelightState::default(), BULB_ID, RGBColor { r: 1, g: 2, b: 3 }, 0).is_err());
+    }
+
+    #[test]
+    fn music_mode_streams_over_the_reverse_connection() {
+        let bulb = FakeBulb::start(&[]);
+        let state = discovered(&bulb);
+
+        start_music_mode(&state, BULB_ID).unwrap();
+        let (_, method, params) = bulb.next();
+        assert_eq!(method, "set_music");
+        assert_eq!(params[0], 1);
+        assert!(state.devices.lock().unwrap()[BULB_ID].music_mode);
+
+        set_rgb(&state, BULB_ID, RGBColor { r: 0, g: 0, b: 255 }, 0).unwrap();
+        set_bright(&state, BULB_ID, 80, 0).unwrap();
+        assert_eq!(bulb.next(), ("music", "set_rgb".to_string(), json!([255, "sudden", 30])));
+        assert_eq!(bulb.next(), ("music", "set_bright".to_string(), json!([80, "sudden", 30])));
+
+        stop_music_mode(&state, BULB_ID).unwrap();
+        assert_eq!(bulb.next(), ("control", "set_music".to_string(), json!([0])));
+        assert!(!state.devices.lock().unwrap()[BULB_ID].music_mode);
+        assert!(state.music_sessions.lock().unwrap().is_empty());
+    }
+}
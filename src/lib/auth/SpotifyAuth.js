@@ -7,6 +7,13 @@ export class SpotifyAuth {
   constructor() {
     this.clientId = import.meta.env.VITE_SPOTIFY_CLIENT_ID;
     this.redirectUri = import.meta.env.VITE_SPOTIFY_REDIRECT_URI || 'musicviz://callback';
+    // 'loopback' uses a one-shot http://127.0.0.1 listener instead of the deep link
+    this.loginMode = import.meta.env.VITE_SPOTIFY_LOGIN_MODE || 'deeplink';
+    this.loopbackPort = Number(import.meta.env.VITE_SPOTIFY_LOOPBACK_PORT) || null;
+    // TV login: Spotify redirects to http://<tv address>:<port>/callback on the
+    // LAN (registered in the Spotify app settings) unless a relay page is set
+    this.companionPort = Number(import.meta.env.VITE_SPOTIFY_COMPANION_PORT) || 8898;
+    this.companionRedirectUri = import.meta.env.VITE_SPOTIFY_COMPANION_REDIRECT_URI || null;
     this.scopes = [
       'user-read-playback-state',
       'user-modify-playback-state',
@@ -21,92 +28,36 @@ export class SpotifyAuth {
     if (!this.clientId) {
       throw new Error('VITE_SPOTIFY_CLIENT_ID not found in environment variables');
     }
-  }
-
-  /**
-   * Generate a random string for PKCE code_verifier
-   * @param {number} length - Length of random string (43-128 chars)
-   * @returns {string}
-   */
-  generateRandomString(length) {
-    const possible = 'ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~';
-    const values = crypto.getRandomValues(new Uint8Array(length));
-    return Array.from(values)
-      .map(x => possible[x % possible.length])
-      .join('');
-  }
-
-  /**
-   * Base64 URL encode (without padding)
-   * @param {ArrayBuffer} buffer
-   * @returns {string}
-   */
-  base64URLEncode(buffer) {
-    const bytes = new Uint8Array(buffer);
-    let binary = '';
-    for (let i = 0; i < bytes.byteLength; i++) {
-      binary += String.fromCharCode(bytes[i]);
-    }
-    return btoa(binary)
-      .replace(/\+/g, '-')
-      .replace(/\//g, '_')
-      .replace(/=/g, '');
-  }
-
-  /**
-   * Generate PKCE code_challenge from code_verifier
-   * @returns {Promise<string>} code_challenge
-   */
-  async generateCodeChallenge() {
-    // Generate random code_verifier (128 chars recommended)
-    const codeVerifier = this.generateRandomString(128);
-
-    // Store verifier in Rust backend (secure, not accessible from JS)
-    await invoke('store_code_verifier', { codeVerifier });
 
-    // Create SHA256 hash
-    const encoder = new TextEncoder();
-    const data = encoder.encode(codeVerifier);
-    const digest = await crypto.subtle.digest('SHA-256', data);
-
-    // Base64 URL encode
-    const codeChallenge = this.base64URLEncode(digest);
-
-    return codeChallenge;
+    // The backend performs token refreshes and needs the client ID for them
+    invoke('set_spotify_client_id', { clientId: this.clientId }).catch((error) => {
+      console.error('Failed to register Spotify client ID:', error);
+    });
   }
 
   /**
-   * Start Device OAuth flow - for TV/devices without easy text input
-   * Shows QR code and waits for user to complete auth on phone
-   * @returns {Promise<{authUrl: string, userCode: string}>}
+   * Start companion (phone) login - for TV/devices without easy text input.
+   * The backend serves a pairing page on the LAN; the phone signs in to
+   * Spotify, is redirected back to the TV and confirms with the pairing code. Completion arrives as
+   * `spotify://auth-changed` (see waitForLogin()).
+   * @returns {Promise<{authUrl: string, pairingCode: string, qrSvg: string, expiresInSecs: number, message: string}>}
    */
   async startDeviceAuth() {
     try {
-      // Generate PKCE challenge
-      const codeChallenge = await this.generateCodeChallenge();
-
-      // Use a web-based redirect that shows the code
-      const webRedirectUri = 'https://spotify-auth-display.github.io/callback';
-
-      // Build authorization URL
-      const params = new URLSearchParams({
-        client_id: this.clientId,
-        response_type: 'code',
-        redirect_uri: webRedirectUri,
-        scope: this.scopes.join(' '),
-        code_challenge_method: 'S256',
-        code_challenge: codeChallenge,
-        show_dialog: 'true',
+      const login = await invoke('spotify_begin_companion_login', {
+        port: this.companionPort,
+        redirectUri: this.companionRedirectUri,
+        scopes: this.scopes,
       });
 
-      const authUrl = `https://accounts.spotify.com/authorize?${params.toString()}`;
-
-      console.log('[DeviceAuth] Generated auth URL for QR code');
+      console.log('[DeviceAuth] Companion login started at', login.url);
 
-      // Return URL for QR code display and wait for manual code entry
       return {
-        authUrl,
-        message: 'Scan QR code with your phone to login'
+        authUrl: login.url,
+        pairingCode: login.pairingCode,
+        qrSvg: login.qrSvg,
+        expiresInSecs: login.expiresInSecs,
+        message: `Scan the QR code with your phone, or open ${login.url} and enter ${login.pairingCode}`,
       };
     } catch (error) {
       console.error('Failed to start device auth:', error);
@@ -115,44 +66,48 @@ export class SpotifyAuth {
   }
 
   /**
-   * Start OAuth flow - opens browser for user authorization
-   * @returns {Promise<string>} Authorization code
+   * Abandon the login in progress
+   * @returns {Promise<boolean>}
    */
-  async startAuth() {
-    try {
-      // Generate PKCE challenge
-      const codeChallenge = await this.generateCodeChallenge();
-
-      // Build authorization URL
-      const params = new URLSearchParams({
-        client_id: this.clientId,
-        response_type: 'code',
-        redirect_uri: this.redirectUri,
-        scope: this.scopes.join(' '),
-        code_challenge_method: 'S256',
-        code_challenge: codeChallenge,
-        show_dialog: 'true', // Always show auth dialog
-      });
+  async cancelLogin() {
+    return invoke('spotify_cancel_login');
+  }
 
-      const authUrl = `https://accounts.spotify.com/authorize?${params.toString()}`;
+  /**
+   * Start OAuth flow - opens browser for user authorization. The backend
+   * completes the login from the redirect and emits `spotify://auth-changed`.
+   * @param {{loopback?: boolean}} options - loopback: redirect to a local
+   *   http://127.0.0.1 listener instead of the musicviz:// deep link
+   * @returns {Promise<void>}
+   */
+  async startAuth({ loopback = this.loginMode === 'loopback' } = {}) {
+    try {
+      // Try to detect if we're on Android
+      const isAndroid = navigator.userAgent.toLowerCase().includes('android');
 
       console.log('Opening authorization URL...');
 
-      // Try to detect if we're on Android
-      const isAndroid = navigator.userAgent.toLowerCase().includes('android');
+      if (loopback) {
+        // Backend listens on 127.0.0.1 and exchanges the code itself
+        await invoke('spotify_begin_loopback_login', {
+          port: this.loopbackPort,
+          scopes: this.scopes,
+        });
+        return;
+      }
+
+      // Backend generates the PKCE verifier/challenge and CSRF state, and
+      // opens the system browser on desktop
+      const authUrl = await invoke('spotify_begin_login', {
+        redirectUri: this.redirectUri,
+        scopes: this.scopes,
+        openBrowser: !isAndroid,
+      });
 
       if (isAndroid) {
-        // On Android, show in-app OAuth modal
+        // On Android, show in-app OAuth
         console.log('[Auth] Android detected, showing in-app OAuth');
-        return await this.showInAppOAuth(authUrl);
-      } else {
-        // On desktop, open in system browser
-        await invoke('open_url', { url: authUrl });
-        // Return promise that will be resolved by deep link handler
-        return new Promise((resolve, reject) => {
-          window._oauthResolve = resolve;
-          window._oauthReject = reject;
-        });
+        this.showInAppOAuth(authUrl);
       }
     } catch (error) {
       console.error('Failed to start auth:', error);
@@ -161,67 +116,55 @@ export class SpotifyAuth {
   }
 
   /**
-   * Show in-app OAuth for Android by navigating current window
-   * @param {string} authUrl - Spotify authorization URL
-   * @returns {Promise<string>} Authorization code
+   * Wait for the backend to finish a login started with startAuth()
+   * @param {number} timeoutMs
+   * @returns {Promise<void>}
    */
-  async showInAppOAuth(authUrl) {
-    console.log('[Auth] Android OAuth: navigating to Spotify');
-
-    // Inject keyboard debugging script after page loads
-    this.injectKeyboardDebugger();
-
-    // Use Tauri's event system
+  async waitForLogin(timeoutMs = 300000) {
     const { listen } = await import('@tauri-apps/api/event');
 
     return new Promise((resolve, reject) => {
       let unlisten;
+      const timer = setTimeout(() => {
+        if (unlisten) unlisten();
+        reject(new Error('OAuth timeout after 5 minutes'));
+      }, timeoutMs);
 
-      // Set up deep-link listener before navigating
-      listen('deep-link', (event) => {
-        console.log('[Auth] Deep-link received:', event);
-
-        const url = event.payload;
-        if (typeof url === 'string' && url.startsWith(this.redirectUri)) {
-          console.log('[Auth] OAuth redirect detected');
-
-          try {
-            const parsedUrl = new URL(url);
-            const code = parsedUrl.searchParams.get('code');
-
-            if (code) {
-              console.log('[Auth] Successfully captured OAuth code');
-              if (unlisten) unlisten();
-              resolve(code);
-            } else {
-              if (unlisten) unlisten();
-              reject(new Error('No code found in redirect URL'));
-            }
-          } catch (err) {
-            if (unlisten) unlisten();
-            reject(err);
-          }
+      listen('spotify://auth-changed', (event) => {
+        clearTimeout(timer);
+        if (unlisten) unlisten();
+
+        if (event.payload.authenticated) {
+          resolve();
+        } else {
+          reject(new Error(event.payload.error || 'Spotify login failed'));
         }
       }).then((unlistenFn) => {
         unlisten = unlistenFn;
       });
+    });
+  }
 
-      // Set timeout
-      setTimeout(() => {
-        if (unlisten) unlisten();
-        reject(new Error('OAuth timeout after 5 minutes'));
-      }, 300000);
+  /**
+   * Show in-app OAuth for Android by navigating current window. The
+   * musicviz://callback deep link is handled by the backend.
+   * @param {string} authUrl - Spotify authorization URL
+   */
+  showInAppOAuth(authUrl) {
+    console.log('[Auth] Android OAuth: navigating to Spotify');
 
-      // Navigate to Spotify OAuth page
-      console.log('[Auth] Navigating to:', authUrl);
-      window.location.href = authUrl;
-    });
+    // Inject keyboard debugging script after page loads
+    this.injectKeyboardDebugger();
+
+    // Navigate to Spotify OAuth page
+    console.log('[Auth] Navigating to:', authUrl);
+    window.location.href = authUrl;
   }
 
   /**
    * Fallback: Show OAuth in a window.open popup
    * @param {string} authUrl - Spotify authorization URL
-   * @returns {Promise<string>} Authorization code
+   * @returns {Promise<void>}
    */
   async showPopupOAuth(authUrl) {
     console.log('[Auth] Using window.open fallback for OAuth');
@@ -253,17 +196,10 @@ export class SpotifyAuth {
           if (popupUrl.startsWith(this.redirectUri)) {
             clearInterval(interval);
 
-            const url = new URL(popupUrl);
-            const code = url.searchParams.get('code');
-
             popup.close();
 
-            if (code) {
-              console.log('[Auth] Successfully captured OAuth code from popup');
-              resolve(code);
-            } else {
-              reject(new Error('No code found in redirect URL'));
-            }
+            console.log('[Auth] Captured OAuth redirect from popup');
+            this.completeLogin(popupUrl).then(resolve, reject);
           }
         } catch (e) {
           // Cross-origin error is expected while on Spotify domain
@@ -282,94 +218,33 @@ export class SpotifyAuth {
   }
 
   /**
-   * Exchange authorization code for access token
-   * @param {string} code - Authorization code from Spotify redirect
-   * @returns {Promise<{access_token: string, refresh_token: string, expires_in: number}>}
+   * Complete a login from a callback URL that did not arrive as a deep link
+   * (e.g. the web callback page). The backend validates `state` and
+   * exchanges the code.
+   * @param {string} callbackUrl - Full redirect URL including query params
+   * @returns {Promise<void>}
    */
-  async exchangeCodeForToken(code) {
+  async completeLogin(callbackUrl) {
     try {
-      // Get code_verifier from Rust backend
-      const codeVerifier = await invoke('get_code_verifier');
-
-      console.log('Exchanging authorization code for token...');
-
-      // Exchange code for token (PKCE - no secret needed!)
-      const response = await fetch('https://accounts.spotify.com/api/token', {
-        method: 'POST',
-        headers: {
-          'Content-Type': 'application/x-www-form-urlencoded',
-        },
-        body: new URLSearchParams({
-          grant_type: 'authorization_code',
-          code: code,
-          redirect_uri: this.redirectUri,
-          client_id: this.clientId,
-          code_verifier: codeVerifier, // PKCE verifier, not secret!
-        }),
-      });
-
-      if (!response.ok) {
-        const error = await response.json();
-        throw new Error(`Token exchange failed: ${error.error_description || error.error}`);
-      }
-
-      const data = await response.json();
-
-      // Store tokens securely in Rust backend
-      await invoke('store_spotify_token', {
-        accessToken: data.access_token,
-        refreshToken: data.refresh_token,
-        expiresIn: data.expires_in,
-      });
-
+      await invoke('spotify_complete_login', { callbackUrl });
       console.log('Successfully obtained and stored tokens');
-
-      return data;
     } catch (error) {
-      console.error('Failed to exchange code for token:', error);
+      console.error('Failed to complete login:', error);
       throw error;
     }
   }
 
   /**
-   * Refresh expired access token
-   * @param {string} refreshToken - Refresh token from previous auth
-   * @returns {Promise<{access_token: string, expires_in: number}>}
+   * Refresh the access token now (the backend runs the refresh_token grant
+   * and persists the rotated tokens)
+   * @returns {Promise<{access_token: string, expires_at: number}>}
    */
-  async refreshToken(refreshToken) {
+  async refreshToken() {
     try {
       console.log('Refreshing access token...');
-
-      // Refresh token (PKCE - no secret needed!)
-      const response = await fetch('https://accounts.spotify.com/api/token', {
-        method: 'POST',
-        headers: {
-          'Content-Type': 'application/x-www-form-urlencoded',
-        },
-        body: new URLSearchParams({
-          grant_type: 'refresh_token',
-          refresh_token: refreshToken,
-          client_id: this.clientId, // Only Client ID needed!
-        }),
-      });
-
-      if (!response.ok) {
-        const error = await response.json();
-        throw new Error(`Token refresh failed: ${error.error_description || error.error}`);
-      }
-
-      const data = await response.json();
-
-      // Store new access token
-      await invoke('store_spotify_token', {
-        accessToken: data.access_token,
-        refreshToken: refreshToken, // Keep existing refresh token
-        expiresIn: data.expires_in,
-      });
-
+      const token = await invoke('refresh_spotify_token');
       console.log('Successfully refreshed token');
-
-      return data;
+      return token;
     } catch (error) {
       console.error('Failed to refresh token:', error);
       throw error;
@@ -377,8 +252,43 @@ export class SpotifyAuth {
   }
 
   /**
-   * Get stored access token from backend
-   * @returns {Promise<{access_token: string, refresh_token: string, expires_at: number} | null>}
+   * Get an access token that is valid for at least the next minute
+   * (refreshed by the backend when needed)
+   * @returns {Promise<string>}
+   */
+  async getValidAccessToken() {
+    return invoke('get_valid_access_token');
+  }
+
+  /**
+   * Check whether the current token has the given scopes
+   * @param {string[]} scopes
+   * @returns {Promise<{granted: string[] | null, missing: string[], hasAll: boolean}>}
+   */
+  async hasScopes(scopes) {
+    return invoke('spotify_has_scopes', { scopes });
+  }
+
+  /**
+   * Make sure the token has the scopes a feature needs, re-authorizing
+   * with the additional scopes when it does not
+   * @param {string[]} scopes
+   * @returns {Promise<boolean>} Whether the scopes are granted afterwards
+   */
+  async ensureScopes(scopes) {
+    const authUrl = await invoke('spotify_request_scopes', { scopes });
+    if (!authUrl) {
+      return true;
+    }
+
+    await this.waitForLogin();
+    const status = await this.hasScopes(scopes);
+    return status.hasAll;
+  }
+
+  /**
+   * Get stored access token from backend (the refresh token stays there)
+   * @returns {Promise<{access_token: string, expires_at: number} | null>}
    */
   async getStoredToken() {
     try {
@@ -401,37 +311,8 @@ export class SpotifyAuth {
    */
   async isAuthenticated() {
     try {
-      let tokenData = await this.getStoredToken();
-
-      if (!tokenData) {
-        return false;
-      }
-
-      const now = Date.now() / 1000; // seconds
-      const expiresAt = tokenData.expires_at || 0;
-      const refreshThreshold = 60; // seconds before expiry to refresh proactively
-
-      const isExpired = expiresAt > 0 && now >= expiresAt;
-      const needsRefresh = expiresAt > 0 && now >= (expiresAt - refreshThreshold);
-
-      if ((isExpired || needsRefresh) && tokenData.refresh_token) {
-        try {
-          console.log('[SpotifyAuth] Access token expired/expiring, attempting refresh');
-          await this.refreshToken(tokenData.refresh_token);
-          tokenData = await this.getStoredToken();
-          return !!tokenData;
-        } catch (refreshError) {
-          console.error('[SpotifyAuth] Failed to refresh token during auth check:', refreshError);
-          return false;
-        }
-      }
-
-      if (isExpired && !tokenData.refresh_token) {
-        console.warn('[SpotifyAuth] Access token expired and no refresh token available');
-        return false;
-      }
-
-      return true;
+      // The backend refreshes expired tokens before answering
+      return await invoke('is_authenticated');
     } catch (error) {
       console.error('Failed to check authentication:', error);
       return false;
@@ -501,31 +382,61 @@ export class SpotifyAuth {
   }
 
   /**
-   * Get current user profile
-   * @returns {Promise<object>}
+   * List Spotify accounts that have logged in on this device
+   * @returns {Promise<{active: string | null, profiles: Array<object>}>}
    */
-  async getCurrentUser() {
-    try {
-      const token = await this.getStoredToken();
+  async listProfiles() {
+    return invoke('spotify_list_profiles');
+  }
 
-      if (!token) {
-        throw new Error('Not authenticated');
-      }
+  /**
+   * Make another logged-in account the active one
+   * @param {string} userId - Spotify user id
+   * @returns {Promise<object>} The profile switched to
+   */
+  async switchProfile(userId) {
+    return invoke('spotify_switch_profile', { userId });
+  }
 
-      const response = await fetch('https://api.spotify.com/v1/me', {
-        headers: {
-          'Authorization': `Bearer ${token.access_token}`,
-        },
-      });
+  /**
+   * Forget an account, its tokens and preferences
+   * @param {string} userId - Spotify user id
+   * @returns {Promise<void>}
+   */
+  async removeProfile(userId) {
+    return invoke('spotify_remove_profile', { userId });
+  }
 
-      if (!response.ok) {
-        throw new Error('Failed to get user profile');
-      }
+  /**
+   * Get preferences of a profile (the active one by default)
+   * @param {string} [userId]
+   * @returns {Promise<object>}
+   */
+  async getProfilePreferences(userId) {
+    return invoke('spotify_get_profile_preferences', { userId });
+  }
 
-      return await response.json();
+  /**
+   * Replace preferences of a profile (the active one by default)
+   * @param {object} preferences
+   * @param {string} [userId]
+   * @returns {Promise<void>}
+   */
+  async setProfilePreferences(preferences, userId) {
+    return invoke('spotify_set_profile_preferences', { userId, preferences });
+  }
+
+  /**
+   * Get current user profile
+   * @returns {Promise<object>}
+   */
+  async getCurrentUser() {
+    try {
+      // Bearer token, refresh on 401 and rate limiting are handled in Rust
+      return await invoke('spotify_api_get_me');
     } catch (error) {
       console.error('Failed to get current user:', error);
-      throw error;
+      throw new Error(error?.message || String(error));
     }
   }
 }
@@ -10,7 +10,19 @@
 import { GoveeDiscovery } from './discovery.js';
 import { GoveeLanApi } from './lanApi.js';
 import { ColorExtractor } from './colorExtractor.js';
-import { DEFAULT_CONFIG, LAN_CMD } from './types.js';
+import { DEFAULT_CONFIG } from './types.js';
+import { get } from 'svelte/store';
+import { audioFeatures, isAnalyzing } from '$lib/stores/audioStore.js';
+
+// Control layers the manager writes colors into (merged in the backend)
+const SYNC_LAYER = 'sync';
+const SCENES_LAYER = 'scenes';
+
+// How long manual colors override sync and scenes
+const MANUAL_HOLD_SECONDS = 600;
+
+// Spectral centroid mapped to a 0-1 pitch over this range
+const PITCH_RANGE_HZ = 8000;
 
 /**
  * Main Govee integration manager
@@ -18,9 +30,9 @@ import { DEFAULT_CONFIG, LAN_CMD } from './types.js';
 export class GoveeManager {
   constructor(options = {}) {
     this.options = {
-      apiKey: options.apiKey || null,
       useLanApi: options.useLanApi !== false, // Default to true
       discoveryTimeout: options.discoveryTimeout || DEFAULT_CONFIG.DISCOVERY_TIMEOUT,
+      manualHoldSeconds: options.manualHoldSeconds || MANUAL_HOLD_SECONDS,
       syncOptions: options.syncOptions || {}
     };
 
@@ -40,7 +52,7 @@ export class GoveeManager {
 
     // Sync state
     this.lastColors = [];
-    this.beatDetector = null;
+    this.spatialField = null;
     this.latencyCompensation = options.latencyCompensation || DEFAULT_CONFIG.LATENCY_COMPENSATION;
 
     console.log('[GoveeManager] Initialized with options:', this.options);
@@ -144,7 +156,7 @@ export class GoveeManager {
   }
 
   /**
-   * Set device color
+   * Set device color (held on the manual layer above sync and scenes)
    * @param {string} deviceId - Device ID
    * @param {import('./types.js').RGBColor} color - RGB color
    * @returns {Promise<boolean>}
@@ -156,7 +168,7 @@ export class GoveeManager {
       return false;
     }
 
-    const success = await this.lanApi.setColor(device.ip, deviceId, color);
+    const [success] = await this.manualOverride([device], color);
     if (success && device.state) {
       device.state.color = color;
     }
@@ -165,45 +177,138 @@ export class GoveeManager {
   }
 
   /**
-   * Set color for all active devices
+   * Set color for all active devices (held on the manual layer)
    * @param {import('./types.js').RGBColor} color - RGB color
    * @returns {Promise<boolean[]>}
    */
   async setAllColors(color) {
-    const commands = this.getActiveDevices().map(device => ({
-      ip: device.ip,
-      id: device.id,
-      command: {
-        cmd: LAN_CMD.COLORWC,
-        data: { color }
-      }
-    }));
-
-    return this.lanApi.sendBatch(commands);
+    return this.manualOverride(this.getActiveDevices(), color);
   }
 
   /**
    * Set colors for zones (multiple devices)
    * @param {import('./types.js').RGBColor[]} colors - Array of colors
+   * @param {string} layerId - Control layer to write into
    * @returns {Promise<boolean[]>}
    */
-  async setZoneColors(colors) {
+  async setZoneColors(colors, layerId = SYNC_LAYER) {
     const devices = this.getActiveDevices();
-    const commands = [];
-
-    devices.forEach((device, index) => {
-      const colorIndex = index % colors.length;
-      commands.push({
-        ip: device.ip,
-        id: device.id,
-        command: {
-          cmd: LAN_CMD.COLORWC,
-          data: { color: colors[colorIndex] }
-        }
+    const frames = devices.map((device, index) => ({
+      target: this.target(device.id),
+      colors: [colors[index % colors.length]]
+    }));
+
+    return this.submitLayer(layerId, devices, frames);
+  }
+
+  /**
+   * Layer target for a whole Govee device
+   * @private
+   */
+  target(deviceId) {
+    return { backend: 'govee', deviceId, segment: null };
+  }
+
+  /**
+   * Write frames into a control layer; the backend merges all layers and
+   * sends the result through caps, the safety limiter and calibration
+   * @private
+   * @returns {Promise<boolean[]>} Success per device
+   */
+  async submitLayer(layerId, devices, frames) {
+    if (devices.length === 0) {
+      return [];
+    }
+
+    try {
+      const { invoke } = await import('@tauri-apps/api/core');
+      const report = await invoke('layers_submit', { layerId, frames });
+      return this.deviceResults(devices, report);
+    } catch (error) {
+      console.error(`[GoveeManager] Failed to write ${layerId} layer:`, error);
+      return devices.map(() => false);
+    }
+  }
+
+  /**
+   * Hold a color on devices through the manual layer
+   * @private
+   * @returns {Promise<boolean[]>} Success per device
+   */
+  async manualOverride(devices, color) {
+    if (devices.length === 0) {
+      return [];
+    }
+
+    try {
+      const { invoke } = await import('@tauri-apps/api/core');
+      const report = await invoke('layers_manual_override', {
+        targets: devices.map(device => this.target(device.id)),
+        color,
+        seconds: this.options.manualHoldSeconds
       });
-    });
+      return this.deviceResults(devices, report);
+    } catch (error) {
+      console.error('[GoveeManager] Manual override failed:', error);
+      return devices.map(() => false);
+    }
+  }
 
-    return this.lanApi.sendBatch(commands);
+  /**
+   * Per-device success from a backend flush report
+   * @private
+   */
+  deviceResults(devices, report) {
+    return devices.map(device =>
+      !report.errors.some(error => error.startsWith(`Govee ${device.id}:`))
+    );
+  }
+
+  /**
+   * Clear a control layer so the layers below show again
+   * @private
+   */
+  async clearLayer(layerId) {
+    try {
+      const { invoke } = await import('@tauri-apps/api/core');
+      await invoke('layers_clear', { layerId, targets: null });
+    } catch (error) {
+      console.error(`[GoveeManager] Failed to clear ${layerId} layer:`, error);
+    }
+  }
+
+  /**
+   * List devices registered to the Govee account; the API key is read by
+   * the backend from the credential vault
+   * @returns {Promise<Array<{device: string, model: string, deviceName: string}>>}
+   */
+  async listCloudDevices() {
+    try {
+      const { invoke } = await import('@tauri-apps/api/core');
+      return await invoke('govee_cloud_list_devices');
+    } catch (error) {
+      console.error('[GoveeManager] Cloud device list failed:', error);
+      return [];
+    }
+  }
+
+  /**
+   * Send a cloud API command ("turn", "brightness", "color" or "colorTem")
+   * @param {string} device - Cloud device ID
+   * @param {string} model - Device model
+   * @param {string} name - Command name
+   * @param {*} value - Command value
+   * @returns {Promise<boolean>}
+   */
+  async cloudControl(device, model, name, value) {
+    try {
+      const { invoke } = await import('@tauri-apps/api/core');
+      await invoke('govee_cloud_control', { device, model, cmd: { name, value } });
+      return true;
+    } catch (error) {
+      console.error(`[GoveeManager] Cloud command ${name} failed:`, error);
+      return false;
+    }
   }
 
   /**
@@ -232,20 +337,32 @@ export class GoveeManager {
     this.currentCanvas = canvas;
     this.colorExtractor.initialize(canvas);
 
+    // Scenes sit above canvas sync; starting sync hands the lights back to it
+    this.clearLayer(SCENES_LAYER);
+
     // Start color extraction
     this.colorExtractor.startExtraction(async (colors) => {
       // Apply latency compensation
       setTimeout(async () => {
+        // A spatial field colors placed lights by position instead
+        if (this.spatialField) {
+          await this.syncSpatialField();
+          return;
+        }
+
         // Check if we have beat information
         const audioFeatures = this.getAudioFeatures();
+        if (audioFeatures) {
+          this.reportAudioLevel(audioFeatures.energy);
+        }
         const enhancedColors = audioFeatures
           ? this.colorExtractor.getAudioReactiveColors(audioFeatures)
           : colors;
 
         console.log('[GoveeManager] Extracted colors:', enhancedColors);
 
-        // Send colors to devices
-        await this.setZoneColors(enhancedColors);
+        // Send colors to devices through the sync layer
+        await this.setZoneColors(enhancedColors, SYNC_LAYER);
         this.lastColors = enhancedColors;
       }, this.latencyCompensation);
     });
@@ -270,29 +387,62 @@ export class GoveeManager {
   }
 
   /**
-   * Set beat detector for audio reactive features
-   * @param {Object} beatDetector - Beat detector instance
+   * Color placed lights (of every backend) from a spatial field on each sync
+   * tick instead of the canvas; pass null to go back to canvas colors
+   * @param {Object|null} field - Spatial field definition (see room layout)
+   * @param {string|null} roomId - Restrict to one room (null: all rooms)
+   */
+  setSpatialField(field, roomId = null) {
+    this.spatialField = field ? { field, roomId } : null;
+    console.log('[GoveeManager] Spatial field set:', this.spatialField);
+  }
+
+  /**
+   * Sample the spatial field at every placed light and send it through the
+   * sync layer (the backend uses the latest audio features)
+   * @private
    */
-  setBeatDetector(beatDetector) {
-    this.beatDetector = beatDetector;
-    console.log('[GoveeManager] Beat detector set');
+  async syncSpatialField() {
+    try {
+      const { invoke } = await import('@tauri-apps/api/core');
+      const report = await invoke('room_layout_sync_field', this.spatialField);
+      if (report.errors.length > 0) {
+        console.warn('[GoveeManager] Spatial sync errors:', report.errors);
+      }
+    } catch (error) {
+      console.error('[GoveeManager] Failed to sync spatial field:', error);
+    }
+  }
+
+  /**
+   * Report the audio energy for the backend's silence detection
+   * @private
+   * @param {number} energy - 0.0 to 1.0
+   */
+  async reportAudioLevel(energy) {
+    try {
+      const { invoke } = await import('@tauri-apps/api/core');
+      await invoke('govee_report_audio_level', { energy });
+    } catch (error) {
+      console.error('[GoveeManager] Failed to report audio level:', error);
+    }
   }
 
   /**
-   * Get current audio features
+   * Get current audio features from the audio analyser
    * @private
+   * @returns {{energy: number, pitch: number, isBeat: boolean}|null} Null while no audio is analysed
    */
   getAudioFeatures() {
-    if (!this.beatDetector) {
+    if (!get(isAnalyzing)) {
       return null;
     }
 
-    // This would interface with your audio analysis
-    // For now, return mock data
+    const features = get(audioFeatures);
     return {
-      energy: Math.random(),
-      pitch: Math.random(),
-      isBeat: Math.random() > 0.9
+      energy: features.volume,
+      pitch: Math.min(1, features.spectralCentroid / PITCH_RANGE_HZ),
+      isBeat: features.beat
     };
   }
 
@@ -312,7 +462,7 @@ export class GoveeManager {
           { r: 75, g: 0, b: 130 },   // Indigo
           { r: 148, g: 0, b: 211 }   // Violet
         ];
-        await this.setZoneColors(colors);
+        await this.setZoneColors(colors, SCENES_LAYER);
       },
       party: async () => {
         // Rapid color changes
@@ -322,18 +472,21 @@ export class GoveeManager {
             g: Math.floor(Math.random() * 256),
             b: Math.floor(Math.random() * 256)
           };
-          await this.setAllColors(randomColor);
+          await this.setZoneColors([randomColor], SCENES_LAYER);
         }, 500);
 
-        // Stop after 10 seconds
-        setTimeout(() => clearInterval(interval), 10000);
+        // Stop after 10 seconds and hand the lights back
+        setTimeout(() => {
+          clearInterval(interval);
+          this.clearLayer(SCENES_LAYER);
+        }, 10000);
       },
       chill: async () => {
         const colors = [
           { r: 0, g: 100, b: 200 },   // Cool blue
           { r: 0, g: 150, b: 150 },   // Teal
         ];
-        await this.setZoneColors(colors);
+        await this.setZoneColors(colors, SCENES_LAYER);
         await this.setBrightnessAll(40);
       },
       sunset: async () => {
@@ -342,7 +495,7 @@ export class GoveeManager {
           { r: 255, g: 154, b: 0 },   // Orange
           { r: 255, g: 206, b: 84 },  // Yellow
         ];
-        await this.setZoneColors(colors);
+        await this.setZoneColors(colors, SCENES_LAYER);
       }
     };
 
@@ -111,16 +111,6 @@ export const LAN_CMD = {
   COLORWC: 'colorwc'  // Color and color temperature combined
 };
 
-/**
- * Cloud API Endpoints
- */
-export const CLOUD_API = {
-  BASE_URL: 'https://developer-api.govee.com/v1',
-  DEVICES: '/devices',
-  CONTROL: '/devices/control',
-  STATE: '/devices/state'
-};
-
 /**
  * Default Configuration
  */
@@ -9,6 +9,7 @@
  * - Frequency band extraction with logarithmic scaling
  * - Time-domain waveform data
  * - Smoothed audio features (volume, bass, etc.)
+ * - Bass-onset beat detection, tempo estimate and spectral centroid
  * - Efficient requestAnimationFrame-based updates
  */
 
@@ -33,6 +34,12 @@ export class AudioAnalyzer {
   // Number of spectrum bars for visualization (logarithmic distribution)
   static SPECTRUM_BAR_COUNT = 64;
 
+  // Beat detection: bass must exceed its running average by this factor
+  static BEAT_THRESHOLD = 1.4;
+  static BEAT_MIN_LEVEL = 0.1; // Ignore onsets in near silence
+  static BEAT_MIN_INTERVAL_MS = 250; // At most 240 BPM
+  static TEMPO_HISTORY = 8; // Beat intervals averaged for the tempo
+
   constructor() {
     this.audioContext = null;
     this.analyserNode = null;
@@ -54,6 +61,11 @@ export class AudioAnalyzer {
     // Previous frame data for smoothing
     this.previousFrequencyData = null;
 
+    // Beat detection state
+    this.bassAverage = 0;
+    this.lastBeatTime = 0;
+    this.beatIntervals = [];
+
     // Callback for data updates
     this.onDataUpdate = null;
   }
@@ -205,6 +217,9 @@ export class AudioAnalyzer {
     // Normalize time-domain data to -1 to 1 range
     const waveform = this._normalizeWaveform();
 
+    const timestamp = performance.now();
+    const beat = this._detectBeat(features.bass, timestamp);
+
     // Prepare data package
     const audioData = {
       // Raw data
@@ -221,9 +236,12 @@ export class AudioAnalyzer {
       bass: features.bass, // Bass level (0-1)
       mid: features.mid, // Mid level (0-1)
       treble: features.treble, // Treble level (0-1)
+      beat, // True on frames with a bass onset
+      tempo: this._estimateTempo(), // Beats per minute (0 when unknown)
+      spectralCentroid: this._spectralCentroid(), // Hz
 
       // Metadata
-      timestamp: performance.now(),
+      timestamp,
       sampleRate: this.audioContext.sampleRate
     };
 
@@ -326,6 +344,67 @@ export class AudioAnalyzer {
     return { volume, bass, mid, treble };
   }
 
+  /**
+   * Detect a beat as a bass onset above the running bass average
+   * @private
+   * @param {number} bass - Current bass level (0-1)
+   * @param {number} timestamp - Frame time in ms
+   * @returns {boolean} Whether this frame starts a beat
+   */
+  _detectBeat(bass, timestamp) {
+    const beat = bass > AudioAnalyzer.BEAT_MIN_LEVEL &&
+      bass > this.bassAverage * AudioAnalyzer.BEAT_THRESHOLD &&
+      timestamp - this.lastBeatTime >= AudioAnalyzer.BEAT_MIN_INTERVAL_MS;
+
+    // Slow average (about one second at 60fps)
+    this.bassAverage = this.bassAverage * 0.98 + bass * 0.02;
+
+    if (beat) {
+      if (this.lastBeatTime > 0) {
+        this.beatIntervals.push(timestamp - this.lastBeatTime);
+        if (this.beatIntervals.length > AudioAnalyzer.TEMPO_HISTORY) {
+          this.beatIntervals.shift();
+        }
+      }
+      this.lastBeatTime = timestamp;
+    }
+
+    return beat;
+  }
+
+  /**
+   * Estimate tempo from recent beat intervals
+   * @private
+   * @returns {number} Beats per minute (0 when unknown)
+   */
+  _estimateTempo() {
+    if (this.beatIntervals.length < 2) {
+      return 0;
+    }
+
+    const sorted = [...this.beatIntervals].sort((a, b) => a - b);
+    const median = sorted[Math.floor(sorted.length / 2)];
+    return median > 0 ? 60000 / median : 0;
+  }
+
+  /**
+   * Calculate the spectral centroid (brightness of the sound)
+   * @private
+   * @returns {number} Centroid frequency in Hz
+   */
+  _spectralCentroid() {
+    const binWidth = this.audioContext.sampleRate / 2 / this.smoothedFrequencyData.length;
+    let weighted = 0;
+    let total = 0;
+
+    for (let i = 0; i < this.smoothedFrequencyData.length; i++) {
+      weighted += i * binWidth * this.smoothedFrequencyData[i];
+      total += this.smoothedFrequencyData[i];
+    }
+
+    return total > 0 ? weighted / total : 0;
+  }
+
   /**
    * Normalize time-domain data to -1 to 1 range
    * @private
@@ -5,10 +5,12 @@
  * for real-time visualization.
  *
  * This module handles the Web Audio API plumbing required to
- * capture audio from the Spotify player for analysis.
+ * capture audio from the Spotify player for analysis, and forwards the
+ * analysed features to the backend.
  */
 
 import { AudioAnalyzer } from './AudioAnalyzer.js';
+import { pushAudioFeatures } from './audioFeatureBridge.js';
 import {
   updateAudioData,
   setAnalysisState,
@@ -85,6 +87,7 @@ export class AudioIntegration {
       // Start the analysis loop
       this.analyzer.startAnalysis((audioData) => {
         updateAudioData(audioData);
+        pushAudioFeatures(audioData);
       });
 
       this.isConnected = true;
@@ -220,6 +223,7 @@ export class AudioIntegration {
     if (this.analyzer && this.isConnected) {
       this.analyzer.startAnalysis((audioData) => {
         updateAudioData(audioData);
+        pushAudioFeatures(audioData);
       });
       setAnalysisState('active');
     }
@@ -9,6 +9,8 @@ export class SpotifyPlayer {
     this.deviceId = null;
     this.isReady = false;
     this.eventListeners = new Map();
+    // Last playback state reported to the backend
+    this.reportedPlaying = null;
 
     // Track current state
     this.currentState = {
@@ -103,6 +105,7 @@ export class SpotifyPlayer {
               track: null,
               volume: this.currentState.volume
             };
+            this.reportPlayback(false);
             this.emit('state_changed', this.currentState);
             return;
           }
@@ -120,6 +123,7 @@ export class SpotifyPlayer {
             volume: this.currentState.volume
           };
 
+          this.reportPlayback(!state.paused);
           this.emit('state_changed', this.currentState);
         });
 
@@ -142,6 +146,24 @@ export class SpotifyPlayer {
    * Wait for Spotify SDK to be loaded
    * @returns {Promise<void>}
    */
+  /**
+   * Tell the backend whether music is playing (drives the lights' idle policy)
+   * @param {boolean} playing
+   * @private
+   */
+  reportPlayback(playing) {
+    if (this.reportedPlaying === playing) {
+      return;
+    }
+    this.reportedPlaying = playing;
+    invoke('govee_report_playback', { playing }).catch((error) => {
+      console.error('Failed to report playback state:', error);
+    });
+    invoke('schedule_report_music_activity', { playing }).catch((error) => {
+      console.error('Failed to report music activity:', error);
+    });
+  }
+
   waitForSpotifySDK() {
     return new Promise((resolve, reject) => {
       if (window.Spotify) {
@@ -169,11 +191,7 @@ export class SpotifyPlayer {
    */
   async getAccessToken() {
     try {
-      const tokenData = await invoke('get_spotify_token');
-      if (!tokenData || !tokenData.access_token) {
-        throw new Error('No access token available');
-      }
-      return tokenData.access_token;
+      return await invoke('get_valid_access_token');
     } catch (error) {
       console.error('Failed to get access token:', error);
       throw error;
@@ -191,24 +209,7 @@ export class SpotifyPlayer {
     }
 
     try {
-      const token = await this.getAccessToken();
-
-      const response = await fetch('https://api.spotify.com/v1/me/player', {
-        method: 'PUT',
-        headers: {
-          'Authorization': `Bearer ${token}`,
-          'Content-Type': 'application/json'
-        },
-        body: JSON.stringify({
-          device_ids: [this.deviceId],
-          play: play
-        })
-      });
-
-      if (!response.ok) {
-        const error = await response.json();
-        throw new Error(`Failed to transfer playback: ${error.error?.message || response.statusText}`);
-      }
+      await invoke('spotify_api_transfer_playback', { deviceId: this.deviceId, play });
 
       console.log('Playback transferred to musicViz Player');
       this.emit('playback_transferred', { device_id: this.deviceId });
@@ -233,8 +234,6 @@ export class SpotifyPlayer {
     }
 
     try {
-      const token = await this.getAccessToken();
-
       // Build request body
       const body = {};
       if (options.context_uri) body.context_uri = options.context_uri;
@@ -242,19 +241,7 @@ export class SpotifyPlayer {
       if (options.offset !== undefined) body.offset = options.offset;
       if (options.position_ms !== undefined) body.position_ms = options.position_ms;
 
-      const response = await fetch(`https://api.spotify.com/v1/me/player/play?device_id=${this.deviceId}`, {
-        method: 'PUT',
-        headers: {
-          'Authorization': `Bearer ${token}`,
-          'Content-Type': 'application/json'
-        },
-        body: JSON.stringify(body)
-      });
-
-      if (!response.ok) {
-        const error = await response.json().catch(() => ({}));
-        throw new Error(`Failed to start playback: ${error.error?.message || response.statusText}`);
-      }
+      await invoke('spotify_api_start_playback', { deviceId: this.deviceId, options: body });
 
       console.log('Playback started on musicViz Player');
       this.emit('playback_started', { device_id: this.deviceId, options });
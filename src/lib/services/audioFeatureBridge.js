@@ -0,0 +1,49 @@
+/**
+ * Audio Feature Bridge
+ *
+ * Forwards analyser output to the backend, where it drives audio-reactive
+ * effects, the schedule's music detection, Govee idle handling and the
+ * session recorder (effects_push_audio_features).
+ */
+
+import { invoke } from '@tauri-apps/api/core';
+
+// Minimum time between pushes (about 30 per second)
+const PUSH_INTERVAL_MS = 33;
+
+let lastPush = 0;
+let pendingBeat = false;
+let inFlight = false;
+
+/**
+ * Push one frame of analyser data (throttled; beats between pushes are
+ * carried over to the next one)
+ * @param {Object} data - Audio analysis data from AudioAnalyzer
+ */
+export function pushAudioFeatures(data) {
+  pendingBeat = pendingBeat || data.beat;
+  if (inFlight || data.timestamp - lastPush < PUSH_INTERVAL_MS) {
+    return;
+  }
+
+  const features = {
+    timeMs: data.timestamp,
+    energy: data.volume,
+    bass: data.bass,
+    mid: data.mid,
+    treble: data.treble,
+    spectrum: Array.from(data.spectrum),
+    beat: pendingBeat,
+    tempo: data.tempo,
+    spectralCentroid: data.spectralCentroid
+  };
+  lastPush = data.timestamp;
+  pendingBeat = false;
+  inFlight = true;
+
+  invoke('effects_push_audio_features', { features })
+    .catch((error) => console.error('[AudioFeatureBridge] Failed to push audio features:', error))
+    .finally(() => {
+      inFlight = false;
+    });
+}
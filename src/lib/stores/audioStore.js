@@ -62,14 +62,20 @@ export const frequencyBands = writable({
  *   volume: 0.6,  // Overall volume/energy
  *   bass: 0.7,    // Bass level
  *   mid: 0.5,     // Mid-range level
- *   treble: 0.4   // Treble level
+ *   treble: 0.4,  // Treble level
+ *   beat: false,  // Bass onset on this frame
+ *   tempo: 120,   // Beats per minute (0 when unknown)
+ *   spectralCentroid: 1800 // Hz
  * }
  */
 export const audioFeatures = writable({
   volume: 0,
   bass: 0,
   mid: 0,
-  treble: 0
+  treble: 0,
+  beat: false,
+  tempo: 0,
+  spectralCentroid: 0
 });
 
 /**
@@ -158,7 +164,10 @@ export function updateAudioData(data) {
     volume: data.volume,
     bass: data.bass,
     mid: data.mid,
-    treble: data.treble
+    treble: data.treble,
+    beat: data.beat,
+    tempo: data.tempo,
+    spectralCentroid: data.spectralCentroid
   });
 
   // Update metadata
@@ -211,7 +220,10 @@ export function resetAudioData() {
     volume: 0,
     bass: 0,
     mid: 0,
-    treble: 0
+    treble: 0,
+    beat: false,
+    tempo: 0,
+    spectralCentroid: 0
   });
 
   analysisState.set('idle');
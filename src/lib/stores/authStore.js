@@ -87,42 +87,14 @@ export async function checkExistingAuth() {
   try {
     const spotifyAuth = new SpotifyAuth();
 
-    // Load stored token (may trigger on-demand hydration from backend)
-    let tokenData = await spotifyAuth.getStoredToken();
+    // Load stored token (the backend hydrates it from storage and refreshes
+    // it when expired)
+    const tokenData = await spotifyAuth.getStoredToken();
     if (!tokenData) {
       console.log('[authStore] No stored token found');
       return false;
     }
 
-    const now = Date.now() / 1000; // seconds
-    const expiresAt = tokenData.expires_at || 0;
-    const refreshThreshold = 60; // seconds before expiry to refresh proactively
-
-    const isExpired = expiresAt > 0 && now >= expiresAt;
-    const needsRefresh = expiresAt > 0 && now >= (expiresAt - refreshThreshold);
-
-    if ((isExpired || needsRefresh) && tokenData.refresh_token) {
-      try {
-        console.log('[authStore] Access token expired/expiring, refreshing...');
-        await spotifyAuth.refreshToken(tokenData.refresh_token);
-        tokenData = await spotifyAuth.getStoredToken();
-
-        if (!tokenData) {
-          console.error('[authStore] Failed to load token after refresh');
-          resetAuth();
-          return false;
-        }
-      } catch (refreshError) {
-        console.error('[authStore] Failed to refresh token:', refreshError);
-        resetAuth();
-        return false;
-      }
-    } else if (isExpired && !tokenData.refresh_token) {
-      console.log('[authStore] Token expired and no refresh token available');
-      resetAuth();
-      return false;
-    }
-
     // Get user profile to verify token is valid
     try {
       const user = await spotifyAuth.getCurrentUser();
@@ -142,31 +114,27 @@ export async function checkExistingAuth() {
     } catch (error) {
       console.error('[authStore] Failed to get user profile:', error);
 
-      // Token might be invalid, try refreshing
-      if (tokenData.refresh_token) {
-        try {
-          const refreshedData = await spotifyAuth.refreshToken(tokenData.refresh_token);
-          const user = await spotifyAuth.getCurrentUser();
-          const latestToken = await spotifyAuth.getStoredToken();
-
-          if (!latestToken) {
-            console.warn('[authStore] No token available after refresh attempt');
-            resetAuth();
-            return false;
-          }
-
-          setAuthenticated(user, latestToken.access_token || refreshedData.access_token);
-          console.log('[authStore] Restored authentication after refresh for user:', user.display_name || user.id);
-          return true;
-        } catch (refreshError) {
-          console.error('[authStore] Failed to refresh and restore auth:', refreshError);
+      // Token might be invalid, try refreshing (fails when the backend
+      // holds no refresh token)
+      try {
+        const refreshedData = await spotifyAuth.refreshToken();
+        const user = await spotifyAuth.getCurrentUser();
+        const latestToken = await spotifyAuth.getStoredToken();
+
+        if (!latestToken) {
+          console.warn('[authStore] No token available after refresh attempt');
           resetAuth();
           return false;
         }
-      }
 
-      resetAuth();
-      return false;
+        setAuthenticated(user, latestToken.access_token || refreshedData.access_token);
+        console.log('[authStore] Restored authentication after refresh for user:', user.display_name || user.id);
+        return true;
+      } catch (refreshError) {
+        console.error('[authStore] Failed to refresh and restore auth:', refreshError);
+        resetAuth();
+        return false;
+      }
     }
   } catch (error) {
     console.error('[authStore] Error checking existing auth:', error);
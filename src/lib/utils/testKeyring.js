@@ -25,7 +25,6 @@ export async function testKeyring() {
       if (token) {
         console.log('Token found!', {
           hasAccessToken: !!token.access_token,
-          hasRefreshToken: !!token.refresh_token,
           expiresAt: token.expires_at,
           expiresIn: new Date(token.expires_at * 1000).toLocaleString()
         });